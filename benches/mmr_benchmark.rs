@@ -6,10 +6,13 @@ use criterion::{black_box, BenchmarkId, Criterion};
 use bytes::Bytes;
 use polkadot_ckb_merkle_mountain_range::ancestry_proof::expected_ancestry_proof_size;
 use polkadot_ckb_merkle_mountain_range::{
-    util::MemStore, Error, MMRStoreReadOps, Merge, Result, MMR,
+    leaf_index_to_mmr_size,
+    util::{MemStore, ThrottledStore},
+    Error, MMRStoreReadOps, Merge, Pos, Result, MMR,
 };
 use rand::{seq::SliceRandom, thread_rng};
 use std::convert::TryFrom;
+use std::time::Duration;
 
 use blake2b_rs::{Blake2b, Blake2bBuilder};
 use itertools::iproduct;
@@ -35,6 +38,7 @@ struct MergeNumberHash;
 
 impl Merge for MergeNumberHash {
     type Item = NumberHash;
+    type Error = Error;
     fn merge(lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item> {
         let mut hasher = new_blake2b();
         let mut hash = [0u8; 32];
@@ -45,11 +49,13 @@ impl Merge for MergeNumberHash {
     }
 }
 
-fn prepare_mmr(count: u32) -> (u64, MemStore<NumberHash>, Vec<u64>, Vec<(u32, NumberHash)>) {
+type PreparedMMR = (Pos, MemStore<NumberHash>, Vec<Pos>, Vec<(u32, NumberHash)>);
+
+fn prepare_mmr(count: u32) -> PreparedMMR {
     let store = MemStore::default();
     let mut prev_roots = Vec::new();
     let mut mmr = MMR::<_, MergeNumberHash, _>::new(0, &store);
-    let positions: Vec<u64> = (0u32..count)
+    let positions: Vec<Pos> = (0u32..count)
         .map(|i| {
             let position = mmr.push(NumberHash::try_from(i).unwrap()).unwrap();
             prev_roots.push((i + 1, mmr.get_root().expect("get root")));
@@ -61,8 +67,8 @@ fn prepare_mmr(count: u32) -> (u64, MemStore<NumberHash>, Vec<u64>, Vec<(u32, Nu
     (mmr_size, store, positions, prev_roots)
 }
 
-const INDEX_OFFSET: u64 = 100_000;
-const INDEX_DOMAIN: u64 = 2_000;
+const INDEX_OFFSET: Pos = 100_000;
+const INDEX_DOMAIN: Pos = 2_000;
 
 fn bench(c: &mut Criterion) {
     {
@@ -76,28 +82,43 @@ fn bench(c: &mut Criterion) {
     }
 
     c.bench_function("MMR gen proof", |b| {
-        let (mmr_size, store, positions, _) = prepare_mmr(1000_000);
+        let (mmr_size, store, positions, _) = prepare_mmr(1_000_000);
+        let mmr = MMR::<_, MergeNumberHash, _>::new(mmr_size, &store);
+        let mut rng = thread_rng();
+        b.iter(|| mmr.gen_proof(vec![*positions.choose(&mut rng).unwrap()]));
+    });
+
+    c.bench_function("MMR gen single proof", |b| {
+        let (mmr_size, store, positions, _) = prepare_mmr(1_000_000);
+        let mmr = MMR::<_, MergeNumberHash, _>::new(mmr_size, &store);
+        let mut rng = thread_rng();
+        b.iter(|| mmr.gen_single_proof(*positions.choose(&mut rng).unwrap()));
+    });
+
+    c.bench_function("MMR gen proof with 20us reads", |b| {
+        let (mmr_size, store, positions, _) = prepare_mmr(1_000_000);
+        let store = ThrottledStore::new(&store).with_read_latency(Duration::from_micros(20));
         let mmr = MMR::<_, MergeNumberHash, _>::new(mmr_size, &store);
         let mut rng = thread_rng();
         b.iter(|| mmr.gen_proof(vec![*positions.choose(&mut rng).unwrap()]));
     });
 
     c.bench_function("MMR gen node-proof", |b| {
-        let (mmr_size, store, positions, _) = prepare_mmr(1000_000);
+        let (mmr_size, store, positions, _) = prepare_mmr(1_000_000);
         let mmr = MMR::<_, MergeNumberHash, _>::new(mmr_size, &store);
         let mut rng = thread_rng();
         b.iter(|| mmr.gen_node_proof(vec![*positions.choose(&mut rng).unwrap()]));
     });
 
     c.bench_function("MMR gen ancestry-proof", |b| {
-        let (mmr_size, store, _positions, roots) = prepare_mmr(1000_000);
+        let (mmr_size, store, _positions, roots) = prepare_mmr(1_000_000);
         let mmr = MMR::<_, MergeNumberHash, _>::new(mmr_size, &store);
         let mut rng = thread_rng();
-        b.iter(|| mmr.gen_ancestry_proof(roots.choose(&mut rng).unwrap().0 as u64));
+        b.iter(|| mmr.gen_ancestry_proof(roots.choose(&mut rng).unwrap().0 as Pos));
     });
 
     c.bench_function("MMR verify", |b| {
-        let (mmr_size, store, positions, _) = prepare_mmr(1000_000);
+        let (mmr_size, store, positions, _) = prepare_mmr(1_000_000);
         let mmr = MMR::<_, MergeNumberHash, _>::new(mmr_size, &store);
         let mut rng = thread_rng();
         let root: NumberHash = mmr.get_root().unwrap();
@@ -118,7 +139,7 @@ fn bench(c: &mut Criterion) {
     });
 
     c.bench_function("MMR verify node-proof", |b| {
-        let (mmr_size, store, positions, _) = prepare_mmr(1000_000);
+        let (mmr_size, store, positions, _) = prepare_mmr(1_000_000);
         let mmr = MMR::<_, MergeNumberHash, _>::new(mmr_size, &store);
         let mut rng = thread_rng();
         let root: NumberHash = mmr.get_root().unwrap();
@@ -139,21 +160,22 @@ fn bench(c: &mut Criterion) {
     });
 
     c.bench_function("MMR verify ancestry-proof", |b| {
-        let (mmr_size, store, _positions, roots) = prepare_mmr(1000_000);
+        let (mmr_size, store, _positions, roots) = prepare_mmr(1_000_000);
         let mmr = MMR::<_, MergeNumberHash, _>::new(mmr_size, &store);
         let mut rng = thread_rng();
         let root: NumberHash = mmr.get_root().unwrap();
         let proofs: Vec<_> = (0..10_000)
             .map(|_| {
-                let (prev_size, prev_root) = roots.choose(&mut rng).unwrap();
-                let proof = mmr.gen_ancestry_proof(*prev_size as u64).unwrap();
+                let (prev_leaves, prev_root) = roots.choose(&mut rng).unwrap();
+                let prev_size = leaf_index_to_mmr_size(*prev_leaves as Pos - 1);
+                let proof = mmr.gen_ancestry_proof(prev_size).unwrap();
                 (prev_root, proof)
             })
             .collect();
         b.iter(|| {
             let (prev_root, proof) = proofs.choose(&mut rng).unwrap();
             proof
-                .verify_ancestor(root.clone(), prev_root.clone().clone())
+                .verify_ancestor(root.clone(), (*prev_root).clone())
                 .unwrap();
         });
     });
@@ -0,0 +1,136 @@
+//! Build and verify MMRs over the lines of a text file.
+//!
+//! ``` sh
+//! cargo run --example mmr-cli --features scale -- build records.txt records.mmr
+//! cargo run --example mmr-cli --features scale -- prove records.mmr 42 line42.proof
+//! cargo run --example mmr-cli --features scale -- verify <root> line42.proof 42 "record 42"
+//! ```
+//!
+//! Every line is a leaf, hashed with blake2b behind a domain separation byte. The store is saved
+//! with `MemStore::to_bytes` and the proofs are SCALE encoded.
+
+use blake2b_rs::Blake2bBuilder;
+use codec::Encode;
+use polkadot_ckb_merkle_mountain_range::util::{MemMMR, MemStore};
+use polkadot_ckb_merkle_mountain_range::{
+    leaf_index_to_pos, Error, LeafHasher, Merge, MerkleProof, Pos,
+};
+use std::{env, fs, process};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+const LEAF: u8 = 0;
+const NODE: u8 = 1;
+const PEAKS: u8 = 2;
+
+struct Blake2bMerge;
+
+impl Blake2bMerge {
+    fn hash(domain: u8, parts: &[&[u8]]) -> Vec<u8> {
+        let mut hasher = Blake2bBuilder::new(32).build();
+        hasher.update(&[domain]);
+        for part in parts {
+            hasher.update(part);
+        }
+        let mut hash = vec![0; 32];
+        hasher.finalize(&mut hash);
+        hash
+    }
+}
+
+impl Merge for Blake2bMerge {
+    type Item = Vec<u8>;
+    type Error = Error;
+
+    fn merge(left: &Vec<u8>, right: &Vec<u8>) -> std::result::Result<Vec<u8>, Error> {
+        Ok(Self::hash(NODE, &[left, right]))
+    }
+
+    fn merge_peaks(right: &Vec<u8>, left: &Vec<u8>) -> std::result::Result<Vec<u8>, Error> {
+        Ok(Self::hash(PEAKS, &[right, left]))
+    }
+}
+
+impl LeafHasher for Blake2bMerge {
+    fn hash_leaf(leaf: &[u8]) -> std::result::Result<Vec<u8>, Error> {
+        Ok(Self::hash(LEAF, &[leaf]))
+    }
+}
+
+fn load_store(path: &str) -> Result<MemStore<Vec<u8>>> {
+    Ok(MemStore::from_bytes(&fs::read(path)?)?)
+}
+
+/// The position of the leaf of the 1-based `line`.
+fn line_pos(line: &str) -> Result<Pos> {
+    match line.parse::<Pos>()? {
+        0 => Err("line numbers start at 1".into()),
+        line => Ok(leaf_index_to_pos(line - 1)),
+    }
+}
+
+fn build(records: &str, store_path: &str) -> Result<()> {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, Blake2bMerge>::new(0, &store);
+    for record in fs::read_to_string(records)?.lines() {
+        mmr.push_raw(record)?;
+    }
+    mmr.commit()?;
+    fs::write(store_path, store.to_bytes())?;
+    println!("leaves {}", mmr.leaves_count());
+    println!("root {}", faster_hex::hex_string(&mmr.get_root()?));
+    Ok(())
+}
+
+fn root(store_path: &str) -> Result<()> {
+    let store = load_store(store_path)?;
+    let mmr = MemMMR::<_, Blake2bMerge>::open(&store)?;
+    println!("leaves {}", mmr.leaves_count());
+    println!("root {}", faster_hex::hex_string(&mmr.get_root()?));
+    Ok(())
+}
+
+fn prove(store_path: &str, line: &str, proof_path: &str) -> Result<()> {
+    let store = load_store(store_path)?;
+    let mmr = MemMMR::<_, Blake2bMerge>::open(&store)?;
+    let proof = mmr.gen_proof(vec![line_pos(line)?])?;
+    fs::write(proof_path, proof.encode())?;
+    println!("proof of {} items", proof.proof_items().len());
+    Ok(())
+}
+
+fn verify(root: &str, proof_path: &str, line: &str, record: &str) -> Result<()> {
+    let mut root_bytes = vec![0; root.len() / 2];
+    faster_hex::hex_decode(root.as_bytes(), &mut root_bytes)?;
+    let proof = MerkleProof::<Vec<u8>, Blake2bMerge>::from_encoded(&fs::read(proof_path)?)?;
+    let leaf = Blake2bMerge::hash_leaf(record.as_bytes())?;
+    if proof.verify(root_bytes, vec![(line_pos(line)?, leaf)])? {
+        println!("valid");
+        Ok(())
+    } else {
+        Err("invalid proof".into())
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args[..] {
+        ["build", records, store] => build(records, store),
+        ["root", store] => root(store),
+        ["prove", store, line, proof] => prove(store, line, proof),
+        ["verify", root, proof, line, record] => verify(root, proof, line, record),
+        _ => {
+            eprintln!(
+                "usage:\n  mmr-cli build <records> <store>\n  mmr-cli root <store>\n  \
+                 mmr-cli prove <store> <line> <proof>\n  \
+                 mmr-cli verify <root> <proof> <line> <record>"
+            );
+            process::exit(2);
+        }
+    };
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,19 @@
+use polkadot_ckb_merkle_mountain_range::{FixedHash, Merge, Result};
+
+pub type Hash = FixedHash<32>;
+
+/// Cheap, non-cryptographic merge, the fuzz targets only exercise the proof logic.
+#[derive(Debug)]
+pub struct MergeFuzz;
+
+impl Merge for MergeFuzz {
+    type Item = Hash;
+    type Error = polkadot_ckb_merkle_mountain_range::Error;
+    fn merge(lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item> {
+        let mut hash = [0u8; 32];
+        for (i, byte) in hash.iter_mut().enumerate() {
+            *byte = lhs.0[i].rotate_left(3) ^ rhs.0[31 - i].wrapping_add(i as u8);
+        }
+        Ok(hash.into())
+    }
+}
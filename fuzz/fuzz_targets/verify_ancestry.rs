@@ -0,0 +1,32 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use polkadot_ckb_merkle_mountain_range as mmr;
+
+mod common;
+
+use common::{Hash, MergeFuzz};
+use mmr::util::{MemStore, MmrState};
+use mmr::{leaf_index_to_mmr_size, AncestryProof};
+
+fuzz_target!(
+    |input: (MmrState<Hash>, u64, AncestryProof<Hash, MergeFuzz>)| {
+        let (state, prev_leaf_index, proof) = input;
+        let store = MemStore::default();
+        let (mmr, positions) = state.build::<MergeFuzz>(&store).expect("build mmr");
+        let root = mmr.get_root().expect("get root");
+        let prev_mmr_size = leaf_index_to_mmr_size(prev_leaf_index % positions.len() as u64);
+        let (_prev_peaks, prev_root) = mmr
+            .get_ancestor_peaks_and_root(prev_mmr_size)
+            .expect("get ancestor root");
+
+        // a genuine proof always verifies
+        let genuine = mmr.gen_ancestry_proof(prev_mmr_size).expect("gen proof");
+        assert!(genuine
+            .verify_ancestor(root, prev_root)
+            .expect("verify ancestor"));
+
+        // malformed proofs may be rejected, but must never panic
+        let _ = proof.verify_ancestor(root, prev_root);
+    }
+);
@@ -0,0 +1,32 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use polkadot_ckb_merkle_mountain_range as mmr;
+
+mod common;
+
+use common::{Hash, MergeFuzz};
+use mmr::util::{MemStore, MmrState};
+use mmr::MerkleProof;
+
+type Input = (
+    MmrState<Hash>,
+    MerkleProof<Hash, MergeFuzz>,
+    Vec<(u64, Hash)>,
+);
+
+fuzz_target!(|input: Input| {
+    let (state, proof, leaves) = input;
+    let store = MemStore::default();
+    let (mmr, positions) = state.build::<MergeFuzz>(&store).expect("build mmr");
+    let root = mmr.get_root().expect("get root");
+
+    // a genuine proof always verifies
+    let pos = positions[leaves.len() % positions.len()];
+    let leaf = state.leaves()[leaves.len() % positions.len()];
+    let genuine = mmr.gen_proof(vec![pos]).expect("gen proof");
+    assert!(genuine.verify(root, vec![(pos, leaf)]).expect("verify"));
+
+    // malformed proofs may be rejected, but must never panic
+    let _ = proof.verify(root, leaves);
+});
@@ -1,64 +1,415 @@
 use crate::collections::VecDeque;
 use crate::helper::{
-    get_peak_map, get_peaks, is_descendant_pos, leaf_index_to_pos, parent_offset,
-    pos_height_in_tree, sibling_offset,
+    get_peak_map, get_peaks, is_descendant_pos, is_valid_mmr_size, iter_peaks,
+    leaf_count_to_mmr_size, leaf_index_to_pos, pos_height_in_tree, PositionScheme, PostOrderScheme,
+    VeqDequeExt,
 };
-use crate::mmr::{bagging_peaks_hashes, take_while_vec};
 use crate::vec::Vec;
-use crate::{Error, Merge, Result};
+use crate::verifier::{
+    bagging_peaks_hashes, push_to_peaks, sort_and_dedup_by_pos, take_while_vec, Iterations,
+};
+use crate::{metrics, Error, Merge, MmrSize, Pos, Result, VerifyLimits};
 use core::fmt::Debug;
 use core::marker::PhantomData;
-use itertools::Itertools;
+use core::ops::Deref;
 
 #[derive(Debug)]
 pub struct NodeMerkleProof<T, M> {
-    mmr_size: u64,
-    proof: Vec<(u64, T)>,
+    mmr_size: Pos,
+    proof: Vec<(Pos, T)>,
     merge: PhantomData<M>,
 }
 
+/// Which nodes `NodeMerkleProof::verify_with_policy` accepts, so one binary can apply the rules
+/// of several protocols.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodePolicy {
+    /// Only leaves, interior nodes fail with `GenProofForInvalidLeaves`.
+    LeavesOnly,
+    /// Leaves and interior nodes.
+    AllowNodes,
+}
+
 #[derive(Debug)]
 pub struct AncestryProof<T, M> {
-    pub prev_mmr_size: u64,
-    pub prev_peaks: Vec<T>,
-    pub prev_peaks_proof: NodeMerkleProof<T, M>,
+    prev_mmr_size: Pos,
+    prev_peaks: Vec<T>,
+    prev_peaks_proof: NodeMerkleProof<T, M>,
+}
+
+/// Proves that appending a known sequence of leaves to the MMR of `prev_mmr_size` yields the
+/// MMR of `mmr_size`, so light clients can follow an MMR block by block. The previous peaks are
+/// all it takes to replay the pushes, hence verification needs no state besides the two roots.
+#[derive(Debug)]
+pub struct UpdateProof<T, M> {
+    prev_mmr_size: Pos,
+    mmr_size: Pos,
+    prev_peaks: Vec<T>,
+    merge: PhantomData<M>,
+}
+
+/// Positions are encoded as compact integers, as are the sizes.
+#[cfg(feature = "scale")]
+impl<T: codec::Encode, M> codec::Encode for NodeMerkleProof<T, M> {
+    fn size_hint(&self) -> usize {
+        codec::Compact(self.mmr_size).size_hint()
+            + codec::Compact(self.proof.len() as u32).size_hint()
+            + self
+                .proof
+                .iter()
+                .map(|(pos, item)| codec::Compact(*pos).size_hint() + item.size_hint())
+                .sum::<usize>()
+    }
+
+    fn encode_to<W: codec::Output + ?Sized>(&self, dest: &mut W) {
+        codec::Compact(self.mmr_size).encode_to(dest);
+        codec::Compact(self.proof.len() as u32).encode_to(dest);
+        for (pos, item) in &self.proof {
+            codec::Compact(*pos).encode_to(dest);
+            item.encode_to(dest);
+        }
+    }
+}
+
+#[cfg(feature = "scale")]
+impl<T: codec::Encode, M> codec::EncodeLike for NodeMerkleProof<T, M> {}
+
+#[cfg(feature = "scale")]
+impl<T: codec::Decode, M> codec::Decode for NodeMerkleProof<T, M> {
+    fn decode<I: codec::Input>(input: &mut I) -> core::result::Result<Self, codec::Error> {
+        let mmr_size = codec::Compact::<Pos>::decode(input)?.0;
+        let len = codec::Compact::<u32>::decode(input)?.0;
+        // don't trust the length for preallocation
+        let mut proof = Vec::new();
+        for _ in 0..len {
+            let pos = codec::Compact::<Pos>::decode(input)?.0;
+            proof.push((pos, T::decode(input)?));
+        }
+        NodeMerkleProof::try_new(mmr_size, proof).map_err(|_| "Invalid node proof".into())
+    }
+}
+
+#[cfg(feature = "scale")]
+impl<T: codec::Encode, M> codec::Encode for AncestryProof<T, M> {
+    fn size_hint(&self) -> usize {
+        codec::Compact(self.prev_mmr_size).size_hint()
+            + self.prev_peaks.size_hint()
+            + self.prev_peaks_proof.size_hint()
+    }
+
+    fn encode_to<W: codec::Output + ?Sized>(&self, dest: &mut W) {
+        codec::Compact(self.prev_mmr_size).encode_to(dest);
+        self.prev_peaks.encode_to(dest);
+        self.prev_peaks_proof.encode_to(dest);
+    }
+}
+
+#[cfg(feature = "scale")]
+impl<T: codec::Encode, M> codec::EncodeLike for AncestryProof<T, M> {}
+
+/// Decoding applies the checks of `AncestryProof::new`.
+#[cfg(feature = "scale")]
+impl<T: codec::Decode, M> codec::Decode for AncestryProof<T, M> {
+    fn decode<I: codec::Input>(input: &mut I) -> core::result::Result<Self, codec::Error> {
+        let prev_mmr_size = codec::Compact::<Pos>::decode(input)?.0;
+        let prev_peaks = Vec::<T>::decode(input)?;
+        let prev_peaks_proof = NodeMerkleProof::decode(input)?;
+        AncestryProof::new(prev_peaks, prev_mmr_size, prev_peaks_proof)
+            .map_err(|_| "Invalid ancestry proof".into())
+    }
+}
+
+#[cfg(feature = "scale")]
+impl<T: codec::Encode, M> codec::Encode for UpdateProof<T, M> {
+    fn size_hint(&self) -> usize {
+        codec::Compact(self.prev_mmr_size).size_hint()
+            + codec::Compact(self.mmr_size).size_hint()
+            + self.prev_peaks.size_hint()
+    }
+
+    fn encode_to<W: codec::Output + ?Sized>(&self, dest: &mut W) {
+        codec::Compact(self.prev_mmr_size).encode_to(dest);
+        codec::Compact(self.mmr_size).encode_to(dest);
+        self.prev_peaks.encode_to(dest);
+    }
+}
+
+#[cfg(feature = "scale")]
+impl<T: codec::Encode, M> codec::EncodeLike for UpdateProof<T, M> {}
+
+/// Decoding applies the checks of `UpdateProof::new`.
+#[cfg(feature = "scale")]
+impl<T: codec::Decode, M> codec::Decode for UpdateProof<T, M> {
+    fn decode<I: codec::Input>(input: &mut I) -> core::result::Result<Self, codec::Error> {
+        let prev_mmr_size = codec::Compact::<Pos>::decode(input)?.0;
+        let mmr_size = codec::Compact::<Pos>::decode(input)?.0;
+        let prev_peaks = Vec::<T>::decode(input)?;
+        UpdateProof::new(prev_peaks, prev_mmr_size, mmr_size)
+            .map_err(|_| "Invalid update proof".into())
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a, T: arbitrary::Arbitrary<'a>, M> arbitrary::Arbitrary<'a> for NodeMerkleProof<T, M> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(NodeMerkleProof {
+            mmr_size: u.arbitrary()?,
+            proof: u.arbitrary()?,
+            merge: PhantomData,
+        })
+    }
+}
+
+/// Bypasses the checks of `AncestryProof::new`, so the verifier gets to see malformed proofs.
+#[cfg(feature = "arbitrary")]
+impl<'a, T: arbitrary::Arbitrary<'a>, M> arbitrary::Arbitrary<'a> for AncestryProof<T, M> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(AncestryProof {
+            prev_mmr_size: u.arbitrary()?,
+            prev_peaks: u.arbitrary()?,
+            prev_peaks_proof: u.arbitrary()?,
+        })
+    }
+}
+
+impl<T, M> AncestryProof<T, M> {
+    /// Construct an ancestry proof, checking that `prev_peaks` matches the peaks of `prev_mmr_size`.
+    ///
+    /// Fails with `CorruptedProof` if `prev_mmr_size` is not a valid mmr size or the number of
+    /// peaks differs from the number of mountains at that size, and with
+    /// `AncestorRootNotPredecessor` if `prev_mmr_size` exceeds the size of the proof.
+    pub fn new(
+        prev_peaks: Vec<T>,
+        prev_mmr_size: Pos,
+        prev_peaks_proof: NodeMerkleProof<T, M>,
+    ) -> Result<Self> {
+        if prev_mmr_size > prev_peaks_proof.mmr_size {
+            return Err(Error::AncestorRootNotPredecessor {
+                prev_mmr_size,
+                mmr_size: prev_peaks_proof.mmr_size,
+            });
+        }
+        if prev_mmr_size == 0 || !is_valid_mmr_size(prev_mmr_size) {
+            return Err(Error::CorruptedProof);
+        }
+        if get_peak_map(prev_mmr_size).count_ones() as usize != prev_peaks.len() {
+            return Err(Error::CorruptedProof);
+        }
+        Ok(AncestryProof {
+            prev_mmr_size,
+            prev_peaks,
+            prev_peaks_proof,
+        })
+    }
+
+    pub fn prev_mmr_size(&self) -> Pos {
+        self.prev_mmr_size
+    }
+
+    /// Same as `prev_mmr_size`, typed, failing with `InvalidMMRSize` if the proof was built
+    /// around `new`, e.g. by `arbitrary`, with an invalid size.
+    pub fn prev_size(&self) -> Result<MmrSize> {
+        MmrSize::new(self.prev_mmr_size)
+    }
+
+    /// Number of leaves of the ancestor MMR.
+    pub fn prev_leaf_count(&self) -> Pos {
+        get_peak_map(self.prev_mmr_size)
+    }
+
+    pub fn prev_peaks(&self) -> &[T] {
+        &self.prev_peaks
+    }
+
+    pub fn prev_peaks_proof(&self) -> &NodeMerkleProof<T, M> {
+        &self.prev_peaks_proof
+    }
 }
 
 impl<T: PartialEq + Debug + Clone, M: Merge<Item = T>> AncestryProof<T, M> {
     // TODO: restrict roots to be T::Node
     pub fn verify_ancestor(&self, root: T, prev_root: T) -> Result<bool> {
-        let current_leaves_count = get_peak_map(self.prev_peaks_proof.mmr_size);
-        if current_leaves_count <= self.prev_peaks.len() as u64 {
-            return Err(Error::CorruptedProof);
-        }
+        let nodes = self.prev_peaks_nodes()?;
         // Test if previous root is correct.
-        let prev_peaks_positions = {
-            let prev_peaks_positions = get_peaks(self.prev_mmr_size);
-            if prev_peaks_positions.len() != self.prev_peaks.len() {
-                return Err(Error::CorruptedProof);
-            }
-            prev_peaks_positions
-        };
+        let calculated_prev_root = bagging_peaks_hashes::<T, M>(self.prev_peaks.clone())?;
+        if calculated_prev_root != prev_root {
+            return Ok(false);
+        }
+        self.prev_peaks_proof.verify(root, nodes)
+    }
 
+    /// Same as `verify_ancestor`, but fails with `LimitExceeded` once `limits` are exceeded.
+    pub fn verify_ancestor_with_limits(
+        &self,
+        root: T,
+        prev_root: T,
+        limits: &VerifyLimits,
+    ) -> Result<bool> {
+        let nodes = self.prev_peaks_nodes()?;
         let calculated_prev_root = bagging_peaks_hashes::<T, M>(self.prev_peaks.clone())?;
         if calculated_prev_root != prev_root {
             return Ok(false);
         }
+        self.prev_peaks_proof
+            .verify_with_limits(root, nodes, limits)
+    }
+
+    /// Same as `verify_ancestor`, but compares the roots in constant time. Both roots are
+    /// always calculated, so the timing doesn't tell which of them mismatched.
+    #[cfg(feature = "subtle")]
+    pub fn verify_ancestor_ct(&self, root: T, prev_root: T) -> Result<bool>
+    where
+        T: subtle::ConstantTimeEq,
+    {
+        let nodes = self.prev_peaks_nodes()?;
+        let calculated_prev_root = bagging_peaks_hashes::<T, M>(self.prev_peaks.clone())?;
+        let calculated_root = self.prev_peaks_proof.calculate_root(nodes)?;
+        Ok((calculated_prev_root.ct_eq(&prev_root) & calculated_root.ct_eq(&root)).into())
+    }
+
+    /// Same as `verify_ancestor`, but against the `current_peaks` of the MMR, along with their
+    /// positions, for protocols committing to the peak set rather than to the bagged root.
+    pub fn verify_against_peaks(&self, current_peaks: &[(Pos, T)], prev_root: T) -> Result<bool> {
+        let nodes = self.prev_peaks_nodes()?;
+        let calculated_prev_root = bagging_peaks_hashes::<T, M>(self.prev_peaks.clone())?;
+        if calculated_prev_root != prev_root {
+            return Ok(false);
+        }
+        self.prev_peaks_proof
+            .verify_against_peaks(current_peaks, nodes)
+    }
+
+    /// The previous peaks along with their positions, the nodes proven by `prev_peaks_proof`.
+    fn prev_peaks_nodes(&self) -> Result<Vec<(Pos, T)>> {
+        // the MMR is an ancestor of itself, with every prev peak a current one
+        let current_leaves_count = get_peak_map(self.prev_peaks_proof.mmr_size);
+        if current_leaves_count < self.prev_peaks.len() as Pos {
+            return Err(Error::CorruptedProof);
+        }
+        let prev_peaks_positions = get_peaks(self.prev_mmr_size);
+        if prev_peaks_positions.len() != self.prev_peaks.len() {
+            return Err(Error::CorruptedProof);
+        }
+        Ok(prev_peaks_positions
+            .into_iter()
+            .zip(self.prev_peaks.iter().cloned())
+            .collect())
+    }
+
+    /// Same as `verify_ancestor`, additionally checking that the ancestor has `prev_leaf_count`
+    /// leaves.
+    pub fn verify_ancestor_for_leaf_count(
+        &self,
+        root: T,
+        prev_root: T,
+        prev_leaf_count: Pos,
+    ) -> Result<bool> {
+        let prev_mmr_size =
+            leaf_count_to_mmr_size(prev_leaf_count).ok_or(Error::InvalidLeafCount {
+                leaf_count: prev_leaf_count,
+            })?;
+        if prev_mmr_size != self.prev_mmr_size {
+            return Ok(false);
+        }
+        self.verify_ancestor(root, prev_root)
+    }
+}
+
+impl<T, M> UpdateProof<T, M> {
+    /// Construct an update proof from the peaks of the MMR of `prev_mmr_size`.
+    ///
+    /// Fails with `AncestorRootNotPredecessor` if `prev_mmr_size` exceeds `mmr_size`, with
+    /// `InvalidMMRSize` if either size is not a valid non-empty size, and with `InvalidPeaks` if
+    /// the number of peaks differs from the number of mountains at `prev_mmr_size`.
+    pub fn new(prev_peaks: Vec<T>, prev_mmr_size: Pos, mmr_size: Pos) -> Result<Self> {
+        if prev_mmr_size > mmr_size {
+            return Err(Error::AncestorRootNotPredecessor {
+                prev_mmr_size,
+                mmr_size,
+            });
+        }
+        for size in [prev_mmr_size, mmr_size] {
+            if size == 0 || !is_valid_mmr_size(size) {
+                return Err(Error::InvalidMMRSize { mmr_size: size });
+            }
+        }
+        if get_peak_map(prev_mmr_size).count_ones() as usize != prev_peaks.len() {
+            return Err(Error::InvalidPeaks {
+                mmr_size: prev_mmr_size,
+            });
+        }
+        Ok(UpdateProof {
+            prev_mmr_size,
+            mmr_size,
+            prev_peaks,
+            merge: PhantomData,
+        })
+    }
 
-        let nodes = self
-            .prev_peaks
-            .clone()
+    pub fn prev_mmr_size(&self) -> Pos {
+        self.prev_mmr_size
+    }
+
+    pub fn mmr_size(&self) -> Pos {
+        self.mmr_size
+    }
+
+    /// Same as `prev_mmr_size`, typed.
+    pub fn prev_size(&self) -> Result<MmrSize> {
+        MmrSize::new(self.prev_mmr_size)
+    }
+
+    /// Same as `mmr_size`, typed.
+    pub fn size(&self) -> Result<MmrSize> {
+        MmrSize::new(self.mmr_size)
+    }
+
+    /// Number of leaves the update appends.
+    pub fn leaves_count(&self) -> Pos {
+        get_peak_map(self.mmr_size) - get_peak_map(self.prev_mmr_size)
+    }
+
+    pub fn prev_peaks(&self) -> &[T] {
+        &self.prev_peaks
+    }
+}
+
+impl<T: Clone + PartialEq, M: Merge<Item = T>> UpdateProof<T, M> {
+    /// Calculate the peaks, along with their positions, after appending `leaves` in insertion
+    /// order, e.g. to check the next update against them.
+    ///
+    /// Fails with `GenProofForInvalidLeaves` unless exactly `leaves_count` leaves are given.
+    pub fn calculate_peaks(&self, leaves: Vec<T>) -> Result<Vec<(Pos, T)>> {
+        if leaves.len() as Pos != self.leaves_count() {
+            return Err(Error::GenProofForInvalidLeaves);
+        }
+        let mut peaks: Vec<(Pos, T)> = get_peaks(self.prev_mmr_size)
             .into_iter()
-            .zip(prev_peaks_positions.iter())
-            .map(|(peak, position)| (*position, peak))
+            .zip(self.prev_peaks.iter().cloned())
             .collect();
+        let mut mmr_size = self.prev_mmr_size;
+        for leaf in leaves {
+            mmr_size = push_to_peaks::<_, M>(&mut peaks, mmr_size, leaf)?;
+        }
+        debug_assert_eq!(mmr_size, self.mmr_size);
+        Ok(peaks)
+    }
 
-        self.prev_peaks_proof.verify(root, nodes)
+    /// Verify that the MMR with `prev_root` becomes the MMR with `root` by appending `leaves`.
+    pub fn verify(&self, prev_root: T, root: T, leaves: Vec<T>) -> Result<bool> {
+        let calculated_prev_root = bagging_peaks_hashes::<T, M>(self.prev_peaks.clone())?;
+        if calculated_prev_root != prev_root {
+            return Ok(false);
+        }
+        let peaks = self.calculate_peaks(leaves)?;
+        let calculated_root =
+            bagging_peaks_hashes::<T, M>(peaks.into_iter().map(|(_pos, peak)| peak).collect())?;
+        Ok(calculated_root == root)
     }
 }
 
-impl<T: Clone + PartialEq, M: Merge<Item = T>> NodeMerkleProof<T, M> {
-    pub fn new(mmr_size: u64, proof: Vec<(u64, T)>) -> Self {
+impl<T, M> NodeMerkleProof<T, M> {
+    pub fn new(mmr_size: Pos, proof: Vec<(Pos, T)>) -> Self {
         NodeMerkleProof {
             mmr_size,
             proof,
@@ -66,16 +417,58 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>> NodeMerkleProof<T, M> {
         }
     }
 
-    pub fn mmr_size(&self) -> u64 {
+    /// Same as `new`, failing with `InvalidMMRSize` if `mmr_size` isn't the size of an MMR and
+    /// with `CorruptedProof` unless the item positions are in the MMR and strictly ascending,
+    /// as `MMR::gen_node_proof` lays them out.
+    pub fn try_new(mmr_size: Pos, proof: Vec<(Pos, T)>) -> Result<Self> {
+        if !is_valid_mmr_size(mmr_size) {
+            return Err(Error::InvalidMMRSize { mmr_size });
+        }
+        if proof.iter().any(|(pos, _)| *pos >= mmr_size)
+            || proof.windows(2).any(|pair| pair[0].0 >= pair[1].0)
+        {
+            return Err(Error::CorruptedProof);
+        }
+        Ok(NodeMerkleProof::new(mmr_size, proof))
+    }
+
+    pub fn mmr_size(&self) -> Pos {
         self.mmr_size
     }
 
-    pub fn proof_items(&self) -> &[(u64, T)] {
+    /// Same as `mmr_size`, typed, failing with `InvalidMMRSize` for a proof built with `new`
+    /// from an invalid size.
+    pub fn size(&self) -> Result<MmrSize> {
+        MmrSize::new(self.mmr_size)
+    }
+
+    /// The items sorted by position, without duplicates if the proof was generated or built
+    /// with `try_new`.
+    pub fn proof_items(&self) -> &[(Pos, T)] {
         &self.proof
     }
+}
 
-    pub fn calculate_root(&self, leaves: Vec<(u64, T)>) -> Result<T> {
-        calculate_root::<_, M, _>(leaves, self.mmr_size, self.proof.iter())
+impl<T: Clone + PartialEq, M: Merge<Item = T>> NodeMerkleProof<T, M> {
+    pub fn calculate_root(&self, leaves: Vec<(Pos, T)>) -> Result<T> {
+        calculate_root::<_, M>(
+            leaves,
+            self.mmr_size,
+            &self.proof,
+            &mut Iterations::unbounded(),
+        )
+        .map(NodeItem::into_owned)
+    }
+
+    /// Same as `calculate_root`, but fails with `LimitExceeded` once `limits` are exceeded.
+    pub fn calculate_root_with_limits(
+        &self,
+        nodes: Vec<(Pos, T)>,
+        limits: &VerifyLimits,
+    ) -> Result<T> {
+        let mut iterations = limits.iterations(self.proof.len(), nodes.len())?;
+        calculate_root::<_, M>(nodes, self.mmr_size, &self.proof, &mut iterations)
+            .map(NodeItem::into_owned)
     }
 
     /// from merkle proof of leaf n to calculate merkle root of n + 1 leaves.
@@ -83,20 +476,109 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>> NodeMerkleProof<T, M> {
     /// https://github.com/jjyr/merkle-mountain-range#construct
     pub fn calculate_root_with_new_leaf(
         &self,
-        mut nodes: Vec<(u64, T)>,
-        new_pos: u64,
+        mut nodes: Vec<(Pos, T)>,
+        new_pos: Pos,
         new_elem: T,
-        new_mmr_size: u64,
+        new_mmr_size: Pos,
     ) -> Result<T> {
         nodes.push((new_pos, new_elem));
-        calculate_root::<_, M, _>(nodes, new_mmr_size, self.proof.iter())
+        calculate_root::<_, M>(
+            nodes,
+            new_mmr_size,
+            &self.proof,
+            &mut Iterations::unbounded(),
+        )
+        .map(NodeItem::into_owned)
     }
 
-    pub fn verify(&self, root: T, nodes: Vec<(u64, T)>) -> Result<bool> {
+    pub fn verify(&self, root: T, nodes: Vec<(Pos, T)>) -> Result<bool> {
         let calculated_root = self.calculate_root(nodes)?;
         Ok(calculated_root == root)
     }
 
+    /// Same as `verify`, but fails with `LimitExceeded` once `limits` are exceeded.
+    pub fn verify_with_limits(
+        &self,
+        root: T,
+        nodes: Vec<(Pos, T)>,
+        limits: &VerifyLimits,
+    ) -> Result<bool> {
+        let calculated_root = self.calculate_root_with_limits(nodes, limits)?;
+        Ok(calculated_root == root)
+    }
+
+    /// Calculate the peaks of the MMR from `nodes` without bagging them.
+    ///
+    /// Peaks right of the nodes are bagged by the prover into a single item, so the last hash is
+    /// the bagged root of those peaks if fewer hashes than peaks are returned.
+    pub fn calculate_peaks_hashes(&self, nodes: Vec<(Pos, T)>) -> Result<Vec<T>> {
+        Ok(calculate_peaks_hashes::<_, M>(
+            nodes,
+            self.mmr_size,
+            &self.proof,
+            &mut Iterations::unbounded(),
+        )?
+        .into_iter()
+        .map(NodeItem::into_owned)
+        .collect())
+    }
+
+    /// Verify `nodes` against the individual `peaks` of the MMR, ordered left to right along
+    /// with their positions, see `calculate_peaks_hashes`.
+    ///
+    /// Fails with `InvalidPeaks` if the positions aren't the peaks of the MMR.
+    pub fn verify_against_peaks(&self, peaks: &[(Pos, T)], nodes: Vec<(Pos, T)>) -> Result<bool> {
+        if !peaks
+            .iter()
+            .map(|(pos, _)| *pos)
+            .eq(iter_peaks(self.mmr_size))
+        {
+            return Err(Error::InvalidPeaks {
+                mmr_size: self.mmr_size,
+            });
+        }
+        let mut peaks_hashes = self.calculate_peaks_hashes(nodes)?;
+        if peaks_hashes.is_empty() || peaks_hashes.len() > peaks.len() {
+            return Err(Error::CorruptedProof);
+        }
+        let bagged_pos = peaks_hashes.len() - 1;
+        let bagged = peaks_hashes.pop().expect("not empty");
+        let rhs_peaks = peaks[bagged_pos..]
+            .iter()
+            .map(|(_, peak)| peak.clone())
+            .collect();
+        Ok(peaks_hashes
+            .iter()
+            .zip(peaks)
+            .all(|(calculated, (_, peak))| calculated == peak)
+            && bagging_peaks_hashes::<T, M>(rhs_peaks)? == bagged)
+    }
+
+    /// Same as `verify`, but only accepts the `nodes` allowed by `policy`.
+    pub fn verify_with_policy(
+        &self,
+        root: T,
+        nodes: Vec<(Pos, T)>,
+        policy: NodePolicy,
+    ) -> Result<bool> {
+        if policy == NodePolicy::LeavesOnly
+            && nodes.iter().any(|(pos, _)| pos_height_in_tree(*pos) > 0)
+        {
+            return Err(Error::GenProofForInvalidLeaves);
+        }
+        self.verify(root, nodes)
+    }
+
+    /// Same as `verify`, but compares the roots in constant time.
+    #[cfg(feature = "subtle")]
+    pub fn verify_ct(&self, root: T, nodes: Vec<(Pos, T)>) -> Result<bool>
+    where
+        T: subtle::ConstantTimeEq,
+    {
+        let calculated_root = self.calculate_root(nodes)?;
+        Ok(calculated_root.ct_eq(&root).into())
+    }
+
     /// Verifies a old root and all incremental leaves.
     ///
     /// If this method returns `true`, it means the following assertion are true:
@@ -106,11 +588,11 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>> NodeMerkleProof<T, M> {
     ///   current MMR.
     pub fn verify_incremental(&self, root: T, prev_root: T, incremental: Vec<T>) -> Result<bool> {
         let current_leaves_count = get_peak_map(self.mmr_size);
-        if current_leaves_count <= incremental.len() as u64 {
+        if current_leaves_count <= incremental.len() as Pos {
             return Err(Error::CorruptedProof);
         }
         // Test if previous root is correct.
-        let prev_leaves_count = current_leaves_count - incremental.len() as u64;
+        let prev_leaves_count = current_leaves_count - incremental.len() as Pos;
 
         let prev_peaks: Vec<_> = self
             .proof_items()
@@ -128,7 +610,7 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>> NodeMerkleProof<T, M> {
             .into_iter()
             .enumerate()
             .map(|(index, leaf)| {
-                let pos = leaf_index_to_pos(prev_leaves_count + index as u64);
+                let pos = leaf_index_to_pos(prev_leaves_count + index as Pos);
                 (pos, leaf)
             })
             .collect();
@@ -136,16 +618,130 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>> NodeMerkleProof<T, M> {
     }
 }
 
-fn calculate_peak_root<
-    'a,
-    T: 'a + PartialEq,
-    M: Merge<Item = T>,
-    // I: Iterator<Item = &'a T>
->(
-    nodes: Vec<(u64, T)>,
-    peak_pos: u64,
-    // proof_iter: &mut I,
-) -> Result<T> {
+/// A node merkle proof borrowing its items, e.g. straight from a decoded wire buffer.
+///
+/// Verification never clones the borrowed items, the root is always derived from the provided
+/// nodes, so verifying an empty list of nodes fails with `CorruptedProof`.
+#[derive(Debug)]
+pub struct MerkleProofRef<'a, T, M> {
+    mmr_size: Pos,
+    proof: &'a [(Pos, T)],
+    merge: PhantomData<M>,
+}
+
+impl<'a, T, M> Clone for MerkleProofRef<'a, T, M> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T, M> Copy for MerkleProofRef<'a, T, M> {}
+
+impl<'a, T, M> MerkleProofRef<'a, T, M> {
+    pub fn new(mmr_size: Pos, proof: &'a [(Pos, T)]) -> Self {
+        MerkleProofRef {
+            mmr_size,
+            proof,
+            merge: PhantomData,
+        }
+    }
+
+    pub fn mmr_size(&self) -> Pos {
+        self.mmr_size
+    }
+
+    pub fn proof_items(&self) -> &'a [(Pos, T)] {
+        self.proof
+    }
+}
+
+impl<'a, T: PartialEq, M: Merge<Item = T>> MerkleProofRef<'a, T, M> {
+    pub fn calculate_root(&self, nodes: Vec<(Pos, T)>) -> Result<T> {
+        self.calculate_root_in(nodes, &mut Iterations::unbounded())
+    }
+
+    /// Same as `calculate_root`, but fails with `LimitExceeded` once `limits` are exceeded.
+    pub fn calculate_root_with_limits(
+        &self,
+        nodes: Vec<(Pos, T)>,
+        limits: &VerifyLimits,
+    ) -> Result<T> {
+        let mut iterations = limits.iterations(self.proof.len(), nodes.len())?;
+        self.calculate_root_in(nodes, &mut iterations)
+    }
+
+    fn calculate_root_in(&self, nodes: Vec<(Pos, T)>, iterations: &mut Iterations) -> Result<T> {
+        if nodes.is_empty() {
+            return Err(Error::CorruptedProof);
+        }
+        match calculate_root::<_, M>(nodes, self.mmr_size, self.proof, iterations)? {
+            NodeItem::Owned(root) => Ok(root),
+            // a root taken verbatim from the proof implies the nodes weren't part of it
+            NodeItem::Borrowed(_) => Err(Error::CorruptedProof),
+        }
+    }
+
+    pub fn verify(&self, root: T, nodes: Vec<(Pos, T)>) -> Result<bool> {
+        let calculated_root = self.calculate_root(nodes)?;
+        Ok(calculated_root == root)
+    }
+
+    /// Same as `verify`, but fails with `LimitExceeded` once `limits` are exceeded.
+    pub fn verify_with_limits(
+        &self,
+        root: T,
+        nodes: Vec<(Pos, T)>,
+        limits: &VerifyLimits,
+    ) -> Result<bool> {
+        let calculated_root = self.calculate_root_with_limits(nodes, limits)?;
+        Ok(calculated_root == root)
+    }
+}
+
+impl<'a, T, M> From<&'a NodeMerkleProof<T, M>> for MerkleProofRef<'a, T, M> {
+    fn from(proof: &'a NodeMerkleProof<T, M>) -> Self {
+        MerkleProofRef::new(proof.mmr_size, &proof.proof)
+    }
+}
+
+/// A hash during verification, either taken from the proof or computed from the nodes.
+#[derive(Debug)]
+enum NodeItem<'a, T> {
+    Owned(T),
+    Borrowed(&'a T),
+}
+
+impl<'a, T: Clone> NodeItem<'a, T> {
+    fn into_owned(self) -> T {
+        match self {
+            NodeItem::Owned(item) => item,
+            NodeItem::Borrowed(item) => item.clone(),
+        }
+    }
+}
+
+impl<'a, T> Deref for NodeItem<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self {
+            NodeItem::Owned(item) => item,
+            NodeItem::Borrowed(item) => item,
+        }
+    }
+}
+
+impl<'a, T: PartialEq> PartialEq for NodeItem<'a, T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+fn calculate_peak_root<'a, T: PartialEq, M: Merge<Item = T>>(
+    nodes: Vec<(Pos, NodeItem<'a, T>)>,
+    peak_pos: Pos,
+    iterations: &mut Iterations,
+) -> Result<NodeItem<'a, T>> {
     debug_assert!(!nodes.is_empty(), "can't be empty");
     // (position, hash, height)
 
@@ -158,6 +754,8 @@ fn calculate_peak_root<
 
     // calculate tree root from each items
     while let Some((pos, item, height)) = queue.pop_front() {
+        iterations.step()?;
+        metrics::on_verification_steps(1);
         if pos == peak_pos {
             if queue.is_empty() {
                 // return root once queue is consumed
@@ -171,7 +769,7 @@ fn calculate_peak_root<
             }
             if queue
                 .iter()
-                .all(|entry| entry.0 == peak_pos && &entry.1 == &item && entry.2 == height)
+                .all(|entry| entry.0 == peak_pos && entry.1 == item && entry.2 == height)
             {
                 // return root if remaining queue consists only of duplicate root entries
                 return Ok(item);
@@ -181,53 +779,54 @@ fn calculate_peak_root<
             continue;
         }
         // calculate sibling
-        let next_height = pos_height_in_tree(pos + 1);
-        let (parent_pos, parent_item) = {
-            let sibling_offset = sibling_offset(height);
-            if next_height > height {
-                // implies pos is right sibling
-                let (sib_pos, parent_pos) = (pos - sibling_offset, pos + 1);
-                let parent_item = if Some(&sib_pos) == queue.front().map(|(pos, _, _)| pos) {
-                    let sibling_item = queue.pop_front().map(|(_, item, _)| item).unwrap();
-                    M::merge(&sibling_item, &item)?
-                } else if Some(&sib_pos) == queue.back().map(|(pos, _, _)| pos) {
-                    let sibling_item = queue.pop_back().map(|(_, item, _)| item).unwrap();
-                    M::merge(&sibling_item, &item)?
-                }
-                // handle special if next queue item is descendant of sibling
-                else if let Some(&(front_pos, ..)) = queue.front() {
-                    if height > 0 && is_descendant_pos(sib_pos, front_pos) {
-                        queue.push_back((pos, item, height));
-                        continue;
-                    } else {
-                        return Err(Error::CorruptedProof);
-                    }
+        let (sib_pos, parent_pos, is_right) = PostOrderScheme::sibling_and_parent_pos(pos, height);
+        let parent_item = if is_right {
+            // implies pos is right sibling
+            if Some(&sib_pos) == queue.front().map(|(pos, _, _)| pos) {
+                let sibling_item = queue.pop_front().map(|(_, item, _)| item).unwrap();
+                NodeItem::Owned(
+                    M::merge_with_pos(parent_pos, &sibling_item, &item).map_err(Into::into)?,
+                )
+            } else if Some(&sib_pos) == queue.back().map(|(pos, _, _)| pos) {
+                let sibling_item = queue.pop_back().map(|(_, item, _)| item).unwrap();
+                NodeItem::Owned(
+                    M::merge_with_pos(parent_pos, &sibling_item, &item).map_err(Into::into)?,
+                )
+            }
+            // handle special if next queue item is descendant of sibling
+            else if let Some(&(front_pos, ..)) = queue.front() {
+                if height > 0 && is_descendant_pos(sib_pos, front_pos) {
+                    queue.push_back((pos, item, height));
+                    continue;
                 } else {
                     return Err(Error::CorruptedProof);
-                };
-                (parent_pos, parent_item)
+                }
             } else {
-                // pos is left sibling
-                let (sib_pos, parent_pos) = (pos + sibling_offset, pos + parent_offset(height));
-                let parent_item = if Some(&sib_pos) == queue.front().map(|(pos, _, _)| pos) {
-                    let sibling_item = queue.pop_front().map(|(_, item, _)| item).unwrap();
-                    M::merge(&item, &sibling_item)?
-                } else if Some(&sib_pos) == queue.back().map(|(pos, _, _)| pos) {
-                    let sibling_item = queue.pop_back().map(|(_, item, _)| item).unwrap();
-                    let parent = M::merge(&item, &sibling_item)?;
-                    sibs_processed_from_back.push((sib_pos, sibling_item, height));
-                    parent
-                } else if let Some(&(front_pos, ..)) = queue.front() {
-                    if height > 0 && is_descendant_pos(sib_pos, front_pos) {
-                        queue.push_back((pos, item, height));
-                        continue;
-                    } else {
-                        return Err(Error::CorruptedProof);
-                    }
+                return Err(Error::CorruptedProof);
+            }
+        } else {
+            // pos is left sibling
+            if Some(&sib_pos) == queue.front().map(|(pos, _, _)| pos) {
+                let sibling_item = queue.pop_front().map(|(_, item, _)| item).unwrap();
+                NodeItem::Owned(
+                    M::merge_with_pos(parent_pos, &item, &sibling_item).map_err(Into::into)?,
+                )
+            } else if Some(&sib_pos) == queue.back().map(|(pos, _, _)| pos) {
+                let sibling_item = queue.pop_back().map(|(_, item, _)| item).unwrap();
+                let parent = NodeItem::Owned(
+                    M::merge_with_pos(parent_pos, &item, &sibling_item).map_err(Into::into)?,
+                );
+                sibs_processed_from_back.push((sib_pos, sibling_item, height));
+                parent
+            } else if let Some(&(front_pos, ..)) = queue.front() {
+                if height > 0 && is_descendant_pos(sib_pos, front_pos) {
+                    queue.push_back((pos, item, height));
+                    continue;
                 } else {
                     return Err(Error::CorruptedProof);
-                };
-                (parent_pos, parent_item)
+                }
+            } else {
+                return Err(Error::CorruptedProof);
             }
         };
 
@@ -246,34 +845,38 @@ fn calculate_peak_root<
     Err(Error::CorruptedProof)
 }
 
-fn calculate_peaks_hashes<
-    'a,
-    T: 'a + PartialEq + Clone,
-    M: Merge<Item = T>,
-    I: Iterator<Item = &'a (u64, T)>,
->(
-    nodes: Vec<(u64, T)>,
-    mmr_size: u64,
-    proof_iter: I,
-) -> Result<Vec<T>> {
+fn calculate_peaks_hashes<'a, T: PartialEq, M: Merge<Item = T>>(
+    nodes: Vec<(Pos, T)>,
+    mmr_size: Pos,
+    proof: &'a [(Pos, T)],
+    iterations: &mut Iterations,
+) -> Result<Vec<NodeItem<'a, T>>> {
     // special handle the only 1 leaf MMR
     if mmr_size == 1 && nodes.len() == 1 && nodes[0].0 == 0 {
-        return Ok(nodes.into_iter().map(|(_pos, item)| item).collect());
+        return Ok(nodes
+            .into_iter()
+            .map(|(_pos, item)| NodeItem::Owned(item))
+            .collect());
     }
 
     // ensure nodes are sorted and unique
     let mut nodes: Vec<_> = nodes
         .into_iter()
-        .chain(proof_iter.cloned())
-        .sorted_by_key(|(pos, _)| *pos)
-        .dedup_by(|a, b| a.0 == b.0)
+        .map(|(pos, item)| (pos, NodeItem::Owned(item)))
+        .chain(
+            proof
+                .iter()
+                .map(|(pos, item)| (*pos, NodeItem::Borrowed(item))),
+        )
         .collect();
+    // a position given twice must carry the same item, whether in the nodes or the proof
+    sort_and_dedup_by_pos(&mut nodes)?;
 
     let peaks = get_peaks(mmr_size);
 
-    let mut peaks_hashes: Vec<T> = Vec::with_capacity(peaks.len() + 1);
+    let mut peaks_hashes = Vec::with_capacity(peaks.len() + 1);
     for peak_pos in peaks {
-        let mut nodes: Vec<(u64, T)> = take_while_vec(&mut nodes, |(pos, _)| *pos <= peak_pos);
+        let mut nodes: Vec<_> = take_while_vec(&mut nodes, |(pos, _)| *pos <= peak_pos);
         let peak_root = if nodes.len() == 1 && nodes[0].0 == peak_pos {
             // leaf is the peak
             nodes.remove(0).1
@@ -283,26 +886,16 @@ fn calculate_peaks_hashes<
             // so we break loop and check no items left
             break;
         } else {
-            calculate_peak_root::<_, M>(nodes, peak_pos)?
+            calculate_peak_root::<_, M>(nodes, peak_pos, iterations)?
         };
-        peaks_hashes.push(peak_root.clone());
+        peaks_hashes.push(peak_root);
     }
 
     // ensure nothing left in leaves
-    if nodes.len() != 0 {
+    if !nodes.is_empty() {
         return Err(Error::CorruptedProof);
     }
 
-    // Old `mmr.rs` code. It's not needed anymore since now we merge the `proof_iter`
-    // items with the nodes.
-    // check rhs peaks
-    // if let Some((_, rhs_peaks_hashes)) = proof_iter.next() {
-    //     peaks_hashes.push(rhs_peaks_hashes.clone());
-    // }
-    // ensure nothing left in proof_iter
-    // if proof_iter.next().is_some() {
-    //     return Err(Error::CorruptedProof);
-    // }
     Ok(peaks_hashes)
 }
 
@@ -310,27 +903,50 @@ fn calculate_peaks_hashes<
 /// 1. sort items by position
 /// 2. calculate root of each peak
 /// 3. bagging peaks
-fn calculate_root<
-    'a,
-    T: 'a + PartialEq + Clone,
-    M: Merge<Item = T>,
-    I: Iterator<Item = &'a (u64, T)>,
->(
-    nodes: Vec<(u64, T)>,
-    mmr_size: u64,
-    proof_iter: I,
-) -> Result<T> {
-    let peaks_hashes = calculate_peaks_hashes::<_, M, _>(nodes, mmr_size, proof_iter)?;
-    bagging_peaks_hashes::<_, M>(peaks_hashes)
-}
-
-pub fn expected_ancestry_proof_size(prev_mmr_size: u64, mmr_size: u64) -> usize {
+fn calculate_root<'a, T: PartialEq, M: Merge<Item = T>>(
+    nodes: Vec<(Pos, T)>,
+    mmr_size: Pos,
+    proof: &'a [(Pos, T)],
+    iterations: &mut Iterations,
+) -> Result<NodeItem<'a, T>> {
+    let mut peaks_hashes = calculate_peaks_hashes::<_, M>(nodes, mmr_size, proof, iterations)?;
+    // bagging from right to left via hash(right, left).
+    while peaks_hashes.len() > 1 {
+        let right_peak = peaks_hashes.pop().expect("pop");
+        let left_peak = peaks_hashes.pop().expect("pop");
+        peaks_hashes.push(NodeItem::Owned(
+            M::merge_peaks(&right_peak, &left_peak).map_err(Into::into)?,
+        ));
+    }
+    peaks_hashes.pop().ok_or(Error::CorruptedProof)
+}
+
+/// Verify that `peaks`, ordered left to right, are the peaks of an MMR of `mmr_size` with `root`,
+/// e.g. the `prev_peaks` of an `AncestryProof` against a known ancestor root.
+///
+/// Fails with `InvalidMMRSize` if `mmr_size` is not a valid non-empty size, and with
+/// `InvalidPeaks` if the number of peaks differs from the number of mountains at that size.
+pub fn verify_peaks<T: Clone + PartialEq, M: Merge<Item = T>>(
+    root: T,
+    mmr_size: Pos,
+    peaks: &[T],
+) -> Result<bool> {
+    if mmr_size == 0 || !is_valid_mmr_size(mmr_size) {
+        return Err(Error::InvalidMMRSize { mmr_size });
+    }
+    if get_peak_map(mmr_size).count_ones() as usize != peaks.len() {
+        return Err(Error::InvalidPeaks { mmr_size });
+    }
+    Ok(bagging_peaks_hashes::<T, M>(peaks.to_vec())? == root)
+}
+
+pub fn expected_ancestry_proof_size(prev_mmr_size: Pos, mmr_size: Pos) -> usize {
     let mut expected_proof_size: usize = 0;
     let mut prev_peaks = get_peaks(prev_mmr_size);
     let peaks = get_peaks(mmr_size);
 
     for peak in peaks.iter() {
-        let local_prev_peaks: Vec<u64> = take_while_vec(&mut prev_peaks, |pos| *pos <= *peak);
+        let local_prev_peaks: Vec<Pos> = take_while_vec(&mut prev_peaks, |pos| *pos <= *peak);
 
         // skip if the peak is also the prev_peak: then trivially no additional proof items
         if local_prev_peaks.as_slice() == [*peak] {
@@ -339,7 +955,7 @@ pub fn expected_ancestry_proof_size(prev_mmr_size: u64, mmr_size: u64) -> usize
 
         // calculate the number of leaves after the last element of local_prev_peaks
         let leaves = 1 << pos_height_in_tree(*peak);
-        let local_prev_peaks_leaves: u64 = local_prev_peaks
+        let local_prev_peaks_leaves: Pos = local_prev_peaks
             .iter()
             .map(|pos| 1 << pos_height_in_tree(*pos))
             .sum();
@@ -354,3 +970,58 @@ pub fn expected_ancestry_proof_size(prev_mmr_size: u64, mmr_size: u64) -> usize
 
     expected_proof_size
 }
+
+/// Number of items `MMR::gen_node_proof` puts in the proof of the nodes at `pos_list` of the
+/// MMR of `mmr_size`, without reading the store.
+///
+/// As in the proof, the path of a node ends at an ancestor also in `pos_list`, and the peaks
+/// right of the last mountain with a node count as one item if there are several. Fails like
+/// `gen_node_proof` if `pos_list` is empty or beyond the MMR.
+pub fn expected_node_proof_size(mmr_size: Pos, mut pos_list: Vec<Pos>) -> Result<usize> {
+    if pos_list.is_empty() {
+        return Err(Error::GenProofForInvalidNodes);
+    }
+    if let Some(&pos) = pos_list.iter().find(|pos| **pos >= mmr_size) {
+        return Err(Error::PositionOutOfRange { pos });
+    }
+    // ensure positions are sorted and unique
+    pos_list.sort_unstable();
+    pos_list.dedup();
+
+    let mut expected_proof_size: usize = 0;
+    let mut bagging_track = 0;
+    for peak_pos in get_peaks(mmr_size) {
+        let pos_list = take_while_vec(&mut pos_list, |pos| *pos <= peak_pos);
+        if pos_list.is_empty() {
+            // the peak itself
+            bagging_track += 1;
+            expected_proof_size += 1;
+            continue;
+        }
+        bagging_track = 0;
+
+        let mut queue: VecDeque<_> = VecDeque::new();
+        for value in pos_list.iter().map(|pos| (pos_height_in_tree(*pos), *pos)) {
+            queue.insert_sorted(value);
+        }
+        while let Some((height, pos)) = queue.pop_front() {
+            if pos == peak_pos {
+                continue;
+            }
+            let (sib_pos, parent_pos, _) = PostOrderScheme::sibling_and_parent_pos(pos, height);
+            if Some(&sib_pos) == queue.front().map(|(_, pos)| pos) {
+                queue.pop_front();
+            } else {
+                expected_proof_size += 1;
+            }
+            if parent_pos < peak_pos {
+                queue.insert_sorted((height + 1, parent_pos));
+            }
+        }
+    }
+    // the rhs peaks are bagged into one item
+    if bagging_track > 1 {
+        expected_proof_size -= bagging_track - 1;
+    }
+    Ok(expected_proof_size)
+}
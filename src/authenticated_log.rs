@@ -0,0 +1,152 @@
+//! Append-only authenticated log
+//!
+//! An `AuthenticatedLog` is a facade over an MMR for transparency logs, numbering the entries by
+//! their sequence number, i.e. their leaf index, instead of by MMR position. Entries are proven
+//! by sequence number with a `LogProof`, and a later state of the log is proven consistent with
+//! an earlier one by the `AncestryProof` of `consistency`, verified with
+//! `AncestryProof::verify_ancestor_for_leaf_count` against the length of the earlier log.
+
+use crate::helper::{get_peak_map, leaf_index_to_pos};
+use crate::mmr_store::{MMRStoreReadOps, MMRStoreWriteOps};
+use crate::vec::Vec;
+use crate::{AncestryProof, Error, Merge, MerkleProof, Pos, Result, MMR};
+use core::ops::{Deref, Range};
+
+/// Sequence number of a log entry, counting from 0.
+pub type SeqNo = Pos;
+
+pub struct AuthenticatedLog<T, M, S> {
+    mmr: MMR<T, M, S>,
+}
+
+/// Proof of consecutive entries of an `AuthenticatedLog`.
+#[derive(Debug)]
+pub struct LogProof<T, M> {
+    entries: Range<SeqNo>,
+    proof: MerkleProof<T, M>,
+}
+
+impl<T, M, S> AuthenticatedLog<T, M, S> {
+    /// Log the leaves of `mmr` as entries, the log holds every leaf pushed to the MMR before.
+    pub fn new(mmr: MMR<T, M, S>) -> Self {
+        AuthenticatedLog { mmr }
+    }
+
+    /// Number of entries, the sequence number of the next one.
+    pub fn len(&self) -> SeqNo {
+        self.mmr.leaves_count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mmr.is_empty()
+    }
+
+    pub fn into_inner(self) -> MMR<T, M, S> {
+        self.mmr
+    }
+
+    fn check_seq_no(&self, seq_no: SeqNo) -> Result<()> {
+        if seq_no >= self.len() {
+            return Err(Error::SeqNoOutOfRange { seq_no });
+        }
+        Ok(())
+    }
+}
+
+impl<T, M, S> Deref for AuthenticatedLog<T, M, S> {
+    type Target = MMR<T, M, S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.mmr
+    }
+}
+
+impl<T: Clone, M: Merge<Item = T>, S: MMRStoreReadOps<T>> AuthenticatedLog<T, M, S> {
+    /// Append `entry`, returning its sequence number.
+    pub fn append(&mut self, entry: T) -> Result<SeqNo> {
+        let seq_no = self.len();
+        self.mmr.push(entry)?;
+        Ok(seq_no)
+    }
+
+    /// The root of the log, committing to all entries.
+    pub fn root(&self) -> Result<T> {
+        self.mmr.get_root()
+    }
+
+    /// Prove the entry at `seq_no`.
+    pub fn prove(&self, seq_no: SeqNo) -> Result<LogProof<T, M>> {
+        self.check_seq_no(seq_no)?;
+        self.prove_range(seq_no..seq_no + 1)
+    }
+
+    /// Prove the entries at `seq`, failing with `GenProofForInvalidLeaves` if it is empty.
+    pub fn prove_range(&self, seq: Range<SeqNo>) -> Result<LogProof<T, M>> {
+        if seq.is_empty() {
+            return Err(Error::GenProofForInvalidLeaves);
+        }
+        self.check_seq_no(seq.end - 1)?;
+        let proof = self
+            .mmr
+            .gen_proof(seq.clone().map(leaf_index_to_pos).collect())?;
+        Ok(LogProof {
+            entries: seq,
+            proof,
+        })
+    }
+
+    /// Prove that the log extends the log of its first `since_seq` entries.
+    pub fn consistency(&self, since_seq: SeqNo) -> Result<AncestryProof<T, M>> {
+        if since_seq > self.len() {
+            return Err(Error::SeqNoOutOfRange { seq_no: since_seq });
+        }
+        self.mmr.gen_ancestry_proof_for_leaf_count(since_seq)
+    }
+}
+
+impl<T: Clone, M: Merge<Item = T>, S: MMRStoreReadOps<T> + MMRStoreWriteOps<T>>
+    AuthenticatedLog<T, M, S>
+{
+    /// Persist the appended entries, see `MMR::commit`.
+    pub fn commit(&mut self) -> Result<()> {
+        self.mmr.commit()
+    }
+}
+
+impl<T, M> LogProof<T, M> {
+    /// A proof of the entries at `entries`, e.g. decoded on the verifier side.
+    pub fn new(entries: Range<SeqNo>, proof: MerkleProof<T, M>) -> Self {
+        LogProof { entries, proof }
+    }
+
+    /// Sequence numbers of the proven entries.
+    pub fn entries(&self) -> Range<SeqNo> {
+        self.entries.clone()
+    }
+
+    /// Number of entries of the log the proof was generated from.
+    pub fn log_len(&self) -> SeqNo {
+        get_peak_map(self.proof.mmr_size())
+    }
+
+    pub fn proof(&self) -> &MerkleProof<T, M> {
+        &self.proof
+    }
+}
+
+impl<T: Clone + PartialEq, M: Merge<Item = T>> LogProof<T, M> {
+    /// Verify the proven `entries` in sequence order against the `root` of the log, failing
+    /// with `CorruptedProof` unless there is one for each sequence number.
+    pub fn verify(&self, root: T, entries: Vec<T>) -> Result<bool> {
+        if entries.len() as Pos != self.entries.end - self.entries.start {
+            return Err(Error::CorruptedProof);
+        }
+        let leaves = self
+            .entries
+            .clone()
+            .map(leaf_index_to_pos)
+            .zip(entries)
+            .collect();
+        self.proof.verify(root, leaves)
+    }
+}
@@ -0,0 +1,129 @@
+//! Intermediate hashes of the peak bagging
+//!
+//! The root bags the peaks from right to left, so bagging the peaks from the `i`th on gives a
+//! commitment to the suffix of the MMR starting at that peak, e.g. the `mmr_root` of the right
+//! bagged peaks some bridges sign. A `BaggingTrace` holds these suffix hashes for every peak,
+//! so a verifier trusting the commitment to one suffix checks leaves of its first mountain with
+//! the proof items of that mountain and the next suffix hash, without the peaks on the left.
+
+use crate::helper::get_peaks;
+use crate::mmr_store::MMRStoreReadOps;
+use crate::vec::Vec;
+use crate::verifier::{calculate_peak_root_from_iter, sort_and_dedup_by_pos, Iterations};
+use crate::{metrics, Error, Merge, MerkleProof, Pos, Result, MMR};
+use core::marker::PhantomData;
+
+#[derive(Debug)]
+pub struct BaggingTrace<T, M> {
+    mmr_size: Pos,
+    /// The bagging of the peaks from each peak on, the first being the root.
+    suffixes: Vec<T>,
+    merge: PhantomData<M>,
+}
+
+impl<T, M> BaggingTrace<T, M> {
+    pub fn new(mmr_size: Pos, suffixes: Vec<T>) -> Self {
+        BaggingTrace {
+            mmr_size,
+            suffixes,
+            merge: PhantomData,
+        }
+    }
+
+    pub fn mmr_size(&self) -> Pos {
+        self.mmr_size
+    }
+
+    /// The bagging of the peaks from the `peak_index`th on.
+    pub fn suffix(&self, peak_index: usize) -> Option<&T> {
+        self.suffixes.get(peak_index)
+    }
+
+    pub fn suffixes(&self) -> &[T] {
+        &self.suffixes
+    }
+
+    /// The root of the MMR, i.e. the bagging of all peaks.
+    pub fn root(&self) -> Option<&T> {
+        self.suffixes.first()
+    }
+}
+
+impl<T: Clone + PartialEq, M: Merge<Item = T>> BaggingTrace<T, M> {
+    /// Verify `leaves` of the mountain at `peak_index` against `commitment`, the bagging of the
+    /// peaks from that one on.
+    ///
+    /// `proof` proves leaves of that mountain only, as generated for `leaves`. Only its items for
+    /// the mountain are read, the bagging of the peaks on the right is taken from the trace.
+    /// Leaves of other mountains fail with `CorruptedProof`.
+    pub fn verify_suffix(
+        &self,
+        commitment: &T,
+        peak_index: usize,
+        proof: &MerkleProof<T, M>,
+        mut leaves: Vec<(Pos, T)>,
+    ) -> Result<bool> {
+        if proof.mmr_size() != self.mmr_size || leaves.is_empty() {
+            return Err(Error::CorruptedProof);
+        }
+        let peaks = get_peaks(self.mmr_size);
+        let peak_pos = *peaks.get(peak_index).ok_or(Error::CorruptedProof)?;
+        let first_pos = peak_index
+            .checked_sub(1)
+            .map_or(0, |prev_peak| peaks[prev_peak] + 1);
+        sort_and_dedup_by_pos(&mut leaves)?;
+        if leaves
+            .iter()
+            .any(|(pos, _)| *pos < first_pos || *pos > peak_pos)
+        {
+            return Err(Error::CorruptedProof);
+        }
+        let peak_root = if leaves.len() == 1 && leaves[0].0 == peak_pos {
+            leaves.remove(0).1
+        } else {
+            // the peaks on the left come first in the proof, one item each
+            let mut proof_iter = proof.proof_items().iter().skip(peak_index);
+            calculate_peak_root_from_iter::<_, M, _>(
+                leaves,
+                peak_pos,
+                &mut proof_iter,
+                &mut Iterations::unbounded(),
+            )?
+        };
+        let suffix = match self.suffixes.get(peak_index + 1) {
+            Some(rhs_suffix) => M::merge_peaks(rhs_suffix, &peak_root).map_err(Into::into)?,
+            None => peak_root,
+        };
+        Ok(&suffix == commitment)
+    }
+}
+
+impl<T: Clone, M: Merge<Item = T>, S: MMRStoreReadOps<T>> MMR<T, M, S> {
+    /// The bagging trace of the peaks, see `bagging_trace`.
+    pub fn gen_bagging_trace(&self) -> Result<BaggingTrace<T, M>> {
+        let peaks = self.peaks()?;
+        if peaks.is_empty() {
+            return Err(Error::GetRootOnEmpty);
+        }
+        metrics::on_merges(peaks.len() as u64 - 1);
+        let mut suffixes = Vec::with_capacity(peaks.len());
+        for (_pos, peak) in peaks.into_iter().rev() {
+            let suffix = match suffixes.last() {
+                Some(rhs_suffix) => M::merge_peaks(rhs_suffix, &peak).map_err(Into::into)?,
+                None => peak,
+            };
+            suffixes.push(suffix);
+        }
+        suffixes.reverse();
+        Ok(BaggingTrace::new(self.mmr_size(), suffixes))
+    }
+
+    /// Same as `gen_proof`, also returning the bagging trace of the peaks.
+    pub fn gen_proof_with_bagging_trace(
+        &self,
+        pos_list: Vec<Pos>,
+    ) -> Result<(MerkleProof<T, M>, BaggingTrace<T, M>)> {
+        let proof = self.gen_proof(pos_list)?;
+        Ok((proof, self.gen_bagging_trace()?))
+    }
+}
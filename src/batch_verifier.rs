@@ -0,0 +1,107 @@
+//! Batch verification
+//!
+//! Proofs against the same root share most of their upper nodes. `BatchVerifier` remembers every
+//! node it merged along with its children, so a node whose children were already merged into it
+//! by an earlier proof isn't hashed again.
+
+use crate::collections::BTreeMap;
+use crate::vec::Vec;
+use crate::verifier::{bagging_peaks_hashes, calculate_peaks_hashes_with, Iterations};
+use crate::{Error, Merge, MerkleProof, Pos, Result};
+
+type ProvenLeaves<T, M> = (MerkleProof<T, M>, Vec<(Pos, T)>);
+
+/// Verifies many proofs against one root, hashing each shared node only once.
+pub struct BatchVerifier<T, M> {
+    root: T,
+    proofs: Vec<ProvenLeaves<T, M>>,
+}
+
+impl<T, M> BatchVerifier<T, M> {
+    pub fn new(root: T) -> Self {
+        BatchVerifier {
+            root,
+            proofs: Vec::new(),
+        }
+    }
+
+    /// Add a `proof` of `leaves` to the batch.
+    pub fn add(&mut self, proof: MerkleProof<T, M>, leaves: Vec<(Pos, T)>) -> &mut Self {
+        self.proofs.push((proof, leaves));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.proofs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.proofs.is_empty()
+    }
+}
+
+impl<T: Clone + PartialEq, M: Merge<Item = T>> BatchVerifier<T, M> {
+    /// Verify all proofs, `false` if any of them doesn't lead to the root.
+    ///
+    /// The proofs must be for the same MMR size, otherwise they can't share a root.
+    pub fn verify(&self) -> Result<bool> {
+        let mmr_size = match self.proofs.first() {
+            Some((proof, _)) => proof.mmr_size(),
+            None => return Ok(true),
+        };
+        // parent position -> (left child, right child, parent)
+        let mut nodes: BTreeMap<Pos, (T, T, T)> = BTreeMap::new();
+        let mut merge_node = |pos: Pos, lhs: &T, rhs: &T| -> Result<T> {
+            if let Some((left, right, parent)) = nodes.get(&pos) {
+                if left == lhs && right == rhs {
+                    return Ok(parent.clone());
+                }
+            }
+            let parent = M::merge_with_pos(pos, lhs, rhs).map_err(Into::into)?;
+            nodes
+                .entry(pos)
+                .or_insert_with(|| (lhs.clone(), rhs.clone(), parent.clone()));
+            Ok(parent)
+        };
+        // the peaks bagged last, proofs with the same peaks bag to the same root
+        let mut bagged_peaks: Option<Vec<T>> = None;
+        for (proof, leaves) in &self.proofs {
+            if proof.mmr_size() != mmr_size {
+                return Err(Error::CorruptedProof);
+            }
+            let peaks_hashes = calculate_peaks_hashes_with(
+                leaves.clone(),
+                mmr_size,
+                proof.proof_items().iter(),
+                &mut Iterations::unbounded(),
+                &mut merge_node,
+            )?;
+            if bagged_peaks.as_ref() == Some(&peaks_hashes) {
+                continue;
+            }
+            if bagging_peaks_hashes::<_, M>(peaks_hashes.clone())? != self.root {
+                return Ok(false);
+            }
+            bagged_peaks = Some(peaks_hashes);
+        }
+        Ok(true)
+    }
+}
+
+impl<T: Clone + PartialEq, M: Merge<Item = T>> MerkleProof<T, M> {
+    /// Verify several leaf `groups` against `root` with this proof of their union, e.g. the
+    /// messages of several bridge transfers proven at once.
+    ///
+    /// Groups may overlap, but `false` is returned if they disagree on the leaf at a position.
+    pub fn verify_batch(&self, root: T, groups: Vec<Vec<(Pos, T)>>) -> Result<bool> {
+        let mut leaves: Vec<(Pos, T)> = groups.into_iter().flatten().collect();
+        leaves.sort_by_key(|(pos, _)| *pos);
+        if leaves
+            .windows(2)
+            .any(|pair| pair[0].0 == pair[1].0 && pair[0].1 != pair[1].1)
+        {
+            return Ok(false);
+        }
+        self.verify(root, leaves)
+    }
+}
@@ -0,0 +1,89 @@
+//! Position free proofs for commutative merges
+//!
+//! When the merge doesn't care about the order of its inputs, a single leaf proof needs neither
+//! the MMR size nor the leaf position: the root is the leaf folded with every proof item in turn,
+//! the siblings up to the peak first, then the bagged right hand peaks, then the left hand peaks
+//! from right to left.
+
+use crate::helper::{iter_peaks, pos_height_in_tree};
+use crate::mmr_store::MMRStoreReadOps;
+use crate::vec;
+use crate::vec::Vec;
+use crate::{metrics, CommutativeMerge, Error, MerkleProof, Pos, Result, MMR};
+use core::marker::PhantomData;
+
+#[derive(Debug)]
+pub struct CommutativeMerkleProof<T, M> {
+    proof: Vec<T>,
+    merge: PhantomData<M>,
+}
+
+impl<T, M> CommutativeMerkleProof<T, M> {
+    pub fn new(proof: Vec<T>) -> Self {
+        CommutativeMerkleProof {
+            proof,
+            merge: PhantomData,
+        }
+    }
+
+    /// The items in folding order.
+    pub fn proof_items(&self) -> &[T] {
+        &self.proof
+    }
+}
+
+impl<T: PartialEq, M: CommutativeMerge<Item = T>> CommutativeMerkleProof<T, M> {
+    pub fn calculate_root(&self, leaf: T) -> Result<T> {
+        metrics::on_verification_steps(self.proof.len() as u64);
+        self.proof.iter().try_fold(leaf, |item, sibling| {
+            M::merge(&item, sibling).map_err(Into::into)
+        })
+    }
+
+    pub fn verify(&self, root: T, leaf: T) -> Result<bool> {
+        self.calculate_root(leaf)
+            .map(|calculated_root| calculated_root == root)
+    }
+}
+
+impl<T, M: CommutativeMerge<Item = T>> MerkleProof<T, M> {
+    /// Convert the proof of the single leaf at `leaf_pos` into a `CommutativeMerkleProof`,
+    /// dropping the MMR size and reordering the items for folding.
+    pub fn into_commutative(self, leaf_pos: Pos) -> Result<CommutativeMerkleProof<T, M>> {
+        let mmr_size = self.mmr_size();
+        if leaf_pos >= mmr_size || pos_height_in_tree(leaf_pos) > 0 {
+            return Err(Error::GenProofForInvalidLeaves);
+        }
+        let mut lhs_peaks_count = 0;
+        let mut peak_pos = None;
+        let mut has_rhs_peaks = false;
+        for pos in iter_peaks(mmr_size) {
+            if peak_pos.is_some() {
+                has_rhs_peaks = true;
+                break;
+            } else if pos < leaf_pos {
+                lhs_peaks_count += 1;
+            } else {
+                peak_pos = Some(pos);
+            }
+        }
+        let peak_pos = peak_pos.ok_or(Error::CorruptedProof)?;
+        let peak_height = pos_height_in_tree(peak_pos) as usize;
+        let mut items = self.into_proof_items();
+        if items.len() != lhs_peaks_count + peak_height + has_rhs_peaks as usize {
+            return Err(Error::CorruptedProof);
+        }
+        // the items are laid out as the lhs peaks, the siblings, then the bagged rhs peaks
+        let mut lhs_peaks: Vec<T> = items.drain(..lhs_peaks_count).collect();
+        lhs_peaks.reverse();
+        items.extend(lhs_peaks);
+        Ok(CommutativeMerkleProof::new(items))
+    }
+}
+
+impl<T: Clone + PartialEq, M: CommutativeMerge<Item = T>, S: MMRStoreReadOps<T>> MMR<T, M, S> {
+    /// Generate a `CommutativeMerkleProof` for the leaf at `pos`.
+    pub fn gen_commutative_proof(&self, pos: Pos) -> Result<CommutativeMerkleProof<T, M>> {
+        self.gen_proof(vec![pos])?.into_commutative(pos)
+    }
+}
@@ -0,0 +1,225 @@
+//! Compact multiproof
+//!
+//! Instead of relying on the verifier to rediscover the proof shape from the leaf positions, the
+//! proof carries a descriptor bitmap describing a pre-order walk of every mountain:
+//!
+//! - `1`: the subtree contains proven leaves, descend into it (or take the next leaf at height 0)
+//! - `0`: the subtree contains no proven leaves, take the next hash from the proof
+//!
+//! A peak without leaves is followed by one more bit telling whether its hash is the bagged hash
+//! of all remaining peaks. Each hash that isn't derivable from the leaves appears exactly once.
+//!
+//! As the walk pins down where every leaf sits, `verify_leaves` takes the leaves in ascending
+//! position order without their positions. For bulk audits the descriptor, a couple of bits per
+//! node along the shared paths, replaces a full position per leaf.
+
+use crate::helper::{get_peaks, pos_height_in_tree};
+use crate::vec::Vec;
+use crate::verifier::{bagging_peaks_hashes, sort_and_dedup_by_pos, ProofItem};
+use crate::{metrics, Error, Merge, Pos, Result};
+use core::marker::PhantomData;
+
+#[derive(Debug)]
+pub struct CompactMerkleProof<T, M> {
+    mmr_size: Pos,
+    descriptor: Vec<u8>,
+    proof: Vec<T>,
+    merge: PhantomData<M>,
+}
+
+impl<T, M> CompactMerkleProof<T, M> {
+    pub fn new(mmr_size: Pos, descriptor: Vec<u8>, proof: Vec<T>) -> Self {
+        CompactMerkleProof {
+            mmr_size,
+            descriptor,
+            proof,
+            merge: PhantomData,
+        }
+    }
+
+    pub fn mmr_size(&self) -> Pos {
+        self.mmr_size
+    }
+
+    /// The walk descriptor, packed LSB first and zero padded to a whole byte.
+    pub fn descriptor(&self) -> &[u8] {
+        &self.descriptor
+    }
+
+    pub fn proof_items(&self) -> &[T] {
+        &self.proof
+    }
+}
+
+impl<T: Clone + PartialEq, M: Merge<Item = T>> CompactMerkleProof<T, M> {
+    pub fn calculate_root(&self, mut leaves: Vec<(Pos, T)>) -> Result<T> {
+        // ensure leaves are sorted and unique
+        sort_and_dedup_by_pos(&mut leaves)?;
+
+        let mut leaves = leaves.into_iter();
+        let root = self.calculate_root_with(|pos| match leaves.next() {
+            Some((leaf_pos, leaf)) if leaf_pos == pos => Ok(leaf),
+            _ => Err(Error::CorruptedProof),
+        })?;
+        if leaves.next().is_some() {
+            return Err(Error::CorruptedProof);
+        }
+        Ok(root)
+    }
+
+    /// Calculate the root from the leaves alone, in ascending position order, their positions
+    /// being described by the proof.
+    pub fn calculate_root_from_leaves(&self, leaves: Vec<T>) -> Result<T> {
+        let mut leaves = leaves.into_iter();
+        let root = self.calculate_root_with(|_pos| leaves.next().ok_or(Error::CorruptedProof))?;
+        if leaves.next().is_some() {
+            return Err(Error::CorruptedProof);
+        }
+        Ok(root)
+    }
+
+    fn calculate_root_with(&self, mut take_leaf: impl FnMut(Pos) -> Result<T>) -> Result<T> {
+        let mut descriptor = DescriptorReader::new(&self.descriptor);
+        let mut proof_iter = self.proof.iter();
+        let peaks = get_peaks(self.mmr_size);
+        let mut peaks_hashes = Vec::with_capacity(peaks.len());
+        for (i, &peak_pos) in peaks.iter().enumerate() {
+            if descriptor.next_bit()? {
+                peaks_hashes.push(calculate_subtree_root::<_, M, _>(
+                    &mut descriptor,
+                    &mut take_leaf,
+                    &mut proof_iter,
+                    peak_pos,
+                    pos_height_in_tree(peak_pos),
+                )?);
+            } else {
+                let peak = proof_iter.next().ok_or(Error::CorruptedProof)?;
+                peaks_hashes.push(peak.into_item());
+                // the hash may be the bagged hash of all the remaining peaks
+                if i + 1 < peaks.len() && descriptor.next_bit()? {
+                    break;
+                }
+            }
+        }
+
+        // ensure everything was consumed
+        if proof_iter.next().is_some() {
+            return Err(Error::CorruptedProof);
+        }
+        descriptor.finish()?;
+        bagging_peaks_hashes::<_, M>(peaks_hashes)
+    }
+
+    pub fn verify(&self, root: T, leaves: Vec<(Pos, T)>) -> Result<bool> {
+        self.calculate_root(leaves)
+            .map(|calculated_root| calculated_root == root)
+    }
+
+    /// Verify leaves given in ascending position order, without their positions.
+    pub fn verify_leaves(&self, root: T, leaves: Vec<T>) -> Result<bool> {
+        self.calculate_root_from_leaves(leaves)
+            .map(|calculated_root| calculated_root == root)
+    }
+}
+
+/// Calculate the root of the subtree at `pos`, after its descriptor bit was read as `1`.
+fn calculate_subtree_root<T, M: Merge<Item = T>, I: Iterator>(
+    descriptor: &mut DescriptorReader,
+    take_leaf: &mut impl FnMut(Pos) -> Result<T>,
+    proof_iter: &mut I,
+    pos: Pos,
+    height: u8,
+) -> Result<T>
+where
+    I::Item: ProofItem<T>,
+{
+    metrics::on_verification_steps(1);
+    if height == 0 {
+        return take_leaf(pos);
+    }
+    let left = calculate_node_root::<_, M, _>(
+        descriptor,
+        take_leaf,
+        proof_iter,
+        pos - (1 << height),
+        height - 1,
+    )?;
+    let right =
+        calculate_node_root::<_, M, _>(descriptor, take_leaf, proof_iter, pos - 1, height - 1)?;
+    M::merge_with_pos(pos, &left, &right).map_err(Into::into)
+}
+
+fn calculate_node_root<T, M: Merge<Item = T>, I: Iterator>(
+    descriptor: &mut DescriptorReader,
+    take_leaf: &mut impl FnMut(Pos) -> Result<T>,
+    proof_iter: &mut I,
+    pos: Pos,
+    height: u8,
+) -> Result<T>
+where
+    I::Item: ProofItem<T>,
+{
+    if descriptor.next_bit()? {
+        calculate_subtree_root::<_, M, _>(descriptor, take_leaf, proof_iter, pos, height)
+    } else {
+        Ok(proof_iter.next().ok_or(Error::CorruptedProof)?.into_item())
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct DescriptorWriter {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl DescriptorWriter {
+    pub(crate) fn push(&mut self, bit: bool) {
+        let bit_offset = self.len % 8;
+        if bit_offset == 0 {
+            self.bytes.push(0);
+        }
+        if bit {
+            *self.bytes.last_mut().expect("pushed") |= 1 << bit_offset;
+        }
+        self.len += 1;
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+pub(crate) struct DescriptorReader<'a> {
+    bytes: &'a [u8],
+    index: usize,
+}
+
+impl<'a> DescriptorReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        DescriptorReader { bytes, index: 0 }
+    }
+
+    pub(crate) fn next_bit(&mut self) -> Result<bool> {
+        let byte = self
+            .bytes
+            .get(self.index / 8)
+            .ok_or(Error::CorruptedProof)?;
+        let bit = byte & (1 << (self.index % 8)) != 0;
+        self.index += 1;
+        Ok(bit)
+    }
+
+    /// Ensure only zero padding is left.
+    pub(crate) fn finish(self) -> Result<()> {
+        let (full_bytes, bit_offset) = (self.index / 8, self.index % 8);
+        let padding = match (self.bytes.len() - full_bytes, bit_offset) {
+            (0, 0) => 0,
+            (1, bit_offset) if bit_offset > 0 => self.bytes[full_bytes] >> bit_offset,
+            _ => return Err(Error::CorruptedProof),
+        };
+        if padding != 0 {
+            return Err(Error::CorruptedProof);
+        }
+        Ok(())
+    }
+}
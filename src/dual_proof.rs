@@ -0,0 +1,80 @@
+//! Proofs of auxiliary leaf digests
+//!
+//! Verifiers on other platforms often want a leaf digest of their own, e.g. an EVM contract a
+//! keccak hash of the payload, while the MMR hashes natively with blake2. With a `DualMerge`,
+//! `MMR::push_dual` pushes a leaf node committing to both the hash and the aux digest. A
+//! `DualMerkleProof` carries the hashes of the proven leaves, so it proves their aux digests
+//! against the root without the verifier hashing the payloads natively.
+
+use crate::mmr_store::MMRStoreReadOps;
+use crate::vec::Vec;
+use crate::{DualMerge, Error, MerkleProof, Pos, Result, MMR};
+
+#[derive(Debug)]
+pub struct DualMerkleProof<T, M> {
+    /// Sorted by position.
+    leaf_hashes: Vec<(Pos, T)>,
+    proof: MerkleProof<T, M>,
+}
+
+impl<T, M> DualMerkleProof<T, M> {
+    pub fn new(mut leaf_hashes: Vec<(Pos, T)>, proof: MerkleProof<T, M>) -> Self {
+        leaf_hashes.sort_by_key(|(pos, _)| *pos);
+        DualMerkleProof { leaf_hashes, proof }
+    }
+
+    /// The hashes of the proven leaves along with their positions.
+    pub fn leaf_hashes(&self) -> &[(Pos, T)] {
+        &self.leaf_hashes
+    }
+
+    /// The proof of the leaf nodes.
+    pub fn proof(&self) -> &MerkleProof<T, M> {
+        &self.proof
+    }
+}
+
+impl<T: Clone + PartialEq, M: DualMerge<Item = T>> DualMerkleProof<T, M> {
+    /// Calculate the root from the `aux` digests of the proven leaves, failing with
+    /// `CorruptedProof` unless there is one digest for each of them.
+    pub fn calculate_root(&self, mut aux: Vec<(Pos, M::Aux)>) -> Result<T> {
+        aux.sort_by_key(|(pos, _)| *pos);
+        if aux.len() != self.leaf_hashes.len() {
+            return Err(Error::CorruptedProof);
+        }
+        let leaves = self
+            .leaf_hashes
+            .iter()
+            .zip(&aux)
+            .map(|((pos, hash), (aux_pos, aux))| {
+                if pos != aux_pos {
+                    return Err(Error::CorruptedProof);
+                }
+                Ok((*pos, M::merge_leaf(hash, aux).map_err(Into::into)?))
+            })
+            .collect::<Result<_>>()?;
+        self.proof.calculate_root(leaves)
+    }
+
+    pub fn verify(&self, root: T, aux: Vec<(Pos, M::Aux)>) -> Result<bool> {
+        self.calculate_root(aux)
+            .map(|calculated_root| calculated_root == root)
+    }
+}
+
+impl<T: Clone, M: DualMerge<Item = T>, S: MMRStoreReadOps<T>> MMR<T, M, S> {
+    /// Push the leaf node committing to the leaf `hash` and its `aux` digest.
+    pub fn push_dual(&mut self, hash: T, aux: &M::Aux) -> Result<Pos> {
+        let elem = M::merge_leaf(&hash, aux).map_err(Into::into)?;
+        self.push(elem)
+    }
+
+    /// Generate a proof of the aux digests of the leaves pushed with `push_dual`, given their
+    /// positions and hashes.
+    pub fn gen_dual_proof(&self, mut leaf_hashes: Vec<(Pos, T)>) -> Result<DualMerkleProof<T, M>> {
+        leaf_hashes.sort_by_key(|(pos, _)| *pos);
+        leaf_hashes.dedup_by(|a, b| a.0 == b.0);
+        let proof = self.gen_proof(leaf_hashes.iter().map(|(pos, _)| *pos).collect())?;
+        Ok(DualMerkleProof::new(leaf_hashes, proof))
+    }
+}
@@ -0,0 +1,337 @@
+//! Structure dumps for debugging
+//!
+//! `MMR::dump_structure` lists the mountains of an MMR with the positions of each level, and
+//! `MMR::dump_nodes` adds the node hashes for types implementing `NodeDisplay`. The `Display`
+//! output is deterministic, so dumps can be diffed or pasted into bug reports:
+//!
+//! ``` txt
+//! mmr_size 19, leaves 11, peaks [14, 17, 18]
+//! mountain 14 (height 3)
+//!   h3: 14
+//!   h2: 6 13
+//!   h1: 2 5 9 12
+//!   h0: 0 1 3 4 7 8 10 11
+//! ...
+//! ```
+//!
+//! `MMR::to_dot` and `MerkleProof::to_dot` export the same layout as Graphviz graphs, the latter
+//! highlighting the proven leaves, the proof items and the nodes the verifier calculates:
+//!
+//! ``` sh
+//! dot -Tsvg proof.dot > proof.svg
+//! ```
+
+use crate::collections::BTreeSet;
+use crate::fixed::FixedHash;
+use crate::helper::{get_peak_map, get_peaks, parent_offset, pos_height_in_tree};
+use crate::mmr_store::MMRStoreReadOps;
+use crate::string::String;
+use crate::vec::Vec;
+use crate::{proof_positions, Error, Merge, MerkleProof, Pos, Result, MMR};
+use core::fmt::{self, Write};
+use core::ops::Range;
+
+/// Renders a node in `MmrDump`s, typically as an abbreviated hash, see `fmt_hex_abbreviated`.
+pub trait NodeDisplay {
+    fn fmt_node(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+}
+
+impl<const N: usize> NodeDisplay for FixedHash<N> {
+    fn fmt_node(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_hex_abbreviated(&self.0, f)
+    }
+}
+
+/// Write the first 4 bytes of `bytes` in hex, followed by `..` if there are more.
+pub fn fmt_hex_abbreviated(bytes: &[u8], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for byte in bytes.iter().take(4) {
+        write!(f, "{:02x}", byte)?;
+    }
+    if bytes.len() > 4 {
+        f.write_str("..")?;
+    }
+    Ok(())
+}
+
+struct DisplayNode<'a, T>(&'a T);
+
+impl<'a, T: NodeDisplay> fmt::Display for DisplayNode<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt_node(f)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpNode {
+    pub pos: Pos,
+    pub height: u8,
+    /// The rendered node, only set by `MMR::dump_nodes`.
+    pub node: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpMountain {
+    pub peak_pos: Pos,
+    pub height: u8,
+    /// The dumped nodes under the peak, in position order.
+    pub nodes: Vec<DumpNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MmrDump {
+    pub mmr_size: Pos,
+    pub leaves_count: Pos,
+    pub mountains: Vec<DumpMountain>,
+    /// Number of positions left out by the `max_positions` limit.
+    pub omitted_positions: Pos,
+}
+
+impl MmrDump {
+    fn new(mmr_size: Pos, max_positions: Pos) -> Self {
+        let dumped_size = mmr_size.min(max_positions);
+        let mut start_pos = 0;
+        let mountains = get_peaks(mmr_size)
+            .into_iter()
+            .map(|peak_pos| {
+                let nodes = (start_pos..=peak_pos)
+                    .take_while(|pos| *pos < dumped_size)
+                    .map(|pos| DumpNode {
+                        pos,
+                        height: pos_height_in_tree(pos),
+                        node: None,
+                    })
+                    .collect();
+                start_pos = peak_pos + 1;
+                DumpMountain {
+                    peak_pos,
+                    height: pos_height_in_tree(peak_pos),
+                    nodes,
+                }
+            })
+            .collect();
+        MmrDump {
+            mmr_size,
+            leaves_count: get_peak_map(mmr_size),
+            mountains,
+            omitted_positions: mmr_size - dumped_size,
+        }
+    }
+}
+
+impl fmt::Display for MmrDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let peaks: Vec<Pos> = self.mountains.iter().map(|m| m.peak_pos).collect();
+        writeln!(
+            f,
+            "mmr_size {}, leaves {}, peaks {:?}",
+            self.mmr_size, self.leaves_count, peaks
+        )?;
+        for mountain in &self.mountains {
+            if mountain.nodes.is_empty() {
+                continue;
+            }
+            writeln!(
+                f,
+                "mountain {} (height {})",
+                mountain.peak_pos, mountain.height
+            )?;
+            for height in (0..=mountain.height).rev() {
+                write!(f, "  h{}:", height)?;
+                for node in mountain.nodes.iter().filter(|node| node.height == height) {
+                    write!(f, " {}", node.pos)?;
+                    if let Some(rendered) = &node.node {
+                        write!(f, ":{}", rendered)?;
+                    }
+                }
+                writeln!(f)?;
+            }
+        }
+        if self.omitted_positions > 0 {
+            writeln!(f, "... {} more positions", self.omitted_positions)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T, M, S> MMR<T, M, S> {
+    /// Dump the mountains of the MMR with the positions and heights of the first `max_positions`
+    /// nodes, see `MmrDump`'s `Display` output.
+    pub fn dump_structure(&self, max_positions: Pos) -> MmrDump {
+        MmrDump::new(self.mmr_size(), max_positions)
+    }
+}
+
+impl<T: Clone + PartialEq + NodeDisplay, M: Merge<Item = T>, S: MMRStoreReadOps<T>> MMR<T, M, S> {
+    /// Same as `dump_structure`, also rendering the nodes, including uncommitted ones.
+    pub fn dump_nodes(&self, max_positions: Pos) -> Result<MmrDump> {
+        let mut dump = self.dump_structure(max_positions);
+        let dumped_size = self.mmr_size() - dump.omitted_positions;
+        let elems = self.batch().get_elems(0..dumped_size)?;
+        for node in dump.mountains.iter_mut().flat_map(|m| m.nodes.iter_mut()) {
+            let pos = node.pos;
+            let elem = elems[pos as usize]
+                .as_ref()
+                .ok_or(Error::InconsistentStore { pos })?;
+            let mut rendered = String::new();
+            write!(rendered, "{}", DisplayNode(elem)).expect("write to string");
+            node.node = Some(rendered);
+        }
+        Ok(dump)
+    }
+}
+
+const DOT_PEAK: &str = "peripheries=2";
+const DOT_PROVEN: &str = "style=filled, fillcolor=palegreen";
+const DOT_PROOF_ITEM: &str = "style=filled, fillcolor=orange";
+const DOT_BAGGED: &str = "style=\"filled,dashed\", fillcolor=orange";
+const DOT_CALCULATED: &str = "style=dashed";
+
+/// Write the mountains of an MMR of `mmr_size` as a Graphviz graph, restricted to the nodes at
+/// `range`. Parents point to their children and the leaves share the bottom rank.
+fn write_dot(
+    dot: &mut String,
+    mmr_size: Pos,
+    range: Range<Pos>,
+    label: fmt::Arguments<'_>,
+    style: impl Fn(Pos) -> Option<&'static str>,
+) -> fmt::Result {
+    writeln!(dot, "digraph mmr {{")?;
+    writeln!(dot, "  label=\"{}\";", label)?;
+    writeln!(dot, "  node [shape=box];")?;
+    let mut leaves = Vec::new();
+    let mut start_pos = 0;
+    for peak_pos in get_peaks(mmr_size) {
+        let positions = start_pos.max(range.start)..(peak_pos + 1).min(range.end);
+        start_pos = peak_pos + 1;
+        if positions.is_empty() {
+            continue;
+        }
+        writeln!(dot, "  subgraph cluster_{} {{", peak_pos)?;
+        writeln!(
+            dot,
+            "    label=\"mountain {} (height {})\";",
+            peak_pos,
+            pos_height_in_tree(peak_pos)
+        )?;
+        for pos in positions.clone() {
+            match style(pos) {
+                Some(style) => writeln!(dot, "    n{} [label=\"{}\", {}];", pos, pos, style)?,
+                None => writeln!(dot, "    n{} [label=\"{}\"];", pos, pos)?,
+            }
+        }
+        writeln!(dot, "  }}")?;
+        for pos in positions.clone() {
+            let height = pos_height_in_tree(pos);
+            if height == 0 {
+                leaves.push(pos);
+                continue;
+            }
+            for child in [pos - (1 << height), pos - 1] {
+                if positions.contains(&child) {
+                    writeln!(dot, "  n{} -> n{};", pos, child)?;
+                }
+            }
+        }
+    }
+    if !leaves.is_empty() {
+        write!(dot, "  {{ rank=same;")?;
+        for pos in leaves {
+            write!(dot, " n{};", pos)?;
+        }
+        writeln!(dot, " }}")?;
+    }
+    writeln!(dot, "}}")
+}
+
+impl<T, M, S> MMR<T, M, S> {
+    /// Export the mountains of the MMR with the nodes at `range` as a Graphviz graph, drawing the
+    /// peaks with a double border.
+    pub fn to_dot(&self, range: Range<Pos>) -> String {
+        let peaks = get_peaks(self.mmr_size());
+        let mut dot = String::new();
+        write_dot(
+            &mut dot,
+            self.mmr_size(),
+            range,
+            format_args!(
+                "mmr_size {}, leaves {}",
+                self.mmr_size(),
+                self.leaves_count()
+            ),
+            |pos| peaks.contains(&pos).then_some(DOT_PEAK),
+        )
+        .expect("write to string");
+        dot
+    }
+}
+
+impl<T, M> MerkleProof<T, M> {
+    /// Export the mountains of the proof as a Graphviz graph, highlighting the proven `leaves`,
+    /// the proof items and, dashed, the nodes calculated while verifying.
+    ///
+    /// Trailing peaks that `MMR::gen_proof` bags into a single item are drawn dashed, and the
+    /// graph label tells the expected number of proof items apart from the actual one, the usual
+    /// suspects when a proof fails. Invalid leaves fail as in `proof_positions`.
+    pub fn to_dot(&self, leaves: &[Pos]) -> Result<String> {
+        let mmr_size = self.mmr_size();
+        let proof_pos = proof_positions(mmr_size, leaves.to_vec())?;
+        let peaks = get_peaks(mmr_size);
+        // the peaks right of the mountain of the last leaf
+        let last_leaf = leaves.iter().copied().max().expect("checked");
+        let bagged: Vec<Pos> = peaks
+            .iter()
+            .copied()
+            .skip_while(|peak_pos| *peak_pos < last_leaf)
+            .skip(1)
+            .collect();
+        let expected_items = if bagged.len() > 1 {
+            proof_pos.len() - bagged.len() + 1
+        } else {
+            proof_pos.len()
+        };
+
+        // every ancestor of a leaf up to its peak
+        let mut calculated = BTreeSet::new();
+        for leaf in leaves {
+            let (mut pos, mut height) = (*leaf, 0);
+            while !peaks.contains(&pos) {
+                pos += if pos_height_in_tree(pos + 1) > height {
+                    1
+                } else {
+                    parent_offset(height)
+                };
+                height += 1;
+                calculated.insert(pos);
+            }
+        }
+
+        let mut dot = String::new();
+        write_dot(
+            &mut dot,
+            mmr_size,
+            0..mmr_size,
+            format_args!(
+                "mmr_size {}, leaves {:?}, {} proof items, {} expected",
+                mmr_size,
+                leaves,
+                self.proof_items().len(),
+                expected_items
+            ),
+            |pos| {
+                if leaves.contains(&pos) {
+                    Some(DOT_PROVEN)
+                } else if bagged.len() > 1 && bagged.contains(&pos) {
+                    Some(DOT_BAGGED)
+                } else if proof_pos.contains(&pos) {
+                    Some(DOT_PROOF_ITEM)
+                } else if calculated.contains(&pos) {
+                    Some(DOT_CALCULATED)
+                } else {
+                    None
+                }
+            },
+        )
+        .expect("write to string");
+        Ok(dot)
+    }
+}
@@ -1,10 +1,36 @@
+use crate::Pos;
+
 pub type Result<T> = core::result::Result<T, Error>;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[non_exhaustive]
 pub enum Error {
-    AncestorRootNotPredecessor,
+    /// The ancestor `prev_mmr_size` exceeds the current `mmr_size`
+    AncestorRootNotPredecessor {
+        prev_mmr_size: Pos,
+        mmr_size: Pos,
+    },
     GetRootOnEmpty,
-    InconsistentStore,
+    /// The store is missing the element at `pos`
+    InconsistentStore {
+        pos: Pos,
+    },
+    /// The store is missing the elements at `positions`, see `MMR::gen_proof_reporting_missing`
+    MissingNodes {
+        positions: crate::vec::Vec<Pos>,
+    },
+    /// The size doesn't correspond to any MMR
+    InvalidMMRSize {
+        mmr_size: Pos,
+    },
+    /// The leaf count doesn't map to an mmr size
+    InvalidLeafCount {
+        leaf_count: Pos,
+    },
+    /// The peaks don't match the peak positions of `mmr_size`
+    InvalidPeaks {
+        mmr_size: Pos,
+    },
     StoreError(crate::string::String),
     /// proof items is not enough to build a tree
     CorruptedProof,
@@ -12,6 +38,58 @@ pub enum Error {
     GenProofForInvalidLeaves,
     /// The nodes are an empty list, or beyond the mmr range
     GenProofForInvalidNodes,
+    /// The position `pos` to prove is beyond the mmr range
+    PositionOutOfRange {
+        pos: Pos,
+    },
+
+    /// The proof exceeds the `VerifyLimits`
+    LimitExceeded,
+    /// The operation was cancelled through its cancellation flag
+    Cancelled,
+    /// The MMR is too large to push another leaf without overflowing `Pos`, checked with the
+    /// `huge` feature
+    PositionOverflow,
+    /// The snapshot is malformed or doesn't match its root
+    CorruptedSnapshot,
+    /// The proof was encoded in an unknown format `version`
+    UnsupportedProofVersion {
+        version: u8,
+    },
+
+    /// Merging the children of `pos` again gave a different node, see `ValidatingMmr`
+    NondeterministicMerge {
+        pos: Pos,
+    },
+
+    /// The shard at `index` doesn't continue the forest, see `ForestBuilder::append_shard`
+    UnexpectedShard {
+        index: Pos,
+    },
+
+    /// The forest has no MMR `name`, see `MmrForest`
+    UnknownMmr {
+        name: crate::string::String,
+    },
+
+    /// The imported node at `pos` isn't the merge of its children, see `import`
+    UnexpectedNode {
+        pos: Pos,
+    },
+    /// The imported MMR doesn't have the expected root, see `import`
+    UnexpectedRoot,
+    /// The log has no entry `seq_no`, see `AuthenticatedLog`
+    SeqNoOutOfRange {
+        seq_no: Pos,
+    },
+    /// The store has no version `version`, or it was retired, see `VersionedStore`
+    UnknownVersion {
+        version: u64,
+    },
+    /// The packed MMR holds `count` items of an incomplete chunk, see `PackedMMR::commit`
+    PendingItems {
+        count: Pos,
+    },
 
     /// The two nodes couldn't merge into one.
     MergeError(crate::string::String),
@@ -21,13 +99,42 @@ impl core::fmt::Display for Error {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         use Error::*;
         match self {
-            AncestorRootNotPredecessor => write!(f, "Ancestor mmr size exceeds current mmr size")?,
+            AncestorRootNotPredecessor {
+                prev_mmr_size,
+                mmr_size,
+            } => write!(
+                f,
+                "Ancestor mmr size {} exceeds current mmr size {}",
+                prev_mmr_size, mmr_size
+            )?,
             GetRootOnEmpty => write!(f, "Get root on an empty MMR")?,
-            InconsistentStore => write!(f, "Inconsistent store")?,
+            InconsistentStore { pos } => write!(f, "Inconsistent store at position {}", pos)?,
+            MissingNodes { positions } => write!(f, "Missing nodes at positions {:?}", positions)?,
+            InvalidMMRSize { mmr_size } => write!(f, "Invalid mmr size {}", mmr_size)?,
+            InvalidLeafCount { leaf_count } => write!(f, "Invalid leaf count {}", leaf_count)?,
+            InvalidPeaks { mmr_size } => write!(f, "Invalid peaks for mmr size {}", mmr_size)?,
             StoreError(msg) => write!(f, "Store error {}", msg)?,
             CorruptedProof => write!(f, "Corrupted proof")?,
             GenProofForInvalidLeaves => write!(f, "Generate proof for invalid leaves")?,
             GenProofForInvalidNodes => write!(f, "Generate proof for invalid nodes")?,
+            PositionOutOfRange { pos } => write!(f, "Position {} out of range", pos)?,
+            PositionOverflow => write!(f, "Position overflow")?,
+            Cancelled => write!(f, "Cancelled")?,
+            LimitExceeded => write!(f, "Verification limit exceeded")?,
+            CorruptedSnapshot => write!(f, "Corrupted snapshot")?,
+            UnsupportedProofVersion { version } => {
+                write!(f, "Unsupported proof format version {}", version)?
+            }
+            NondeterministicMerge { pos } => {
+                write!(f, "Nondeterministic merge of the node at position {}", pos)?
+            }
+            UnexpectedShard { index } => write!(f, "Unexpected shard {}", index)?,
+            UnknownMmr { name } => write!(f, "Unknown MMR {}", name)?,
+            UnexpectedNode { pos } => write!(f, "Unexpected node at position {}", pos)?,
+            UnexpectedRoot => write!(f, "Unexpected root")?,
+            SeqNoOutOfRange { seq_no } => write!(f, "Sequence number {} out of range", seq_no)?,
+            UnknownVersion { version } => write!(f, "Unknown store version {}", version)?,
+            PendingItems { count } => write!(f, "{} items pending", count)?,
             MergeError(msg) => write!(f, "Merge error {}", msg)?,
         }
         Ok(())
@@ -0,0 +1,130 @@
+//! Fixed-size hashes and an allocation free verifier
+//!
+//! Meant for embedded or enclave verifiers where the heap is unavailable or restricted. A single
+//! leaf proof is walked directly from the proof items slice, so the work is bounded by the MMR
+//! height and the number of peaks, and nothing is buffered.
+
+use crate::helper::{iter_peaks, parent_offset, pos_height_in_tree};
+use crate::{Error, Merge, Pos, Result};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct FixedHash<const N: usize>(pub [u8; N]);
+
+impl<const N: usize> Default for FixedHash<N> {
+    fn default() -> Self {
+        FixedHash([0; N])
+    }
+}
+
+impl<const N: usize> From<[u8; N]> for FixedHash<N> {
+    fn from(bytes: [u8; N]) -> Self {
+        FixedHash(bytes)
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for FixedHash<N> {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<const N: usize> core::convert::TryFrom<&[u8]> for FixedHash<N> {
+    type Error = core::array::TryFromSliceError;
+
+    fn try_from(bytes: &[u8]) -> core::result::Result<Self, Self::Error> {
+        Ok(FixedHash(<[u8; N]>::try_from(bytes)?))
+    }
+}
+
+#[cfg(feature = "subtle")]
+impl<const N: usize> subtle::ConstantTimeEq for FixedHash<N> {
+    fn ct_eq(&self, other: &Self) -> subtle::Choice {
+        self.0[..].ct_eq(&other.0[..])
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a, const N: usize> arbitrary::Arbitrary<'a> for FixedHash<N> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(FixedHash(u.arbitrary()?))
+    }
+}
+
+/// Verify a single leaf proof, as generated by `MMR::gen_proof`, without allocating.
+///
+/// `proof_items` are expected in the order of `MerkleProof::proof_items`: the peaks left of the
+/// leaf's mountain, the siblings from the leaf up to its peak, then the bagged peaks to the right.
+pub fn verify_fixed<M, const N: usize>(
+    root: &FixedHash<N>,
+    mmr_size: Pos,
+    leaf_pos: Pos,
+    leaf: FixedHash<N>,
+    proof_items: &[FixedHash<N>],
+) -> Result<bool>
+where
+    M: Merge<Item = FixedHash<N>>,
+{
+    if leaf_pos >= mmr_size || pos_height_in_tree(leaf_pos) > 0 {
+        return Err(Error::GenProofForInvalidLeaves);
+    }
+    let mut lhs_peaks_count = 0;
+    let mut peak_pos = None;
+    let mut has_rhs_peaks = false;
+    for pos in iter_peaks(mmr_size) {
+        if peak_pos.is_some() {
+            has_rhs_peaks = true;
+            break;
+        } else if pos < leaf_pos {
+            lhs_peaks_count += 1;
+        } else {
+            peak_pos = Some(pos);
+        }
+    }
+    let peak_pos = peak_pos.ok_or(Error::CorruptedProof)?;
+    let peak_height = pos_height_in_tree(peak_pos) as usize;
+    if proof_items.len() != lhs_peaks_count + peak_height + has_rhs_peaks as usize {
+        return Err(Error::CorruptedProof);
+    }
+    let (lhs_peaks, rest) = proof_items.split_at(lhs_peaks_count);
+    let (siblings, rhs_peaks) = rest.split_at(peak_height);
+
+    // calculate the peak root from the leaf
+    let (mut pos, mut item) = (leaf_pos, leaf);
+    for (height, sibling) in siblings.iter().enumerate() {
+        let height = height as u8;
+        if pos_height_in_tree(pos + 1) > height {
+            // implies pos is right sibling
+            pos += 1;
+            item = M::merge_with_pos(pos, sibling, &item).map_err(Into::into)?;
+        } else {
+            // pos is left sibling
+            pos += parent_offset(height);
+            item = M::merge_with_pos(pos, &item, sibling).map_err(Into::into)?;
+        }
+    }
+    if pos != peak_pos {
+        return Err(Error::CorruptedProof);
+    }
+
+    // bagging from right to left via hash(right, left).
+    if let Some(rhs_peak) = rhs_peaks.first() {
+        item = M::merge_peaks(rhs_peak, &item).map_err(Into::into)?;
+    }
+    for lhs_peak in lhs_peaks.iter().rev() {
+        item = M::merge_peaks(&item, lhs_peak).map_err(Into::into)?;
+    }
+    Ok(roots_eq(&item, root))
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "subtle")] {
+        // constant time, as verifiers relying on this module are often side channel sensitive
+        fn roots_eq<const N: usize>(lhs: &FixedHash<N>, rhs: &FixedHash<N>) -> bool {
+            subtle::ConstantTimeEq::ct_eq(lhs, rhs).into()
+        }
+    } else {
+        fn roots_eq<const N: usize>(lhs: &FixedHash<N>, rhs: &FixedHash<N>) -> bool {
+            lhs == rhs
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Parallel bulk construction
+//!
+//! `MMR::push` is serial, since every push may merge with the peaks left by the previous ones.
+//! The nodes of a perfect subtree only depend on its leaves and its position though, so a
+//! `ForestBuilder` splits the leaves into shards of a fixed power of two, which threads build
+//! independently with `Shard::build`. Appending the shards in order then only merges the shard
+//! roots into the parents crossing the shard boundaries.
+//!
+//! ``` ignore
+//! let shards: Vec<Shard<_>> = leaves
+//!     .par_chunks(shard_leaves as usize)
+//!     .enumerate()
+//!     .map(|(index, chunk)| Shard::build::<M>(shard_leaves, index as Pos, chunk.to_vec()))
+//!     .collect::<Result<_>>()?;
+//! let mut builder = ForestBuilder::<_, M, _>::new(shard_leaves, store)?;
+//! for shard in shards {
+//!     builder.append_shard(shard)?;
+//! }
+//! let mmr = builder.finish()?;
+//! ```
+
+use crate::helper::{get_peak_map, leaf_index_to_pos, pos_height_in_tree};
+use crate::mmr_store::MMRStoreWriteOps;
+use crate::vec::Vec;
+use crate::verifier::merge_node;
+use crate::{Error, Merge, Pos, Result, MMR};
+use core::marker::PhantomData;
+
+/// The nodes of up to `shard_leaves` consecutive leaves starting at leaf `index * shard_leaves`.
+#[derive(Debug, Clone)]
+pub struct Shard<T> {
+    shard_leaves: Pos,
+    index: Pos,
+    leaves_count: Pos,
+    nodes: Vec<T>,
+}
+
+impl<T: Clone> Shard<T> {
+    /// Build the nodes of the shard at `index` from its `leaves`, which may only be fewer than
+    /// `shard_leaves` for the last shard.
+    ///
+    /// Fails with `InvalidLeafCount` if `shard_leaves` is not a power of two, or if there are no
+    /// leaves or more than `shard_leaves`.
+    pub fn build<M: Merge<Item = T>>(
+        shard_leaves: Pos,
+        index: Pos,
+        leaves: Vec<T>,
+    ) -> Result<Self> {
+        check_shard_leaves(shard_leaves)?;
+        let leaves_count = leaves.len() as Pos;
+        if leaves_count == 0 || leaves_count > shard_leaves {
+            return Err(Error::InvalidLeafCount {
+                leaf_count: leaves_count,
+            });
+        }
+        let mut pos = leaf_index_to_pos(index * shard_leaves);
+        let mut nodes = Vec::with_capacity(2 * leaves.len());
+        // the peaks within the shard, with their heights
+        let mut peaks: Vec<(u8, T)> = Vec::new();
+        for leaf in leaves {
+            nodes.push(leaf.clone());
+            peaks.push((0, leaf));
+            pos += 1;
+            while peaks.len() > 1 && peaks[peaks.len() - 1].0 == peaks[peaks.len() - 2].0 {
+                let (height, right) = peaks.pop().expect("pop");
+                let (_, left) = peaks.pop().expect("pop");
+                let parent = merge_node::<_, M>(pos, &left, &right)?;
+                nodes.push(parent.clone());
+                peaks.push((height + 1, parent));
+                pos += 1;
+            }
+        }
+        Ok(Shard {
+            shard_leaves,
+            index,
+            leaves_count,
+            nodes,
+        })
+    }
+
+    pub fn index(&self) -> Pos {
+        self.index
+    }
+
+    pub fn leaves_count(&self) -> Pos {
+        self.leaves_count
+    }
+
+    /// The nodes of the shard, in position order.
+    pub fn nodes(&self) -> &[T] {
+        &self.nodes
+    }
+
+    fn is_full(&self) -> bool {
+        self.leaves_count == self.shard_leaves
+    }
+}
+
+fn check_shard_leaves(shard_leaves: Pos) -> Result<()> {
+    if !shard_leaves.is_power_of_two() {
+        return Err(Error::InvalidLeafCount {
+            leaf_count: shard_leaves,
+        });
+    }
+    Ok(())
+}
+
+/// Assembles the `Shard`s of an MMR into `store`, see the module docs.
+pub struct ForestBuilder<T, M, S> {
+    shard_leaves: Pos,
+    mmr_size: Pos,
+    peaks: Vec<(Pos, T)>,
+    /// Set once a partial shard was appended, which has to be the last.
+    finished: bool,
+    store: S,
+    merge: PhantomData<M>,
+}
+
+impl<T: Clone, M: Merge<Item = T>, S: MMRStoreWriteOps<T>> ForestBuilder<T, M, S> {
+    /// Start an empty MMR in `store`, assembled from shards of `shard_leaves` leaves.
+    ///
+    /// Fails with `InvalidLeafCount` if `shard_leaves` is not a power of two.
+    pub fn new(shard_leaves: Pos, store: S) -> Result<Self> {
+        check_shard_leaves(shard_leaves)?;
+        Ok(ForestBuilder {
+            shard_leaves,
+            mmr_size: 0,
+            peaks: Vec::new(),
+            finished: false,
+            store,
+            merge: PhantomData,
+        })
+    }
+
+    pub fn mmr_size(&self) -> Pos {
+        self.mmr_size
+    }
+
+    /// Index of the shard `append_shard` expects next.
+    pub fn next_index(&self) -> Pos {
+        get_peak_map(self.mmr_size) / self.shard_leaves
+    }
+
+    /// Write the nodes of `shard` to the store, followed by the parents it completes across the
+    /// shard boundaries.
+    ///
+    /// Fails with `UnexpectedShard` unless the shard has the `shard_leaves` of the builder and is
+    /// the `next_index` one, after a full shard.
+    pub fn append_shard(&mut self, shard: Shard<T>) -> Result<()> {
+        if self.finished
+            || shard.shard_leaves != self.shard_leaves
+            || shard.index != self.next_index()
+        {
+            return Err(Error::UnexpectedShard { index: shard.index });
+        }
+        let full = shard.is_full();
+        let root = shard.nodes.last().cloned().expect("not empty");
+        let start_pos = self.mmr_size;
+        self.mmr_size += shard.nodes.len() as Pos;
+        self.store.append(start_pos, shard.nodes)?;
+        if !full {
+            // a partial shard leaves its own peaks, below the shard height
+            self.finished = true;
+            return Ok(());
+        }
+
+        self.peaks.push((self.mmr_size - 1, root));
+        let parents_pos = self.mmr_size;
+        let mut parents = Vec::new();
+        while self.peaks.len() > 1 {
+            let (right_pos, _) = &self.peaks[self.peaks.len() - 1];
+            let (left_pos, _) = &self.peaks[self.peaks.len() - 2];
+            if pos_height_in_tree(*left_pos) != pos_height_in_tree(*right_pos) {
+                break;
+            }
+            let (_, right) = self.peaks.pop().expect("pop");
+            let (_, left) = self.peaks.pop().expect("pop");
+            let parent = merge_node::<_, M>(self.mmr_size, &left, &right)?;
+            parents.push(parent.clone());
+            self.peaks.push((self.mmr_size, parent));
+            self.mmr_size += 1;
+        }
+        if !parents.is_empty() {
+            self.store.append(parents_pos, parents)?;
+        }
+        Ok(())
+    }
+
+    /// Record the size in the store and open the assembled MMR.
+    pub fn finish(mut self) -> Result<MMR<T, M, S>> {
+        self.store.set_mmr_size(self.mmr_size)?;
+        Ok(MMR::new(self.mmr_size, self.store))
+    }
+}
@@ -1,27 +1,75 @@
-use crate::vec;
+use crate::collections::VecDeque;
 use crate::vec::Vec;
+use core::ops::Range;
 
-pub fn leaf_index_to_pos(index: u64) -> u64 {
+/// Node positions, MMR sizes and leaf indices.
+///
+/// `u64` unless the `u32-positions` feature is enabled, which halves positions in memory, stores
+/// and proofs for MMRs known to stay below `u32::MAX` nodes.
+#[cfg(not(feature = "u32-positions"))]
+pub type Pos = u64;
+#[cfg(feature = "u32-positions")]
+pub type Pos = u32;
+
+const OVERFLOW: &str = "position overflow";
+
+// `lhs + rhs`, checked with the `huge` feature so it panics in release builds as well rather
+// than wrapping to a wrong position
+fn pos_add(lhs: Pos, rhs: Pos) -> Pos {
+    if cfg!(feature = "huge") {
+        lhs.checked_add(rhs).expect(OVERFLOW)
+    } else {
+        lhs + rhs
+    }
+}
+
+// `lhs << bits`, checked like `pos_add` not to shift out any bit
+fn pos_shl(lhs: Pos, bits: u8) -> Pos {
+    if cfg!(feature = "huge") {
+        lhs.checked_shl(bits as u32)
+            .filter(|shifted| shifted >> bits == lhs)
+            .expect(OVERFLOW)
+    } else {
+        lhs << bits
+    }
+}
+
+pub fn leaf_index_to_pos(index: Pos) -> Pos {
     // mmr_size - H - 1, H is the height(intervals) of last peak
-    leaf_index_to_mmr_size(index) - (index + 1).trailing_zeros() as u64 - 1
+    leaf_index_to_mmr_size(index) - (index + 1).trailing_zeros() as Pos - 1
 }
 
-pub fn leaf_index_to_mmr_size(index: u64) -> u64 {
+pub fn leaf_index_to_mmr_size(index: Pos) -> Pos {
     // leaf index start with 0
-    let leaves_count = index + 1;
+    let leaves_count = pos_add(index, 1);
 
     // the peak count(k) is actually the count of 1 in leaves count's binary representation
-    let peak_count = leaves_count.count_ones() as u64;
+    let peak_count = leaves_count.count_ones() as Pos;
+
+    // 2 * leaves_count - peak_count, without overflowing for the largest MMR
+    pos_add(leaves_count, leaves_count - peak_count)
+}
 
-    2 * leaves_count - peak_count
+/// The mmr size after pushing `leaf_count` leaves, `None` if there are none or too many.
+pub fn leaf_count_to_mmr_size(leaf_count: Pos) -> Option<Pos> {
+    // 2 * leaf_count - peak_count, without overflowing for the largest MMR
+    leaf_count
+        .checked_add(leaf_count - leaf_count.count_ones() as Pos)
+        .filter(|_| leaf_count > 0)
 }
 
-pub fn pos_height_in_tree(mut pos: u64) -> u8 {
+/// Returns whether `mmr_size` is the size of some MMR, i.e. it doesn't end in the middle of a merge.
+pub fn is_valid_mmr_size(mmr_size: Pos) -> bool {
+    let leaves_count = get_peak_map(mmr_size);
+    mmr_size == 0 || leaf_index_to_mmr_size(leaves_count - 1) == mmr_size
+}
+
+pub fn pos_height_in_tree(mut pos: Pos) -> u8 {
     if pos == 0 {
         return 0;
     }
 
-    let mut peak_size = u64::MAX >> pos.leading_zeros();
+    let mut peak_size = Pos::MAX >> pos.leading_zeros();
     while peak_size > 0 {
         if pos >= peak_size {
             pos -= peak_size;
@@ -31,12 +79,12 @@ pub fn pos_height_in_tree(mut pos: u64) -> u8 {
     pos as u8
 }
 
-pub fn parent_offset(height: u8) -> u64 {
-    2 << height
+pub fn parent_offset(height: u8) -> Pos {
+    pos_shl(2, height)
 }
 
-pub fn sibling_offset(height: u8) -> u64 {
-    (2 << height) - 1
+pub fn sibling_offset(height: u8) -> Pos {
+    parent_offset(height) - 1
 }
 
 /// Returns the height of the peaks in the mmr, presented by a bitmap.
@@ -55,13 +103,13 @@ pub fn sibling_offset(height: u8) -> u64 {
 ///   2     5
 ///  / \   /  \
 /// 0   1 3   4
-pub fn get_peak_map(mmr_size: u64) -> u64 {
+pub fn get_peak_map(mmr_size: Pos) -> Pos {
     if mmr_size == 0 {
         return 0;
     }
 
     let mut pos = mmr_size;
-    let mut peak_size = u64::MAX >> pos.leading_zeros();
+    let mut peak_size = Pos::MAX >> pos.leading_zeros();
     let mut peak_map = 0;
     while peak_size > 0 {
         peak_map <<= 1;
@@ -75,14 +123,42 @@ pub fn get_peak_map(mmr_size: u64) -> u64 {
     peak_map
 }
 
+/// Returns the positions of the nodes in the subtree under `pos`, including `pos` itself.
+///
+/// A subtree occupies a contiguous range of positions, ending with its root.
+pub fn subtree_range(pos: Pos) -> Range<Pos> {
+    pos + 1 - sibling_offset(pos_height_in_tree(pos))..pos + 1
+}
+
+/// Returns the position of the leftmost leaf under `pos`, the first node of its subtree.
+pub fn leftmost_leaf(pos: Pos) -> Pos {
+    subtree_range(pos).start
+}
+
+/// Returns the position of the rightmost leaf under `pos`, which the parents up to `pos` follow.
+pub fn rightmost_leaf(pos: Pos) -> Pos {
+    pos - pos_height_in_tree(pos) as Pos
+}
+
+/// Returns the lowest node having both `a` and `b` in its subtree, which is one of them if the
+/// other is its descendant.
+///
+/// Nodes in different mountains still have a common ancestor in the larger MMRs that merge
+/// those mountains, so the result may lie beyond the current size of the MMR.
+pub fn common_ancestor(a: Pos, b: Pos) -> Pos {
+    let (height_a, index_a) = PostOrderScheme::node_coords(a);
+    let (height_b, index_b) = PostOrderScheme::node_coords(b);
+    let (leaf_a, leaf_b) = (index_a << height_a, index_b << height_b);
+    // the leaves under a node share all index bits above its height
+    let diverging_bits = (Pos::BITS - (leaf_a ^ leaf_b).leading_zeros()) as u8;
+    let height = height_a.max(height_b).max(diverging_bits);
+    PostOrderScheme::node_pos(height, leaf_a >> height)
+}
+
 /// Returns whether `descendant_contender` is a descendant of `ancestor_contender` in a tree of the MMR.
-pub fn is_descendant_pos(ancestor_contender: u64, descendant_contender: u64) -> bool {
+pub fn is_descendant_pos(ancestor_contender: Pos, descendant_contender: Pos) -> bool {
     // NOTE: "ancestry" here refers to the hierarchy within an MMR tree, not temporal hierarchy.
-    // the descendant needs to have been added to the mmr prior to the ancestor
-    descendant_contender <= ancestor_contender
-        // the descendant needs to be within the cone of positions descendant from the ancestor
-        && descendant_contender
-            >= (ancestor_contender + 1 - sibling_offset(pos_height_in_tree(ancestor_contender)))
+    subtree_range(ancestor_contender).contains(&descendant_contender)
 }
 
 /// Returns the pos of the peaks in the mmr.
@@ -100,23 +176,277 @@ pub fn is_descendant_pos(ancestor_contender: u64, descendant_contender: u64) ->
 ///   2     5
 ///  / \   /  \
 /// 0   1 3   4
-pub fn get_peaks(mmr_size: u64) -> Vec<u64> {
-    if mmr_size == 0 {
-        return vec![];
-    }
+pub fn get_peaks(mmr_size: Pos) -> Vec<Pos> {
+    let mut peaks = Vec::with_capacity((Pos::BITS - mmr_size.leading_zeros()) as usize);
+    peaks.extend(iter_peaks(mmr_size));
+    peaks
+}
 
-    let leading_zeros = mmr_size.leading_zeros();
+/// Same as `get_peaks`, without allocating.
+pub fn iter_peaks(mmr_size: Pos) -> impl Iterator<Item = Pos> {
     let mut pos = mmr_size;
-    let mut peak_size = u64::MAX >> leading_zeros;
-    let mut peaks = Vec::with_capacity(64 - leading_zeros as usize);
+    let mut peak_size = Pos::MAX.checked_shr(mmr_size.leading_zeros()).unwrap_or(0);
     let mut peaks_sum = 0;
-    while peak_size > 0 {
-        if pos >= peak_size {
-            pos -= peak_size;
-            peaks.push(peaks_sum + peak_size - 1);
-            peaks_sum += peak_size;
+    core::iter::from_fn(move || {
+        while peak_size > 0 {
+            let size = peak_size;
+            peak_size >>= 1;
+            if pos >= size {
+                pos -= size;
+                peaks_sum += size;
+                return Some(peaks_sum - 1);
+            }
         }
-        peak_size >>= 1;
+        None
+    })
+}
+
+/// Numbering of the nodes of an MMR.
+///
+/// A scheme maps the coordinates of a node, its height and its index among the nodes of that
+/// height from left to right, to a position and back. The rest of the position math follows
+/// from the coordinates, so tools reading MMRs laid out differently, e.g. by a database using a
+/// flat binary layout, only implement the two mappings and can `convert` positions from and to
+/// the `PostOrderScheme` of this crate.
+///
+/// The MMR, its stores and the proofs climb the mountains through `PostOrderScheme`, the only
+/// numbering in which pushes append, so they aren't generic over the scheme.
+pub trait PositionScheme {
+    /// Position of the node at `height` with `index`.
+    fn node_pos(height: u8, index: Pos) -> Pos;
+
+    /// Height and index of the node at `pos`.
+    fn node_coords(pos: Pos) -> (u8, Pos);
+
+    fn leaf_index_to_pos(index: Pos) -> Pos {
+        Self::node_pos(0, index)
+    }
+
+    fn pos_height(pos: Pos) -> u8 {
+        Self::node_coords(pos).0
+    }
+
+    fn parent_pos(pos: Pos) -> Pos {
+        let (height, index) = Self::node_coords(pos);
+        Self::node_pos(height + 1, index >> 1)
+    }
+
+    fn sibling_pos(pos: Pos) -> Pos {
+        let (height, index) = Self::node_coords(pos);
+        Self::node_pos(height, index ^ 1)
+    }
+
+    /// The left and right child of the node at `pos`, `None` for a leaf.
+    fn children_pos(pos: Pos) -> Option<(Pos, Pos)> {
+        match Self::node_coords(pos) {
+            (0, _) => None,
+            (height, index) => Some((
+                Self::node_pos(height - 1, index << 1),
+                Self::node_pos(height - 1, (index << 1) | 1),
+            )),
+        }
+    }
+
+    /// The sibling and the parent of the node at `pos` of `height`, and whether that node is the
+    /// right child, for climbing a mountain a node at a time as proofs are generated and verified.
+    fn sibling_and_parent_pos(pos: Pos, height: u8) -> (Pos, Pos, bool) {
+        let (_, index) = Self::node_coords(pos);
+        (
+            Self::node_pos(height, index ^ 1),
+            Self::node_pos(height + 1, index >> 1),
+            index & 1 == 1,
+        )
+    }
+
+    /// Positions of the peaks of the MMR of `leaf_count` leaves, from left to right.
+    fn peaks(leaf_count: Pos) -> Vec<Pos> {
+        let mut peaks = Vec::with_capacity(leaf_count.count_ones() as usize);
+        let mut leaves = 0;
+        for height in (0..Pos::BITS as u8).rev() {
+            if leaf_count >> height & 1 == 1 {
+                peaks.push(Self::node_pos(height, leaves >> height));
+                leaves += 1 << height;
+            }
+        }
+        peaks
+    }
+
+    /// The position in scheme `P` of the node at `pos`.
+    fn convert<P: PositionScheme>(pos: Pos) -> Pos {
+        let (height, index) = Self::node_coords(pos);
+        P::node_pos(height, index)
+    }
+}
+
+/// The post-order numbering of this crate, in which every node follows its children, so pushes
+/// only ever append, see `get_peak_map`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PostOrderScheme;
+
+impl PositionScheme for PostOrderScheme {
+    fn node_pos(height: u8, index: Pos) -> Pos {
+        // the parents of the last leaf under the node follow it
+        pos_add(
+            leaf_index_to_pos(pos_shl(pos_add(index, 1), height) - 1),
+            height as Pos,
+        )
+    }
+
+    fn node_coords(pos: Pos) -> (u8, Pos) {
+        let height = pos_height_in_tree(pos);
+        // the MMR before the last leaf under the node has `last_leaf_index` leaves
+        let last_leaf_index = get_peak_map(pos - height as Pos);
+        (height, ((last_leaf_index + 1) >> height) - 1)
+    }
+
+    fn leaf_index_to_pos(index: Pos) -> Pos {
+        leaf_index_to_pos(index)
+    }
+
+    fn pos_height(pos: Pos) -> u8 {
+        pos_height_in_tree(pos)
+    }
+
+    fn parent_pos(pos: Pos) -> Pos {
+        let height = pos_height_in_tree(pos);
+        if pos_height_in_tree(pos_add(pos, 1)) > height {
+            pos + 1
+        } else {
+            pos_add(pos, parent_offset(height))
+        }
+    }
+
+    fn sibling_pos(pos: Pos) -> Pos {
+        let height = pos_height_in_tree(pos);
+        if pos_height_in_tree(pos_add(pos, 1)) > height {
+            pos - sibling_offset(height)
+        } else {
+            pos_add(pos, sibling_offset(height))
+        }
+    }
+
+    fn children_pos(pos: Pos) -> Option<(Pos, Pos)> {
+        match pos_height_in_tree(pos) {
+            0 => None,
+            height => Some((pos - (1 << height), pos - 1)),
+        }
+    }
+
+    fn sibling_and_parent_pos(pos: Pos, height: u8) -> (Pos, Pos, bool) {
+        if pos_height_in_tree(pos_add(pos, 1)) > height {
+            (pos - sibling_offset(height), pos + 1, true)
+        } else {
+            (
+                pos_add(pos, sibling_offset(height)),
+                pos_add(pos, parent_offset(height)),
+                false,
+            )
+        }
+    }
+
+    fn peaks(leaf_count: Pos) -> Vec<Pos> {
+        leaf_count_to_mmr_size(leaf_count).map_or_else(Vec::new, get_peaks)
+    }
+}
+
+pub trait VeqDequeExt<T: Ord> {
+    fn insert_sorted(&mut self, value: T);
+}
+
+impl<T: Ord> VeqDequeExt<T> for VecDeque<T> {
+    fn insert_sorted(&mut self, value: T) {
+        match self.binary_search(&value) {
+            Ok(_pos) => {
+                // element already in vector @ `pos`
+            }
+            Err(pos) => self.insert(pos, value),
+        }
+    }
+}
+
+/// `u128` variants of the helpers, for MMRs beyond `2^63` leaves, e.g. to plan how to split the
+/// positions of a long-running log over several `Pos` positioned MMRs.
+#[cfg(feature = "huge")]
+pub mod huge {
+    use crate::vec::Vec;
+
+    pub fn leaf_index_to_pos(index: u128) -> u128 {
+        leaf_index_to_mmr_size(index) - (index + 1).trailing_zeros() as u128 - 1
+    }
+
+    pub fn leaf_index_to_mmr_size(index: u128) -> u128 {
+        let leaves_count = index.checked_add(1).expect(super::OVERFLOW);
+        leaf_count_to_mmr_size(leaves_count).expect(super::OVERFLOW)
+    }
+
+    /// The mmr size after pushing `leaf_count` leaves, `None` if there are none or too many.
+    pub fn leaf_count_to_mmr_size(leaf_count: u128) -> Option<u128> {
+        leaf_count
+            .checked_add(leaf_count - leaf_count.count_ones() as u128)
+            .filter(|_| leaf_count > 0)
+    }
+
+    pub fn is_valid_mmr_size(mmr_size: u128) -> bool {
+        let leaves_count = get_peak_map(mmr_size);
+        mmr_size == 0 || leaf_index_to_mmr_size(leaves_count - 1) == mmr_size
+    }
+
+    pub fn pos_height_in_tree(mut pos: u128) -> u8 {
+        if pos == 0 {
+            return 0;
+        }
+
+        let mut peak_size = u128::MAX >> pos.leading_zeros();
+        while peak_size > 0 {
+            if pos >= peak_size {
+                pos -= peak_size;
+            }
+            peak_size >>= 1;
+        }
+        pos as u8
+    }
+
+    pub fn parent_offset(height: u8) -> u128 {
+        2 << height
+    }
+
+    pub fn sibling_offset(height: u8) -> u128 {
+        (2 << height) - 1
+    }
+
+    pub fn get_peak_map(mmr_size: u128) -> u128 {
+        if mmr_size == 0 {
+            return 0;
+        }
+
+        let mut pos = mmr_size;
+        let mut peak_size = u128::MAX >> pos.leading_zeros();
+        let mut peak_map = 0;
+        while peak_size > 0 {
+            peak_map <<= 1;
+            if pos >= peak_size {
+                pos -= peak_size;
+                peak_map |= 1;
+            }
+            peak_size >>= 1;
+        }
+
+        peak_map
+    }
+
+    pub fn get_peaks(mmr_size: u128) -> Vec<u128> {
+        let mut peaks = Vec::new();
+        let mut pos = mmr_size;
+        let mut peak_size = u128::MAX.checked_shr(mmr_size.leading_zeros()).unwrap_or(0);
+        let mut peaks_sum = 0;
+        while peak_size > 0 {
+            if pos >= peak_size {
+                pos -= peak_size;
+                peaks_sum += peak_size;
+                peaks.push(peaks_sum - 1);
+            }
+            peak_size >>= 1;
+        }
+        peaks
     }
-    peaks
 }
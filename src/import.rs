@@ -0,0 +1,154 @@
+//! Migration from other MMR implementations
+//!
+//! Datasets kept by other MMR implementations come either as their leaves or as all their
+//! nodes. `from_leaf_hashes` rebuilds an MMR from the leaves in insertion order, while
+//! `from_node_hashes` takes the nodes as they are if the other implementation uses the same
+//! layout, e.g. the hash file of an unpruned Grin PMMR, which stores the nodes in post-order
+//! with the parents merged with their position. Both check the result against the root the
+//! dataset is known by, and return the MMR with its nodes pending, so nothing is written to the
+//! store unless the caller commits it.
+
+use crate::helper::{is_valid_mmr_size, pos_height_in_tree};
+use crate::mmr::check_cancelled;
+use crate::mmr_store::MMRStoreReadOps;
+use crate::vec::Vec;
+use crate::verifier::merge_node;
+use crate::{Error, Merge, Pos, Result, MMR};
+use core::sync::atomic::AtomicBool;
+
+/// Rebuild an MMR in the empty `store` by pushing `leaves` in insertion order.
+///
+/// Fails with `UnexpectedRoot` if the root of the result differs from `expected_root`.
+pub fn from_leaf_hashes<T, M, S>(
+    leaves: impl IntoIterator<Item = T>,
+    expected_root: &T,
+    store: S,
+) -> Result<MMR<T, M, S>>
+where
+    T: Clone + PartialEq,
+    M: Merge<Item = T>,
+    S: MMRStoreReadOps<T>,
+{
+    from_leaf_hashes_with(leaves, expected_root, store, None)
+}
+
+/// Same as `from_leaf_hashes`, failing with `Cancelled` once `cancel` is set. The flag is
+/// checked before every leaf.
+pub fn from_leaf_hashes_cancellable<T, M, S>(
+    leaves: impl IntoIterator<Item = T>,
+    expected_root: &T,
+    store: S,
+    cancel: &AtomicBool,
+) -> Result<MMR<T, M, S>>
+where
+    T: Clone + PartialEq,
+    M: Merge<Item = T>,
+    S: MMRStoreReadOps<T>,
+{
+    from_leaf_hashes_with(leaves, expected_root, store, Some(cancel))
+}
+
+fn from_leaf_hashes_with<T, M, S>(
+    leaves: impl IntoIterator<Item = T>,
+    expected_root: &T,
+    store: S,
+    cancel: Option<&AtomicBool>,
+) -> Result<MMR<T, M, S>>
+where
+    T: Clone + PartialEq,
+    M: Merge<Item = T>,
+    S: MMRStoreReadOps<T>,
+{
+    let mut mmr = MMR::new(0, store);
+    for leaf in leaves {
+        check_cancelled(cancel)?;
+        mmr.push(leaf)?;
+    }
+    check_root(mmr, expected_root)
+}
+
+/// Take all `nodes` of an MMR in post-order as the nodes of an MMR in the empty `store`,
+/// checking every parent against the merge of its children.
+///
+/// Fails with `InvalidMMRSize` if the number of nodes isn't an MMR size, with `UnexpectedNode`
+/// at the first parent that isn't the merge of its children, e.g. since the other
+/// implementation merges differently, and with `UnexpectedRoot` if the root of the result
+/// differs from `expected_root`.
+pub fn from_node_hashes<T, M, S>(
+    nodes: impl IntoIterator<Item = T>,
+    expected_root: &T,
+    store: S,
+) -> Result<MMR<T, M, S>>
+where
+    T: Clone + PartialEq,
+    M: Merge<Item = T>,
+    S: MMRStoreReadOps<T>,
+{
+    from_node_hashes_with(nodes, expected_root, store, None)
+}
+
+/// Same as `from_node_hashes`, failing with `Cancelled` once `cancel` is set. The flag is
+/// checked before every node.
+pub fn from_node_hashes_cancellable<T, M, S>(
+    nodes: impl IntoIterator<Item = T>,
+    expected_root: &T,
+    store: S,
+    cancel: &AtomicBool,
+) -> Result<MMR<T, M, S>>
+where
+    T: Clone + PartialEq,
+    M: Merge<Item = T>,
+    S: MMRStoreReadOps<T>,
+{
+    from_node_hashes_with(nodes, expected_root, store, Some(cancel))
+}
+
+fn from_node_hashes_with<T, M, S>(
+    nodes: impl IntoIterator<Item = T>,
+    expected_root: &T,
+    store: S,
+    cancel: Option<&AtomicBool>,
+) -> Result<MMR<T, M, S>>
+where
+    T: Clone + PartialEq,
+    M: Merge<Item = T>,
+    S: MMRStoreReadOps<T>,
+{
+    let nodes: Vec<T> = nodes.into_iter().collect();
+    // the roots of the complete subtrees so far, with their heights
+    let mut peaks: Vec<(u8, &T)> = Vec::new();
+    for (pos, node) in nodes.iter().enumerate() {
+        check_cancelled(cancel)?;
+        let pos = pos as Pos;
+        let height = pos_height_in_tree(pos);
+        if height > 0 {
+            // the children are the last two subtrees, one height below
+            let (right_height, right) = peaks.pop().ok_or(Error::UnexpectedNode { pos })?;
+            let (left_height, left) = peaks.pop().ok_or(Error::UnexpectedNode { pos })?;
+            debug_assert!(left_height + 1 == height && right_height + 1 == height);
+            if merge_node::<T, M>(pos, left, right)? != *node {
+                return Err(Error::UnexpectedNode { pos });
+            }
+        }
+        peaks.push((height, node));
+    }
+    let mmr_size = nodes.len() as Pos;
+    if !is_valid_mmr_size(mmr_size) {
+        return Err(Error::InvalidMMRSize { mmr_size });
+    }
+    let mut mmr = MMR::new(mmr_size, store);
+    mmr.batch_mut().append(0, nodes);
+    check_root(mmr, expected_root)
+}
+
+fn check_root<T, M, S>(mmr: MMR<T, M, S>, expected_root: &T) -> Result<MMR<T, M, S>>
+where
+    T: Clone + PartialEq,
+    M: Merge<Item = T>,
+    S: MMRStoreReadOps<T>,
+{
+    if mmr.get_root()? != *expected_root {
+        return Err(Error::UnexpectedRoot);
+    }
+    Ok(mmr)
+}
@@ -0,0 +1,256 @@
+//! Proof layouts of BEEFY light clients
+//!
+//! Substrate's MMR pallet serves proofs by leaf index and leaf count rather than by position and
+//! MMR size, as `LeafProof` and `AncestryProof` here, and the Solidity MMR verifiers of the
+//! Ethereum BEEFY light clients take the leaves of a `LeafProof` as `MmrLeaf`s, which add the
+//! index of each leaf within its mountain. Both keep the order of the proof items of this crate:
+//! the peaks left of the proven mountains, the items of the mountains, then the peaks on the right
+//! bagged into a single item.
+//!
+//! Light clients proving a single leaf per block instead take a `SimplifiedProof`, the items in
+//! the order they are hashed with the leaf along with a bitfield telling the side of each item.
+
+use crate::helper::{
+    get_peak_map, get_peaks, leaf_count_to_mmr_size, leaf_index_to_pos, parent_offset,
+    pos_height_in_tree,
+};
+use crate::vec::Vec;
+use crate::{AncestryProof as MmrAncestryProof, Error, Merge, MerkleProof, NodeMerkleProof};
+use crate::{Pos, Result};
+use core::convert::TryFrom;
+
+/// Proof of leaves by index, the layout of `sp_mmr_primitives::LeafProof`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeafProof<T> {
+    /// Indices of the proven leaves, in ascending order.
+    pub leaf_indices: Vec<u64>,
+    /// Number of leaves of the MMR the proof is for.
+    pub leaf_count: u64,
+    pub items: Vec<T>,
+}
+
+/// A proven leaf as the Solidity MMR verifiers take it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MmrLeaf<T> {
+    /// Index of the leaf within its mountain.
+    pub k_index: u64,
+    pub leaf_index: u64,
+    pub hash: T,
+}
+
+/// Ancestry proof by leaf counts, the layout of `sp_mmr_primitives::AncestryProof`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AncestryProof<T> {
+    pub prev_peaks: Vec<T>,
+    pub prev_leaf_count: u64,
+    pub leaf_count: u64,
+    /// Proof items of the previous peaks along with their positions.
+    pub items: Vec<(u64, T)>,
+}
+
+/// Proof of a single leaf, with the items in hashing order.
+///
+/// The root is the leaf hashed with each item in turn, the item on the left if bit `i` of `order`
+/// is set and on the right otherwise. Since the peaks are hashed with `Merge::merge` as well,
+/// this needs a merge whose `merge_peaks` and `merge_with_pos` don't differ from `merge`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimplifiedProof<T> {
+    pub items: Vec<T>,
+    pub order: u64,
+}
+
+fn to_pos(n: u64) -> Result<Pos> {
+    Pos::try_from(n).map_err(|_| Error::CorruptedProof)
+}
+
+fn to_mmr_size(leaf_count: u64) -> Result<Pos> {
+    let leaf_count = to_pos(leaf_count)?;
+    leaf_count_to_mmr_size(leaf_count).ok_or(Error::InvalidLeafCount { leaf_count })
+}
+
+/// Index of the leaf at `leaf_index` within its mountain, in an MMR of `leaf_count` leaves.
+pub fn k_index(leaf_index: u64, leaf_count: u64) -> Result<u64> {
+    if leaf_index >= leaf_count {
+        return Err(Error::CorruptedProof);
+    }
+    // the mountains hold the leaves of the bits of the leaf count, the largest first
+    let mut first_leaf = 0;
+    for bit in (0..u64::BITS).rev() {
+        let mountain_leaves = leaf_count & (1 << bit);
+        if leaf_index < first_leaf + mountain_leaves {
+            break;
+        }
+        first_leaf += mountain_leaves;
+    }
+    Ok(leaf_index - first_leaf)
+}
+
+impl<T> LeafProof<T> {
+    /// Convert a proof of the leaves at `leaf_positions`, failing with `GenProofForInvalidLeaves`
+    /// or `CorruptedProof` if it isn't shaped like a proof of them, see
+    /// `MerkleProof::validate_shape`.
+    pub fn from_proof<M>(proof: MerkleProof<T, M>, mut leaf_positions: Vec<Pos>) -> Result<Self> {
+        proof.validate_shape(proof.mmr_size(), &leaf_positions)?;
+        leaf_positions.sort_unstable();
+        leaf_positions.dedup();
+        Ok(LeafProof {
+            // a leaf at `pos` follows the leaves of the MMR of size `pos`
+            leaf_indices: leaf_positions
+                .into_iter()
+                .map(|pos| get_peak_map(pos) as u64)
+                .collect(),
+            leaf_count: get_peak_map(proof.mmr_size()) as u64,
+            items: proof.into_proof_items(),
+        })
+    }
+
+    /// Convert back into a proof, along with the positions of the proven leaves.
+    pub fn into_proof<M>(self) -> Result<(MerkleProof<T, M>, Vec<Pos>)> {
+        let mmr_size = to_mmr_size(self.leaf_count)?;
+        let leaf_positions = self
+            .leaf_indices
+            .iter()
+            .map(|index| {
+                if *index >= self.leaf_count {
+                    return Err(Error::CorruptedProof);
+                }
+                Ok(leaf_index_to_pos(to_pos(*index)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let proof = MerkleProof::new(mmr_size, self.items);
+        proof.validate_shape(mmr_size, &leaf_positions)?;
+        Ok((proof, leaf_positions))
+    }
+
+    /// The proven leaves as the Solidity verifiers take them, given their `hashes` in the order of
+    /// `leaf_indices`. Fails with `CorruptedProof` unless there is one hash for each leaf.
+    pub fn mmr_leaves(&self, hashes: Vec<T>) -> Result<Vec<MmrLeaf<T>>> {
+        if hashes.len() != self.leaf_indices.len() {
+            return Err(Error::CorruptedProof);
+        }
+        self.leaf_indices
+            .iter()
+            .zip(hashes)
+            .map(|(leaf_index, hash)| {
+                Ok(MmrLeaf {
+                    k_index: k_index(*leaf_index, self.leaf_count)?,
+                    leaf_index: *leaf_index,
+                    hash,
+                })
+            })
+            .collect()
+    }
+
+    /// The size of the MMR, which the Solidity verifiers take instead of the leaf count.
+    pub fn mmr_size(&self) -> Result<u64> {
+        Ok(to_mmr_size(self.leaf_count)? as u64)
+    }
+}
+
+impl<T: Clone> AncestryProof<T> {
+    pub fn from_proof<M>(proof: &MmrAncestryProof<T, M>) -> Self {
+        let prev_peaks_proof = proof.prev_peaks_proof();
+        AncestryProof {
+            prev_peaks: proof.prev_peaks().to_vec(),
+            prev_leaf_count: get_peak_map(proof.prev_mmr_size()) as u64,
+            leaf_count: get_peak_map(prev_peaks_proof.mmr_size()) as u64,
+            items: prev_peaks_proof
+                .proof_items()
+                .iter()
+                .map(|(pos, item)| {
+                    // the layout keeps `u64` positions, also with `u32-positions`
+                    #[allow(clippy::unnecessary_cast)]
+                    let pos = *pos as u64;
+                    (pos, item.clone())
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<T> AncestryProof<T> {
+    /// Convert back into an ancestry proof, failing like `crate::AncestryProof::new`.
+    pub fn into_proof<M>(self) -> Result<MmrAncestryProof<T, M>> {
+        let prev_mmr_size = to_mmr_size(self.prev_leaf_count)?;
+        let mmr_size = to_mmr_size(self.leaf_count)?;
+        let items = self
+            .items
+            .into_iter()
+            .map(|(pos, item)| Ok((to_pos(pos)?, item)))
+            .collect::<Result<Vec<_>>>()?;
+        MmrAncestryProof::new(
+            self.prev_peaks,
+            prev_mmr_size,
+            NodeMerkleProof::new(mmr_size, items),
+        )
+    }
+}
+
+impl<T> SimplifiedProof<T> {
+    /// Convert a proof of the single leaf at `leaf_pos`, failing with `GenProofForInvalidLeaves`
+    /// if it is not a leaf of the MMR and with `CorruptedProof` unless the proof has one item for
+    /// each level of the mountain and each peak, with the peaks on the right bagged.
+    pub fn from_proof<M>(proof: MerkleProof<T, M>, leaf_pos: Pos) -> Result<Self> {
+        let mmr_size = proof.mmr_size();
+        if leaf_pos >= mmr_size || pos_height_in_tree(leaf_pos) > 0 {
+            return Err(Error::GenProofForInvalidLeaves);
+        }
+        let peaks = get_peaks(mmr_size);
+        let peak_index = peaks
+            .iter()
+            .position(|peak_pos| *peak_pos >= leaf_pos)
+            .ok_or(Error::CorruptedProof)?;
+        let height = pos_height_in_tree(peaks[peak_index]) as usize;
+        let has_rhs_peaks = peak_index + 1 < peaks.len();
+        let mut items = proof.into_proof_items();
+        if items.len() != peak_index + height + has_rhs_peaks as usize {
+            return Err(Error::CorruptedProof);
+        }
+
+        // the proof starts with the peaks on the left, which are hashed last, right to left
+        let mut lhs_peaks: Vec<T> = items.drain(..peak_index).collect();
+        lhs_peaks.reverse();
+        items.extend(lhs_peaks);
+        let mut order = 0;
+        let mut pos = leaf_pos;
+        for bit in 0..height {
+            let height = bit as u8;
+            if pos_height_in_tree(pos + 1) > height {
+                // a right child, so its sibling is on the left
+                order |= 1 << bit;
+                pos += 1;
+            } else {
+                pos += parent_offset(height);
+            }
+        }
+        if has_rhs_peaks {
+            order |= 1 << height;
+        }
+        Ok(SimplifiedProof { items, order })
+    }
+}
+
+impl<T: PartialEq> SimplifiedProof<T> {
+    /// Calculate the root from the `leaf` hash, failing with `CorruptedProof` if there are more
+    /// items than bits of `order`.
+    pub fn calculate_root<M: Merge<Item = T>>(&self, leaf: T) -> Result<T> {
+        if self.items.len() > u64::BITS as usize {
+            return Err(Error::CorruptedProof);
+        }
+        self.items
+            .iter()
+            .enumerate()
+            .try_fold(leaf, |node, (bit, item)| {
+                if self.order & (1 << bit) != 0 {
+                    M::merge(item, &node)
+                } else {
+                    M::merge(&node, item)
+                }
+                .map_err(Into::into)
+            })
+    }
+
+    pub fn verify<M: Merge<Item = T>>(&self, root: T, leaf: T) -> Result<bool> {
+        Ok(self.calculate_root::<M>(leaf)? == root)
+    }
+}
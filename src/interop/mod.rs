@@ -0,0 +1,6 @@
+//! Layouts of proofs expected by verifiers outside this crate
+//!
+//! Each submodule converts the proofs of this crate to and from the structs of one ecosystem, so
+//! relayers pass them on without mapping fields by hand.
+
+pub mod beefy;
@@ -0,0 +1,321 @@
+//! K-ary mountain ranges
+//!
+//! A `KaryMMR` merges `KaryMerge::ARITY` children into every parent instead of two, so its
+//! mountains are `log_k` of the leaves high. Verifiers hashing wide inputs cheaply, e.g. with
+//! Poseidon in a circuit, take fewer hashes along each path, with `ARITY - 1` proof items per
+//! level instead of one.
+//!
+//! The nodes are numbered in post-order like those of an `MMR`, every parent following its
+//! children, and a leaf count of `n` has a mountain of `k^h` leaves for every unit of the digit
+//! of `k^h` in `n` written in base `k`, the largest first. The positions depend on the arity,
+//! so the API addresses the leaves by leaf index. With an arity of 2 it is the same MMR as
+//! `MMR` builds.
+
+use crate::mmr_store::{MMRBatch, MMRStoreReadOps, MMRStoreWriteOps};
+use crate::vec::Vec;
+use crate::verifier::{bagging_peaks_hashes, sort_and_dedup_by_pos};
+use crate::{Error, KaryMerge, Pos, Result};
+use core::marker::PhantomData;
+
+pub struct KaryMMR<T, M, S> {
+    leaf_count: Pos,
+    mmr_size: Pos,
+    batch: MMRBatch<T, S>,
+    merge: PhantomData<M>,
+}
+
+/// Proof of leaves of a `KaryMMR`.
+///
+/// Holds the peaks left of the first mountain with a leaf, then for every mountain with leaves
+/// the missing children of each parent from the bottom up and left to right, then the peaks on
+/// the right bagged into a single item.
+#[derive(Debug)]
+pub struct KaryMerkleProof<T, M> {
+    leaf_count: Pos,
+    items: Vec<T>,
+    merge: PhantomData<M>,
+}
+
+fn arity<M: KaryMerge>() -> Pos {
+    assert!(M::ARITY >= 2, "merge arity below 2");
+    M::ARITY as Pos
+}
+
+/// Number of nodes of a perfect tree of `height`.
+fn tree_size(arity: Pos, height: u8) -> Pos {
+    (arity.pow(height as u32 + 1) - 1) / (arity - 1)
+}
+
+/// Position of the leaf at `index`, the number of nodes of the MMR of `index` leaves.
+fn leaf_index_to_pos(arity: Pos, index: Pos) -> Pos {
+    let mut pos = 0;
+    let mut rest = index;
+    let mut height = 0;
+    while rest > 0 {
+        pos += rest % arity * tree_size(arity, height);
+        rest /= arity;
+        height += 1;
+    }
+    pos
+}
+
+/// Position of the node at `height` with `index` among the nodes of that height.
+fn node_pos(arity: Pos, height: u8, index: Pos) -> Pos {
+    // the parents of the last leaf under the node follow it
+    let last_leaf = (index + 1) * arity.pow(height as u32) - 1;
+    leaf_index_to_pos(arity, last_leaf) + height as Pos
+}
+
+/// Height and first leaf of each mountain of the MMR of `leaf_count` leaves, from left to right.
+fn mountains(arity: Pos, leaf_count: Pos) -> Vec<(u8, Pos)> {
+    let mut span: Pos = 1;
+    let mut height = 0;
+    while let Some(next_span) = span.checked_mul(arity).filter(|span| *span <= leaf_count) {
+        span = next_span;
+        height += 1;
+    }
+    let mut mountains = Vec::new();
+    let mut first_leaf = 0;
+    loop {
+        while leaf_count - first_leaf >= span {
+            mountains.push((height, first_leaf));
+            first_leaf += span;
+        }
+        if height == 0 {
+            return mountains;
+        }
+        span /= arity;
+        height -= 1;
+    }
+}
+
+impl<T, M: KaryMerge<Item = T>, S> KaryMMR<T, M, S> {
+    /// The MMR of `leaf_count` leaves in `store`.
+    ///
+    /// Panics if `M::ARITY` is below 2.
+    pub fn new(leaf_count: Pos, store: S) -> Self {
+        KaryMMR {
+            leaf_count,
+            mmr_size: leaf_index_to_pos(arity::<M>(), leaf_count),
+            batch: MMRBatch::new(store),
+            merge: PhantomData,
+        }
+    }
+
+    pub fn leaf_count(&self) -> Pos {
+        self.leaf_count
+    }
+
+    pub fn mmr_size(&self) -> Pos {
+        self.mmr_size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaf_count == 0
+    }
+
+    /// Position of the leaf at `leaf_index` in the store.
+    pub fn leaf_pos(&self, leaf_index: Pos) -> Pos {
+        leaf_index_to_pos(arity::<M>(), leaf_index)
+    }
+
+    pub fn store(&self) -> &S {
+        self.batch.store()
+    }
+}
+
+impl<T: Clone, M: KaryMerge<Item = T>, S: MMRStoreReadOps<T>> KaryMMR<T, M, S> {
+    fn get_node(&self, height: u8, index: Pos) -> Result<T> {
+        let pos = node_pos(arity::<M>(), height, index);
+        self.batch
+            .get_elem(pos)?
+            .ok_or(Error::InconsistentStore { pos })
+    }
+
+    /// Push `elem`, returning its leaf index.
+    pub fn push(&mut self, elem: T) -> Result<Pos> {
+        let arity = arity::<M>();
+        let leaf_index = self.leaf_count;
+        let mut elems = Vec::new();
+        let mut node = elem.clone();
+        elems.push(elem);
+        // every trailing zero of the new leaf count in base `arity` completes a tree
+        let mut leaf_count = leaf_index + 1;
+        let mut height = 0;
+        while leaf_count.is_multiple_of(arity) {
+            let parent_index = leaf_count / arity - 1;
+            let mut children = Vec::with_capacity(M::ARITY);
+            for index in parent_index * arity..(parent_index + 1) * arity - 1 {
+                children.push(self.get_node(height, index)?);
+            }
+            children.push(node);
+            node = M::merge_children(&children).map_err(Into::into)?;
+            elems.push(node.clone());
+            leaf_count /= arity;
+            height += 1;
+        }
+        let elems_len = elems.len() as Pos;
+        self.batch.append(self.mmr_size, elems);
+        self.mmr_size += elems_len;
+        self.leaf_count += 1;
+        Ok(leaf_index)
+    }
+
+    /// The peaks from left to right.
+    pub fn peaks(&self) -> Result<Vec<T>> {
+        let arity = arity::<M>();
+        mountains(arity, self.leaf_count)
+            .into_iter()
+            .map(|(height, first_leaf)| {
+                self.get_node(height, first_leaf / arity.pow(height as u32))
+            })
+            .collect()
+    }
+
+    pub fn get_root(&self) -> Result<T> {
+        if self.leaf_count == 0 {
+            return Err(Error::GetRootOnEmpty);
+        }
+        bagging_peaks_hashes::<T, M>(self.peaks()?)
+    }
+
+    /// Prove the leaves at `leaf_indices`, failing with `GenProofForInvalidLeaves` if there are
+    /// none or one is beyond the leaves of the MMR.
+    pub fn gen_proof(&self, mut leaf_indices: Vec<Pos>) -> Result<KaryMerkleProof<T, M>> {
+        leaf_indices.sort_unstable();
+        leaf_indices.dedup();
+        match leaf_indices.last() {
+            Some(last) if *last < self.leaf_count => {}
+            _ => return Err(Error::GenProofForInvalidLeaves),
+        }
+        let arity = arity::<M>();
+        let mut items = Vec::new();
+        let mut rhs_peaks = Vec::new();
+        let mut leaves = leaf_indices.as_slice();
+        for (height, first_leaf) in mountains(arity, self.leaf_count) {
+            let span = arity.pow(height as u32);
+            let leaves_len = leaves
+                .iter()
+                .take_while(|index| **index < first_leaf + span)
+                .count();
+            let (mountain_leaves, rest) = leaves.split_at(leaves_len);
+            leaves = rest;
+            if mountain_leaves.is_empty() {
+                let peak = self.get_node(height, first_leaf / span)?;
+                if leaves.is_empty() {
+                    rhs_peaks.push(peak);
+                } else {
+                    items.push(peak);
+                }
+                continue;
+            }
+            let mut nodes = mountain_leaves.to_vec();
+            for height in 0..height {
+                let mut parents = Vec::with_capacity(nodes.len());
+                let mut nodes_iter = nodes.into_iter().peekable();
+                while let Some(node) = nodes_iter.peek() {
+                    let parent = *node / arity;
+                    for child in parent * arity..(parent + 1) * arity {
+                        if nodes_iter.next_if_eq(&child).is_none() {
+                            items.push(self.get_node(height, child)?);
+                        }
+                    }
+                    parents.push(parent);
+                }
+                nodes = parents;
+            }
+        }
+        if !rhs_peaks.is_empty() {
+            items.push(bagging_peaks_hashes::<T, M>(rhs_peaks)?);
+        }
+        Ok(KaryMerkleProof::new(self.leaf_count, items))
+    }
+}
+
+impl<T: Clone, M, S: MMRStoreWriteOps<T>> KaryMMR<T, M, S> {
+    /// Write the pending nodes to the store, along with the new size.
+    pub fn commit(&mut self) -> Result<()> {
+        self.batch.set_mmr_size(self.mmr_size);
+        self.batch.commit()
+    }
+}
+
+impl<T, M> KaryMerkleProof<T, M> {
+    pub fn new(leaf_count: Pos, items: Vec<T>) -> Self {
+        KaryMerkleProof {
+            leaf_count,
+            items,
+            merge: PhantomData,
+        }
+    }
+
+    /// Number of leaves of the MMR the proof is for.
+    pub fn leaf_count(&self) -> Pos {
+        self.leaf_count
+    }
+
+    pub fn proof_items(&self) -> &[T] {
+        &self.items
+    }
+}
+
+impl<T: Clone + PartialEq, M: KaryMerge<Item = T>> KaryMerkleProof<T, M> {
+    /// Calculate the root from `leaves` given by leaf index, failing with
+    /// `GenProofForInvalidLeaves` if there are none or one is beyond the leaves of the MMR, and
+    /// with `CorruptedProof` unless the proof has exactly the items for them.
+    pub fn calculate_root(&self, mut leaves: Vec<(Pos, T)>) -> Result<T> {
+        sort_and_dedup_by_pos(&mut leaves)?;
+        match leaves.last() {
+            Some((last, _)) if *last < self.leaf_count => {}
+            _ => return Err(Error::GenProofForInvalidLeaves),
+        }
+        let arity = arity::<M>();
+        let mut items = self.items.iter();
+        let mut peaks = Vec::new();
+        let mut leaves = leaves.into_iter().peekable();
+        for (height, first_leaf) in mountains(arity, self.leaf_count) {
+            let span = arity.pow(height as u32);
+            let mut nodes = Vec::new();
+            while let Some(leaf) = leaves.next_if(|(index, _)| *index < first_leaf + span) {
+                nodes.push(leaf);
+            }
+            if nodes.is_empty() {
+                let peak = items.next().ok_or(Error::CorruptedProof)?.clone();
+                peaks.push(peak);
+                if leaves.peek().is_none() {
+                    // the peaks on the right are bagged into this one
+                    break;
+                }
+                continue;
+            }
+            for _ in 0..height {
+                let mut parents = Vec::with_capacity(nodes.len());
+                let mut nodes_iter = nodes.into_iter().peekable();
+                while let Some((node, _)) = nodes_iter.peek() {
+                    let parent = *node / arity;
+                    let mut children = Vec::with_capacity(M::ARITY);
+                    for child in parent * arity..(parent + 1) * arity {
+                        match nodes_iter.next_if(|(index, _)| *index == child) {
+                            Some((_, item)) => children.push(item),
+                            None => {
+                                children.push(items.next().ok_or(Error::CorruptedProof)?.clone())
+                            }
+                        }
+                    }
+                    parents.push((parent, M::merge_children(&children).map_err(Into::into)?));
+                }
+                nodes = parents;
+            }
+            peaks.push(nodes.pop().expect("a node per mountain").1);
+        }
+        if items.next().is_some() {
+            return Err(Error::CorruptedProof);
+        }
+        bagging_peaks_hashes::<T, M>(peaks)
+    }
+
+    pub fn verify(&self, root: T, leaves: Vec<(Pos, T)>) -> Result<bool> {
+        self.calculate_root(leaves)
+            .map(|calculated_root| calculated_root == root)
+    }
+}
@@ -0,0 +1,74 @@
+//! Reverse leaf lookup
+//!
+//! Applications often need to know whether a hash is already in the MMR and where. With the
+//! leaf index enabled, the MMR indexes its leaves into the store as they are pushed, so
+//! `MMR::find_leaf` can answer that instead of a side table maintained by hand. Only the leaves
+//! pushed while the index is enabled are found.
+
+use crate::mmr_store::{MMRBatch, MMRStoreReadOps};
+use crate::vec::Vec;
+use crate::{Error, Pos, Result, MMR};
+
+/// Looks a leaf up in the index, set by `set_leaf_index_first` to index first occurrences only.
+pub(crate) type IsIndexed<T, S> = fn(&MMRBatch<T, S>, &T) -> Result<bool>;
+
+impl<T, M, S> MMR<T, M, S> {
+    /// Start indexing every pushed leaf into the store, which must implement
+    /// `append_leaf_index`, so `find_leaf` returns all positions of a leaf. `false` stops it.
+    pub fn set_leaf_index(&mut self, enabled: bool) {
+        self.leaf_index = enabled;
+        self.leaf_index_first = None;
+    }
+
+    pub fn leaf_index(&self) -> bool {
+        self.leaf_index
+    }
+
+    /// Whether only the first occurrence of a leaf is indexed, see `set_leaf_index_first`.
+    pub fn leaf_index_first(&self) -> bool {
+        self.leaf_index_first.is_some()
+    }
+}
+
+impl<T: Clone + PartialEq, M, S: MMRStoreReadOps<T>> MMR<T, M, S> {
+    /// Positions of the indexed leaves equal to `leaf` in ascending order, including
+    /// uncommitted pushes.
+    pub fn find_leaf(&self, leaf: &T) -> Result<Vec<Pos>> {
+        self.batch().get_leaf_positions(leaf)
+    }
+
+    /// Same as `set_leaf_index(true)`, indexing only the first occurrence of a leaf, for MMRs
+    /// whose leaves are unique anyway or where the first position is all that matters. Every
+    /// push looks the leaf up first.
+    pub fn set_leaf_index_first(&mut self) {
+        self.leaf_index = true;
+        self.leaf_index_first = Some(is_indexed::<T, S>);
+    }
+}
+
+fn is_indexed<T: Clone + PartialEq, S: MMRStoreReadOps<T>>(
+    batch: &MMRBatch<T, S>,
+    leaf: &T,
+) -> Result<bool> {
+    Ok(!batch.get_leaf_positions(leaf)?.is_empty())
+}
+
+impl<T: Clone, M, S: MMRStoreReadOps<T>> MMR<T, M, S> {
+    /// Index the leaf pushed at `leaf_pos`.
+    pub(crate) fn index_leaf(&mut self, leaf_pos: Pos) -> Result<()> {
+        if !self.leaf_index {
+            return Ok(());
+        }
+        let leaf = self
+            .batch()
+            .get_elem(leaf_pos)?
+            .ok_or(Error::InconsistentStore { pos: leaf_pos })?;
+        if let Some(is_indexed) = self.leaf_index_first {
+            if is_indexed(self.batch(), &leaf)? {
+                return Ok(());
+            }
+        }
+        self.batch_mut().append_leaf_index(leaf_pos, leaf);
+        Ok(())
+    }
+}
@@ -4,18 +4,88 @@ pub mod ancestry_proof;
 mod error;
 pub mod helper;
 mod merge;
+pub mod metrics;
 mod mmr;
-mod mmr_store;
-#[cfg(test)]
-mod tests;
-pub mod util;
+pub mod raw;
+pub mod types;
+pub mod verifier;
 
-pub use ancestry_proof::{AncestryProof, NodeMerkleProof};
+pub use ancestry_proof::{
+    verify_peaks, AncestryProof, MerkleProofRef, NodeMerkleProof, NodePolicy, UpdateProof,
+};
 pub use error::{Error, Result};
-pub use helper::{leaf_index_to_mmr_size, leaf_index_to_pos};
-pub use merge::Merge;
-pub use mmr::{MerkleProof, MMR};
-pub use mmr_store::{MMRStoreReadOps, MMRStoreWriteOps};
+pub use helper::{leaf_index_to_mmr_size, leaf_index_to_pos, Pos, PositionScheme, PostOrderScheme};
+pub use merge::{
+    CommutativeMerge, DualMerge, KaryMerge, LeafHasher, Merge, PackedMerge, TombstoneMerge,
+};
+#[cfg(feature = "rkyv")]
+pub use mmr::ArchivedMerkleProof;
+pub use mmr::{
+    proof_positions, verify_proof_streaming, verify_with_peaks, MerkleProof, ProofSource,
+    VerifyLimits,
+};
+pub use types::{LeafIndex, MmrSize, NodePosition};
+
+// the prover, the stores and the other proof formats, left out by `verify-only`
+cfg_if::cfg_if! {
+    if #[cfg(not(feature = "verify-only"))] {
+        pub mod authenticated_log;
+        pub mod bagging_trace;
+        pub mod batch_verifier;
+        pub mod commutative_proof;
+        pub mod compact_proof;
+        pub mod dual_proof;
+        pub mod dump;
+        pub mod fixed;
+        pub mod forest;
+        pub mod import;
+        pub mod interop;
+        pub mod kary;
+        #[cfg(feature = "leaf-index")]
+        pub mod leaf_index;
+        pub mod mmr_forest;
+        mod mmr_store;
+        pub mod packed;
+        pub mod proof_layout;
+        #[cfg(feature = "root-log")]
+        pub mod root_log;
+        #[cfg(feature = "std")]
+        pub mod snapshot;
+        #[cfg(feature = "testing")]
+        pub mod testing;
+        #[cfg(test)]
+        mod tests;
+        pub mod tombstone;
+        pub mod transcript;
+        pub mod util;
+        pub mod validating_mmr;
+        pub mod versioned_store;
+        pub mod witness;
+
+        pub use authenticated_log::{AuthenticatedLog, LogProof, SeqNo};
+        pub use bagging_trace::BaggingTrace;
+        pub use batch_verifier::BatchVerifier;
+        pub use commutative_proof::CommutativeMerkleProof;
+        pub use compact_proof::CompactMerkleProof;
+        pub use dual_proof::DualMerkleProof;
+        pub use dump::{MmrDump, NodeDisplay};
+        pub use fixed::{verify_fixed, FixedHash};
+        pub use forest::{ForestBuilder, Shard};
+        pub use kary::{KaryMMR, KaryMerkleProof};
+        pub use mmr::{ProofArena, PushResult, MMR};
+        pub use mmr_forest::{MmrForest, MmrForestProof};
+        pub use mmr_store::{MMRStoreDeleteOps, MMRStoreReadOps, MMRStoreWriteOps};
+        pub use packed::{PackedItemProof, PackedMMR};
+        pub use proof_layout::ProofLayout;
+        #[cfg(feature = "root-log")]
+        pub use root_log::RootLog;
+        pub use tombstone::LivenessProof;
+        pub use transcript::{MergeStep, Transcript};
+        pub use validating_mmr::ValidatingMmr;
+        pub use versioned_store::{PinnedStore, VersionedStore};
+        pub use witness::{verify_witness, LeafWitness, MmrWitness};
+    }
+}
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "std")] {
@@ -1,11 +1,97 @@
-use crate::Result;
+use crate::{Error, Pos};
+use core::result::Result;
 
 pub trait Merge {
     type Item;
+    /// Failures of the hash backend, e.g. a custom HSM or arithmetic error, converted into
+    /// `crate::Error` once they surface from a push or a proof.
+    type Error: Into<Error>;
 
-    fn merge(left: &Self::Item, right: &Self::Item) -> Result<Self::Item>;
+    fn merge(left: &Self::Item, right: &Self::Item) -> Result<Self::Item, Self::Error>;
 
-    fn merge_peaks(peak1: &Self::Item, peak2: &Self::Item) -> Result<Self::Item> {
+    /// Merge two siblings into their parent at `parent_pos`.
+    ///
+    /// Used for every node inside a mountain, both when pushing and when verifying proofs, so
+    /// implementations can commit to the node position. Defaults to `merge`.
+    fn merge_with_pos(
+        parent_pos: Pos,
+        left: &Self::Item,
+        right: &Self::Item,
+    ) -> Result<Self::Item, Self::Error> {
+        let _ = parent_pos;
+        Self::merge(left, right)
+    }
+
+    fn merge_peaks(peak1: &Self::Item, peak2: &Self::Item) -> Result<Self::Item, Self::Error> {
         Self::merge(peak1, peak2)
     }
+
+    /// The root of an MMR without leaves, e.g. the hash of an empty string.
+    ///
+    /// If defined, `MMR::get_root` returns it instead of failing with `GetRootOnEmpty`, and an
+    /// empty proof of no leaves verifies against it. Defaults to `None`.
+    fn empty_root() -> Option<Self::Item> {
+        None
+    }
+}
+
+/// Marker for merges where the order of the two items doesn't matter, e.g. hashing the sorted
+/// pair, as used by some bridge designs.
+///
+/// Implementors guarantee that `merge(a, b) == merge(b, a)` and that `merge_with_pos` and
+/// `merge_peaks` don't differ from `merge`, which lets proofs drop everything positional, see
+/// `CommutativeMerkleProof`.
+pub trait CommutativeMerge: Merge {}
+
+/// Merges that also hash raw leaf payloads, so applications can push the payloads with
+/// `MMR::push_raw` instead of hashing them externally.
+///
+/// Implementations should domain separate leaf hashes from node hashes, e.g. with a prefix byte,
+/// so that no leaf can pass for an interior node.
+pub trait LeafHasher: Merge {
+    fn hash_leaf(leaf: &[u8]) -> Result<Self::Item, Self::Error>;
+}
+
+/// Merges of `ARITY` children at once, e.g. a Poseidon hash with cheap wide inputs, building
+/// the mountains of a `KaryMMR`, see `kary`. The peaks are still bagged pairwise with
+/// `merge_peaks`.
+pub trait KaryMerge: Merge {
+    /// Number of children of every parent, at least 2.
+    const ARITY: usize;
+
+    /// Merge the `ARITY` children of a parent, from left to right.
+    fn merge_children(children: &[Self::Item]) -> Result<Self::Item, Self::Error>;
+}
+
+/// Merges that also pack several application items into one leaf, see `packed`.
+///
+/// Implementations should domain separate the nodes within a leaf from the MMR nodes, e.g. with
+/// a prefix byte, so that no packed item can pass for an MMR node or the other way around.
+pub trait PackedMerge: Merge {
+    /// Merge two nodes of the binary tree within a leaf.
+    fn merge_packed(left: &Self::Item, right: &Self::Item) -> Result<Self::Item, Self::Error>;
+}
+
+/// Merges whose leaves can be tombstones, which mark an earlier leaf as deleted, see
+/// `tombstone`.
+///
+/// The leaves have to tell tombstones apart from other leaves, e.g. by a tag byte followed by
+/// the index of the deleted leaf.
+pub trait TombstoneMerge: Merge {
+    /// The tombstone of the leaf at `leaf_index`.
+    fn tombstone(leaf_index: Pos) -> Result<Self::Item, Self::Error>;
+
+    /// The index of the leaf `item` marks as deleted, `None` unless it is a tombstone.
+    fn tombstoned(item: &Self::Item) -> Option<Pos>;
+}
+
+/// Merges whose leaves commit to an auxiliary digest besides their hash, e.g. a keccak hash for
+/// EVM verifiers next to the blake2 hash used natively, see `dual_proof`.
+///
+/// Only the leaf nodes carry the aux digest, the parents merge as usual.
+pub trait DualMerge: Merge {
+    type Aux;
+
+    /// The leaf node committing to the leaf `hash` and its `aux` digest.
+    fn merge_leaf(hash: &Self::Item, aux: &Self::Aux) -> Result<Self::Item, Self::Error>;
 }
@@ -0,0 +1,87 @@
+//! Instrumentation hooks
+//!
+//! With the `metrics` feature enabled, a process wide `Metrics` implementation can be installed
+//! via `set_metrics`, which the crate then feeds with counters. Without the feature the hooks
+//! compile to nothing.
+//!
+//! With the `tracing` feature, the counters are emitted as `tracing` events as well, inside
+//! spans around pushes, commits, proof generation and root calculation, along with the peak
+//! counts and the number of nodes per level. Services can then diagnose hot paths with their
+//! usual subscriber instead of a `Metrics` sink.
+
+/// Receives counters from the MMR, all methods default to doing nothing.
+pub trait Metrics: Sync {
+    /// Round-trips to the backing store.
+    fn store_reads(&self, _count: u64) {}
+
+    /// Merges performed while pushing elements or bagging peaks.
+    fn merges(&self, _count: u64) {}
+
+    /// Items emitted in a generated proof.
+    fn proof_items(&self, _count: u64) {}
+
+    /// Iterations spent calculating peak roots during verification.
+    fn verification_steps(&self, _count: u64) {}
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "metrics")] {
+        static METRICS: std::sync::OnceLock<&'static dyn Metrics> = std::sync::OnceLock::new();
+
+        /// Install the metrics sink, this can only be done once per process.
+        ///
+        /// Returns the given sink back if one was already installed.
+        pub fn set_metrics(metrics: &'static dyn Metrics) -> Result<(), &'static dyn Metrics> {
+            METRICS.set(metrics)
+        }
+
+        #[inline]
+        fn metrics() -> Option<&'static dyn Metrics> {
+            METRICS.get().copied()
+        }
+    } else {
+        #[inline]
+        fn metrics() -> Option<&'static dyn Metrics> {
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[inline]
+pub(crate) fn on_store_reads(count: u64) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(count, "store reads");
+    if let Some(metrics) = metrics() {
+        metrics.store_reads(count)
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[inline]
+pub(crate) fn on_merges(count: u64) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(count, "merges");
+    if let Some(metrics) = metrics() {
+        metrics.merges(count)
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[inline]
+pub(crate) fn on_proof_items(count: u64) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(count, "proof items");
+    if let Some(metrics) = metrics() {
+        metrics.proof_items(count)
+    }
+}
+
+#[inline]
+pub(crate) fn on_verification_steps(count: u64) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(count, "verification steps");
+    if let Some(metrics) = metrics() {
+        metrics.verification_steps(count)
+    }
+}
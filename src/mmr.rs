@@ -4,70 +4,309 @@
 //! https://github.com/mimblewimble/grin/blob/master/doc/mmr.md#structure
 //! https://github.com/mimblewimble/grin/blob/0ff6763ee64e5a14e70ddd4642b99789a1648a32/core/src/core/pmmr.rs#L606
 
-use crate::ancestry_proof::{AncestryProof, NodeMerkleProof};
+#[cfg(not(feature = "verify-only"))]
+use crate::ancestry_proof::{AncestryProof, NodeMerkleProof, UpdateProof};
+#[cfg(not(feature = "verify-only"))]
 use crate::borrow::Cow;
-use crate::collections::VecDeque;
+use crate::collections::{BTreeMap, VecDeque};
+#[cfg(not(feature = "verify-only"))]
+use crate::compact_proof::{CompactMerkleProof, DescriptorWriter};
 use crate::helper::{
-    get_peak_map, get_peaks, leaf_index_to_mmr_size, leaf_index_to_pos, parent_offset,
-    pos_height_in_tree, sibling_offset,
+    get_peak_map, get_peaks, is_valid_mmr_size, iter_peaks, leaf_index_to_mmr_size,
+    leaf_index_to_pos, pos_height_in_tree, PositionScheme, PostOrderScheme,
 };
-use crate::mmr_store::{MMRBatch, MMRStoreReadOps, MMRStoreWriteOps};
-use crate::util::VeqDequeExt;
+#[cfg(not(feature = "verify-only"))]
+use crate::helper::{leaf_count_to_mmr_size, VeqDequeExt};
+#[cfg(not(feature = "verify-only"))]
+use crate::mmr_store::{MMRBatch, MMRStoreDeleteOps, MMRStoreReadOps, MMRStoreWriteOps};
 use crate::vec;
 use crate::vec::Vec;
-use crate::{Error, Merge, Result};
+use crate::verifier::{
+    self, bagging_peaks_hashes, calculate_peak_root_from_iter, calculate_peaks_hashes,
+    calculate_root_from_iter, push_to_peaks, sort_and_dedup_by_pos, take_while_vec, Iterations,
+};
+use crate::{metrics, Error, LeafIndex, Merge, MmrSize, Pos, Result};
+#[cfg(not(feature = "verify-only"))]
+use crate::{string::ToString, LeafHasher, NodePosition};
+#[cfg(not(feature = "verify-only"))]
+use core::cell::RefCell;
+use core::convert::TryFrom;
 use core::fmt::Debug;
 use core::marker::PhantomData;
+#[cfg(not(feature = "verify-only"))]
+use core::ops::Range;
+#[cfg(not(feature = "verify-only"))]
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Which state proofs and roots are generated from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofSource {
+    /// Only elements persisted via `commit`.
+    CommittedOnly,
+    /// Committed elements and the pending pushes in the batch.
+    IncludePending,
+}
+
+/// Bounds on the work done by the `verify_with_limits` functions of the proofs, for verifiers
+/// that must not be kept busy by adversarial proofs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyLimits {
+    /// Maximum number of proof items.
+    pub max_proof_items: usize,
+    /// Maximum number of leaves to verify.
+    pub max_nodes: usize,
+    /// Maximum number of steps taken while calculating the peak roots.
+    pub max_iterations: u64,
+}
+
+impl Default for VerifyLimits {
+    fn default() -> Self {
+        // enough for 1024 leaves in an MMR of height 64
+        VerifyLimits {
+            max_proof_items: 64 * 1024,
+            max_nodes: 1024,
+            max_iterations: 65 * 1024,
+        }
+    }
+}
+
+impl VerifyLimits {
+    /// Check the proof and node counts, returning the iterations left for the peak roots.
+    pub(crate) fn iterations(&self, proof_items: usize, nodes: usize) -> Result<Iterations> {
+        if proof_items > self.max_proof_items || nodes > self.max_nodes {
+            return Err(Error::LimitExceeded);
+        }
+        Ok(Iterations(self.max_iterations))
+    }
+}
+
+/// Buffers reused across proof generations, so a server generating many proofs doesn't
+/// allocate the positions, queues and items of every proof anew, see `MMR::gen_proof_in`.
+///
+/// The proof items move into the returned proof, hand the proof back with `recycle` once it
+/// is sent to reuse them as well.
+#[cfg(not(feature = "verify-only"))]
+#[derive(Debug)]
+pub struct ProofArena<T> {
+    leaves: Vec<Pos>,
+    proof_pos: Vec<Pos>,
+    queue: VecDeque<(Pos, u8)>,
+    sorted_pos: Vec<Pos>,
+    elems: Vec<(Pos, T)>,
+    items: Vec<T>,
+}
 
+#[cfg(not(feature = "verify-only"))]
+impl<T> ProofArena<T> {
+    pub fn new() -> Self {
+        ProofArena {
+            leaves: Vec::new(),
+            proof_pos: Vec::new(),
+            queue: VecDeque::new(),
+            sorted_pos: Vec::new(),
+            elems: Vec::new(),
+            items: Vec::new(),
+        }
+    }
+
+    /// Take back the items of a `proof` generated with this arena.
+    pub fn recycle<M>(&mut self, proof: MerkleProof<T, M>) {
+        let mut items = proof.into_proof_items();
+        if items.capacity() > self.items.capacity() {
+            items.clear();
+            self.items = items;
+        }
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+impl<T> Default for ProofArena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The nodes added by `MMR::push_detailed`.
+#[cfg(not(feature = "verify-only"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushResult<T> {
+    /// Position of the pushed leaf.
+    pub leaf_pos: Pos,
+    /// Positions and hashes of the parents merged above the leaf, from the bottom up.
+    pub parents: Vec<(Pos, T)>,
+    /// Positions of the peaks after the push, from left to right.
+    pub peaks: Vec<Pos>,
+}
+
+#[cfg(not(feature = "verify-only"))]
 #[allow(clippy::upper_case_acronyms)]
 pub struct MMR<T, M, S> {
-    mmr_size: u64,
+    mmr_size: Pos,
     batch: MMRBatch<T, S>,
+    #[cfg(feature = "root-log")]
+    pub(crate) root_log: Option<crate::root_log::RootLog>,
+    #[cfg(feature = "leaf-index")]
+    pub(crate) leaf_index: bool,
+    #[cfg(feature = "leaf-index")]
+    pub(crate) leaf_index_first: Option<crate::leaf_index::IsIndexed<T, S>>,
+    auto_commit: Option<usize>,
+    /// The arena of `gen_proof_with_scratch`, only borrowed through `&mut self` so the MMR
+    /// stays `Sync`.
+    scratch: ProofArena<T>,
     merge: PhantomData<M>,
 }
 
+#[cfg(not(feature = "verify-only"))]
 impl<T, M, S> MMR<T, M, S> {
-    pub fn new(mmr_size: u64, store: S) -> Self {
+    pub fn new(mmr_size: Pos, store: S) -> Self {
+        Self::with_batch(mmr_size, MMRBatch::new(store))
+    }
+
+    /// Same as `new`, typed, see `types`.
+    pub fn with_size(mmr_size: MmrSize, store: S) -> Self {
+        Self::new(mmr_size.get(), store)
+    }
+
+    /// Same as `new`, preallocating the batch for `capacity` pushes, see `MMRBatch::with_capacity`.
+    pub fn with_capacity(mmr_size: Pos, store: S, capacity: usize) -> Self {
+        Self::with_batch(mmr_size, MMRBatch::with_capacity(store, capacity))
+    }
+
+    fn with_batch(mmr_size: Pos, batch: MMRBatch<T, S>) -> Self {
         MMR {
             mmr_size,
-            batch: MMRBatch::new(store),
+            batch,
+            #[cfg(feature = "root-log")]
+            root_log: None,
+            #[cfg(feature = "leaf-index")]
+            leaf_index: false,
+            #[cfg(feature = "leaf-index")]
+            leaf_index_first: None,
+            auto_commit: None,
+            scratch: ProofArena::new(),
             merge: PhantomData,
         }
     }
 
-    pub fn mmr_size(&self) -> u64 {
+    /// Commit from `push_auto_commit` once the batch holds `threshold` pending elements, `None`
+    /// to only commit explicitly, the default.
+    pub fn set_auto_commit(&mut self, threshold: Option<usize>) {
+        self.auto_commit = threshold;
+    }
+
+    pub fn auto_commit(&self) -> Option<usize> {
+        self.auto_commit
+    }
+
+    pub fn mmr_size(&self) -> Pos {
         self.mmr_size
     }
 
+    /// Same as `mmr_size`, typed, see `types`.
+    pub fn size(&self) -> MmrSize {
+        MmrSize::new_unchecked(self.mmr_size)
+    }
+
     pub fn is_empty(&self) -> bool {
         self.mmr_size == 0
     }
 
+    /// Number of leaves pushed to the MMR.
+    pub fn leaves_count(&self) -> Pos {
+        // the peak map doubles as the leaves count, since a peak of height h holds 2^h leaves
+        self.peak_map()
+    }
+
+    /// Heights of the peaks as a bitmap, see `helper::get_peak_map`.
+    pub fn peak_map(&self) -> Pos {
+        get_peak_map(self.mmr_size)
+    }
+
+    /// Size of the MMR as persisted in the store, excluding pending pushes.
+    pub fn committed_mmr_size(&self) -> Pos {
+        self.batch.pending_start().unwrap_or(self.mmr_size)
+    }
+
     pub fn batch(&self) -> &MMRBatch<T, S> {
         &self.batch
     }
 
+    pub(crate) fn batch_mut(&mut self) -> &mut MMRBatch<T, S> {
+        &mut self.batch
+    }
+
     pub fn store(&self) -> &S {
         self.batch.store()
     }
 }
 
-impl<T: Clone + PartialEq, M: Merge<Item = T>, S: MMRStoreReadOps<T>> MMR<T, M, S> {
+#[cfg(not(feature = "verify-only"))]
+impl<T, M, S: MMRStoreReadOps<T>> MMR<T, M, S> {
+    /// Reopen the MMR persisted in `store`, restoring the size recorded by the last commit.
+    ///
+    /// A store that doesn't track the size, or never had a commit, opens as an empty MMR.
+    pub fn open(store: S) -> Result<Self> {
+        let mmr_size = store.mmr_size()?.unwrap_or(0);
+        if !is_valid_mmr_size(mmr_size) {
+            return Err(Error::InvalidMMRSize { mmr_size });
+        }
+        Ok(MMR::new(mmr_size, store))
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+impl<T: Clone, M: Merge<Item = T>, S: MMRStoreReadOps<T>> MMR<T, M, S> {
     // find internal MMR elem, the pos must exists, otherwise a error will return
-    fn find_elem<'b>(&self, pos: u64, hashes: &'b [T]) -> Result<Cow<'b, T>> {
+    fn find_elem<'b>(&self, pos: Pos, hashes: &'b [T]) -> Result<Cow<'b, T>> {
         let pos_offset = pos.checked_sub(self.mmr_size);
         if let Some(elem) = pos_offset.and_then(|i| hashes.get(i as usize)) {
             return Ok(Cow::Borrowed(elem));
         }
-        let elem = self.batch.get_elem(pos)?.ok_or(Error::InconsistentStore)?;
+        let elem = self
+            .batch
+            .get_elem(pos)?
+            .ok_or(Error::InconsistentStore { pos })?;
         Ok(Cow::Owned(elem))
     }
 
     // push a element and return position
-    pub fn push(&mut self, elem: T) -> Result<u64> {
+    pub fn push(&mut self, elem: T) -> Result<Pos> {
+        self.push_with(elem, |_| ())
+    }
+
+    /// Same as `push`, returning the typed position of the leaf.
+    pub fn push_leaf(&mut self, elem: T) -> Result<NodePosition> {
+        self.push(elem).map(NodePosition::new)
+    }
+
+    /// Same as `push`, also returning the parents merged above the leaf and the new peaks, e.g.
+    /// for an indexer mirroring the nodes into its own database.
+    pub fn push_detailed(&mut self, elem: T) -> Result<PushResult<T>> {
+        let leaf_pos = self.mmr_size;
+        let mut parents = Vec::new();
+        self.push_with(elem, |elems| {
+            // the parents follow the leaf
+            parents.extend((leaf_pos + 1..).zip(elems[1..].iter().cloned()))
+        })?;
+        Ok(PushResult {
+            leaf_pos,
+            parents,
+            peaks: get_peaks(self.mmr_size),
+        })
+    }
+
+    /// Push `elem`, handing the leaf and its new parents to `inspect` before they are batched.
+    fn push_with(&mut self, elem: T, inspect: impl FnOnce(&[T])) -> Result<Pos> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("mmr_push", mmr_size = self.mmr_size).entered();
+        if cfg!(feature = "huge")
+            && leaf_count_to_mmr_size(self.leaves_count().saturating_add(1)).is_none()
+        {
+            return Err(Error::PositionOverflow);
+        }
         let mut elems = vec![elem];
         let elem_pos = self.mmr_size;
-        let peak_map = get_peak_map(self.mmr_size);
+        let peak_map = self.peak_map();
         let mut pos = self.mmr_size;
         let mut peak = 1;
         while (peak_map & peak) != 0 {
@@ -76,141 +315,299 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>, S: MMRStoreReadOps<T>> MMR<T, M,
             let left_pos = pos - peak;
             let left_elem = self.find_elem(left_pos, &elems)?;
             let right_elem = elems.last().expect("checked");
-            let parent_elem = M::merge(&left_elem, right_elem)?;
+            let parent_elem = M::merge_with_pos(pos, &left_elem, right_elem).map_err(Into::into)?;
             elems.push(parent_elem);
         }
+        metrics::on_merges(elems.len() as u64 - 1);
+        inspect(&elems);
         // store hashes
         self.batch.append(elem_pos, elems);
         // update mmr_size
         self.mmr_size = pos + 1;
+        #[cfg(feature = "leaf-index")]
+        self.index_leaf(elem_pos)?;
+        #[cfg(feature = "root-log")]
+        self.log_root()?;
         Ok(elem_pos)
     }
 
+    /// Metadata of the leaf at `leaf_index`, including uncommitted pushes.
+    pub fn get_leaf_meta(&self, leaf_index: Pos) -> Result<Option<Vec<u8>>> {
+        self.batch.get_leaf_meta(leaf_index)
+    }
+
     /// get_root
     pub fn get_root(&self) -> Result<T> {
         if self.mmr_size == 0 {
-            return Err(Error::GetRootOnEmpty);
+            return M::empty_root().ok_or(Error::GetRootOnEmpty);
         } else if self.mmr_size == 1 {
-            return self.batch.get_elem(0)?.ok_or(Error::InconsistentStore);
+            return self
+                .batch
+                .get_elem(0)?
+                .ok_or(Error::InconsistentStore { pos: 0 });
         }
-        let peaks: Vec<T> = get_peaks(self.mmr_size)
-            .into_iter()
-            .map(|peak_pos| {
-                self.batch
-                    .get_elem(peak_pos)
-                    .and_then(|elem| elem.ok_or(Error::InconsistentStore))
-            })
-            .collect::<Result<Vec<T>>>()?;
-        self.bag_rhs_peaks(peaks)?.ok_or(Error::InconsistentStore)
+        let peaks: Vec<T> = self.peaks()?.into_iter().map(|(_pos, peak)| peak).collect();
+        self.bag_rhs_peaks(peaks)?.ok_or(Error::InvalidMMRSize {
+            mmr_size: self.mmr_size,
+        })
+    }
+
+    /// Root of the MMR padded with `empty_leaf`s up to the next power of two leaves count.
+    ///
+    /// This is the root of a classic perfect binary merkle tree over the leaves, for verifiers
+    /// that only support those. Fully empty subtrees are hashed with `Merge::merge`, so their
+    /// roots don't depend on the position and can be precomputed by the verifier.
+    pub fn get_root_padded(&self, empty_leaf: T) -> Result<T> {
+        if self.mmr_size == 0 {
+            return Err(Error::GetRootOnEmpty);
+        }
+        let invalid_size = Error::InvalidMMRSize {
+            mmr_size: self.mmr_size,
+        };
+        let target_height = self.leaves_count().next_power_of_two().trailing_zeros() as u8;
+        let mut peaks = self.peaks()?;
+        // climb from the rightmost peak, which is the only one with padding to its right
+        let (mut pos, mut root) = peaks.pop().ok_or_else(|| invalid_size.clone())?;
+        let mut height = pos_height_in_tree(pos);
+        let mut empty_root = empty_leaf;
+        for _ in 0..height {
+            empty_root = M::merge(&empty_root, &empty_root).map_err(Into::into)?;
+        }
+        while height < target_height {
+            let (_, parent_pos, is_right) = PostOrderScheme::sibling_and_parent_pos(pos, height);
+            pos = parent_pos;
+            if is_right {
+                // the left sibling is the next peak
+                let (_, left) = peaks.pop().ok_or_else(|| invalid_size.clone())?;
+                root = M::merge_with_pos(pos, &left, &root).map_err(Into::into)?;
+            } else {
+                root = M::merge_with_pos(pos, &root, &empty_root).map_err(Into::into)?;
+            }
+            empty_root = M::merge(&empty_root, &empty_root).map_err(Into::into)?;
+            height += 1;
+        }
+        if !peaks.is_empty() {
+            return Err(invalid_size);
+        }
+        Ok(root)
+    }
+
+    /// Like `get_root`, but `ProofSource::CommittedOnly` returns the root of the committed state.
+    pub fn get_root_with_source(&self, source: ProofSource) -> Result<T> {
+        match source {
+            ProofSource::IncludePending => self.get_root(),
+            ProofSource::CommittedOnly => self.committed().get_root(),
+        }
+    }
+
+    /// Positions and hashes of the peaks from left to right, including uncommitted pushes.
+    pub fn peaks(&self) -> Result<Vec<(Pos, T)>> {
+        let peaks_pos = get_peaks(self.mmr_size);
+        let peaks = self.get_elems_in_runs(&peaks_pos)?;
+        Ok(peaks_pos.into_iter().zip(peaks).collect())
+    }
+
+    /// The position `elem` would get from `push` and the root after pushing it, calculated from
+    /// the peaks without touching the batch, e.g. to compare the roots of candidate leaves.
+    pub fn simulate_push(&self, elem: &T) -> Result<(Pos, T)> {
+        let mut peaks = self.peaks()?;
+        push_to_peaks::<_, M>(&mut peaks, self.mmr_size, elem.clone())?;
+        let root =
+            bagging_peaks_hashes::<_, M>(peaks.into_iter().map(|(_pos, peak)| peak).collect())?;
+        Ok((self.mmr_size, root))
+    }
+
+    /// The authentication path of the leaf at `pos` within its mountain: the positions and
+    /// hashes of its siblings from the leaf up to the peak, without any other peak.
+    ///
+    /// Verify it with `verifier::calculate_peak_root` against the peak from `peaks`.
+    pub fn path_to_peak(&self, mut pos: Pos) -> Result<Vec<(Pos, T)>> {
+        if pos >= self.mmr_size || pos_height_in_tree(pos) > 0 {
+            return Err(Error::GenProofForInvalidLeaves);
+        }
+        let peak_pos = iter_peaks(self.mmr_size)
+            .find(|peak_pos| *peak_pos >= pos)
+            .expect("pos is below mmr_size");
+        let mut siblings = Vec::new();
+        let mut height = 0;
+        while pos < peak_pos {
+            let (sib_pos, parent_pos, _) = PostOrderScheme::sibling_and_parent_pos(pos, height);
+            siblings.push(sib_pos);
+            pos = parent_pos;
+            height += 1;
+        }
+        let hashes = self.get_elems_in_runs(&siblings)?;
+        Ok(siblings.into_iter().zip(hashes).collect())
+    }
+
+    /// Fetch the elements at `positions`, reading each run of consecutive positions with a single
+    /// `get_elems` call. The elements are returned in the order of `positions`.
+    fn get_elems_in_runs(&self, positions: &[Pos]) -> Result<Vec<T>> {
+        self.get_elems_in_runs_with(positions, false)
+    }
+
+    /// Same as `get_elems_in_runs`, but with `report_missing` a missing element doesn't fail
+    /// right away, all of them are returned as `MissingNodes` instead.
+    fn get_elems_in_runs_with(&self, positions: &[Pos], report_missing: bool) -> Result<Vec<T>> {
+        let mut elems = Vec::with_capacity(positions.len());
+        self.get_elems_in_runs_into(
+            positions,
+            report_missing,
+            None,
+            &mut Vec::new(),
+            &mut Vec::new(),
+            &mut elems,
+        )?;
+        Ok(elems)
+    }
+
+    /// Same as `get_elems_in_runs_with`, appending the elements to `out` and working in the
+    /// `sorted_positions` and `elems` buffers. Fails with `Cancelled` before reading a run once
+    /// `cancel` is set.
+    fn get_elems_in_runs_into(
+        &self,
+        positions: &[Pos],
+        report_missing: bool,
+        cancel: Option<&AtomicBool>,
+        sorted_positions: &mut Vec<Pos>,
+        elems: &mut Vec<(Pos, T)>,
+        out: &mut Vec<T>,
+    ) -> Result<()> {
+        sorted_positions.clear();
+        sorted_positions.extend_from_slice(positions);
+        sorted_positions.sort_unstable();
+        sorted_positions.dedup();
+
+        elems.clear();
+        elems.reserve(sorted_positions.len());
+        let mut missing = Vec::new();
+        let mut run_start = 0;
+        for i in 0..sorted_positions.len() {
+            let run_end = sorted_positions[i] + 1;
+            if sorted_positions.get(i + 1) == Some(&run_end) {
+                continue;
+            }
+            check_cancelled(cancel)?;
+            let start_pos = sorted_positions[run_start];
+            for (offset, elem) in self
+                .batch
+                .get_elems(start_pos..run_end)?
+                .into_iter()
+                .enumerate()
+            {
+                let pos = start_pos + offset as Pos;
+                match elem {
+                    Some(elem) => elems.push((pos, elem)),
+                    None if report_missing => missing.push(pos),
+                    None => return Err(Error::InconsistentStore { pos }),
+                }
+            }
+            run_start = i + 1;
+        }
+        if !missing.is_empty() {
+            return Err(Error::MissingNodes { positions: missing });
+        }
+
+        out.reserve(positions.len());
+        for pos in positions {
+            let i = elems
+                .binary_search_by_key(pos, |(pos, _)| *pos)
+                .map_err(|_| Error::InconsistentStore { pos: *pos })?;
+            out.push(elems[i].1.clone());
+        }
+        elems.clear();
+        Ok(())
     }
 
     /// get_ancestor_root
-    pub fn get_ancestor_peaks_and_root(&self, prev_mmr_size: u64) -> Result<(Vec<T>, T)> {
+    pub fn get_ancestor_peaks_and_root(&self, prev_mmr_size: Pos) -> Result<(Vec<T>, T)> {
         if self.mmr_size == 0 {
             return Err(Error::GetRootOnEmpty);
         } else if self.mmr_size == 1 && prev_mmr_size == 1 {
-            let singleton = self.batch.get_elem(0)?.ok_or(Error::InconsistentStore);
+            let singleton = self
+                .batch
+                .get_elem(0)?
+                .ok_or(Error::InconsistentStore { pos: 0 });
             match singleton {
                 Ok(singleton) => return Ok((vec![singleton.clone()], singleton)),
                 Err(e) => return Err(e),
             }
         } else if prev_mmr_size > self.mmr_size {
-            return Err(Error::AncestorRootNotPredecessor);
+            return Err(Error::AncestorRootNotPredecessor {
+                prev_mmr_size,
+                mmr_size: self.mmr_size,
+            });
         }
         let peaks: Result<Vec<T>> = get_peaks(prev_mmr_size)
             .into_iter()
             .map(|peak_pos| {
                 self.batch
                     .get_elem(peak_pos)
-                    .and_then(|elem| elem.ok_or(Error::InconsistentStore))
+                    .and_then(|elem| elem.ok_or(Error::InconsistentStore { pos: peak_pos }))
             })
             .collect::<Result<Vec<T>>>();
         match peaks {
             Ok(peaks) => {
                 let root = self
                     .bag_rhs_peaks(peaks.clone())?
-                    .ok_or(Error::InconsistentStore)?;
-                return Ok((peaks, root));
+                    .ok_or(Error::InvalidMMRSize {
+                        mmr_size: prev_mmr_size,
+                    })?;
+                Ok((peaks, root))
             }
             Err(e) => Err(e),
         }
     }
 
-    fn bag_rhs_peaks(&self, mut rhs_peaks: Vec<T>) -> Result<Option<T>> {
-        while rhs_peaks.len() > 1 {
-            let right_peak = rhs_peaks.pop().expect("pop");
-            let left_peak = rhs_peaks.pop().expect("pop");
-            rhs_peaks.push(M::merge_peaks(&right_peak, &left_peak)?);
-        }
-        Ok(rhs_peaks.pop())
-    }
-
-    /// generate merkle proof for a peak
-    /// the pos_list must be sorted, otherwise the behaviour is undefined
+    /// The roots the MMR had at each of the `sizes`, e.g. to serve the historical roots of a
+    /// range of blocks.
     ///
-    /// 1. find a lower tree in peak that can generate a complete merkle proof for position
-    /// 2. find that tree by compare positions
-    /// 3. generate proof for each positions
-    fn gen_proof_for_peak(
-        &self,
-        proof: &mut Vec<T>,
-        pos_list: Vec<u64>,
-        peak_pos: u64,
-    ) -> Result<()> {
-        // do nothing if position itself is the peak
-        if pos_list.len() == 1 && pos_list == [peak_pos] {
-            return Ok(());
-        }
-        // take peak root from store if no positions need to be proof
-        if pos_list.is_empty() {
-            proof.push(
-                self.batch
-                    .get_elem(peak_pos)?
-                    .ok_or(Error::InconsistentStore)?,
-            );
-            return Ok(());
+    /// The peaks of all sizes are read together, runs of adjacent positions with a single
+    /// `get_elems` call and peaks shared by several sizes once. Fails with `InvalidMMRSize` or
+    /// `AncestorRootNotPredecessor` for sizes that are not a past size of the MMR, and with
+    /// `GetRootOnEmpty` for `0` unless `Merge::empty_root` is defined.
+    pub fn get_roots_at(&self, sizes: &[Pos]) -> Result<Vec<T>> {
+        let mut peaks_pos = Vec::new();
+        for &mmr_size in sizes {
+            if !is_valid_mmr_size(mmr_size) {
+                return Err(Error::InvalidMMRSize { mmr_size });
+            }
+            if mmr_size > self.mmr_size {
+                return Err(Error::AncestorRootNotPredecessor {
+                    prev_mmr_size: mmr_size,
+                    mmr_size: self.mmr_size,
+                });
+            }
+            peaks_pos.extend(iter_peaks(mmr_size));
         }
-
-        let mut queue: VecDeque<_> = pos_list.into_iter().map(|pos| (pos, 0)).collect();
-
-        // Generate sub-tree merkle proof for positions
-        while let Some((pos, height)) = queue.pop_front() {
-            debug_assert!(pos <= peak_pos);
-            if pos == peak_pos {
-                if queue.is_empty() {
-                    break;
-                } else {
-                    return Err(Error::CorruptedProof);
+        let mut peaks = self.get_elems_in_runs(&peaks_pos)?.into_iter();
+        sizes
+            .iter()
+            .map(|&mmr_size| {
+                let size_peaks: Vec<T> = peaks
+                    .by_ref()
+                    .take(get_peak_map(mmr_size).count_ones() as usize)
+                    .collect();
+                match self.bag_rhs_peaks(size_peaks)? {
+                    Some(root) => Ok(root),
+                    None => M::empty_root().ok_or(Error::GetRootOnEmpty),
                 }
-            }
+            })
+            .collect()
+    }
 
-            // calculate sibling
-            let (sib_pos, parent_pos) = {
-                let next_height = pos_height_in_tree(pos + 1);
-                let sibling_offset = sibling_offset(height);
-                if next_height > height {
-                    // implies pos is right sibling
-                    (pos - sibling_offset, pos + 1)
-                } else {
-                    // pos is left sibling
-                    (pos + sibling_offset, pos + parent_offset(height))
-                }
-            };
+    fn bag_rhs_peaks(&self, mut rhs_peaks: Vec<T>) -> Result<Option<T>> {
+        self.bag_peaks_from(&mut rhs_peaks, 0)?;
+        Ok(rhs_peaks.pop())
+    }
 
-            if Some(&sib_pos) == queue.front().map(|(pos, _)| pos) {
-                // drop sibling
-                queue.pop_front();
-            } else {
-                proof.push(
-                    self.batch
-                        .get_elem(sib_pos)?
-                        .ok_or(Error::InconsistentStore)?,
-                );
-            }
-            if parent_pos < peak_pos {
-                // save pos to tree buf
-                queue.push_back((parent_pos, height + 1));
-            }
+    /// Bag the peaks in `items` from `start` on in place into a single item.
+    fn bag_peaks_from(&self, items: &mut Vec<T>, start: usize) -> Result<()> {
+        metrics::on_merges(items.len().saturating_sub(start + 1) as u64);
+        while items.len() > start + 1 {
+            let right_peak = items.pop().expect("pop");
+            let left_peak = items.pop().expect("pop");
+            items.push(M::merge_peaks(&right_peak, &left_peak).map_err(Into::into)?);
         }
         Ok(())
     }
@@ -222,9 +619,9 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>, S: MMRStoreReadOps<T>> MMR<T, M,
     /// 3. generate proof for each positions
     fn gen_node_proof_for_peak(
         &self,
-        proof: &mut Vec<(u64, T)>,
-        pos_list: Vec<u64>,
-        peak_pos: u64,
+        proof: &mut Vec<(Pos, T)>,
+        pos_list: Vec<Pos>,
+        peak_pos: Pos,
     ) -> Result<()> {
         // do nothing if position itself is the peak
         if pos_list.len() == 1 && pos_list == [peak_pos] {
@@ -236,7 +633,7 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>, S: MMRStoreReadOps<T>> MMR<T, M,
                 peak_pos,
                 self.batch
                     .get_elem(peak_pos)?
-                    .ok_or(Error::InconsistentStore)?,
+                    .ok_or(Error::InconsistentStore { pos: peak_pos })?,
             ));
             return Ok(());
         }
@@ -258,17 +655,7 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>, S: MMRStoreReadOps<T>> MMR<T, M,
             }
 
             // calculate sibling
-            let (sib_pos, parent_pos) = {
-                let next_height = pos_height_in_tree(pos + 1);
-                let sibling_offset = sibling_offset(height);
-                if next_height > height {
-                    // implies pos is right sibling
-                    (pos - sibling_offset, pos + 1)
-                } else {
-                    // pos is left sibling
-                    (pos + sibling_offset, pos + parent_offset(height))
-                }
-            };
+            let (sib_pos, parent_pos, _) = PostOrderScheme::sibling_and_parent_pos(pos, height);
 
             if Some(&sib_pos) == queue.front().map(|(_, pos)| pos) {
                 // drop sibling
@@ -277,8 +664,8 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>, S: MMRStoreReadOps<T>> MMR<T, M,
                 let sibling = (
                     sib_pos,
                     self.batch
-                        .get_elem(sib_pos.clone())?
-                        .ok_or(Error::InconsistentStore)?,
+                        .get_elem(sib_pos)?
+                        .ok_or(Error::InconsistentStore { pos: sib_pos })?,
                 );
 
                 proof.push(sibling);
@@ -291,74 +678,431 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>, S: MMRStoreReadOps<T>> MMR<T, M,
         Ok(())
     }
 
+    /// Like `gen_proof`, but `ProofSource::CommittedOnly` proves against the committed state,
+    /// so the proof stays valid if the pending pushes are dropped. Proving a pending leaf fails
+    /// with `PositionOutOfRange` then, and the proof's `mmr_size` is the committed one.
+    pub fn gen_proof_with_source(
+        &self,
+        pos_list: Vec<Pos>,
+        source: ProofSource,
+    ) -> Result<MerkleProof<T, M>> {
+        match source {
+            ProofSource::IncludePending => self.gen_proof(pos_list),
+            ProofSource::CommittedOnly => self.committed().gen_proof(pos_list),
+        }
+    }
+
+    /// A view of the committed state, reading the store directly.
+    fn committed(&self) -> MMR<T, M, CommittedStore<'_, S>> {
+        MMR::new(self.committed_mmr_size(), CommittedStore(self.store()))
+    }
+
     /// Generate merkle proof for positions
     /// 1. sort positions
     /// 2. push merkle proof to proof by peak from left to right
     /// 3. push bagged right hand side root
-    pub fn gen_proof(&self, mut pos_list: Vec<u64>) -> Result<MerkleProof<T, M>> {
-        if pos_list.is_empty() {
-            return Err(Error::GenProofForInvalidLeaves);
-        }
-        if self.mmr_size == 1 && pos_list == [0] {
-            return Ok(MerkleProof::new(self.mmr_size, Vec::new()));
-        }
-        if pos_list.iter().any(|pos| pos_height_in_tree(*pos) > 0) {
+    pub fn gen_proof(&self, pos_list: Vec<Pos>) -> Result<MerkleProof<T, M>> {
+        self.gen_proof_with(pos_list, false, None)
+    }
+
+    /// Same as `gen_proof`, for the leaves at `leaves` rather than positions.
+    pub fn gen_leaf_proof(&self, leaves: &[LeafIndex]) -> Result<MerkleProof<T, M>> {
+        self.gen_proof(leaves.iter().map(|leaf| leaf.to_pos().get()).collect())
+    }
+
+    /// Same as `gen_proof` for the single leaf at `pos`, walking its path to the peak directly
+    /// instead of through the queue and the sorted position buffers of a multiproof.
+    pub fn gen_single_proof(&self, mut pos: Pos) -> Result<MerkleProof<T, M>> {
+        check_in_range(self.mmr_size, &[pos])?;
+        if pos_height_in_tree(pos) > 0 {
             return Err(Error::GenProofForInvalidLeaves);
         }
-        // ensure positions are sorted and unique
-        pos_list.sort_unstable();
-        pos_list.dedup();
-        let peaks = get_peaks(self.mmr_size);
-        let mut proof: Vec<T> = Vec::new();
-        // generate merkle proof for each peaks
-        let mut bagging_track = 0;
-        for peak_pos in peaks {
-            let pos_list: Vec<_> = take_while_vec(&mut pos_list, |&pos| pos <= peak_pos);
-            if pos_list.is_empty() {
-                bagging_track += 1;
+        let get_node = |pos| {
+            self.batch
+                .get_elem(pos)?
+                .ok_or(Error::InconsistentStore { pos })
+        };
+        let peak_map = self.peak_map();
+        // peaks plus the siblings up to the highest one
+        let mut items = Vec::with_capacity(
+            peak_map.count_ones() as usize + (Pos::BITS - peak_map.leading_zeros()) as usize,
+        );
+        let mut in_rhs = false;
+        let mut rhs_peaks = 0;
+        for peak_pos in iter_peaks(self.mmr_size) {
+            if peak_pos < pos {
+                items.push(get_node(peak_pos)?);
+            } else if in_rhs {
+                items.push(get_node(peak_pos)?);
+                rhs_peaks += 1;
             } else {
-                bagging_track = 0;
+                let mut height = 0;
+                while pos < peak_pos {
+                    let (sib_pos, parent_pos, _) =
+                        PostOrderScheme::sibling_and_parent_pos(pos, height);
+                    items.push(get_node(sib_pos)?);
+                    pos = parent_pos;
+                    height += 1;
+                }
+                in_rhs = true;
             }
-            self.gen_proof_for_peak(&mut proof, pos_list, peak_pos)?;
         }
+        // bag the peaks on the right into a single item
+        if rhs_peaks > 1 {
+            let start = items.len() - rhs_peaks;
+            self.bag_peaks_from(&mut items, start)?;
+        }
+        metrics::on_proof_items(items.len() as u64);
+        Ok(MerkleProof::new(self.mmr_size, items))
+    }
 
-        // ensure no remain positions
-        if !pos_list.is_empty() {
+    /// Same as `gen_proof`, but for stores with pruned or absent nodes: instead of failing on
+    /// the first missing node, fail with `MissingNodes` listing every node the proof needs and
+    /// the store lacks, e.g. so they can be fetched from a peer before retrying.
+    pub fn gen_proof_reporting_missing(&self, pos_list: Vec<Pos>) -> Result<MerkleProof<T, M>> {
+        self.gen_proof_with(pos_list, true, None)
+    }
+
+    /// Same as `gen_proof`, failing with `Cancelled` once `cancel` is set, e.g. by a service whose
+    /// client disconnected. The flag is checked before every read from the store.
+    pub fn gen_proof_cancellable(
+        &self,
+        pos_list: Vec<Pos>,
+        cancel: &AtomicBool,
+    ) -> Result<MerkleProof<T, M>> {
+        self.gen_proof_with(pos_list, false, Some(cancel))
+    }
+
+    fn gen_proof_with(
+        &self,
+        pos_list: Vec<Pos>,
+        report_missing: bool,
+        cancel: Option<&AtomicBool>,
+    ) -> Result<MerkleProof<T, M>> {
+        // sort the positions in place rather than copying them into the arena
+        let mut arena = ProofArena::new();
+        arena.leaves = pos_list;
+        self.gen_proof_from_leaves(report_missing, cancel, &mut arena)
+    }
+
+    /// Same as `gen_proof`, working in the buffers of `arena` instead of allocating them for
+    /// every proof. The store reads still allocate as the store implements them.
+    pub fn gen_proof_in(
+        &self,
+        pos_list: &[Pos],
+        arena: &mut ProofArena<T>,
+    ) -> Result<MerkleProof<T, M>> {
+        self.gen_proof_in_with(pos_list, false, arena)
+    }
+
+    /// Same as `gen_proof_in`, with the arena the MMR keeps for its own hot paths, e.g. a single
+    /// writer proving every leaf it pushes. Hand the proof back with `recycle_proof` once it is
+    /// sent to reuse its items as well.
+    pub fn gen_proof_with_scratch(&mut self, pos_list: &[Pos]) -> Result<MerkleProof<T, M>> {
+        let mut scratch = core::mem::take(&mut self.scratch);
+        let proof = self.gen_proof_in(pos_list, &mut scratch);
+        self.scratch = scratch;
+        proof
+    }
+
+    /// Take back the items of a `proof` generated with `gen_proof_with_scratch`.
+    pub fn recycle_proof(&mut self, proof: MerkleProof<T, M>) {
+        self.scratch.recycle(proof);
+    }
+
+    fn gen_proof_in_with(
+        &self,
+        pos_list: &[Pos],
+        report_missing: bool,
+        arena: &mut ProofArena<T>,
+    ) -> Result<MerkleProof<T, M>> {
+        arena.leaves.clear();
+        arena.leaves.extend_from_slice(pos_list);
+        self.gen_proof_from_leaves(report_missing, None, arena)
+    }
+
+    /// Generate the proof of the positions in `arena.leaves`, in any order.
+    fn gen_proof_from_leaves(
+        &self,
+        report_missing: bool,
+        cancel: Option<&AtomicBool>,
+        arena: &mut ProofArena<T>,
+    ) -> Result<MerkleProof<T, M>> {
+        let leaves = &mut arena.leaves;
+        if leaves.is_empty() {
             return Err(Error::GenProofForInvalidLeaves);
         }
-
+        check_in_range(self.mmr_size, leaves)?;
+        if leaves.iter().any(|pos| pos_height_in_tree(*pos) > 0) {
+            return Err(Error::GenProofForInvalidLeaves);
+        }
+        // ensure positions are sorted and unique
+        leaves.sort_unstable();
+        leaves.dedup();
+        arena.items.clear();
+        if self.mmr_size == 1 && *leaves == [0] {
+            return Ok(MerkleProof::new(
+                self.mmr_size,
+                core::mem::take(&mut arena.items),
+            ));
+        }
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "mmr_gen_proof",
+            mmr_size = self.mmr_size,
+            leaves = leaves.len()
+        )
+        .entered();
+        arena.proof_pos.clear();
+        let bagging_track = gen_proof_positions_into(
+            self.mmr_size,
+            leaves,
+            &mut arena.proof_pos,
+            &mut arena.queue,
+        )?;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            nodes = arena.proof_pos.len(),
+            bagged_peaks = bagging_track,
+            "proof positions"
+        );
+        self.get_elems_in_runs_into(
+            &arena.proof_pos,
+            report_missing,
+            cancel,
+            &mut arena.sorted_pos,
+            &mut arena.elems,
+            &mut arena.items,
+        )?;
         if bagging_track > 1 {
-            let rhs_peaks = proof.split_off(proof.len() - bagging_track);
-            proof.push(self.bag_rhs_peaks(rhs_peaks)?.expect("bagging rhs peaks"));
+            let start = arena.items.len() - bagging_track;
+            self.bag_peaks_from(&mut arena.items, start)?;
         }
 
-        Ok(MerkleProof::new(self.mmr_size, proof))
+        metrics::on_proof_items(arena.items.len() as u64);
+        Ok(MerkleProof::new(
+            self.mmr_size,
+            core::mem::take(&mut arena.items),
+        ))
     }
 
-    /// Generate node merkle proof for positions
+    /// Same as `gen_proof`, also returning the canonical leaf positions, sorted and deduplicated.
+    ///
+    /// Verification sorts the leaves itself, but supplying them in this order, one per position,
+    /// avoids any doubt about which leaves the proof covers.
+    pub fn gen_proof_with_positions(
+        &self,
+        mut pos_list: Vec<Pos>,
+    ) -> Result<(MerkleProof<T, M>, Vec<Pos>)> {
+        pos_list.sort_unstable();
+        pos_list.dedup();
+        let proof = self.gen_proof(pos_list.clone())?;
+        Ok((proof, pos_list))
+    }
+
+    /// Generate compact multiproof for leaf positions, see `compact_proof`
     /// 1. sort positions
-    /// 2. push merkle proof to proof by peak from left to right
-    /// 3. push bagged right hand side root
-    pub fn gen_node_proof(&self, mut pos_list: Vec<u64>) -> Result<NodeMerkleProof<T, M>> {
+    /// 2. walk each mountain top-down, descending only into subtrees that contain positions
+    /// 3. bag the peaks right of the last position into a single hash
+    pub fn gen_compact_multiproof(
+        &self,
+        mut pos_list: Vec<Pos>,
+    ) -> Result<CompactMerkleProof<T, M>> {
         if pos_list.is_empty() {
-            return Err(Error::GenProofForInvalidNodes);
+            return Err(Error::GenProofForInvalidLeaves);
         }
-        if self.mmr_size == 1 && pos_list == [0] {
-            return Ok(NodeMerkleProof::new(self.mmr_size, Vec::new()));
+        check_in_range(self.mmr_size, &pos_list)?;
+        if pos_list.iter().any(|pos| pos_height_in_tree(*pos) > 0) {
+            return Err(Error::GenProofForInvalidLeaves);
         }
         // ensure positions are sorted and unique
         pos_list.sort_unstable();
         pos_list.dedup();
+
+        let mut descriptor = DescriptorWriter::default();
+        let mut proof: Vec<T> = Vec::new();
         let peaks = get_peaks(self.mmr_size);
-        let mut proof: Vec<(u64, T)> = Vec::new();
-        // generate merkle proof for each peaks
-        let mut bagging_track = 0;
-        for peak_pos in peaks {
-            let pos_list: Vec<_> = take_while_vec(&mut pos_list, |&pos| pos <= peak_pos);
-            if pos_list.is_empty() {
-                bagging_track += 1;
-            } else {
-                bagging_track = 0;
+        for (i, &peak_pos) in peaks.iter().enumerate() {
+            let peak_pos_list: Vec<_> = take_while_vec(&mut pos_list, |&pos| pos <= peak_pos);
+            if !peak_pos_list.is_empty() {
+                let height = pos_height_in_tree(peak_pos);
+                self.gen_compact_proof_for_node(
+                    &mut descriptor,
+                    &mut proof,
+                    &peak_pos_list,
+                    peak_pos,
+                    height,
+                )?;
+                continue;
+            }
+            descriptor.push(false);
+            if i + 1 < peaks.len() {
+                // no positions left, so bag all the remaining peaks
+                let bag_rhs = pos_list.is_empty();
+                descriptor.push(bag_rhs);
+                if bag_rhs {
+                    let rhs_peaks = peaks[i..]
+                        .iter()
+                        .map(|&pos| {
+                            self.batch
+                                .get_elem(pos)?
+                                .ok_or(Error::InconsistentStore { pos })
+                        })
+                        .collect::<Result<Vec<T>>>()?;
+                    proof.push(self.bag_rhs_peaks(rhs_peaks)?.expect("bagging rhs peaks"));
+                    break;
+                }
+            }
+            proof.push(
+                self.batch
+                    .get_elem(peak_pos)?
+                    .ok_or(Error::InconsistentStore { pos: peak_pos })?,
+            );
+        }
+
+        metrics::on_proof_items(proof.len() as u64);
+        Ok(CompactMerkleProof::new(
+            self.mmr_size,
+            descriptor.into_bytes(),
+            proof,
+        ))
+    }
+
+    fn gen_compact_proof_for_node(
+        &self,
+        descriptor: &mut DescriptorWriter,
+        proof: &mut Vec<T>,
+        pos_list: &[Pos],
+        pos: Pos,
+        height: u8,
+    ) -> Result<()> {
+        if pos_list.is_empty() {
+            descriptor.push(false);
+            proof.push(
+                self.batch
+                    .get_elem(pos)?
+                    .ok_or(Error::InconsistentStore { pos })?,
+            );
+            return Ok(());
+        }
+        descriptor.push(true);
+        if height == 0 {
+            return Ok(());
+        }
+        let left_pos = pos - (1 << height);
+        let split = pos_list.partition_point(|&leaf_pos| leaf_pos <= left_pos);
+        self.gen_compact_proof_for_node(
+            descriptor,
+            proof,
+            &pos_list[..split],
+            left_pos,
+            height - 1,
+        )?;
+        self.gen_compact_proof_for_node(descriptor, proof, &pos_list[split..], pos - 1, height - 1)
+    }
+
+    /// Generate merkle proof for leaf positions, passing each item with its position to `sink`
+    /// in ascending position order instead of collecting them, see `verify_proof_streaming`.
+    ///
+    /// Walks each mountain top-down like `gen_compact_multiproof`, so besides the items only the
+    /// walk of a single path is held in memory. Unlike `gen_proof`, the peaks right of the last
+    /// position are passed one by one rather than bagged.
+    pub fn gen_proof_streaming(
+        &self,
+        mut pos_list: Vec<Pos>,
+        mut sink: impl FnMut(Pos, T) -> Result<()>,
+    ) -> Result<()> {
+        if pos_list.is_empty() {
+            return Err(Error::GenProofForInvalidLeaves);
+        }
+        check_in_range(self.mmr_size, &pos_list)?;
+        if pos_list.iter().any(|pos| pos_height_in_tree(*pos) > 0) {
+            return Err(Error::GenProofForInvalidLeaves);
+        }
+        // ensure positions are sorted and unique
+        pos_list.sort_unstable();
+        pos_list.dedup();
+
+        let mut proof_items = 0;
+        let mut pos_list = &pos_list[..];
+        for peak_pos in iter_peaks(self.mmr_size) {
+            let split = pos_list.partition_point(|&pos| pos <= peak_pos);
+            self.gen_streaming_proof_for_node(
+                &mut sink,
+                &mut proof_items,
+                &pos_list[..split],
+                peak_pos,
+                pos_height_in_tree(peak_pos),
+            )?;
+            pos_list = &pos_list[split..];
+        }
+
+        metrics::on_proof_items(proof_items);
+        Ok(())
+    }
+
+    fn gen_streaming_proof_for_node(
+        &self,
+        sink: &mut impl FnMut(Pos, T) -> Result<()>,
+        proof_items: &mut u64,
+        pos_list: &[Pos],
+        pos: Pos,
+        height: u8,
+    ) -> Result<()> {
+        if pos_list.is_empty() {
+            let elem = self
+                .batch
+                .get_elem(pos)?
+                .ok_or(Error::InconsistentStore { pos })?;
+            *proof_items += 1;
+            return sink(pos, elem);
+        }
+        if height == 0 {
+            return Ok(());
+        }
+        let left_pos = pos - (1 << height);
+        let split = pos_list.partition_point(|&leaf_pos| leaf_pos <= left_pos);
+        self.gen_streaming_proof_for_node(
+            sink,
+            proof_items,
+            &pos_list[..split],
+            left_pos,
+            height - 1,
+        )?;
+        self.gen_streaming_proof_for_node(
+            sink,
+            proof_items,
+            &pos_list[split..],
+            pos - 1,
+            height - 1,
+        )
+    }
+
+    /// Generate node merkle proof for positions
+    /// 1. sort positions
+    /// 2. push merkle proof to proof by peak from left to right
+    /// 3. push bagged right hand side root
+    pub fn gen_node_proof(&self, mut pos_list: Vec<Pos>) -> Result<NodeMerkleProof<T, M>> {
+        if pos_list.is_empty() {
+            return Err(Error::GenProofForInvalidNodes);
+        }
+        check_in_range(self.mmr_size, &pos_list)?;
+        if self.mmr_size == 1 && pos_list == [0] {
+            return Ok(NodeMerkleProof::new(self.mmr_size, Vec::new()));
+        }
+        // ensure positions are sorted and unique
+        pos_list.sort_unstable();
+        pos_list.dedup();
+        let peaks = get_peaks(self.mmr_size);
+        let mut proof: Vec<(Pos, T)> = Vec::new();
+        // generate merkle proof for each peaks
+        let mut bagging_track = 0;
+        for peak_pos in peaks {
+            let pos_list: Vec<_> = take_while_vec(&mut pos_list, |&pos| pos <= peak_pos);
+            if pos_list.is_empty() {
+                bagging_track += 1;
+            } else {
+                bagging_track = 0;
             }
             self.gen_node_proof_for_peak(&mut proof, pos_list, peak_pos)?;
         }
@@ -383,31 +1127,56 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>, S: MMRStoreReadOps<T>> MMR<T, M,
 
         proof.sort_by_key(|(pos, _)| *pos);
 
+        metrics::on_proof_items(proof.len() as u64);
         Ok(NodeMerkleProof::new(self.mmr_size, proof))
     }
 
+    /// Same as `gen_node_proof`, for typed positions.
+    pub fn gen_node_proof_at(&self, positions: &[NodePosition]) -> Result<NodeMerkleProof<T, M>> {
+        self.gen_node_proof(positions.iter().map(|pos| pos.get()).collect())
+    }
+
+    /// Same as `gen_ancestry_proof`, for the typed size of the ancestor.
+    pub fn gen_ancestry_proof_since(&self, prev_size: MmrSize) -> Result<AncestryProof<T, M>> {
+        self.gen_ancestry_proof(prev_size.get())
+    }
+
+    /// Same as `gen_ancestry_proof`, but for the MMR after `prev_leaf_count` leaves were pushed.
+    pub fn gen_ancestry_proof_for_leaf_count(
+        &self,
+        prev_leaf_count: Pos,
+    ) -> Result<AncestryProof<T, M>> {
+        let prev_mmr_size =
+            leaf_count_to_mmr_size(prev_leaf_count).ok_or(Error::InvalidLeafCount {
+                leaf_count: prev_leaf_count,
+            })?;
+        if prev_leaf_count > self.leaves_count() {
+            return Err(Error::AncestorRootNotPredecessor {
+                prev_mmr_size,
+                mmr_size: self.mmr_size,
+            });
+        }
+        self.gen_ancestry_proof(prev_mmr_size)
+    }
+
     /// Generate proof that prior merkle root r' is an ancestor of current merkle proof r
     /// 1. calculate positions of peaks of old root r' given mmr size n
     /// 2. generate membership proof of peaks in root r
     /// 3. calculate r' from peaks(n)
     /// 4. return (mmr root r', peak hashes, membership proof of peaks(n) in r)
-    pub fn gen_ancestry_proof(&self, prev_mmr_size: u64) -> Result<AncestryProof<T, M>> {
+    ///
+    /// With `prev_mmr_size` the current size the peaks are their own proof, so the membership
+    /// proof is empty and `verify_ancestor` holds iff both roots are the root of the peaks.
+    pub fn gen_ancestry_proof(&self, prev_mmr_size: Pos) -> Result<AncestryProof<T, M>> {
         let mut pos_list = get_peaks(prev_mmr_size);
         if pos_list.is_empty() {
             return Err(Error::GenProofForInvalidNodes);
         }
-        if self.mmr_size == 1 && pos_list == [0] {
-            return Ok(AncestryProof {
-                prev_peaks: Vec::new(),
-                prev_mmr_size: self.mmr_size,
-                prev_peaks_proof: NodeMerkleProof::new(self.mmr_size(), Vec::new()),
-            });
-        }
         // ensure positions are sorted and unique
         pos_list.sort_unstable();
         pos_list.dedup();
         let peaks = get_peaks(self.mmr_size);
-        let mut proof: Vec<(u64, T)> = Vec::new();
+        let mut proof: Vec<(Pos, T)> = Vec::new();
         // generate merkle proof for each peaks
         let mut bagging_track = 0;
         for peak_pos in peaks {
@@ -441,30 +1210,337 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>, S: MMRStoreReadOps<T>> MMR<T, M,
         proof.sort_by_key(|(pos, _)| *pos);
 
         let (prev_peaks, _prev_root) = self.get_ancestor_peaks_and_root(prev_mmr_size)?;
+        metrics::on_proof_items((prev_peaks.len() + proof.len()) as u64);
 
-        Ok(AncestryProof {
+        AncestryProof::new(
             prev_peaks,
-            prev_mmr_size: prev_mmr_size,
-            prev_peaks_proof: NodeMerkleProof::new(self.mmr_size, proof),
-        })
+            prev_mmr_size,
+            NodeMerkleProof::new(self.mmr_size, proof),
+        )
+    }
+
+    /// Generate a proof of `pos_list` in the ancestor MMR of `prev_mmr_size`, along with the
+    /// ancestry proof of its root, e.g. for light clients tracking historical roots.
+    ///
+    /// Both proofs read the nodes through a shared cache, so the ancestor peaks and every other
+    /// node needed by both are read from the store once.
+    pub fn gen_proof_at_ancestor(
+        &self,
+        pos_list: Vec<Pos>,
+        prev_mmr_size: Pos,
+    ) -> Result<(MerkleProof<T, M>, AncestryProof<T, M>)> {
+        if prev_mmr_size > self.mmr_size {
+            return Err(Error::AncestorRootNotPredecessor {
+                prev_mmr_size,
+                mmr_size: self.mmr_size,
+            });
+        }
+        let store = CachingStore::new(&self.batch);
+        let ancestry_proof =
+            MMR::<T, M, _>::new(self.mmr_size, &store).gen_ancestry_proof(prev_mmr_size)?;
+        let proof = MMR::<T, M, _>::new(prev_mmr_size, &store).gen_proof(pos_list)?;
+        Ok((proof, ancestry_proof))
+    }
+
+    /// Generate a proof that appending the leaves pushed since `prev_mmr_size` to the MMR of that
+    /// size yields the current MMR, see `UpdateProof`.
+    pub fn gen_update_proof(&self, prev_mmr_size: Pos) -> Result<UpdateProof<T, M>> {
+        if prev_mmr_size > self.mmr_size {
+            return Err(Error::AncestorRootNotPredecessor {
+                prev_mmr_size,
+                mmr_size: self.mmr_size,
+            });
+        }
+        if prev_mmr_size == 0 || !is_valid_mmr_size(prev_mmr_size) {
+            return Err(Error::InvalidMMRSize {
+                mmr_size: prev_mmr_size,
+            });
+        }
+        let prev_peaks = get_peaks(prev_mmr_size)
+            .into_iter()
+            .map(|pos| {
+                self.batch
+                    .get_elem(pos)?
+                    .ok_or(Error::InconsistentStore { pos })
+            })
+            .collect::<Result<Vec<T>>>()?;
+        metrics::on_proof_items(prev_peaks.len() as u64);
+        UpdateProof::new(prev_peaks, prev_mmr_size, self.mmr_size)
     }
 }
 
+#[cfg(not(feature = "verify-only"))]
+impl<T: Clone + PartialEq, M: Merge<Item = T>, S: MMRStoreReadOps<T>> MMR<T, M, S> {
+    /// Recompute the interior nodes above the leaves at `leaf_range` from the stored leaves, e.g.
+    /// after an audit found corrupted nodes in the store.
+    ///
+    /// Nodes that differ from their recomputed value are rewritten into the batch and persisted
+    /// on the next `commit` with `MMRStoreWriteOps::overwrite`. Returns the number of rewritten
+    /// nodes.
+    pub fn rebuild_range(&mut self, leaf_range: Range<Pos>) -> Result<Pos> {
+        self.rebuild_range_with(leaf_range, None)
+    }
+
+    /// Same as `rebuild_range`, failing with `Cancelled` once `cancel` is set. The flag is
+    /// checked before every node, the nodes rewritten until then stay in the batch.
+    pub fn rebuild_range_cancellable(
+        &mut self,
+        leaf_range: Range<Pos>,
+        cancel: &AtomicBool,
+    ) -> Result<Pos> {
+        self.rebuild_range_with(leaf_range, Some(cancel))
+    }
+
+    fn rebuild_range_with(
+        &mut self,
+        leaf_range: Range<Pos>,
+        cancel: Option<&AtomicBool>,
+    ) -> Result<Pos> {
+        if leaf_range.end > self.leaves_count() {
+            return Err(Error::InvalidLeafCount {
+                leaf_count: leaf_range.end,
+            });
+        }
+        let peaks = get_peaks(self.mmr_size);
+        let mut nodes = leaf_range
+            .map(|leaf_index| {
+                check_cancelled(cancel)?;
+                let pos = leaf_index_to_pos(leaf_index);
+                let leaf = self
+                    .batch
+                    .get_elem(pos)?
+                    .ok_or(Error::InconsistentStore { pos })?;
+                Ok((pos, leaf))
+            })
+            .collect::<Result<Vec<(Pos, T)>>>()?;
+        let mut height = 0;
+        let mut rewritten = 0;
+        // recompute the mountains level by level, keeping the nodes of a level sorted
+        while !nodes.is_empty() {
+            let mut parents = Vec::with_capacity(nodes.len() / 2 + 1);
+            let mut nodes_iter = nodes.into_iter().peekable();
+            while let Some((pos, item)) = nodes_iter.next() {
+                check_cancelled(cancel)?;
+                if peaks.binary_search(&pos).is_ok() {
+                    continue;
+                }
+                let (sib_pos, parent_pos, is_right) =
+                    PostOrderScheme::sibling_and_parent_pos(pos, height);
+                let parent_item = if is_right {
+                    // pos is right sibling, the left one isn't part of the level
+                    let sibling = self
+                        .batch
+                        .get_elem(sib_pos)?
+                        .ok_or(Error::InconsistentStore { pos: sib_pos })?;
+                    M::merge_with_pos(parent_pos, &sibling, &item).map_err(Into::into)?
+                } else {
+                    let sibling = match nodes_iter.next_if(|(next_pos, _)| *next_pos == sib_pos) {
+                        Some((_, sibling)) => sibling,
+                        None => self
+                            .batch
+                            .get_elem(sib_pos)?
+                            .ok_or(Error::InconsistentStore { pos: sib_pos })?,
+                    };
+                    M::merge_with_pos(parent_pos, &item, &sibling).map_err(Into::into)?
+                };
+                metrics::on_merges(1);
+                if self.batch.get_elem(parent_pos)?.as_ref() != Some(&parent_item) {
+                    self.batch.overwrite(parent_pos, parent_item.clone());
+                    rewritten += 1;
+                }
+                parents.push((parent_pos, parent_item));
+            }
+            nodes = parents;
+            height += 1;
+        }
+        Ok(rewritten)
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+impl<T: Clone, M: LeafHasher<Item = T>, S: MMRStoreReadOps<T>> MMR<T, M, S> {
+    /// Hash the raw `leaf` payload with `LeafHasher::hash_leaf` and push the hash.
+    pub fn push_raw<L: AsRef<[u8]>>(&mut self, leaf: L) -> Result<Pos> {
+        let elem = M::hash_leaf(leaf.as_ref()).map_err(Into::into)?;
+        self.push(elem)
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
 impl<T, M, S: MMRStoreWriteOps<T>> MMR<T, M, S> {
+    /// Construct a light MMR knowing only the `peaks` of an MMR of `mmr_size`, e.g. as tracked by
+    /// a light client. The peaks are written to `store`, which suffices to push further leaves
+    /// and calculate roots, but not to generate proofs for the leaves under the imported peaks.
+    pub fn from_peaks(peaks: Vec<(Pos, T)>, mmr_size: Pos, mut store: S) -> Result<Self> {
+        if !is_valid_mmr_size(mmr_size) {
+            return Err(Error::InvalidMMRSize { mmr_size });
+        }
+        if !peaks.iter().map(|(pos, _)| *pos).eq(iter_peaks(mmr_size)) {
+            return Err(Error::InvalidPeaks { mmr_size });
+        }
+        for (pos, peak) in peaks {
+            store.append(pos, vec![peak])?;
+        }
+        store.set_mmr_size(mmr_size)?;
+        Ok(MMR::new(mmr_size, store))
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+impl<T: Clone, M: Merge<Item = T>, S: MMRStoreReadOps<T> + MMRStoreWriteOps<T>> MMR<T, M, S> {
+    /// Write the pending nodes to the store, along with the new size.
+    ///
+    /// On failure the nodes stay pending, so the commit can be retried.
     pub fn commit(&mut self) -> Result<()> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "mmr_commit",
+            mmr_size = self.mmr_size,
+            pending = self.batch.pending_len()
+        )
+        .entered();
+        #[cfg(feature = "root-log")]
+        self.log_commit_root()?;
+        self.batch.set_mmr_size(self.mmr_size);
         self.batch.commit()
     }
 }
 
+#[cfg(not(feature = "verify-only"))]
+impl<T: Clone, M: Merge<Item = T>, S: MMRStoreReadOps<T> + MMRStoreWriteOps<T>> MMR<T, M, S> {
+    /// Push a leaf along with application data, e.g. the preimage of its hash.
+    ///
+    /// The metadata is keyed by leaf index and committed together with the nodes. Fails without
+    /// pushing if the store doesn't support leaf metadata, see `supports_leaf_meta`.
+    pub fn push_with_leaf_meta(&mut self, elem: T, meta: Vec<u8>) -> Result<Pos> {
+        if !self.batch.store().supports_leaf_meta() {
+            return Err(Error::StoreError(
+                "leaf metadata is not supported".to_string(),
+            ));
+        }
+        let leaf_index = self.leaves_count();
+        let pos = self.push(elem)?;
+        self.batch.append_leaf_meta(leaf_index, meta);
+        Ok(pos)
+    }
+
+    /// Same as `push`, committing once the batch reaches the `set_auto_commit` threshold, so bulk
+    /// imports keep the batch bounded.
+    ///
+    /// Note that auto-committed pushes are persisted, `rewind` removes them from the store.
+    pub fn push_auto_commit(&mut self, elem: T) -> Result<Pos> {
+        let pos = self.push(elem)?;
+        if let Some(threshold) = self.auto_commit {
+            if self.batch.pending_len() >= threshold {
+                self.commit()?;
+            }
+        }
+        Ok(pos)
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+impl<T, M, S: MMRStoreDeleteOps<T>> MMR<T, M, S> {
+    /// Roll the MMR back to a previous `to_mmr_size`, e.g. on a chain reorg.
+    ///
+    /// Pending elements past the target are dropped and persisted ones are removed from the store.
+    /// The store's recorded size is updated by the next commit.
+    pub fn rewind(&mut self, to_mmr_size: Pos) -> Result<()> {
+        if to_mmr_size > self.mmr_size {
+            return Err(Error::AncestorRootNotPredecessor {
+                prev_mmr_size: to_mmr_size,
+                mmr_size: self.mmr_size,
+            });
+        }
+        if !is_valid_mmr_size(to_mmr_size) {
+            return Err(Error::InvalidMMRSize {
+                mmr_size: to_mmr_size,
+            });
+        }
+        self.batch.rewind(to_mmr_size)?;
+        self.batch.set_mmr_size(to_mmr_size);
+        self.mmr_size = to_mmr_size;
+        Ok(())
+    }
+}
+
+/// Read-only access to a store, bypassing the pending batch.
+#[cfg(not(feature = "verify-only"))]
+struct CommittedStore<'a, S>(&'a S);
+
+#[cfg(not(feature = "verify-only"))]
+impl<'a, T, S: MMRStoreReadOps<T>> MMRStoreReadOps<T> for CommittedStore<'a, S> {
+    fn get_elem(&self, pos: Pos) -> Result<Option<T>> {
+        self.0.get_elem(pos)
+    }
+
+    fn get_elems(&self, range: Range<Pos>) -> Result<Vec<Option<T>>> {
+        self.0.get_elems(range)
+    }
+}
+
+/// Read-only access to a batch, remembering the elements read so far.
+#[cfg(not(feature = "verify-only"))]
+struct CachingStore<'a, T, S> {
+    batch: &'a MMRBatch<T, S>,
+    cache: RefCell<BTreeMap<Pos, T>>,
+}
+
+#[cfg(not(feature = "verify-only"))]
+impl<'a, T, S> CachingStore<'a, T, S> {
+    fn new(batch: &'a MMRBatch<T, S>) -> Self {
+        CachingStore {
+            batch,
+            cache: RefCell::new(BTreeMap::new()),
+        }
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+impl<'a, 'b, T: Clone, S: MMRStoreReadOps<T>> MMRStoreReadOps<T> for &'b CachingStore<'a, T, S> {
+    fn get_elem(&self, pos: Pos) -> Result<Option<T>> {
+        if let Some(elem) = self.cache.borrow().get(&pos) {
+            return Ok(Some(elem.clone()));
+        }
+        let elem = self.batch.get_elem(pos)?;
+        if let Some(elem) = &elem {
+            self.cache.borrow_mut().insert(pos, elem.clone());
+        }
+        Ok(elem)
+    }
+
+    fn get_elems(&self, range: Range<Pos>) -> Result<Vec<Option<T>>> {
+        let cached: Vec<Option<T>> = {
+            let cache = self.cache.borrow();
+            range.clone().map(|pos| cache.get(&pos).cloned()).collect()
+        };
+        if cached.iter().all(Option::is_some) {
+            return Ok(cached);
+        }
+        let elems = self.batch.get_elems(range.clone())?;
+        let mut cache = self.cache.borrow_mut();
+        for (pos, elem) in range.zip(&elems) {
+            if let Some(elem) = elem {
+                cache.insert(pos, elem.clone());
+            }
+        }
+        Ok(elems)
+    }
+}
+
 #[derive(Debug)]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize))]
 pub struct MerkleProof<T, M> {
-    mmr_size: u64,
+    mmr_size: Pos,
     proof: Vec<T>,
     merge: PhantomData<M>,
 }
 
-impl<T: Clone + PartialEq, M: Merge<Item = T>> MerkleProof<T, M> {
-    pub fn new(mmr_size: u64, proof: Vec<T>) -> Self {
+impl<T, M> MerkleProof<T, M> {
+    /// Version of the encoded proof format, the leading byte of the SCALE encoding.
+    pub const FORMAT_VERSION: u8 = 1;
+
+    pub fn new(mmr_size: Pos, proof: Vec<T>) -> Self {
         MerkleProof {
             mmr_size,
             proof,
@@ -472,16 +1548,369 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>> MerkleProof<T, M> {
         }
     }
 
-    pub fn mmr_size(&self) -> u64 {
+    /// Same as `new`, failing with `InvalidMMRSize` if `mmr_size` isn't the size of an MMR and
+    /// with `CorruptedProof` if there are more items than nodes besides a leaf, which no proof
+    /// has. This does not make the proof canonical: items carry no positions, see
+    /// `proof_items`, so their number and bagging can only be checked against the proven
+    /// leaves with `validate_canonical`.
+    pub fn try_new(mmr_size: Pos, proof: Vec<T>) -> Result<Self> {
+        if !is_valid_mmr_size(mmr_size) {
+            return Err(Error::InvalidMMRSize { mmr_size });
+        }
+        // compare without truncating the size where `usize` is narrower than `Pos`
+        let max_items = usize::try_from(mmr_size.saturating_sub(1)).unwrap_or(usize::MAX);
+        if proof.len() > max_items {
+            return Err(Error::CorruptedProof);
+        }
+        Ok(MerkleProof::new(mmr_size, proof))
+    }
+
+    pub fn mmr_size(&self) -> Pos {
         self.mmr_size
     }
 
+    /// Same as `mmr_size`, typed, failing with `InvalidMMRSize` for a proof built with `new`
+    /// from an invalid size.
+    pub fn size(&self) -> Result<MmrSize> {
+        MmrSize::new(self.mmr_size)
+    }
+
+    /// The items in the order the verifier takes them. Their positions follow from the proven
+    /// leaves, see `proof_positions`, which are unique and never a proven position as long as
+    /// the proof passes `validate_canonical` for those leaves, as `MMR::gen_proof` proofs do.
     pub fn proof_items(&self) -> &[T] {
         &self.proof
     }
 
-    pub fn calculate_root(&self, leaves: Vec<(u64, T)>) -> Result<T> {
-        calculate_root::<_, M, _>(leaves, self.mmr_size, self.proof.iter())
+    /// Check that the proof has the shape of a proof of the leaves at `leaf_positions` in the MMR
+    /// of `mmr_size` before hashing anything, so untrusted proofs can be rejected cheaply.
+    ///
+    /// The positions of the proof items follow from the leaf positions, see `proof_positions`,
+    /// so this checks the sizes, the leaves and the number of items, with the peaks right of the
+    /// last mountain with a leaf either bagged into one item or listed one by one. Fails with
+    /// `InvalidMMRSize`, `GenProofForInvalidLeaves` if a position is not a leaf of the MMR, and
+    /// `CorruptedProof` if the proof is for another size or has the wrong number of items.
+    pub fn validate_shape(&self, mmr_size: Pos, leaf_positions: &[Pos]) -> Result<()> {
+        let (unbagged_len, bagged_len) = self.shape_lens(mmr_size, leaf_positions)?;
+        if self.proof.len() != unbagged_len && self.proof.len() != bagged_len {
+            return Err(Error::CorruptedProof);
+        }
+        Ok(())
+    }
+
+    /// Same as `validate_shape`, but also fails with `CorruptedProof` if the peaks right of the
+    /// last mountain with a leaf are listed one by one rather than bagged into one item as
+    /// `MMR::gen_proof` does, so every proof has one canonical encoding, e.g. for consensus code
+    /// hashing proof bytes.
+    pub fn validate_canonical(&self, mmr_size: Pos, leaf_positions: &[Pos]) -> Result<()> {
+        let (_, bagged_len) = self.shape_lens(mmr_size, leaf_positions)?;
+        if self.proof.len() != bagged_len {
+            return Err(Error::CorruptedProof);
+        }
+        Ok(())
+    }
+
+    /// Number of items of a proof of `leaf_positions` with the rhs peaks listed one by one and
+    /// bagged into one item, see `validate_shape`.
+    fn shape_lens(&self, mmr_size: Pos, leaf_positions: &[Pos]) -> Result<(usize, usize)> {
+        if !is_valid_mmr_size(mmr_size) {
+            return Err(Error::InvalidMMRSize { mmr_size });
+        }
+        if mmr_size != self.mmr_size {
+            return Err(Error::CorruptedProof);
+        }
+        let mut pos_list = leaf_positions.to_vec();
+        pos_list.sort_unstable();
+        pos_list.dedup();
+        if pos_list.is_empty()
+            || pos_list
+                .iter()
+                .any(|pos| pos_height_in_tree(*pos) > 0 || *pos >= mmr_size)
+        {
+            return Err(Error::GenProofForInvalidLeaves);
+        }
+        let (proof_pos, bagging_track) = gen_proof_positions(mmr_size, pos_list)?;
+        let unbagged_len = proof_pos.len();
+        Ok((unbagged_len, unbagged_len - bagging_track.saturating_sub(1)))
+    }
+
+    #[cfg(not(feature = "verify-only"))]
+    pub(crate) fn into_proof_items(self) -> Vec<T> {
+        self.proof
+    }
+
+    /// Combine this proof of the leaves at `pos_list` with a proof of the leaves at
+    /// `other_pos_list` against the same MMR, into a proof of the union of both leaf sets.
+    ///
+    /// Items that become derivable from the other proof's leaves are dropped, so the result is
+    /// the same as generating a proof for all the leaves at once, without access to the store.
+    pub fn merge(self, pos_list: Vec<Pos>, other: Self, other_pos_list: Vec<Pos>) -> Result<Self> {
+        if self.mmr_size != other.mmr_size {
+            return Err(Error::CorruptedProof);
+        }
+        let mmr_size = self.mmr_size;
+        let mut items = BTreeMap::new();
+        let mut bagged_items = BTreeMap::new();
+        let mut all_pos_list = Vec::with_capacity(pos_list.len() + other_pos_list.len());
+        for (proof, mut pos_list) in [(self, pos_list), (other, other_pos_list)] {
+            pos_list.sort_unstable();
+            pos_list.dedup();
+            if pos_list.iter().any(|pos| pos_height_in_tree(*pos) > 0) {
+                return Err(Error::GenProofForInvalidLeaves);
+            }
+            all_pos_list.extend_from_slice(&pos_list);
+            let (mut proof_pos, bagging_track) = gen_proof_positions(mmr_size, pos_list)?;
+            let bagged_pos = split_bagged_pos(&mut proof_pos, bagging_track);
+            if proof.proof.len() != proof_pos.len() + bagged_pos.is_some() as usize {
+                return Err(Error::CorruptedProof);
+            }
+            let mut proof_iter = proof.proof.into_iter();
+            items.extend(proof_pos.into_iter().zip(&mut proof_iter));
+            if let Some(bagged_pos) = bagged_pos {
+                bagged_items.insert(bagged_pos, proof_iter.next().expect("checked length"));
+            }
+        }
+
+        all_pos_list.sort_unstable();
+        all_pos_list.dedup();
+        if mmr_size == 1 && all_pos_list == [0] {
+            return Ok(MerkleProof::new(mmr_size, Vec::new()));
+        }
+        let (mut proof_pos, bagging_track) = gen_proof_positions(mmr_size, all_pos_list)?;
+        let bagged_pos = split_bagged_pos(&mut proof_pos, bagging_track);
+        let mut proof = proof_pos
+            .into_iter()
+            .map(|pos| items.remove(&pos).ok_or(Error::CorruptedProof))
+            .collect::<Result<Vec<T>>>()?;
+        if let Some(bagged_pos) = bagged_pos {
+            proof.push(
+                bagged_items
+                    .remove(&bagged_pos)
+                    .ok_or(Error::CorruptedProof)?,
+            );
+        }
+        Ok(MerkleProof::new(mmr_size, proof))
+    }
+
+    /// Whether the proof has the shape of a proof of the leaves at `pos_list`, i.e. verifying it
+    /// against these leaves has exactly the items it needs, leaving only the hashes to check, see
+    /// `validate_shape`. Items carry no positions, so leaf sets with the same layout, e.g. two
+    /// sibling leaves, can't be told apart.
+    pub fn can_prove(&self, pos_list: &[Pos]) -> bool {
+        self.validate_shape(self.mmr_size, pos_list).is_ok()
+    }
+
+    /// Positions of the leaves proven along with the `leaves` the proof was generated for: the
+    /// leaves themselves and the sibling leaves carried as proof items, in ascending order.
+    ///
+    /// Empty if the proof isn't shaped for `leaves`, see `can_prove`.
+    pub fn covered_positions(&self, leaves: &[Pos]) -> Vec<Pos> {
+        let Some(item_positions) = self.item_positions(leaves) else {
+            return Vec::new();
+        };
+        let mut positions: Vec<Pos> = leaves
+            .iter()
+            .copied()
+            .chain(
+                item_positions
+                    .into_iter()
+                    .filter(|pos| pos_height_in_tree(*pos) == 0),
+            )
+            .collect();
+        positions.sort_unstable();
+        positions.dedup();
+        positions
+    }
+
+    /// Positions of the proof items if the proof is shaped for `pos_list`, see `can_prove`,
+    /// excluding the item bagging several peaks.
+    fn item_positions(&self, pos_list: &[Pos]) -> Option<Vec<Pos>> {
+        if !self.can_prove(pos_list) {
+            return None;
+        }
+        let mut pos_list = pos_list.to_vec();
+        pos_list.sort_unstable();
+        pos_list.dedup();
+        let (mut proof_pos, bagging_track) = gen_proof_positions(self.mmr_size, pos_list).ok()?;
+        if self.proof.len() != proof_pos.len() {
+            split_bagged_pos(&mut proof_pos, bagging_track);
+        }
+        Some(proof_pos)
+    }
+}
+
+#[cfg(feature = "scale")]
+impl<T: codec::Decode, M> MerkleProof<T, M> {
+    /// Decode a proof from exactly `bytes`, failing with `UnsupportedProofVersion` if it was
+    /// encoded in another format version and with `CorruptedProof` if it is malformed.
+    pub fn from_encoded(bytes: &[u8]) -> Result<Self> {
+        match bytes.first() {
+            Some(&version) if version != Self::FORMAT_VERSION => {
+                Err(Error::UnsupportedProofVersion { version })
+            }
+            _ => codec::DecodeAll::decode_all(&mut &bytes[..]).map_err(|_| Error::CorruptedProof),
+        }
+    }
+}
+
+#[cfg(feature = "scale")]
+impl<T: codec::Encode, M> codec::Encode for MerkleProof<T, M> {
+    fn size_hint(&self) -> usize {
+        1 + codec::Compact(self.mmr_size).size_hint() + self.proof.size_hint()
+    }
+
+    fn encode_to<W: codec::Output + ?Sized>(&self, dest: &mut W) {
+        Self::FORMAT_VERSION.encode_to(dest);
+        codec::Compact(self.mmr_size).encode_to(dest);
+        self.proof.encode_to(dest);
+    }
+}
+
+#[cfg(feature = "scale")]
+impl<T: codec::Encode, M> codec::EncodeLike for MerkleProof<T, M> {}
+
+/// Decoding checks the version and goes through `MerkleProof::try_new`, so a decoded proof may
+/// still not be canonical for the leaves it is verified against, see `validate_canonical`.
+#[cfg(feature = "scale")]
+impl<T: codec::Decode, M> codec::Decode for MerkleProof<T, M> {
+    fn decode<I: codec::Input>(input: &mut I) -> core::result::Result<Self, codec::Error> {
+        if u8::decode(input)? != Self::FORMAT_VERSION {
+            return Err("Unsupported proof version".into());
+        }
+        let mmr_size = codec::Compact::<Pos>::decode(input)?.0;
+        let proof = Vec::<T>::decode(input)?;
+        MerkleProof::try_new(mmr_size, proof).map_err(|_| "Invalid proof".into())
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a, T: arbitrary::Arbitrary<'a>, M> arbitrary::Arbitrary<'a> for MerkleProof<T, M> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(MerkleProof::new(u.arbitrary()?, u.arbitrary()?))
+    }
+}
+
+/// An archived proof is read in place, e.g. from a memory-mapped file, after `rkyv::access`
+/// checked its bytes. It verifies without being deserialized if the items of `M` are the
+/// archived items, e.g. byte array hashes, which archive as themselves.
+#[cfg(feature = "rkyv")]
+impl<T: rkyv::Archive, M> ArchivedMerkleProof<T, M> {
+    pub fn mmr_size(&self) -> Pos {
+        self.mmr_size.to_native()
+    }
+
+    pub fn proof_items(&self) -> &[T::Archived] {
+        &self.proof
+    }
+
+    /// Deserialize the proof, failing like `MerkleProof::try_new`, which checks no more than
+    /// the size and the number of items.
+    pub fn to_proof(&self) -> Result<MerkleProof<T, M>>
+    where
+        T::Archived: rkyv::Deserialize<T, rkyv::api::high::HighDeserializer<rkyv::rancor::Error>>,
+    {
+        let proof = rkyv::deserialize::<Vec<T>, rkyv::rancor::Error>(&self.proof)
+            .map_err(|_| Error::CorruptedProof)?;
+        MerkleProof::try_new(self.mmr_size(), proof)
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl<T: rkyv::Archive, M: Merge<Item = T::Archived>> ArchivedMerkleProof<T, M>
+where
+    T::Archived: Clone + PartialEq,
+{
+    /// Same as `MerkleProof::calculate_root`, reading the items in place.
+    pub fn calculate_root(&self, leaves: Vec<(Pos, T::Archived)>) -> Result<T::Archived> {
+        verifier::calculate_root::<_, M>(leaves, self.mmr_size(), &self.proof)
+    }
+
+    /// Same as `MerkleProof::verify`, reading the items in place.
+    pub fn verify(&self, root: T::Archived, leaves: Vec<(Pos, T::Archived)>) -> Result<bool> {
+        self.calculate_root(leaves)
+            .map(|calculated_root| calculated_root == root)
+    }
+}
+
+impl<T: PartialEq, M: Merge<Item = T>> MerkleProof<T, M> {
+    /// Same as `calculate_root`, but consumes the proof so its items are moved rather than cloned.
+    pub fn into_calculate_root(self, leaves: Vec<(Pos, T)>) -> Result<T> {
+        calculate_root_from_iter::<_, M, _>(
+            leaves,
+            self.mmr_size,
+            self.proof.into_iter(),
+            &mut Iterations::unbounded(),
+        )
+    }
+
+    /// Same as `verify`, but consumes the proof so `T` doesn't need to be `Clone`.
+    pub fn into_verify(self, root: T, leaves: Vec<(Pos, T)>) -> Result<bool> {
+        self.into_calculate_root(leaves)
+            .map(|calculated_root| calculated_root == root)
+    }
+
+    /// Verifies leaves against the root of a single mountain instead of the bagged root.
+    ///
+    /// All leaves must be under the peak at `peak_pos`. Every peak left of it contributes exactly
+    /// one proof item (its root), which is skipped; the items right of the mountain are ignored.
+    pub fn verify_under_peak(
+        &self,
+        peak_hash: T,
+        peak_pos: Pos,
+        mut leaves: Vec<(Pos, T)>,
+    ) -> Result<bool> {
+        let peaks = get_peaks(self.mmr_size);
+        let peak_index = peaks
+            .iter()
+            .position(|&pos| pos == peak_pos)
+            .ok_or(Error::CorruptedProof)?;
+        if leaves.iter().any(|(pos, _)| pos_height_in_tree(*pos) > 0) {
+            return Err(Error::GenProofForInvalidLeaves);
+        }
+        let lower_bound = peak_index.checked_sub(1).map_or(0, |i| peaks[i] + 1);
+        if leaves.is_empty()
+            || leaves
+                .iter()
+                .any(|(pos, _)| *pos < lower_bound || *pos > peak_pos)
+        {
+            return Err(Error::CorruptedProof);
+        }
+        // ensure leaves are sorted and unique
+        sort_and_dedup_by_pos(&mut leaves)?;
+
+        let peak_root = if leaves.len() == 1 && leaves[0].0 == peak_pos {
+            leaves.remove(0).1
+        } else {
+            let mut proof_iter = self.proof.iter().skip(peak_index);
+            calculate_peak_root_from_iter::<_, M, _>(
+                leaves,
+                peak_pos,
+                &mut proof_iter,
+                &mut Iterations::unbounded(),
+            )?
+        };
+        Ok(peak_root == peak_hash)
+    }
+}
+
+impl<T: Clone + PartialEq, M: Merge<Item = T>> MerkleProof<T, M> {
+    pub fn calculate_root(&self, leaves: Vec<(Pos, T)>) -> Result<T> {
+        verifier::calculate_root::<_, M>(leaves, self.mmr_size, &self.proof)
+    }
+
+    /// Same as `calculate_root`, but fails with `LimitExceeded` once `limits` are exceeded.
+    pub fn calculate_root_with_limits(
+        &self,
+        leaves: Vec<(Pos, T)>,
+        limits: &VerifyLimits,
+    ) -> Result<T> {
+        let mut iterations = limits.iterations(self.proof.len(), leaves.len())?;
+        calculate_root_from_iter::<_, M, _>(
+            leaves,
+            self.mmr_size,
+            self.proof.iter(),
+            &mut iterations,
+        )
     }
 
     /// from merkle proof of leaf n to calculate merkle root of n + 1 leaves.
@@ -490,16 +1919,20 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>> MerkleProof<T, M> {
     /// this is kinda tricky, but it works, and useful
     pub fn calculate_root_with_new_leaf(
         &self,
-        mut leaves: Vec<(u64, T)>,
-        new_pos: u64,
+        mut leaves: Vec<(Pos, T)>,
+        new_pos: Pos,
         new_elem: T,
-        new_mmr_size: u64,
+        new_mmr_size: Pos,
     ) -> Result<T> {
         let pos_height = pos_height_in_tree(new_pos);
-        let next_height = pos_height_in_tree(new_pos + 1);
-        if next_height > pos_height {
-            let mut peaks_hashes =
-                calculate_peaks_hashes::<_, M, _>(leaves, self.mmr_size, self.proof.iter())?;
+        let (_, _, is_right) = PostOrderScheme::sibling_and_parent_pos(new_pos, pos_height);
+        if is_right {
+            let mut peaks_hashes = calculate_peaks_hashes::<_, M, _>(
+                leaves,
+                self.mmr_size,
+                self.proof.iter(),
+                &mut Iterations::unbounded(),
+            )?;
             let peaks_pos = get_peaks(new_mmr_size);
             // reverse touched peaks
             let mut i = 0;
@@ -507,18 +1940,207 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>> MerkleProof<T, M> {
                 i += 1
             }
             peaks_hashes[i..].reverse();
-            calculate_root::<_, M, _>(vec![(new_pos, new_elem)], new_mmr_size, peaks_hashes.iter())
+            calculate_root_from_iter::<_, M, _>(
+                vec![(new_pos, new_elem)],
+                new_mmr_size,
+                peaks_hashes.into_iter(),
+                &mut Iterations::unbounded(),
+            )
         } else {
             leaves.push((new_pos, new_elem));
-            calculate_root::<_, M, _>(leaves, new_mmr_size, self.proof.iter())
+            calculate_root_from_iter::<_, M, _>(
+                leaves,
+                new_mmr_size,
+                self.proof.iter(),
+                &mut Iterations::unbounded(),
+            )
         }
     }
 
-    pub fn verify(&self, root: T, leaves: Vec<(u64, T)>) -> Result<bool> {
+    /// from merkle proof of leaves to calculate merkle root after appending `new_leaves`.
+    /// the proof must yield every peak hash, i.e. it must not contain a bagged rhs peak, which
+    /// holds for any proof that includes the last leaf.
+    /// `new_leaves` are the positions and hashes of the appended leaves in insertion order.
+    pub fn calculate_root_with_new_leaves(
+        &self,
+        leaves: Vec<(Pos, T)>,
+        new_leaves: Vec<(Pos, T)>,
+        new_mmr_size: Pos,
+    ) -> Result<T> {
+        let peaks_pos = get_peaks(self.mmr_size);
+        let peaks_hashes = calculate_peaks_hashes::<_, M, _>(
+            leaves,
+            self.mmr_size,
+            self.proof.iter(),
+            &mut Iterations::unbounded(),
+        )?;
+        if peaks_hashes.len() != peaks_pos.len() {
+            return Err(Error::CorruptedProof);
+        }
+        let mut peaks: Vec<(Pos, T)> = peaks_pos.into_iter().zip(peaks_hashes).collect();
+
+        // replay the pushes on the peaks only
+        let mut mmr_size = self.mmr_size;
+        for (pos, leaf) in new_leaves {
+            if pos != mmr_size {
+                return Err(Error::GenProofForInvalidLeaves);
+            }
+            mmr_size = push_to_peaks::<_, M>(&mut peaks, mmr_size, leaf)?;
+        }
+        if mmr_size != new_mmr_size {
+            return Err(Error::CorruptedProof);
+        }
+        bagging_peaks_hashes::<_, M>(peaks.into_iter().map(|(_pos, peak)| peak).collect())
+    }
+
+    /// Verify `leaves` against `root`. The leaves may come in any order, they are sorted by
+    /// position first, and a position given twice with different leaves fails with
+    /// `CorruptedProof`.
+    pub fn verify(&self, root: T, leaves: Vec<(Pos, T)>) -> Result<bool> {
         self.calculate_root(leaves)
             .map(|calculated_root| calculated_root == root)
     }
 
+    /// Same as `verify`, for `leaves` given by leaf index rather than position.
+    pub fn verify_leaves(&self, root: T, leaves: Vec<(LeafIndex, T)>) -> Result<bool> {
+        self.verify(
+            root,
+            leaves
+                .into_iter()
+                .map(|(leaf, item)| (leaf.to_pos().get(), item))
+                .collect(),
+        )
+    }
+
+    /// Same as `verify`, but compares the roots in constant time.
+    #[cfg(feature = "subtle")]
+    pub fn verify_ct(&self, root: T, leaves: Vec<(Pos, T)>) -> Result<bool>
+    where
+        T: subtle::ConstantTimeEq,
+    {
+        self.calculate_root(leaves)
+            .map(|calculated_root| calculated_root.ct_eq(&root).into())
+    }
+
+    /// Same as `verify`, but fails with `LimitExceeded` once `limits` are exceeded.
+    pub fn verify_with_limits(
+        &self,
+        root: T,
+        leaves: Vec<(Pos, T)>,
+        limits: &VerifyLimits,
+    ) -> Result<bool> {
+        self.calculate_root_with_limits(leaves, limits)
+            .map(|calculated_root| calculated_root == root)
+    }
+
+    /// Same as `verify`, reading the leaves at `pos_list` from `store`, e.g. to audit a store
+    /// against a trusted root.
+    ///
+    /// Fails with `InconsistentStore` if the store is missing one of the leaves.
+    #[cfg(not(feature = "verify-only"))]
+    pub fn verify_positions_against_store<S: MMRStoreReadOps<T>>(
+        &self,
+        root: T,
+        store: S,
+        mut pos_list: Vec<Pos>,
+    ) -> Result<bool> {
+        pos_list.sort_unstable();
+        pos_list.dedup();
+        let leaves = pos_list
+            .into_iter()
+            .map(|pos| {
+                let leaf = store
+                    .get_elem(pos)?
+                    .ok_or(Error::InconsistentStore { pos })?;
+                Ok((pos, leaf))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.verify(root, leaves)
+    }
+
+    /// Same as `verify`, taking the hashes of `known_peaks` from the caller rather than the
+    /// proof, e.g. peaks a light client verified against a root before and keeps across calls.
+    ///
+    /// The known peaks are trusted. The peak of every mountain with leaves is compared to its
+    /// known peak, so a mismatch fails before the rest of the proof is hashed, and once all of
+    /// them match the leaves are proven without looking at the mountains without leaves or
+    /// bagging the peaks into `root`. Otherwise the peaks are bagged as in `verify`, the known
+    /// peaks standing in for the items of mountains without leaves. Fails with `InvalidPeaks`
+    /// if a known peak isn't a peak of the MMR.
+    pub fn verify_with_known_peaks(
+        &self,
+        root: T,
+        mut leaves: Vec<(Pos, T)>,
+        known_peaks: &[(Pos, T)],
+    ) -> Result<bool> {
+        let peaks = get_peaks(self.mmr_size);
+        if known_peaks.iter().any(|(pos, _)| !peaks.contains(pos)) {
+            return Err(Error::InvalidPeaks {
+                mmr_size: self.mmr_size,
+            });
+        }
+        if leaves.iter().any(|(pos, _)| pos_height_in_tree(*pos) > 0) {
+            return Err(Error::GenProofForInvalidLeaves);
+        }
+        if leaves.is_empty() {
+            return Err(Error::CorruptedProof);
+        }
+        // ensure leaves are sorted and unique
+        sort_and_dedup_by_pos(&mut leaves)?;
+        let known_peak = |peak_pos: Pos| {
+            known_peaks
+                .iter()
+                .find(|(pos, _)| *pos == peak_pos)
+                .map(|(_, peak)| peak)
+        };
+
+        let mut proof_iter = self.proof.iter();
+        let mut peaks_hashes = Vec::with_capacity(peaks.len());
+        let mut all_known = true;
+        for peak_pos in peaks {
+            if leaves.is_empty() {
+                // the rhs peaks are bagged into one item
+                if let Some(rhs_peaks_hashes) = proof_iter.next() {
+                    peaks_hashes.push(rhs_peaks_hashes.clone());
+                }
+                break;
+            }
+            let mut peak_leaves = take_while_vec(&mut leaves, |(pos, _)| *pos <= peak_pos);
+            let peak_root = if peak_leaves.is_empty() {
+                let item = proof_iter.next().ok_or(Error::CorruptedProof)?;
+                known_peak(peak_pos).unwrap_or(item).clone()
+            } else {
+                let peak_root = if peak_leaves.len() == 1 && peak_leaves[0].0 == peak_pos {
+                    // leaf is the peak
+                    peak_leaves.remove(0).1
+                } else {
+                    calculate_peak_root_from_iter::<_, M, _>(
+                        peak_leaves,
+                        peak_pos,
+                        &mut proof_iter,
+                        &mut Iterations::unbounded(),
+                    )?
+                };
+                match known_peak(peak_pos) {
+                    Some(known) if *known != peak_root => return Ok(false),
+                    Some(_) => {}
+                    None => all_known = false,
+                }
+                peak_root
+            };
+            peaks_hashes.push(peak_root);
+        }
+
+        // ensure nothing left in leaves and proof_iter
+        if !leaves.is_empty() || proof_iter.next().is_some() {
+            return Err(Error::CorruptedProof);
+        }
+        if all_known {
+            return Ok(true);
+        }
+        bagging_peaks_hashes::<_, M>(peaks_hashes).map(|calculated_root| calculated_root == root)
+    }
+
     /// Verifies a old root and all incremental leaves.
     ///
     /// If this method returns `true`, it means the following assertion are true:
@@ -528,11 +2150,11 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>> MerkleProof<T, M> {
     ///   current MMR.
     pub fn verify_incremental(&self, root: T, prev_root: T, incremental: Vec<T>) -> Result<bool> {
         let current_leaves_count = get_peak_map(self.mmr_size);
-        if current_leaves_count <= incremental.len() as u64 {
+        if current_leaves_count <= incremental.len() as Pos {
             return Err(Error::CorruptedProof);
         }
         // Test if previous root is correct.
-        let prev_leaves_count = current_leaves_count - incremental.len() as u64;
+        let prev_leaves_count = current_leaves_count - incremental.len() as Pos;
         let prev_peaks_positions = {
             let prev_index = prev_leaves_count - 1;
             let prev_mmr_size = leaf_index_to_mmr_size(prev_index);
@@ -566,7 +2188,7 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>> MerkleProof<T, M> {
             .into_iter()
             .enumerate()
             .map(|(index, leaf)| {
-                let pos = leaf_index_to_pos(prev_leaves_count + index as u64);
+                let pos = leaf_index_to_pos(prev_leaves_count + index as Pos);
                 (pos, leaf)
             })
             .collect();
@@ -574,153 +2196,277 @@ impl<T: Clone + PartialEq, M: Merge<Item = T>> MerkleProof<T, M> {
     }
 }
 
-fn calculate_peak_root<'a, T: 'a, M: Merge<Item = T>, I: Iterator<Item = &'a T>>(
-    leaves: Vec<(u64, T)>,
-    peak_pos: u64,
-    proof_iter: &mut I,
-) -> Result<T> {
-    debug_assert!(!leaves.is_empty(), "can't be empty");
-    // (position, hash, height)
+/// generate the positions of the merkle proof items for a peak
+/// the pos_list must be sorted, otherwise the behaviour is undefined
+///
+/// 1. find a lower tree in peak that can generate a complete merkle proof for position
+/// 2. find that tree by compare positions
+/// 3. generate proof for each positions
+fn gen_proof_for_peak(
+    proof_pos: &mut Vec<Pos>,
+    pos_list: &[Pos],
+    peak_pos: Pos,
+    queue: &mut VecDeque<(Pos, u8)>,
+) -> Result<()> {
+    // do nothing if position itself is the peak
+    if pos_list.len() == 1 && pos_list == [peak_pos] {
+        return Ok(());
+    }
+    // take peak root from store if no positions need to be proof
+    if pos_list.is_empty() {
+        proof_pos.push(peak_pos);
+        return Ok(());
+    }
 
-    let mut queue: VecDeque<_> = leaves
-        .into_iter()
-        .map(|(pos, item)| (pos, item, 0))
-        .collect();
+    queue.clear();
+    queue.extend(pos_list.iter().map(|&pos| (pos, 0)));
 
-    // calculate tree root from each items
-    while let Some((pos, item, height)) = queue.pop_front() {
+    // Generate sub-tree merkle proof for positions
+    while let Some((pos, height)) = queue.pop_front() {
+        debug_assert!(pos <= peak_pos);
         if pos == peak_pos {
             if queue.is_empty() {
-                // return root once queue is consumed
-                return Ok(item);
+                break;
             } else {
                 return Err(Error::CorruptedProof);
             }
         }
+
         // calculate sibling
-        let next_height = pos_height_in_tree(pos + 1);
-        let (parent_pos, parent_item) = {
-            let sibling_offset = sibling_offset(height);
-            if next_height > height {
-                // implies pos is right sibling
-                let sib_pos = pos - sibling_offset;
-                let parent_pos = pos + 1;
-                let parent_item = if Some(&sib_pos) == queue.front().map(|(pos, _, _)| pos) {
-                    let sibling_item = queue.pop_front().map(|(_, item, _)| item).unwrap();
-                    M::merge(&sibling_item, &item)?
-                } else {
-                    let sibling_item = proof_iter.next().ok_or(Error::CorruptedProof)?;
-                    M::merge(sibling_item, &item)?
-                };
-                (parent_pos, parent_item)
-            } else {
-                // pos is left sibling
-                let sib_pos = pos + sibling_offset;
-                let parent_pos = pos + parent_offset(height);
-                let parent_item = if Some(&sib_pos) == queue.front().map(|(pos, _, _)| pos) {
-                    let sibling_item = queue.pop_front().map(|(_, item, _)| item).unwrap();
-                    M::merge(&item, &sibling_item)?
-                } else {
-                    let sibling_item = proof_iter.next().ok_or(Error::CorruptedProof)?;
-                    M::merge(&item, sibling_item)?
-                };
-                (parent_pos, parent_item)
-            }
-        };
+        let (sib_pos, parent_pos, _) = PostOrderScheme::sibling_and_parent_pos(pos, height);
 
-        if parent_pos <= peak_pos {
-            queue.push_back((parent_pos, parent_item, height + 1))
+        if Some(&sib_pos) == queue.front().map(|(pos, _)| pos) {
+            // drop sibling
+            queue.pop_front();
         } else {
-            return Err(Error::CorruptedProof);
+            proof_pos.push(sib_pos);
+        }
+        if parent_pos < peak_pos {
+            // save pos to tree buf
+            queue.push_back((parent_pos, height + 1));
         }
     }
-    Err(Error::CorruptedProof)
+    Ok(())
 }
 
-fn calculate_peaks_hashes<'a, T: 'a + Clone, M: Merge<Item = T>, I: Iterator<Item = &'a T>>(
-    mut leaves: Vec<(u64, T)>,
-    mmr_size: u64,
-    mut proof_iter: I,
-) -> Result<Vec<T>> {
-    if leaves.iter().any(|(pos, _)| pos_height_in_tree(*pos) > 0) {
+/// Positions of the proof items for the sorted and unique leaf `pos_list`, along with the number
+/// of trailing peaks to bag into a single item.
+fn gen_proof_positions(mmr_size: Pos, pos_list: Vec<Pos>) -> Result<(Vec<Pos>, usize)> {
+    let mut proof_pos = Vec::new();
+    let bagging_track =
+        gen_proof_positions_into(mmr_size, &pos_list, &mut proof_pos, &mut VecDeque::new())?;
+    Ok((proof_pos, bagging_track))
+}
+
+/// Same as `gen_proof_positions`, appending the positions to `proof_pos` and returning the
+/// number of trailing peaks to bag.
+fn gen_proof_positions_into(
+    mmr_size: Pos,
+    mut pos_list: &[Pos],
+    proof_pos: &mut Vec<Pos>,
+    queue: &mut VecDeque<(Pos, u8)>,
+) -> Result<usize> {
+    // generate merkle proof for each peaks
+    let mut bagging_track = 0;
+    for peak_pos in iter_peaks(mmr_size) {
+        let (peak_pos_list, rest) =
+            pos_list.split_at(pos_list.partition_point(|&pos| pos <= peak_pos));
+        pos_list = rest;
+        if peak_pos_list.is_empty() {
+            bagging_track += 1;
+        } else {
+            bagging_track = 0;
+        }
+        gen_proof_for_peak(proof_pos, peak_pos_list, peak_pos, queue)?;
+    }
+
+    // ensure no remain positions
+    if !pos_list.is_empty() {
         return Err(Error::GenProofForInvalidLeaves);
     }
+    Ok(bagging_track)
+}
+
+/// Fail with `Cancelled` if `cancel` is set.
+#[cfg(not(feature = "verify-only"))]
+pub(crate) fn check_cancelled(cancel: Option<&AtomicBool>) -> Result<()> {
+    match cancel {
+        Some(cancel) if cancel.load(Ordering::Relaxed) => Err(Error::Cancelled),
+        _ => Ok(()),
+    }
+}
+
+/// Fail with `PositionOutOfRange` at the first of `pos_list` beyond an MMR of `mmr_size`, before
+/// walking the peaks for a proof.
+#[cfg(not(feature = "verify-only"))]
+fn check_in_range(mmr_size: Pos, pos_list: &[Pos]) -> Result<()> {
+    match pos_list.iter().find(|pos| **pos >= mmr_size) {
+        Some(&pos) => Err(Error::PositionOutOfRange { pos }),
+        None => Ok(()),
+    }
+}
 
-    // special handle the only 1 leaf MMR
-    if mmr_size == 1 && leaves.len() == 1 && leaves[0].0 == 0 {
-        return Ok(leaves.into_iter().map(|(_pos, item)| item).collect());
+/// Positions of the nodes a proof of the leaves at `pos_list` is made of, in proof item order,
+/// without reading or hashing anything, e.g. to request them from a peer before proving.
+///
+/// The peaks right of the last mountain with a leaf are listed one by one, while `gen_proof`
+/// bags them into a single trailing item if there are several.
+pub fn proof_positions(mmr_size: Pos, mut pos_list: Vec<Pos>) -> Result<Vec<Pos>> {
+    if !is_valid_mmr_size(mmr_size) {
+        return Err(Error::InvalidMMRSize { mmr_size });
+    }
+    // ensure positions are sorted and unique
+    pos_list.sort_unstable();
+    pos_list.dedup();
+    if pos_list.is_empty()
+        || pos_list
+            .iter()
+            .any(|pos| pos_height_in_tree(*pos) > 0 || *pos >= mmr_size)
+    {
+        return Err(Error::GenProofForInvalidLeaves);
+    }
+    gen_proof_positions(mmr_size, pos_list).map(|(proof_pos, _)| proof_pos)
+}
+
+/// Remove the positions of the peaks that get bagged, returning the leftmost of them to identify
+/// the bagged item.
+fn split_bagged_pos(proof_pos: &mut Vec<Pos>, bagging_track: usize) -> Option<Pos> {
+    if bagging_track > 1 {
+        proof_pos.truncate(proof_pos.len() - bagging_track + 1);
+        proof_pos.pop()
+    } else {
+        None
+    }
+}
+
+/// Verify `leaves` against `root` with the `(pos, item)` proof items of
+/// `MMR::gen_proof_streaming`, consuming them one at a time in ascending position order.
+///
+/// Leaves and items are merged into subtrees as soon as both children are known, so only the
+/// roots of the unfinished subtrees are held besides the leaves.
+pub fn verify_proof_streaming<T: PartialEq, M: Merge<Item = T>>(
+    root: T,
+    mmr_size: Pos,
+    mut leaves: Vec<(Pos, T)>,
+    proof_items: impl IntoIterator<Item = (Pos, T)>,
+) -> Result<bool> {
+    if !is_valid_mmr_size(mmr_size) {
+        return Err(Error::InvalidMMRSize { mmr_size });
+    }
+    if leaves.is_empty()
+        || leaves
+            .iter()
+            .any(|(pos, _)| pos_height_in_tree(*pos) > 0 || *pos >= mmr_size)
+    {
+        return Err(Error::GenProofForInvalidLeaves);
     }
     // ensure leaves are sorted and unique
-    leaves.sort_by_key(|(pos, _)| *pos);
-    leaves.dedup_by(|a, b| a.0 == b.0);
-    let peaks = get_peaks(mmr_size);
+    sort_and_dedup_by_pos(&mut leaves)?;
 
-    let mut peaks_hashes: Vec<T> = Vec::with_capacity(peaks.len() + 1);
-    for peak_pos in peaks {
-        let mut leaves: Vec<_> = take_while_vec(&mut leaves, |(pos, _)| *pos <= peak_pos);
-        let peak_root = if leaves.len() == 1 && leaves[0].0 == peak_pos {
-            // leaf is the peak
-            leaves.remove(0).1
-        } else if leaves.is_empty() {
-            // if empty, means the next proof is a peak root or rhs bagged root
-            if let Some(peak_root) = proof_iter.next() {
-                peak_root.clone()
-            } else {
-                // means that either all right peaks are bagged, or proof is corrupted
-                // so we break loop and check no items left
+    let mut leaves = leaves.into_iter().peekable();
+    let mut proof_items = proof_items.into_iter().peekable();
+    // roots of the subtrees whose parent isn't known yet, from left to right
+    let mut subtrees: Vec<(Pos, T)> = Vec::new();
+    loop {
+        let next = match (leaves.peek(), proof_items.peek()) {
+            (Some((leaf_pos, _)), Some((item_pos, _))) if leaf_pos < item_pos => leaves.next(),
+            (_, Some(_)) => proof_items.next(),
+            (Some(_), None) => leaves.next(),
+            (None, None) => break,
+        };
+        let (mut pos, mut item) = next.expect("peeked");
+        // positions must be ascending and beyond the subtrees seen so far
+        if pos >= mmr_size
+            || subtrees
+                .last()
+                .is_some_and(|(last_pos, _)| pos <= *last_pos)
+        {
+            return Err(Error::CorruptedProof);
+        }
+        let mut height = pos_height_in_tree(pos);
+        // merge right children with their left sibling
+        loop {
+            let (sib_pos, parent_pos, is_right) =
+                PostOrderScheme::sibling_and_parent_pos(pos, height);
+            if !is_right || subtrees.last().map(|(last_pos, _)| *last_pos) != Some(sib_pos) {
                 break;
             }
-        } else {
-            calculate_peak_root::<_, M, _>(leaves, peak_pos, &mut proof_iter)?
-        };
-        peaks_hashes.push(peak_root.clone());
+            let (_, sibling) = subtrees.pop().expect("checked");
+            pos = parent_pos;
+            height += 1;
+            metrics::on_verification_steps(1);
+            item = M::merge_with_pos(pos, &sibling, &item).map_err(Into::into)?;
+        }
+        subtrees.push((pos, item));
     }
 
-    // ensure nothing left in leaves
-    if !leaves.is_empty() {
+    // everything must have been merged up to the peaks
+    if !subtrees
+        .iter()
+        .map(|(pos, _)| *pos)
+        .eq(iter_peaks(mmr_size))
+    {
         return Err(Error::CorruptedProof);
     }
+    let peaks = subtrees.into_iter().map(|(_, peak)| peak).collect();
+    bagging_peaks_hashes::<_, M>(peaks).map(|calculated_root| calculated_root == root)
+}
 
-    // check rhs peaks
-    if let Some(rhs_peaks_hashes) = proof_iter.next() {
-        peaks_hashes.push(rhs_peaks_hashes.clone());
+/// Verify `leaves` against the individual `peaks` of an MMR of `mmr_size`, without bagging.
+///
+/// For protocols committing to each peak rather than to the bagged root. `proof_items` are laid
+/// out as in `MerkleProof::proof_items`: the roots of peaks left of the leaves are compared to
+/// the supplied peaks, while the trailing item for the peaks right of the leaves isn't checked.
+pub fn verify_with_peaks<T: PartialEq, M: Merge<Item = T>>(
+    peaks: Vec<(Pos, T)>,
+    mmr_size: Pos,
+    mut leaves: Vec<(Pos, T)>,
+    proof_items: &[T],
+) -> Result<bool> {
+    if !peaks.iter().map(|(pos, _)| *pos).eq(iter_peaks(mmr_size)) {
+        return Err(Error::InvalidPeaks { mmr_size });
     }
-    // ensure nothing left in proof_iter
-    if proof_iter.next().is_some() {
+    if leaves.iter().any(|(pos, _)| pos_height_in_tree(*pos) > 0) {
+        return Err(Error::GenProofForInvalidLeaves);
+    }
+    if leaves.is_empty() {
         return Err(Error::CorruptedProof);
     }
-    Ok(peaks_hashes)
-}
+    // ensure leaves are sorted and unique
+    sort_and_dedup_by_pos(&mut leaves)?;
 
-pub(crate) fn bagging_peaks_hashes<T, M: Merge<Item = T>>(mut peaks_hashes: Vec<T>) -> Result<T> {
-    // bagging peaks
-    // bagging from right to left via hash(right, left).
-    while peaks_hashes.len() > 1 {
-        let right_peak = peaks_hashes.pop().expect("pop");
-        let left_peak = peaks_hashes.pop().expect("pop");
-        peaks_hashes.push(M::merge_peaks(&right_peak, &left_peak)?);
+    let mut proof_iter = proof_items.iter();
+    for (peak_pos, peak) in peaks {
+        if leaves.is_empty() {
+            break;
+        }
+        let mut peak_leaves = take_while_vec(&mut leaves, |(pos, _)| *pos <= peak_pos);
+        let peak_root_matches = if peak_leaves.is_empty() {
+            proof_iter.next().ok_or(Error::CorruptedProof)? == &peak
+        } else if peak_leaves.len() == 1 && peak_leaves[0].0 == peak_pos {
+            // leaf is the peak
+            peak_leaves.remove(0).1 == peak
+        } else {
+            calculate_peak_root_from_iter::<_, M, _>(
+                peak_leaves,
+                peak_pos,
+                &mut proof_iter,
+                &mut Iterations::unbounded(),
+            )? == peak
+        };
+        if !peak_root_matches {
+            return Ok(false);
+        }
     }
-    peaks_hashes.pop().ok_or(Error::CorruptedProof)
-}
 
-/// merkle proof
-/// 1. sort items by position
-/// 2. calculate root of each peak
-/// 3. bagging peaks
-fn calculate_root<'a, T: 'a + Clone, M: Merge<Item = T>, I: Iterator<Item = &'a T>>(
-    leaves: Vec<(u64, T)>,
-    mmr_size: u64,
-    proof_iter: I,
-) -> Result<T> {
-    let peaks_hashes = calculate_peaks_hashes::<_, M, _>(leaves, mmr_size, proof_iter)?;
-    bagging_peaks_hashes::<_, M>(peaks_hashes)
-}
-
-pub(crate) fn take_while_vec<T, P: Fn(&T) -> bool>(v: &mut Vec<T>, p: P) -> Vec<T> {
-    for i in 0..v.len() {
-        if !p(&v[i]) {
-            return v.drain(..i).collect();
-        }
+    // ensure nothing left in leaves
+    if !leaves.is_empty() {
+        return Err(Error::CorruptedProof);
+    }
+    // skip the (bagged) rhs peaks, then ensure nothing left in proof_iter
+    proof_iter.next();
+    if proof_iter.next().is_some() {
+        return Err(Error::CorruptedProof);
     }
-    v.drain(..).collect()
+    Ok(true)
 }
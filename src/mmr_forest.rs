@@ -0,0 +1,237 @@
+//! Forests of named MMRs
+//!
+//! Logs with several streams, e.g. events and blocks, often want a single commitment across
+//! them. A `MmrForest` keeps one MMR per name in a single store, the name prefixing the keys of
+//! its nodes, and commits to all of them with a super-root: the root of an MMR with one leaf per
+//! name in name order, the merge of the name's `LeafHasher::hash_leaf` and the root of its MMR.
+//! An empty MMR has the root `Merge::empty_root` if there is one, and the name hash alone as its
+//! leaf otherwise, so names can be opened before anything is pushed to them.
+//! A `MmrForestProof` proves leaves of one MMR against the super-root.
+//!
+//! Every MMR commits on its own, so a store needs one transaction around `MmrForest::commit`
+//! for the forest to be written atomically.
+
+use crate::helper::leaf_index_to_pos;
+use crate::mmr_store::{MMRStoreReadOps, MMRStoreWriteOps};
+use crate::string::String;
+use crate::util::MemStore;
+use crate::vec;
+use crate::vec::Vec;
+use crate::{Error, LeafHasher, MerkleProof, Pos, Result, MMR};
+
+/// Read access to a store holding the nodes of several MMRs, apart by name.
+pub trait NamedStoreReadOps<T> {
+    fn get_elem(&self, name: &str, pos: Pos) -> Result<Option<T>>;
+
+    /// The size persisted for the MMR `name`, see `MMRStoreReadOps::mmr_size`.
+    fn mmr_size(&self, _name: &str) -> Result<Option<Pos>> {
+        Ok(None)
+    }
+}
+
+/// Write access to a store holding the nodes of several MMRs, apart by name.
+pub trait NamedStoreWriteOps<T> {
+    fn append(&mut self, name: &str, pos: Pos, elems: Vec<T>) -> Result<()>;
+
+    /// Persist the size of the MMR `name`, see `MMRStoreWriteOps::set_mmr_size`.
+    fn set_mmr_size(&mut self, _name: &str, _mmr_size: Pos) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The MMR `name` of a named store, as a plain MMR store.
+#[derive(Debug, Clone)]
+pub struct PrefixedStore<S> {
+    name: String,
+    store: S,
+}
+
+impl<S> PrefixedStore<S> {
+    pub fn new(name: &str, store: S) -> Self {
+        PrefixedStore {
+            name: String::from(name),
+            store,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<T, S: NamedStoreReadOps<T>> MMRStoreReadOps<T> for PrefixedStore<S> {
+    fn get_elem(&self, pos: Pos) -> Result<Option<T>> {
+        self.store.get_elem(&self.name, pos)
+    }
+
+    fn mmr_size(&self) -> Result<Option<Pos>> {
+        self.store.mmr_size(&self.name)
+    }
+}
+
+impl<T, S: NamedStoreWriteOps<T>> MMRStoreWriteOps<T> for PrefixedStore<S> {
+    fn append(&mut self, pos: Pos, elems: Vec<T>) -> Result<()> {
+        self.store.append(&self.name, pos, elems)
+    }
+
+    fn set_mmr_size(&mut self, mmr_size: Pos) -> Result<()> {
+        self.store.set_mmr_size(&self.name, mmr_size)
+    }
+}
+
+/// The MMR `name` of a `MmrForest`.
+pub type NamedMMR<T, M, S> = MMR<T, M, PrefixedStore<S>>;
+
+/// Named MMRs sharing a store, see the module docs.
+pub struct MmrForest<T, M, S> {
+    /// Sorted by name.
+    mmrs: Vec<(String, NamedMMR<T, M, S>)>,
+}
+
+/// Proof of leaves of one MMR of a `MmrForest` against its super-root.
+#[derive(Debug)]
+pub struct MmrForestProof<T, M> {
+    name: String,
+    /// Index of the MMR in name order, its leaf index under the super-root.
+    mmr_index: Pos,
+    mmr_proof: MerkleProof<T, M>,
+    super_proof: MerkleProof<T, M>,
+}
+
+impl<T, M, S: Clone + NamedStoreReadOps<T>> MmrForest<T, M, S> {
+    /// Open the MMRs `names` in `store`, restoring the sizes of their last commits.
+    ///
+    /// The names make up the forest, so they have to be the same whenever it is opened.
+    pub fn open(store: S, names: &[&str]) -> Result<Self> {
+        let mut names = names.to_vec();
+        names.sort_unstable();
+        names.dedup();
+        let mmrs = names
+            .into_iter()
+            .map(|name| {
+                let mmr = MMR::open(PrefixedStore::new(name, store.clone()))?;
+                Ok((String::from(name), mmr))
+            })
+            .collect::<Result<_>>()?;
+        Ok(MmrForest { mmrs })
+    }
+}
+
+impl<T, M, S> MmrForest<T, M, S> {
+    /// The names of the MMRs in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.mmrs.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn mmr(&self, name: &str) -> Option<&NamedMMR<T, M, S>> {
+        let index = self.index_of(name).ok()?;
+        Some(&self.mmrs[index].1)
+    }
+
+    pub fn mmr_mut(&mut self, name: &str) -> Option<&mut NamedMMR<T, M, S>> {
+        let index = self.index_of(name).ok()?;
+        Some(&mut self.mmrs[index].1)
+    }
+
+    fn index_of(&self, name: &str) -> Result<usize> {
+        self.mmrs
+            .binary_search_by(|(mmr_name, _)| mmr_name.as_str().cmp(name))
+            .map_err(|_| Error::UnknownMmr {
+                name: String::from(name),
+            })
+    }
+}
+
+impl<T: Clone, M: LeafHasher<Item = T>, S: NamedStoreReadOps<T>> MmrForest<T, M, S> {
+    /// Push `leaf` to the MMR `name`, failing with `UnknownMmr` if the forest has none.
+    pub fn push(&mut self, name: &str, leaf: T) -> Result<Pos> {
+        let index = self.index_of(name)?;
+        self.mmrs[index].1.push(leaf)
+    }
+
+    /// The root committing to the roots of all MMRs, including uncommitted pushes.
+    pub fn super_root(&self) -> Result<T> {
+        let store = MemStore::default();
+        self.super_mmr(&store)?.get_root()
+    }
+
+    /// Generate a proof of the leaves at `pos_list` in the MMR `name` against the super-root.
+    pub fn gen_proof(&self, name: &str, pos_list: Vec<Pos>) -> Result<MmrForestProof<T, M>> {
+        let index = self.index_of(name)?;
+        let mmr_proof = self.mmrs[index].1.gen_proof(pos_list)?;
+        let store = MemStore::default();
+        let super_proof = self
+            .super_mmr(&store)?
+            .gen_proof(vec![leaf_index_to_pos(index as Pos)])?;
+        Ok(MmrForestProof {
+            name: String::from(name),
+            mmr_index: index as Pos,
+            mmr_proof,
+            super_proof,
+        })
+    }
+
+    /// The MMR over the super-root leaves, built in `store`.
+    fn super_mmr<'a>(&self, store: &'a MemStore<T>) -> Result<MMR<T, M, &'a MemStore<T>>> {
+        let mut super_mmr = MMR::new(0, store);
+        for (name, mmr) in &self.mmrs {
+            let leaf = if mmr.mmr_size() == 0 && M::empty_root().is_none() {
+                M::hash_leaf(name.as_bytes()).map_err(Into::into)?
+            } else {
+                super_leaf::<T, M>(name, &mmr.get_root()?)?
+            };
+            super_mmr.push(leaf)?;
+        }
+        Ok(super_mmr)
+    }
+}
+
+impl<T: Clone, M: LeafHasher<Item = T>, S: NamedStoreReadOps<T> + NamedStoreWriteOps<T>>
+    MmrForest<T, M, S>
+{
+    /// Commit every MMR, see the module docs on atomicity.
+    pub fn commit(&mut self) -> Result<()> {
+        for (_, mmr) in &mut self.mmrs {
+            mmr.commit()?;
+        }
+        Ok(())
+    }
+}
+
+impl<T, M> MmrForestProof<T, M> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn mmr_index(&self) -> Pos {
+        self.mmr_index
+    }
+
+    /// The proof of the leaves in their MMR.
+    pub fn mmr_proof(&self) -> &MerkleProof<T, M> {
+        &self.mmr_proof
+    }
+
+    /// The proof of the MMR's super-root leaf.
+    pub fn super_proof(&self) -> &MerkleProof<T, M> {
+        &self.super_proof
+    }
+}
+
+impl<T: Clone + PartialEq, M: LeafHasher<Item = T>> MmrForestProof<T, M> {
+    /// Verify `leaves` of the MMR `name` against `super_root`.
+    pub fn verify(&self, super_root: T, leaves: Vec<(Pos, T)>) -> Result<bool> {
+        let root = self.mmr_proof.calculate_root(leaves)?;
+        let super_leaf = super_leaf::<T, M>(&self.name, &root)?;
+        self.super_proof.verify(
+            super_root,
+            vec![(leaf_index_to_pos(self.mmr_index), super_leaf)],
+        )
+    }
+}
+
+/// The leaf committing to the MMR `name` with `root`.
+fn super_leaf<T, M: LeafHasher<Item = T>>(name: &str, root: &T) -> Result<T> {
+    let name_hash = M::hash_leaf(name.as_bytes()).map_err(Into::into)?;
+    M::merge(&name_hash, root).map_err(Into::into)
+}
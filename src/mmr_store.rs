@@ -1,8 +1,18 @@
-use crate::{vec::Vec, Result};
+use crate::helper::get_peak_map;
+use crate::vec;
+use crate::{metrics, string::ToString, vec::Vec, Error, Pos, Result};
+use core::ops::Range;
 
 #[derive(Default)]
 pub struct MMRBatch<Elem, Store> {
-    memory_batch: Vec<(u64, Vec<Elem>)>,
+    memory_batch: Vec<(Pos, Vec<Elem>)>,
+    overwrites: Vec<(Pos, Elem)>,
+    leaf_metas: Vec<(Pos, Vec<u8>)>,
+    #[cfg(feature = "root-log")]
+    roots: Vec<(Pos, Elem)>,
+    #[cfg(feature = "leaf-index")]
+    indexed_leaves: Vec<(Pos, Elem)>,
+    mmr_size: Option<Pos>,
     store: Store,
 }
 
@@ -10,56 +20,421 @@ impl<Elem, Store> MMRBatch<Elem, Store> {
     pub fn new(store: Store) -> Self {
         MMRBatch {
             memory_batch: Vec::new(),
+            overwrites: Vec::new(),
+            leaf_metas: Vec::new(),
+            #[cfg(feature = "root-log")]
+            roots: Vec::new(),
+            #[cfg(feature = "leaf-index")]
+            indexed_leaves: Vec::new(),
+            mmr_size: None,
             store,
         }
     }
 
-    pub fn append(&mut self, pos: u64, elems: Vec<Elem>) {
+    /// Same as `new`, preallocating room for `capacity` pushes, so bulk imports don't reallocate
+    /// the batch while it grows.
+    pub fn with_capacity(store: Store, capacity: usize) -> Self {
+        let mut batch = MMRBatch::new(store);
+        batch.memory_batch.reserve_exact(capacity);
+        batch
+    }
+
+    /// Number of pending elements, including the overwrites of committed ones.
+    pub fn pending_len(&self) -> usize {
+        self.memory_batch
+            .iter()
+            .map(|(_, elems)| elems.len())
+            .sum::<usize>()
+            + self.overwrites.len()
+    }
+
+    /// Estimate of the bytes allocated by the pending elements and metadata.
+    ///
+    /// Only the inline size of the elements is counted, heap data owned by an element, e.g. the
+    /// buffer of a `Vec<u8>` hash, is not.
+    pub fn estimated_memory(&self) -> usize {
+        let elem_size = core::mem::size_of::<Elem>();
+        let mut memory = self.memory_batch.capacity() * core::mem::size_of::<(Pos, Vec<Elem>)>()
+            + self.overwrites.capacity() * core::mem::size_of::<(Pos, Elem)>()
+            + self.leaf_metas.capacity() * core::mem::size_of::<(Pos, Vec<u8>)>();
+        memory += self
+            .memory_batch
+            .iter()
+            .map(|(_, elems)| elems.capacity() * elem_size)
+            .sum::<usize>();
+        memory += self
+            .leaf_metas
+            .iter()
+            .map(|(_, meta)| meta.capacity())
+            .sum::<usize>();
+        #[cfg(feature = "root-log")]
+        {
+            memory += self.roots.capacity() * core::mem::size_of::<(Pos, Elem)>();
+        }
+        #[cfg(feature = "leaf-index")]
+        {
+            memory += self.indexed_leaves.capacity() * core::mem::size_of::<(Pos, Elem)>();
+        }
+        memory
+    }
+
+    pub fn append(&mut self, pos: Pos, elems: Vec<Elem>) {
         self.memory_batch.push((pos, elems));
     }
 
+    /// Replace the element at `pos`, e.g. to repair a corrupted node.
+    pub fn overwrite(&mut self, pos: Pos, elem: Elem) {
+        for (start_pos, elems) in self.memory_batch.iter_mut().rev() {
+            if pos >= *start_pos && pos < *start_pos + elems.len() as Pos {
+                elems[(pos - *start_pos) as usize] = elem;
+                return;
+            }
+        }
+        // committed elements are patched on reads until the next commit
+        self.overwrites
+            .retain(|(overwritten_pos, _)| *overwritten_pos != pos);
+        self.overwrites.push((pos, elem));
+    }
+
+    /// Attach application data to the leaf at `leaf_index`, committed together with the nodes.
+    pub fn append_leaf_meta(&mut self, leaf_index: Pos, meta: Vec<u8>) {
+        self.leaf_metas.push((leaf_index, meta));
+    }
+
+    /// Log the `root` of the MMR of `mmr_size`, committed together with the nodes.
+    ///
+    /// Logging another root for the latest size replaces it.
+    #[cfg(feature = "root-log")]
+    pub fn append_root(&mut self, mmr_size: Pos, root: Elem) {
+        if self.roots.last().is_some_and(|(size, _)| *size == mmr_size) {
+            self.roots.pop();
+        }
+        self.roots.push((mmr_size, root));
+    }
+
+    /// Index the `leaf` at `pos` for `get_leaf_positions`, committed together with the nodes.
+    #[cfg(feature = "leaf-index")]
+    pub fn append_leaf_index(&mut self, pos: Pos, leaf: Elem) {
+        self.indexed_leaves.push((pos, leaf));
+    }
+
+    /// Record the MMR size to persist with the next commit, see `MMRStoreWriteOps::set_mmr_size`.
+    pub fn set_mmr_size(&mut self, mmr_size: Pos) {
+        self.mmr_size = Some(mmr_size);
+    }
+
     pub fn store(&self) -> &Store {
         &self.store
     }
+
+    /// Position of the first pending element, `None` if everything is committed.
+    pub fn pending_start(&self) -> Option<Pos> {
+        self.memory_batch.first().map(|(pos, _)| *pos)
+    }
+
+    /// Drop all pending elements at positions `>= mmr_size`, and the metadata of their leaves.
+    pub fn truncate(&mut self, mmr_size: Pos) {
+        self.memory_batch.retain_mut(|(pos, elems)| {
+            if *pos >= mmr_size {
+                return false;
+            }
+            elems.truncate((mmr_size - *pos) as usize);
+            true
+        });
+        self.overwrites.retain(|(pos, _)| *pos < mmr_size);
+        let leaves_count = get_peak_map(mmr_size);
+        self.leaf_metas
+            .retain(|(leaf_index, _)| *leaf_index < leaves_count);
+        #[cfg(feature = "root-log")]
+        self.roots.retain(|(size, _)| *size <= mmr_size);
+        #[cfg(feature = "leaf-index")]
+        self.indexed_leaves.retain(|(pos, _)| *pos < mmr_size);
+    }
 }
 
 impl<Elem: Clone, Store: MMRStoreReadOps<Elem>> MMRBatch<Elem, Store> {
-    pub fn get_elem(&self, pos: u64) -> Result<Option<Elem>> {
+    pub fn get_elem(&self, pos: Pos) -> Result<Option<Elem>> {
         for (start_pos, elems) in self.memory_batch.iter().rev() {
             if pos < *start_pos {
                 continue;
-            } else if pos < start_pos + elems.len() as u64 {
+            } else if pos < start_pos + elems.len() as Pos {
                 return Ok(elems.get((pos - start_pos) as usize).cloned());
             } else {
                 break;
             }
         }
+        if let Some((_, elem)) = self
+            .overwrites
+            .iter()
+            .find(|(overwritten_pos, _)| *overwritten_pos == pos)
+        {
+            return Ok(Some(elem.clone()));
+        }
+        metrics::on_store_reads(1);
         self.store.get_elem(pos)
     }
+
+    pub fn get_leaf_meta(&self, leaf_index: Pos) -> Result<Option<Vec<u8>>> {
+        if let Some((_, meta)) = self
+            .leaf_metas
+            .iter()
+            .rev()
+            .find(|(index, _)| *index == leaf_index)
+        {
+            return Ok(Some(meta.clone()));
+        }
+        metrics::on_store_reads(1);
+        self.store.get_leaf_meta(leaf_index)
+    }
+
+    /// The root logged for `mmr_size`, including uncommitted ones.
+    #[cfg(feature = "root-log")]
+    pub fn get_root_at(&self, mmr_size: Pos) -> Result<Option<Elem>> {
+        if let Some((_, root)) = self.roots.iter().find(|(size, _)| *size == mmr_size) {
+            return Ok(Some(root.clone()));
+        }
+        metrics::on_store_reads(1);
+        self.store.get_root_at(mmr_size)
+    }
+
+    /// Positions of the indexed leaves equal to `leaf` in ascending order, including
+    /// uncommitted ones.
+    #[cfg(feature = "leaf-index")]
+    pub fn get_leaf_positions(&self, leaf: &Elem) -> Result<Vec<Pos>>
+    where
+        Elem: PartialEq,
+    {
+        metrics::on_store_reads(1);
+        let mut positions = self.store.get_leaf_positions(leaf)?;
+        positions.extend(
+            self.indexed_leaves
+                .iter()
+                .filter(|(_, indexed)| indexed == leaf)
+                .map(|(pos, _)| *pos),
+        );
+        Ok(positions)
+    }
+
+    pub fn get_elems(&self, range: Range<Pos>) -> Result<Vec<Option<Elem>>> {
+        // pending elements always come after the committed ones
+        let batch_start = self.memory_batch.first().map_or(range.end, |(pos, _)| *pos);
+        let store_end = range.end.min(batch_start).max(range.start);
+        if store_end > range.start {
+            metrics::on_store_reads(1);
+        }
+        let mut elems = self.store.get_elems(range.start..store_end)?;
+        for (pos, elem) in &self.overwrites {
+            if (range.start..store_end).contains(pos) {
+                elems[(pos - range.start) as usize] = Some(elem.clone());
+            }
+        }
+        for pos in store_end..range.end {
+            elems.push(self.get_elem(pos)?);
+        }
+        Ok(elems)
+    }
 }
 
-impl<Elem, Store: MMRStoreWriteOps<Elem>> MMRBatch<Elem, Store> {
+impl<Elem: Clone, Store: MMRStoreWriteOps<Elem>> MMRBatch<Elem, Store> {
+    /// Write the batch to the store, wrapped in a store transaction.
+    ///
+    /// If a write fails the transaction is aborted and the batch is kept, so the commit can be
+    /// retried. The batch is only cleared once the store committed the transaction.
     pub fn commit(&mut self) -> Result<()> {
-        for (pos, elems) in self.memory_batch.drain(..) {
+        self.store.start_transaction()?;
+        let committed = match self.write_batch() {
+            Ok(()) => self.store.commit_transaction(),
+            Err(err) => {
+                // the write error is more useful than a failure to abort
+                let _ = self.store.abort_transaction();
+                Err(err)
+            }
+        };
+        if committed.is_ok() {
+            self.clear();
+        }
+        committed
+    }
+
+    fn write_batch(&mut self) -> Result<()> {
+        for (pos, elem) in &self.overwrites {
+            self.store.overwrite(*pos, elem.clone())?;
+        }
+        for (pos, elems) in coalesce_runs(&self.memory_batch) {
             self.store.append(pos, elems)?;
         }
+        for (leaf_index, meta) in &self.leaf_metas {
+            self.store.append_leaf_meta(*leaf_index, meta.clone())?;
+        }
+        #[cfg(feature = "root-log")]
+        for (mmr_size, root) in &self.roots {
+            self.store.append_root(*mmr_size, root.clone())?;
+        }
+        #[cfg(feature = "leaf-index")]
+        for (pos, leaf) in &self.indexed_leaves {
+            self.store.append_leaf_index(*pos, leaf.clone())?;
+        }
+        if let Some(mmr_size) = self.mmr_size {
+            self.store.set_mmr_size(mmr_size)?;
+        }
         Ok(())
     }
+
+    fn clear(&mut self) {
+        self.overwrites.clear();
+        self.memory_batch.clear();
+        self.leaf_metas.clear();
+        #[cfg(feature = "root-log")]
+        self.roots.clear();
+        #[cfg(feature = "leaf-index")]
+        self.indexed_leaves.clear();
+        self.mmr_size = None;
+    }
+}
+
+/// Join the chunks continuing where the previous one ends, so a run of pushes reaches the store
+/// in a single `append`.
+fn coalesce_runs<Elem: Clone>(chunks: &[(Pos, Vec<Elem>)]) -> Vec<(Pos, Vec<Elem>)> {
+    let mut runs: Vec<(Pos, Vec<Elem>)> = Vec::new();
+    for (pos, elems) in chunks {
+        match runs.last_mut() {
+            Some((start_pos, run)) if *start_pos + run.len() as Pos == *pos => {
+                run.extend_from_slice(elems)
+            }
+            _ => runs.push((*pos, elems.clone())),
+        }
+    }
+    runs
+}
+
+impl<Elem, Store: MMRStoreDeleteOps<Elem>> MMRBatch<Elem, Store> {
+    pub fn rewind(&mut self, mmr_size: Pos) -> Result<()> {
+        self.truncate(mmr_size);
+        self.store.truncate(mmr_size)
+    }
 }
 
 impl<Elem, Store> IntoIterator for MMRBatch<Elem, Store> {
-    type Item = (u64, Vec<Elem>);
+    type Item = (Pos, Vec<Elem>);
     type IntoIter = crate::vec::IntoIter<Self::Item>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.memory_batch.into_iter()
+        let mut batch: Vec<_> = self
+            .overwrites
+            .into_iter()
+            .map(|(pos, elem)| (pos, vec![elem]))
+            .collect();
+        batch.extend(self.memory_batch);
+        batch.into_iter()
     }
 }
 
 pub trait MMRStoreReadOps<Elem> {
-    fn get_elem(&self, pos: u64) -> Result<Option<Elem>>;
+    fn get_elem(&self, pos: Pos) -> Result<Option<Elem>>;
+
+    /// Get the elements at `range`, backends can override this with a single range scan.
+    fn get_elems(&self, range: Range<Pos>) -> Result<Vec<Option<Elem>>> {
+        range.map(|pos| self.get_elem(pos)).collect()
+    }
+
+    /// Get the metadata of the leaf at `leaf_index`, stores without a metadata table have none.
+    fn get_leaf_meta(&self, _leaf_index: Pos) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// The root logged for `mmr_size` by `MMRStoreWriteOps::append_root`.
+    #[cfg(feature = "root-log")]
+    fn get_root_at(&self, _mmr_size: Pos) -> Result<Option<Elem>> {
+        Ok(None)
+    }
+
+    /// Positions of the leaves equal to `leaf` indexed by `MMRStoreWriteOps::append_leaf_index`,
+    /// in ascending order. Stores without a leaf index have none.
+    #[cfg(feature = "leaf-index")]
+    fn get_leaf_positions(&self, _leaf: &Elem) -> Result<Vec<Pos>>
+    where
+        Elem: PartialEq,
+    {
+        Ok(Vec::new())
+    }
+
+    /// The MMR size persisted by `MMRStoreWriteOps::set_mmr_size`, `None` if the store doesn't
+    /// track it or no size was committed yet.
+    fn mmr_size(&self) -> Result<Option<Pos>> {
+        Ok(None)
+    }
 }
 
 pub trait MMRStoreWriteOps<Elem> {
-    fn append(&mut self, pos: u64, elems: Vec<Elem>) -> Result<()>;
+    /// Persist `elems` at the positions from `pos` on, past the committed nodes.
+    fn append(&mut self, pos: Pos, elems: Vec<Elem>) -> Result<()>;
+
+    /// Replace the committed node at `pos`, written by `MMRBatch::commit` for the repairs of
+    /// `MMR::rebuild_range`. Append-only stores don't support it.
+    fn overwrite(&mut self, _pos: Pos, _elem: Elem) -> Result<()> {
+        Err(Error::StoreError(
+            "overwriting nodes is not supported".to_string(),
+        ))
+    }
+
+    /// Whether the store persists leaf metadata, checked by `MMR::push_with_leaf_meta` before
+    /// anything is pushed. Stores implementing `append_leaf_meta` return `true`.
+    fn supports_leaf_meta(&self) -> bool {
+        false
+    }
+
+    /// Persist the metadata of the leaf at `leaf_index`, as part of the same commit as `append`.
+    fn append_leaf_meta(&mut self, _leaf_index: Pos, _meta: Vec<u8>) -> Result<()> {
+        Err(Error::StoreError(
+            "leaf metadata is not supported".to_string(),
+        ))
+    }
+
+    /// Whether the store persists logged roots, checked by `MMR::set_root_log`. Stores
+    /// implementing `append_root` return `true`.
+    #[cfg(feature = "root-log")]
+    fn supports_root_log(&self) -> bool {
+        false
+    }
+
+    /// Persist the `root` of the MMR of `mmr_size`, see `root_log`.
+    #[cfg(feature = "root-log")]
+    fn append_root(&mut self, _mmr_size: Pos, _root: Elem) -> Result<()> {
+        Err(Error::StoreError("root log is not supported".to_string()))
+    }
+
+    /// Index the `leaf` at `pos`, see `leaf_index`. Backends typically keep a table keyed by
+    /// the leaf, or a bloom filter in front of one.
+    #[cfg(feature = "leaf-index")]
+    fn append_leaf_index(&mut self, _pos: Pos, _leaf: Elem) -> Result<()> {
+        Err(Error::StoreError("leaf index is not supported".to_string()))
+    }
+
+    /// Persist the MMR size, written by `MMRBatch::commit` after the nodes of the same commit so
+    /// the store can be reopened with `MMR::open`. Stores that don't track the size ignore it.
+    fn set_mmr_size(&mut self, _mmr_size: Pos) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called by `MMRBatch::commit` before the first write, backends can open an atomic
+    /// transaction here so a crash mid-commit doesn't leave a torn MMR.
+    fn start_transaction(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called by `MMRBatch::commit` after all writes of the batch succeeded.
+    fn commit_transaction(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called by `MMRBatch::commit` instead of `commit_transaction` when a write failed.
+    fn abort_transaction(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub trait MMRStoreDeleteOps<Elem> {
+    /// Remove all elements at positions `>= mmr_size`, along with the metadata and index entries
+    /// of their leaves and the roots logged for larger sizes.
+    fn truncate(&mut self, mmr_size: Pos) -> Result<()>;
 }
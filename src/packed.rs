@@ -0,0 +1,255 @@
+//! Leaf packing
+//!
+//! A `PackedMMR` packs `K` application items into every MMR leaf, the root of a perfect binary
+//! tree over the items merged with `PackedMerge::merge_packed`. Logs with many small items keep the MMR, and
+//! thus its proofs and ancestry proofs, `log2(K)` levels shorter, while every item stays
+//! provable with a `PackedItemProof`: the path to its leaf followed by the MMR proof of the leaf.
+//!
+//! Only whole chunks are pushed, the items of an incomplete chunk are held back until it fills,
+//! so `commit` fails while items are pending. The MMR stores leaves only, so proving an item
+//! takes the items of its chunk.
+
+use crate::helper::leaf_index_to_pos;
+use crate::mmr_store::{MMRStoreReadOps, MMRStoreWriteOps};
+use crate::vec;
+use crate::vec::Vec;
+use crate::{Error, Merge, MerkleProof, PackedMerge, Pos, Result, MMR};
+use core::ops::Deref;
+
+pub struct PackedMMR<T, M, S, const K: usize> {
+    mmr: MMR<T, M, S>,
+    pending: Vec<T>,
+}
+
+/// Proof of an item of a `PackedMMR`.
+#[derive(Debug)]
+pub struct PackedItemProof<T, M, const K: usize> {
+    item_index: Pos,
+    /// Siblings of the item within its leaf, bottom-up.
+    item_path: Vec<T>,
+    leaf_proof: MerkleProof<T, M>,
+}
+
+impl<T, M, S, const K: usize> PackedMMR<T, M, S, K> {
+    /// Pack the items into the leaves of `mmr`, which has to be empty or hold packed leaves only.
+    ///
+    /// Fails with `InvalidLeafCount` if `K` is not a power of two.
+    pub fn new(mmr: MMR<T, M, S>) -> Result<Self> {
+        if !K.is_power_of_two() {
+            return Err(Error::InvalidLeafCount {
+                leaf_count: K as Pos,
+            });
+        }
+        Ok(PackedMMR {
+            mmr,
+            pending: Vec::with_capacity(K),
+        })
+    }
+
+    /// Number of items pushed, including the pending ones.
+    pub fn items_count(&self) -> Pos {
+        self.mmr.leaves_count() * K as Pos + self.pending.len() as Pos
+    }
+
+    /// The items of the incomplete chunk, not in the MMR yet.
+    pub fn pending_items(&self) -> &[T] {
+        &self.pending
+    }
+
+    /// The MMR and the pending items, which aren't in it.
+    pub fn into_inner(self) -> (MMR<T, M, S>, Vec<T>) {
+        (self.mmr, self.pending)
+    }
+}
+
+impl<T, M, S, const K: usize> Deref for PackedMMR<T, M, S, K> {
+    type Target = MMR<T, M, S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.mmr
+    }
+}
+
+impl<T: Clone + PartialEq, M: PackedMerge<Item = T>, S: MMRStoreReadOps<T>, const K: usize>
+    PackedMMR<T, M, S, K>
+{
+    /// Push `item`, and the leaf packing its chunk once the chunk is complete. Returns the index
+    /// of the item.
+    ///
+    /// If packing or pushing the leaf fails, the item is not kept, so the chunk stays incomplete
+    /// and the push can be retried.
+    pub fn push_item(&mut self, item: T) -> Result<Pos> {
+        let item_index = self.items_count();
+        self.pending.push(item);
+        if self.pending.len() == K {
+            if let Err(err) = pack_items::<T, M>(&self.pending).and_then(|leaf| self.mmr.push(leaf))
+            {
+                self.pending.pop();
+                return Err(err);
+            }
+            self.pending.clear();
+        }
+        Ok(item_index)
+    }
+
+    /// Generate a proof of the item at `item_index`, given the `chunk` of `K` items it was packed
+    /// with.
+    ///
+    /// Fails with `GenProofForInvalidLeaves` if the item is still pending or the chunk has the
+    /// wrong length, and with `InconsistentStore` if the chunk doesn't pack into the stored leaf.
+    pub fn gen_item_proof(&self, item_index: Pos, chunk: &[T]) -> Result<PackedItemProof<T, M, K>> {
+        let leaf_index = item_index / K as Pos;
+        if leaf_index >= self.mmr.leaves_count() || chunk.len() != K {
+            return Err(Error::GenProofForInvalidLeaves);
+        }
+        let pos = leaf_index_to_pos(leaf_index);
+        let leaf = self
+            .mmr
+            .batch()
+            .get_elem(pos)?
+            .ok_or(Error::InconsistentStore { pos })?;
+        let (packed_leaf, item_path) = item_path::<T, M>(chunk, (item_index % K as Pos) as usize)?;
+        if packed_leaf != leaf {
+            return Err(Error::InconsistentStore { pos });
+        }
+        Ok(PackedItemProof {
+            item_index,
+            item_path,
+            leaf_proof: self.mmr.gen_proof(vec![pos])?,
+        })
+    }
+}
+
+impl<T: Clone, M: Merge<Item = T>, S: MMRStoreReadOps<T> + MMRStoreWriteOps<T>, const K: usize>
+    PackedMMR<T, M, S, K>
+{
+    /// Commit the pushed leaves.
+    ///
+    /// Fails with `PendingItems` unless the items pushed so far fill whole chunks, since the
+    /// items of an incomplete chunk have no leaf to persist them in yet.
+    pub fn commit(&mut self) -> Result<()> {
+        if !self.pending.is_empty() {
+            return Err(Error::PendingItems {
+                count: self.pending.len() as Pos,
+            });
+        }
+        self.mmr.commit()
+    }
+}
+
+impl<T, M, const K: usize> PackedItemProof<T, M, K> {
+    /// Build a proof of the item at `item_index` from the siblings of the item within its leaf,
+    /// bottom-up, and the proof of the leaf.
+    ///
+    /// Fails with `CorruptedProof` unless the path has the `log2(K)` siblings of a chunk.
+    pub fn new(item_index: Pos, item_path: Vec<T>, leaf_proof: MerkleProof<T, M>) -> Result<Self> {
+        if !is_chunk_path::<K>(item_path.len()) {
+            return Err(Error::CorruptedProof);
+        }
+        Ok(PackedItemProof {
+            item_index,
+            item_path,
+            leaf_proof,
+        })
+    }
+
+    pub fn item_index(&self) -> Pos {
+        self.item_index
+    }
+
+    pub fn item_path(&self) -> &[T] {
+        &self.item_path
+    }
+
+    pub fn leaf_proof(&self) -> &MerkleProof<T, M> {
+        &self.leaf_proof
+    }
+}
+
+impl<T: Clone + PartialEq, M: PackedMerge<Item = T>, const K: usize> PackedItemProof<T, M, K> {
+    /// Calculate the leaf packing `item` from the item path.
+    ///
+    /// Fails with `CorruptedProof` unless the path has the `log2(K)` siblings of a chunk.
+    pub fn calculate_leaf(&self, item: T) -> Result<T> {
+        if !is_chunk_path::<K>(self.item_path.len()) {
+            return Err(Error::CorruptedProof);
+        }
+        let mut index = self.item_index;
+        let mut node = item;
+        for sibling in &self.item_path {
+            node = if index & 1 == 0 {
+                M::merge_packed(&node, sibling)
+            } else {
+                M::merge_packed(sibling, &node)
+            }
+            .map_err(Into::into)?;
+            index /= 2;
+        }
+        Ok(node)
+    }
+
+    /// Verify `item` against `root`.
+    pub fn verify(&self, root: T, item: T) -> Result<bool> {
+        let leaf = self.calculate_leaf(item)?;
+        let pos = leaf_index_to_pos(self.item_index / K as Pos);
+        self.leaf_proof.verify(root, vec![(pos, leaf)])
+    }
+}
+
+#[cfg(feature = "scale")]
+impl<T: codec::Encode, M, const K: usize> codec::Encode for PackedItemProof<T, M, K> {
+    fn size_hint(&self) -> usize {
+        codec::Compact(self.item_index).size_hint()
+            + self.item_path.size_hint()
+            + self.leaf_proof.size_hint()
+    }
+
+    fn encode_to<W: codec::Output + ?Sized>(&self, dest: &mut W) {
+        codec::Compact(self.item_index).encode_to(dest);
+        self.item_path.encode_to(dest);
+        self.leaf_proof.encode_to(dest);
+    }
+}
+
+#[cfg(feature = "scale")]
+impl<T: codec::Encode, M, const K: usize> codec::EncodeLike for PackedItemProof<T, M, K> {}
+
+/// Decoding applies the checks of `PackedItemProof::new`.
+#[cfg(feature = "scale")]
+impl<T: codec::Decode, M, const K: usize> codec::Decode for PackedItemProof<T, M, K> {
+    fn decode<I: codec::Input>(input: &mut I) -> core::result::Result<Self, codec::Error> {
+        let item_index = codec::Compact::<Pos>::decode(input)?.0;
+        let item_path = Vec::<T>::decode(input)?;
+        let leaf_proof = MerkleProof::decode(input)?;
+        PackedItemProof::new(item_index, item_path, leaf_proof)
+            .map_err(|_| "Invalid packed item proof".into())
+    }
+}
+
+/// Whether a path of `len` siblings leads from an item to the root of a chunk of `K` items.
+fn is_chunk_path<const K: usize>(len: usize) -> bool {
+    K.is_power_of_two() && len == K.trailing_zeros() as usize
+}
+
+/// Merge the items of a chunk level by level into the leaf.
+fn pack_items<T: Clone, M: PackedMerge<Item = T>>(items: &[T]) -> Result<T> {
+    item_path::<T, M>(items, 0).map(|(leaf, _)| leaf)
+}
+
+/// Merge the items of a chunk into the leaf, collecting the siblings of the item at `index`.
+fn item_path<T: Clone, M: PackedMerge<Item = T>>(
+    items: &[T],
+    mut index: usize,
+) -> Result<(T, Vec<T>)> {
+    let mut level = items.to_vec();
+    let mut path = Vec::new();
+    while level.len() > 1 {
+        path.push(level[index ^ 1].clone());
+        level = level
+            .chunks(2)
+            .map(|pair| M::merge_packed(&pair[0], &pair[1]).map_err(Into::into))
+            .collect::<Result<_>>()?;
+        index /= 2;
+    }
+    Ok((level.pop().expect("power of two items"), path))
+}
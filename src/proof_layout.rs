@@ -0,0 +1,121 @@
+//! Position-free encoding of node proofs
+//!
+//! The positions of a `NodeMerkleProof` can take more space than the hashes themselves. Yet
+//! given the mmr size and the proven positions, every position a proof item may occupy is known
+//! upfront: the siblings along the paths from the proven positions to their peaks, and the
+//! peaks. A `ProofLayout` only stores which of these candidates are present, one bit each.
+
+use crate::ancestry_proof::NodeMerkleProof;
+use crate::compact_proof::{DescriptorReader, DescriptorWriter};
+use crate::helper::{iter_peaks, pos_height_in_tree, PositionScheme, PostOrderScheme};
+use crate::vec::Vec;
+use crate::{Error, Merge, Pos, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofLayout {
+    mmr_size: Pos,
+    positions: Vec<Pos>,
+    bitmap: Vec<u8>,
+}
+
+impl ProofLayout {
+    pub fn new(mmr_size: Pos, positions: Vec<Pos>, bitmap: Vec<u8>) -> Self {
+        ProofLayout {
+            mmr_size,
+            positions,
+            bitmap,
+        }
+    }
+
+    pub fn mmr_size(&self) -> Pos {
+        self.mmr_size
+    }
+
+    /// The proven positions the layout was derived from.
+    pub fn positions(&self) -> &[Pos] {
+        &self.positions
+    }
+
+    /// One bit per candidate position in ascending order, packed LSB first.
+    pub fn bitmap(&self) -> &[u8] {
+        &self.bitmap
+    }
+}
+
+/// All positions a proof item for `positions` may occupy, sorted and unique.
+fn candidate_positions(mmr_size: Pos, positions: &[Pos]) -> Result<Vec<Pos>> {
+    let peaks: Vec<Pos> = iter_peaks(mmr_size).collect();
+    let mut candidates = peaks.clone();
+    for &target in positions {
+        let peak_pos = *peaks
+            .iter()
+            .find(|&&peak_pos| peak_pos >= target)
+            .ok_or(Error::GenProofForInvalidNodes)?;
+        let (mut pos, mut height) = (target, pos_height_in_tree(target));
+        while pos < peak_pos {
+            let (sib_pos, parent_pos, _) = PostOrderScheme::sibling_and_parent_pos(pos, height);
+            candidates.push(sib_pos);
+            pos = parent_pos;
+            height += 1;
+        }
+    }
+    candidates.sort_unstable();
+    candidates.dedup();
+    candidates.retain(|pos| positions.binary_search(pos).is_err());
+    Ok(candidates)
+}
+
+fn sorted_unique(positions: &[Pos]) -> Vec<Pos> {
+    let mut positions = positions.to_vec();
+    positions.sort_unstable();
+    positions.dedup();
+    positions
+}
+
+impl<T: Clone + PartialEq, M: Merge<Item = T>> NodeMerkleProof<T, M> {
+    /// Split the proof into a layout for the proven `positions` and the bare hashes.
+    pub fn to_layout(&self, positions: &[Pos]) -> Result<(ProofLayout, Vec<T>)> {
+        let positions = sorted_unique(positions);
+        let candidates = candidate_positions(self.mmr_size(), &positions)?;
+        let mut bitmap = DescriptorWriter::default();
+        let mut proof_iter = self.proof_items().iter().peekable();
+        for pos in candidates {
+            let present = proof_iter
+                .next_if(|(item_pos, _)| *item_pos == pos)
+                .is_some();
+            bitmap.push(present);
+        }
+        // the proof has items the layout can't express
+        if proof_iter.next().is_some() {
+            return Err(Error::CorruptedProof);
+        }
+        let hashes = self
+            .proof_items()
+            .iter()
+            .map(|(_pos, item)| item.clone())
+            .collect();
+        Ok((
+            ProofLayout::new(self.mmr_size(), positions, bitmap.into_bytes()),
+            hashes,
+        ))
+    }
+
+    /// Reconstruct the `(pos, T)` proof items from a layout and the hashes in position order.
+    pub fn from_layout(layout: &ProofLayout, hashes: Vec<T>) -> Result<Self> {
+        let positions = sorted_unique(&layout.positions);
+        let candidates = candidate_positions(layout.mmr_size, &positions)?;
+        let mut bitmap = DescriptorReader::new(&layout.bitmap);
+        let mut hashes = hashes.into_iter();
+        let mut proof = Vec::new();
+        for pos in candidates {
+            if bitmap.next_bit()? {
+                proof.push((pos, hashes.next().ok_or(Error::CorruptedProof)?));
+            }
+        }
+        if hashes.next().is_some() {
+            return Err(Error::CorruptedProof);
+        }
+        bitmap.finish()?;
+        Ok(NodeMerkleProof::new(layout.mmr_size, proof))
+    }
+}
@@ -0,0 +1,78 @@
+//! Low-level proof calculation
+//!
+//! The exact schedule `MerkleProof` verification hashes in, for code that has to mirror it
+//! step by step, e.g. zero-knowledge circuits, and cross-test against the crate. These
+//! functions are stable, and so are the invariants below:
+//!
+//! - Leaves are `(pos, item)` pairs of leaf positions, i.e. of height 0.
+//! - Inside a mountain the nodes are merged one level at a time from left to right with
+//!   `Merge::merge_with_pos` at the parent position. A left node takes its sibling from the
+//!   leaves or calculated nodes if present and from the proof items otherwise, a right node
+//!   always from the proof items.
+//! - The peaks are calculated from left to right. A mountain without leaves takes its peak from
+//!   the next proof item, and once the proof items run out the remaining peaks count as bagged.
+//!   Any single item left then is the bagging of the peaks right of the last mountain with
+//!   leaves.
+//! - The peaks are bagged from right to left with `Merge::merge_peaks(right, left)`, see
+//!   `bagging_peaks_hashes`.
+
+use crate::borrow::Borrow;
+use crate::helper::pos_height_in_tree;
+use crate::vec::Vec;
+use crate::verifier::{self, calculate_peak_root_from_iter, Iterations};
+use crate::{Error, Merge, Pos, Result};
+
+pub use crate::verifier::bagging_peaks_hashes;
+
+/// Calculate the peaks of an MMR of `mmr_size` from `leaves` and the proof items of a
+/// `MerkleProof`, without bagging them.
+///
+/// The leaves may come in any order, they are sorted by position first and a position given
+/// twice must carry the same leaf. The last hash is the bagging of the peaks right of the last
+/// mountain with leaves if the proof bagged them, see the module docs. Fails with
+/// `CorruptedProof` if proof items are missing or left over.
+pub fn calculate_peaks_hashes<T: Clone + PartialEq, M: Merge<Item = T>>(
+    leaves: Vec<(Pos, T)>,
+    mmr_size: Pos,
+    proof_items: &[T],
+) -> Result<Vec<T>> {
+    verifier::calculate_peaks_hashes::<_, M, _>(
+        leaves,
+        mmr_size,
+        proof_items.iter(),
+        &mut Iterations::unbounded(),
+    )
+}
+
+/// Calculate the root of the mountain at `peak_pos` from its `leaves`, taking the siblings from
+/// `proof_iter` as they are needed.
+///
+/// Unlike `verifier::calculate_peak_root`, the proof items after the mountain are left in
+/// `proof_iter`, so the mountains of a proof can be calculated one after the other. Fails with
+/// `GenProofForInvalidLeaves` unless the leaves are sorted, unique leaf positions under the peak
+/// and with `CorruptedProof` if there are none or proof items are missing.
+pub fn calculate_peak_root<T, M: Merge<Item = T>, I: Iterator>(
+    leaves: Vec<(Pos, T)>,
+    peak_pos: Pos,
+    proof_iter: &mut I,
+) -> Result<T>
+where
+    I::Item: Borrow<T>,
+{
+    if leaves
+        .iter()
+        .any(|(pos, _)| pos_height_in_tree(*pos) > 0 || *pos > peak_pos)
+        || leaves.windows(2).any(|pair| pair[0].0 >= pair[1].0)
+    {
+        return Err(Error::GenProofForInvalidLeaves);
+    }
+    if leaves.is_empty() {
+        return Err(Error::CorruptedProof);
+    }
+    calculate_peak_root_from_iter::<_, M, _>(
+        leaves,
+        peak_pos,
+        proof_iter,
+        &mut Iterations::unbounded(),
+    )
+}
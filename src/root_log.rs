@@ -0,0 +1,76 @@
+//! Historical roots
+//!
+//! Verifying an ancestry proof or an old inclusion proof needs the root the MMR had back then.
+//! With a `RootLog` set, the MMR logs `(mmr_size, root)` into the store as it grows, so the
+//! roots can be looked up later with `MMR::root_at_size` instead of being tracked by hand.
+
+use crate::mmr_store::{MMRStoreReadOps, MMRStoreWriteOps};
+use crate::{string::ToString, Error, Merge, Pos, Result, MMR};
+
+/// When the MMR logs the new root.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootLog {
+    /// Log the root after every push.
+    EveryPush,
+    /// Log only the root at commit time, calculated once per commit.
+    EveryCommit,
+}
+
+impl<T, M, S> MMR<T, M, S> {
+    pub fn root_log(&self) -> Option<RootLog> {
+        self.root_log
+    }
+}
+
+impl<T, M, S: MMRStoreWriteOps<T>> MMR<T, M, S> {
+    /// Start logging roots into the store, `None` stops it.
+    ///
+    /// Fails if the store doesn't support logging roots, see `supports_root_log`.
+    pub fn set_root_log(&mut self, root_log: Option<RootLog>) -> Result<()> {
+        if root_log.is_some() && !self.store().supports_root_log() {
+            return Err(Error::StoreError("root log is not supported".to_string()));
+        }
+        self.root_log = root_log;
+        Ok(())
+    }
+}
+
+impl<T: Clone, M: Merge<Item = T>, S: MMRStoreReadOps<T>> MMR<T, M, S> {
+    /// The root logged when the MMR had `mmr_size`, including uncommitted pushes.
+    pub fn root_at_size(&self, mmr_size: Pos) -> Result<Option<T>> {
+        if mmr_size > self.mmr_size() {
+            return Ok(None);
+        }
+        if mmr_size == self.mmr_size() && self.pending_commit_root() {
+            return self.get_root().map(Some);
+        }
+        self.batch().get_root_at(mmr_size)
+    }
+
+    /// Log the root after the push of a leaf, in `EveryPush` mode.
+    pub(crate) fn log_root(&mut self) -> Result<()> {
+        if self.root_log != Some(RootLog::EveryPush) {
+            return Ok(());
+        }
+        let root = self.get_root()?;
+        let mmr_size = self.mmr_size();
+        self.batch_mut().append_root(mmr_size, root);
+        Ok(())
+    }
+
+    /// Log the root of the pending pushes, in `EveryCommit` mode.
+    pub(crate) fn log_commit_root(&mut self) -> Result<()> {
+        if !self.pending_commit_root() {
+            return Ok(());
+        }
+        let root = self.get_root()?;
+        let mmr_size = self.mmr_size();
+        self.batch_mut().append_root(mmr_size, root);
+        Ok(())
+    }
+
+    /// Whether the next commit logs the root of the current size.
+    fn pending_commit_root(&self) -> bool {
+        self.root_log == Some(RootLog::EveryCommit) && self.batch().pending_start().is_some()
+    }
+}
@@ -0,0 +1,138 @@
+//! Portable MMR snapshots
+//!
+//! A snapshot holds every node of an MMR, so a new node can be bootstrapped from a peer without
+//! replaying the appends. All integers are little endian:
+//!
+//! - header: the magic `MMRS`, the format version as `u8`, the `mmr_size` as `u64` and the root
+//! - body: the `mmr_size` nodes in position order
+//!
+//! The root and the nodes are each prefixed by their byte length as `u32`.
+
+use crate::helper::{is_valid_mmr_size, pos_height_in_tree};
+use crate::mmr_store::{MMRStoreReadOps, MMRStoreWriteOps};
+use crate::verifier::bagging_peaks_hashes;
+use crate::{Error, Merge, Pos, Result, MMR};
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"MMRS";
+pub const SNAPSHOT_VERSION: u8 = 1;
+
+/// Number of nodes fetched from the store at once while exporting.
+const EXPORT_CHUNK_SIZE: Pos = 1024;
+
+impl<T, M, S> MMR<T, M, S>
+where
+    T: Clone + PartialEq + AsRef<[u8]>,
+    M: Merge<Item = T>,
+    S: MMRStoreReadOps<T>,
+{
+    /// Stream all nodes of the MMR, including pending ones, to `writer`.
+    pub fn export_snapshot<W: Write>(&self, mut writer: W) -> Result<()> {
+        let root = self.get_root()?;
+        writer.write_all(&SNAPSHOT_MAGIC).map_err(io_error)?;
+        writer.write_all(&[SNAPSHOT_VERSION]).map_err(io_error)?;
+        // the header keeps a `u64` size, also with `u32-positions`
+        #[allow(clippy::unnecessary_cast)]
+        let mmr_size = self.mmr_size() as u64;
+        writer
+            .write_all(&mmr_size.to_le_bytes())
+            .map_err(io_error)?;
+        write_item(&mut writer, &root)?;
+        let mut pos = 0;
+        while pos < self.mmr_size() {
+            let end = self.mmr_size().min(pos + EXPORT_CHUNK_SIZE);
+            for (offset, elem) in self.batch().get_elems(pos..end)?.into_iter().enumerate() {
+                let elem = elem.ok_or(Error::InconsistentStore {
+                    pos: pos + offset as Pos,
+                })?;
+                write_item(&mut writer, &elem)?;
+            }
+            pos = end;
+        }
+        writer.flush().map_err(io_error)
+    }
+}
+
+impl<T, M, S> MMR<T, M, S>
+where
+    T: Clone + PartialEq + for<'a> TryFrom<&'a [u8]>,
+    M: Merge<Item = T>,
+    S: MMRStoreWriteOps<T>,
+{
+    /// Import a snapshot written by `export_snapshot` into the empty `store`.
+    ///
+    /// Every interior node is checked against the merge of its children and the bagged peaks
+    /// against the root of the header, so a corrupted snapshot fails with `CorruptedSnapshot`
+    /// before anything is written to the store.
+    pub fn import_snapshot<R: Read>(mut reader: R, mut store: S) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic).map_err(io_error)?;
+        let mut version = [0u8; 1];
+        reader.read_exact(&mut version).map_err(io_error)?;
+        if magic != SNAPSHOT_MAGIC || version[0] != SNAPSHOT_VERSION {
+            return Err(Error::CorruptedSnapshot);
+        }
+        let mut mmr_size = [0u8; 8];
+        reader.read_exact(&mut mmr_size).map_err(io_error)?;
+        let mmr_size =
+            Pos::try_from(u64::from_le_bytes(mmr_size)).map_err(|_| Error::CorruptedSnapshot)?;
+        if mmr_size == 0 || !is_valid_mmr_size(mmr_size) {
+            return Err(Error::CorruptedSnapshot);
+        }
+        let root: T = read_item(&mut reader)?;
+
+        let mut elems = Vec::new();
+        // the roots of the subtrees not merged yet, ends up with the peaks
+        let mut subtrees: Vec<T> = Vec::new();
+        for pos in 0..mmr_size {
+            let elem: T = read_item(&mut reader)?;
+            if pos_height_in_tree(pos) > 0 {
+                let right = subtrees.pop().ok_or(Error::CorruptedSnapshot)?;
+                let left = subtrees.pop().ok_or(Error::CorruptedSnapshot)?;
+                if M::merge_with_pos(pos, &left, &right).map_err(Into::into)? != elem {
+                    return Err(Error::CorruptedSnapshot);
+                }
+            }
+            subtrees.push(elem.clone());
+            elems.push(elem);
+        }
+        if reader.read(&mut [0u8; 1]).map_err(io_error)? != 0 {
+            return Err(Error::CorruptedSnapshot);
+        }
+        if bagging_peaks_hashes::<_, M>(subtrees)? != root {
+            return Err(Error::CorruptedSnapshot);
+        }
+
+        store.append(0, elems)?;
+        store.set_mmr_size(mmr_size)?;
+        Ok(MMR::new(mmr_size, store))
+    }
+}
+
+fn write_item<W: Write, T: AsRef<[u8]>>(writer: &mut W, item: &T) -> Result<()> {
+    let bytes = item.as_ref();
+    let len = u32::try_from(bytes.len())
+        .map_err(|_| Error::StoreError("snapshot item exceeds u32::MAX bytes".to_string()))?;
+    writer.write_all(&len.to_le_bytes()).map_err(io_error)?;
+    writer.write_all(bytes).map_err(io_error)
+}
+
+fn read_item<R: Read, T: for<'a> TryFrom<&'a [u8]>>(reader: &mut R) -> Result<T> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len).map_err(io_error)?;
+    let len = u32::from_le_bytes(len) as u64;
+    // don't trust the length for preallocation
+    let mut bytes = Vec::new();
+    if reader.take(len).read_to_end(&mut bytes).map_err(io_error)? as u64 != len {
+        return Err(Error::CorruptedSnapshot);
+    }
+    T::try_from(&bytes).map_err(|_| Error::CorruptedSnapshot)
+}
+
+fn io_error(err: io::Error) -> Error {
+    match err.kind() {
+        io::ErrorKind::UnexpectedEof => Error::CorruptedSnapshot,
+        _ => Error::StoreError(err.to_string()),
+    }
+}
@@ -0,0 +1,192 @@
+//! Proptest strategies for downstream tests
+//!
+//! Generates MMR sizes, leaf subsets, valid proofs and near-valid corrupted proofs, so crates
+//! building on the MMR don't need to copy the test harness of this crate.
+
+use crate::helper::{get_peak_map, leaf_count_to_mmr_size};
+use crate::util::{MemMMR, MemStore};
+use crate::{leaf_index_to_pos, Merge, MerkleProof, Pos, Result};
+use core::fmt;
+use proptest::prelude::*;
+use proptest::sample::{subsequence, Index};
+
+/// Valid MMR sizes of `1..=max_leaves` leaves.
+pub fn mmr_size(max_leaves: Pos) -> impl Strategy<Value = Pos> {
+    (1..=max_leaves)
+        .prop_map(|leaf_count| leaf_count_to_mmr_size(leaf_count).expect("leaf count below max"))
+}
+
+/// Sorted positions of a non-empty subset of the leaves of an MMR with `leaf_count` leaves.
+pub fn leaf_subset(leaf_count: Pos) -> impl Strategy<Value = Vec<Pos>> {
+    assert!(leaf_count > 0, "an empty MMR has no leaves");
+    let positions: Vec<Pos> = (0..leaf_count).map(leaf_index_to_pos).collect();
+    subsequence(positions, 1..=leaf_count as usize)
+}
+
+/// A proof together with the root and leaves it proves.
+pub struct ProofCase<T, M> {
+    pub root: T,
+    pub proof: MerkleProof<T, M>,
+    pub leaves: Vec<(Pos, T)>,
+}
+
+impl<T: Clone + PartialEq, M: Merge<Item = T>> ProofCase<T, M> {
+    pub fn verify(&self) -> Result<bool> {
+        self.proof.verify(self.root.clone(), self.leaves.clone())
+    }
+}
+
+impl<T: Clone, M> Clone for ProofCase<T, M> {
+    fn clone(&self) -> Self {
+        ProofCase {
+            root: self.root.clone(),
+            proof: MerkleProof::new(self.proof.mmr_size(), self.proof.proof_items().to_vec()),
+            leaves: self.leaves.clone(),
+        }
+    }
+}
+
+// implemented by hand, as `M` is rarely `Debug`
+impl<T: fmt::Debug, M> fmt::Debug for ProofCase<T, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ProofCase")
+            .field("root", &self.root)
+            .field("mmr_size", &self.proof.mmr_size())
+            .field("proof_items", &self.proof.proof_items())
+            .field("leaves", &self.leaves)
+            .finish()
+    }
+}
+
+/// Valid proofs of random leaf subsets, in MMRs of `1..=max_leaves` leaves drawn from `leaf`.
+pub fn valid_proof<T, M, S>(leaf: S, max_leaves: Pos) -> impl Strategy<Value = ProofCase<T, M>>
+where
+    T: Clone + PartialEq + fmt::Debug,
+    M: Merge<Item = T>,
+    S: Strategy<Value = T> + Clone,
+{
+    (1..=max_leaves)
+        .prop_flat_map(move |leaf_count| {
+            (
+                prop::collection::vec(leaf.clone(), leaf_count as usize),
+                leaf_subset(leaf_count),
+            )
+        })
+        .prop_map(|(elems, positions)| {
+            let store = MemStore::default();
+            let mut mmr = MemMMR::<T, M>::new(0, &store);
+            for elem in &elems {
+                mmr.push(elem.clone()).expect("push");
+            }
+            let proof = mmr.gen_proof(positions.clone()).expect("gen proof");
+            let leaves = positions
+                .into_iter()
+                .map(|pos| (pos, elems[get_peak_map(pos) as usize].clone()))
+                .collect();
+            ProofCase {
+                root: mmr.get_root().expect("get root"),
+                proof,
+                leaves,
+            }
+        })
+}
+
+/// Mutations turning a valid proof into a near-valid one that fails verification.
+///
+/// Each one adds, drops or changes an input of the root calculation. Moving a leaf isn't among
+/// them, since with a position agnostic `Merge` the moved leaf can still hash to the same root.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Corruption {
+    DropProofItem,
+    ExtraProofItem,
+    ReplaceProofItem,
+    ReplaceLeaf,
+}
+
+impl Arbitrary for Corruption {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            Just(Corruption::DropProofItem),
+            Just(Corruption::ExtraProofItem),
+            Just(Corruption::ReplaceProofItem),
+            Just(Corruption::ReplaceLeaf),
+        ]
+        .boxed()
+    }
+}
+
+/// Apply a random `Corruption` to `case`, with replacement items drawn from `item`.
+///
+/// Mutations that wouldn't change the case, e.g. replacing an item by an equal one, are
+/// rejected, so `item` must produce more than a single value.
+pub fn corrupt<T, M, S>(case: ProofCase<T, M>, item: S) -> impl Strategy<Value = ProofCase<T, M>>
+where
+    T: Clone + PartialEq + fmt::Debug,
+    S: Strategy<Value = T>,
+{
+    (any::<Corruption>(), any::<Index>(), item).prop_filter_map(
+        "corruption doesn't change the proof",
+        move |(corruption, index, item)| apply_corruption(&case, corruption, index, item),
+    )
+}
+
+/// Corrupted proofs, see `valid_proof` and `corrupt`.
+pub fn corrupted_proof<T, M, S>(leaf: S, max_leaves: Pos) -> impl Strategy<Value = ProofCase<T, M>>
+where
+    T: Clone + PartialEq + fmt::Debug,
+    M: Merge<Item = T>,
+    S: Strategy<Value = T> + Clone,
+{
+    valid_proof(leaf.clone(), max_leaves).prop_flat_map(move |case| corrupt(case, leaf.clone()))
+}
+
+fn apply_corruption<T: Clone + PartialEq, M>(
+    case: &ProofCase<T, M>,
+    corruption: Corruption,
+    index: Index,
+    item: T,
+) -> Option<ProofCase<T, M>> {
+    let mmr_size = case.proof.mmr_size();
+    let mut items = case.proof.proof_items().to_vec();
+    let mut leaves = case.leaves.clone();
+    match corruption {
+        Corruption::DropProofItem => {
+            if items.is_empty() {
+                return None;
+            }
+            items.remove(index.index(items.len()));
+        }
+        Corruption::ExtraProofItem => {
+            // the verifier ignores the proof of a single leaf MMR
+            if mmr_size == 1 {
+                return None;
+            }
+            items.insert(index.index(items.len() + 1), item);
+        }
+        Corruption::ReplaceProofItem => {
+            if items.is_empty() {
+                return None;
+            }
+            let replaced = index.get_mut(&mut items);
+            if *replaced == item {
+                return None;
+            }
+            *replaced = item;
+        }
+        Corruption::ReplaceLeaf => {
+            let replaced = &mut index.get_mut(&mut leaves).1;
+            if *replaced == item {
+                return None;
+            }
+            *replaced = item;
+        }
+    }
+    Some(ProofCase {
+        root: case.root.clone(),
+        proof: MerkleProof::new(mmr_size, items),
+        leaves,
+    })
+}
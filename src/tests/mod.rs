@@ -1,10 +1,55 @@
+mod reference;
 mod test_accumulate_headers;
 mod test_ancestry;
+#[cfg(feature = "arbitrary")]
+mod test_arbitrary;
+mod test_authenticated_log;
+mod test_bagging_trace;
+mod test_batch_verifier;
+mod test_beefy;
+mod test_commutative_proof;
+mod test_compact_proof;
+mod test_dual_proof;
+mod test_dump;
+mod test_fixed;
+mod test_forest;
 mod test_helper;
+#[cfg(feature = "huge")]
+mod test_huge;
+mod test_import;
 mod test_incremental;
+mod test_kary;
+#[cfg(feature = "leaf-index")]
+mod test_leaf_index;
+mod test_mem_store;
+#[cfg(feature = "metrics")]
+mod test_metrics;
 mod test_mmr;
+mod test_mmr_forest;
 mod test_node_mmr;
+mod test_packed;
+mod test_proof_layout;
+mod test_reference;
+#[cfg(feature = "rkyv")]
+mod test_rkyv;
+#[cfg(feature = "root-log")]
+mod test_root_log;
+#[cfg(feature = "scale")]
+mod test_scale;
 mod test_sequence;
+#[cfg(feature = "std")]
+mod test_snapshot;
+#[cfg(feature = "testing")]
+mod test_testing;
+mod test_tombstone;
+#[cfg(feature = "tracing")]
+mod test_tracing;
+mod test_transcript;
+mod test_types;
+mod test_validating_mmr;
+mod test_verifier;
+mod test_versioned_store;
+mod test_witness;
 
 use crate::{Merge, Result};
 use blake2b_rs::{Blake2b, Blake2bBuilder};
@@ -30,6 +75,7 @@ struct MergeNumberHash;
 
 impl Merge for MergeNumberHash {
     type Item = NumberHash;
+    type Error = crate::Error;
     fn merge(lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item> {
         let mut hasher = new_blake2b();
         let mut hash = [0u8; 32];
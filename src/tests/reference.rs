@@ -0,0 +1,158 @@
+//! A naive reference MMR for differential tests. Every query rebuilds the mountains from the
+//! leaves recursively, without any of the position arithmetic of `helper`.
+
+use crate::{Merge, Pos, Result};
+use core::marker::PhantomData;
+
+pub(super) struct ReferenceMmr<T, M> {
+    leaves: Vec<T>,
+    merge: PhantomData<M>,
+}
+
+/// A perfect tree of `2^height` leaves, laid out in post-order from `start_pos`.
+struct Mountain {
+    start_pos: Pos,
+    height: u32,
+    first_leaf: usize,
+}
+
+impl Mountain {
+    fn size(&self) -> Pos {
+        (2 << self.height) - 1
+    }
+
+    fn peak_pos(&self) -> Pos {
+        self.start_pos + self.size() - 1
+    }
+}
+
+impl<T: Clone, M: Merge<Item = T>> ReferenceMmr<T, M> {
+    pub(super) fn new() -> Self {
+        ReferenceMmr {
+            leaves: Vec::new(),
+            merge: PhantomData,
+        }
+    }
+
+    pub(super) fn push(&mut self, leaf: T) {
+        self.leaves.push(leaf);
+    }
+
+    /// Drop all but the first `leaves_count` leaves.
+    pub(super) fn rewind(&mut self, leaves_count: usize) {
+        self.leaves.truncate(leaves_count);
+    }
+
+    pub(super) fn leaves_count(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// The mountains from left to right, the largest power of two leaves first.
+    fn mountains(&self) -> Vec<Mountain> {
+        let mut mountains = Vec::new();
+        let mut start_pos = 0;
+        let mut first_leaf = 0;
+        let mut remaining = self.leaves.len();
+        while remaining > 0 {
+            let height = usize::BITS - 1 - remaining.leading_zeros();
+            let mountain = Mountain {
+                start_pos,
+                height,
+                first_leaf,
+            };
+            start_pos += mountain.size();
+            first_leaf += 1 << height;
+            remaining -= 1 << height;
+            mountains.push(mountain);
+        }
+        mountains
+    }
+
+    pub(super) fn mmr_size(&self) -> Pos {
+        self.mountains().iter().map(Mountain::size).sum()
+    }
+
+    /// The position of the leaf at `index`.
+    pub(super) fn leaf_pos(&self, index: usize) -> Pos {
+        let mountain = self
+            .mountains()
+            .into_iter()
+            .find(|m| index < m.first_leaf + (1 << m.height))
+            .expect("leaf exists");
+        let mut pos = mountain.start_pos;
+        let mut height = mountain.height;
+        let mut offset = index - mountain.first_leaf;
+        // descend, skipping the left subtree whenever the leaf is in the right one
+        while height > 0 {
+            height -= 1;
+            if offset >= 1 << height {
+                pos += (2 << height) - 1;
+                offset -= 1 << height;
+            }
+        }
+        pos
+    }
+
+    /// All nodes of the subtree in post-order, i.e. ordered by position.
+    fn subtree_nodes(
+        &self,
+        start_pos: Pos,
+        height: u32,
+        first_leaf: usize,
+        nodes: &mut Vec<T>,
+    ) -> Result<T> {
+        if height == 0 {
+            let leaf = self.leaves[first_leaf].clone();
+            nodes.push(leaf.clone());
+            return Ok(leaf);
+        }
+        let left = self.subtree_nodes(start_pos, height - 1, first_leaf, nodes)?;
+        let right_start = start_pos + (1 << height) - 1;
+        let right = self.subtree_nodes(
+            right_start,
+            height - 1,
+            first_leaf + (1 << (height - 1)),
+            nodes,
+        )?;
+        let parent_pos = start_pos + (2 << height) - 2;
+        let parent = M::merge_with_pos(parent_pos, &left, &right).map_err(Into::into)?;
+        nodes.push(parent.clone());
+        Ok(parent)
+    }
+
+    /// Every node of the MMR, indexed by position.
+    pub(super) fn nodes(&self) -> Result<Vec<T>> {
+        let mut nodes = Vec::new();
+        for mountain in self.mountains() {
+            self.subtree_nodes(
+                mountain.start_pos,
+                mountain.height,
+                mountain.first_leaf,
+                &mut nodes,
+            )?;
+        }
+        Ok(nodes)
+    }
+
+    pub(super) fn peaks(&self) -> Result<Vec<(Pos, T)>> {
+        let nodes = self.nodes()?;
+        Ok(self
+            .mountains()
+            .iter()
+            .map(|m| (m.peak_pos(), nodes[m.peak_pos() as usize].clone()))
+            .collect())
+    }
+
+    /// The peaks bagged from right to left, `None` without leaves.
+    pub(super) fn root(&self) -> Result<Option<T>> {
+        let mut peaks = self.peaks()?.into_iter().rev().map(|(_, peak)| peak);
+        let mut root = match peaks.next() {
+            Some(peak) => peak,
+            None => return Ok(None),
+        };
+        for left in peaks {
+            root = M::merge_peaks(&root, &left).map_err(Into::into)?;
+        }
+        Ok(Some(root))
+    }
+}
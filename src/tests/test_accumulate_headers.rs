@@ -1,5 +1,7 @@
 use super::new_blake2b;
-use crate::{leaf_index_to_pos, util::MemStore, MMRStoreReadOps, Merge, MerkleProof, Result, MMR};
+use crate::{
+    leaf_index_to_pos, util::MemStore, MMRStoreReadOps, Merge, MerkleProof, Pos, Result, MMR,
+};
 use bytes::{Bytes, BytesMut};
 use std::fmt;
 
@@ -72,6 +74,7 @@ struct MergeHashWithTD;
 
 impl Merge for MergeHashWithTD {
     type Item = HashWithTD;
+    type Error = crate::Error;
     fn merge(lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item> {
         let mut hasher = new_blake2b();
         let mut hash = [0u8; 32];
@@ -88,7 +91,7 @@ impl Merge for MergeHashWithTD {
 
 struct Prover {
     headers: Vec<(Header, u64)>,
-    positions: Vec<u64>,
+    positions: Vec<Pos>,
     store: MemStore<HashWithTD>,
 }
 
@@ -103,7 +106,7 @@ impl Prover {
     }
 
     fn gen_blocks(&mut self, count: u64) -> Result<()> {
-        let mut mmr = MMR::<_, MergeHashWithTD, _>::new(self.positions.len() as u64, &self.store);
+        let mut mmr = MMR::<_, MergeHashWithTD, _>::new(self.positions.len() as Pos, &self.store);
         // get previous element
         let mut previous = if let Some(pos) = self.positions.last() {
             mmr.store().get_elem(*pos)?.expect("exists")
@@ -159,7 +162,7 @@ impl Prover {
         mmr.gen_proof(vec![pos])
     }
 
-    fn get_pos(&self, number: u64) -> u64 {
+    fn get_pos(&self, number: u64) -> Pos {
         self.positions[number as usize]
     }
 }
@@ -185,7 +188,7 @@ fn test_insert_header() {
     };
     // gen proof,  blocks are in the same chain
     let proof = prover.gen_proof(h1, h2).expect("gen proof");
-    let pos = leaf_index_to_pos(h1);
+    let pos = leaf_index_to_pos(h1 as Pos);
     assert_eq!(pos, prover.get_pos(h1));
     assert_eq!(prove_elem, (&prover.store).get_elem(pos).unwrap().unwrap());
     let result = proof.verify(root, vec![(pos, prove_elem)]).expect("verify");
@@ -1,7 +1,11 @@
 use super::{MergeNumberHash, NumberHash};
 use crate::ancestry_proof::expected_ancestry_proof_size;
-use crate::leaf_index_to_mmr_size;
+use crate::helper::get_peaks;
 use crate::util::{MemMMR, MemStore};
+use crate::{
+    leaf_index_to_mmr_size, leaf_index_to_pos, verify_peaks, AncestryProof, Error, MerkleProofRef,
+    NodeMerkleProof, Pos, UpdateProof, VerifyLimits,
+};
 
 #[test]
 fn test_ancestry() {
@@ -17,14 +21,445 @@ fn test_ancestry() {
 
     let root = mmr.get_root().expect("get root");
     for i in 0..mmr_size {
-        let prev_size = leaf_index_to_mmr_size(i.into());
+        let prev_size = leaf_index_to_mmr_size(i as Pos);
         let ancestry_proof = mmr.gen_ancestry_proof(prev_size).expect("gen proof");
         assert!(ancestry_proof
             .verify_ancestor(root.clone(), prev_roots[i as usize].clone())
             .unwrap());
         assert_eq!(
-            expected_ancestry_proof_size(ancestry_proof.prev_mmr_size, mmr.mmr_size()),
-            ancestry_proof.prev_peaks_proof.proof_items().len()
+            expected_ancestry_proof_size(ancestry_proof.prev_mmr_size(), mmr.mmr_size()),
+            ancestry_proof.prev_peaks_proof().proof_items().len()
+        );
+    }
+}
+
+#[test]
+fn test_ancestry_for_leaf_count() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let mut prev_roots = Vec::new();
+    for i in 0..50u32 {
+        mmr.push(NumberHash::from(i)).unwrap();
+        prev_roots.push(mmr.get_root().expect("get root"));
+    }
+    let root = mmr.get_root().expect("get root");
+    for leaf_count in 1..=50 {
+        let prev_root = prev_roots[leaf_count as usize - 1].clone();
+        let ancestry_proof = mmr
+            .gen_ancestry_proof_for_leaf_count(leaf_count)
+            .expect("gen proof");
+        assert_eq!(ancestry_proof.prev_leaf_count(), leaf_count);
+        assert!(ancestry_proof
+            .verify_ancestor_for_leaf_count(root.clone(), prev_root.clone(), leaf_count)
+            .unwrap());
+        if leaf_count > 1 {
+            assert!(!ancestry_proof
+                .verify_ancestor_for_leaf_count(root.clone(), prev_root, leaf_count - 1)
+                .unwrap());
+        }
+    }
+
+    assert_eq!(
+        mmr.gen_ancestry_proof_for_leaf_count(0).err(),
+        Some(Error::InvalidLeafCount { leaf_count: 0 })
+    );
+    assert_eq!(
+        mmr.gen_ancestry_proof_for_leaf_count(51).err(),
+        Some(Error::AncestorRootNotPredecessor {
+            prev_mmr_size: leaf_index_to_mmr_size(50),
+            mmr_size: mmr.mmr_size()
+        })
+    );
+}
+
+#[test]
+fn test_ancestry_with_limits() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    (0u32..20).for_each(|i| {
+        mmr.push(NumberHash::from(i)).unwrap();
+    });
+    let prev_root = mmr.get_root().expect("get root");
+    let prev_size = mmr.mmr_size();
+    (20u32..100).for_each(|i| {
+        mmr.push(NumberHash::from(i)).unwrap();
+    });
+    let root = mmr.get_root().expect("get root");
+    let ancestry_proof = mmr.gen_ancestry_proof(prev_size).expect("gen proof");
+    let limits = VerifyLimits::default();
+    assert!(ancestry_proof
+        .verify_ancestor_with_limits(root.clone(), prev_root.clone(), &limits)
+        .unwrap());
+
+    let node_proof = ancestry_proof.prev_peaks_proof();
+    let nodes: Vec<_> = get_peaks(prev_size)
+        .into_iter()
+        .zip(ancestry_proof.prev_peaks().iter().cloned())
+        .collect();
+    assert!(node_proof
+        .verify_with_limits(root.clone(), nodes.clone(), &limits)
+        .unwrap());
+    assert!(MerkleProofRef::from(node_proof)
+        .verify_with_limits(root.clone(), nodes.clone(), &limits)
+        .unwrap());
+
+    for limits in [
+        VerifyLimits {
+            max_proof_items: node_proof.proof_items().len() - 1,
+            ..limits
+        },
+        VerifyLimits {
+            max_nodes: nodes.len() - 1,
+            ..limits
+        },
+        VerifyLimits {
+            max_iterations: 2,
+            ..limits
+        },
+    ] {
+        assert_eq!(
+            ancestry_proof.verify_ancestor_with_limits(root.clone(), prev_root.clone(), &limits),
+            Err(Error::LimitExceeded)
+        );
+        assert_eq!(
+            node_proof.verify_with_limits(root.clone(), nodes.clone(), &limits),
+            Err(Error::LimitExceeded)
+        );
+        assert_eq!(
+            MerkleProofRef::from(node_proof).verify_with_limits(
+                root.clone(),
+                nodes.clone(),
+                &limits
+            ),
+            Err(Error::LimitExceeded)
+        );
+    }
+}
+
+#[test]
+fn test_ancestry_against_peaks() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let mut prev_roots = Vec::new();
+    for i in 0..100u32 {
+        mmr.push(NumberHash::from(i)).unwrap();
+        prev_roots.push(mmr.get_root().expect("get root"));
+    }
+    let peaks = mmr.peaks().unwrap();
+    for i in 0..99 {
+        let prev_size = leaf_index_to_mmr_size(i);
+        let ancestry_proof = mmr.gen_ancestry_proof(prev_size).expect("gen proof");
+        let prev_root = prev_roots[i as usize].clone();
+        assert!(ancestry_proof
+            .verify_against_peaks(&peaks, prev_root.clone())
+            .unwrap());
+        assert!(!ancestry_proof
+            .verify_against_peaks(&peaks, prev_roots[i as usize + 1].clone())
+            .unwrap());
+        // every peak is checked, also the ones bagged into a single proof item
+        for tampered in 0..peaks.len() {
+            let mut tampered_peaks = peaks.clone();
+            tampered_peaks[tampered].1 = NumberHash::from(1000);
+            assert!(!ancestry_proof
+                .verify_against_peaks(&tampered_peaks, prev_root.clone())
+                .unwrap());
+        }
+    }
+
+    let ancestry_proof = mmr.gen_ancestry_proof(8).expect("gen proof");
+    assert_eq!(
+        ancestry_proof.verify_against_peaks(&peaks[1..], prev_roots[4].clone()),
+        Err(Error::InvalidPeaks {
+            mmr_size: mmr.mmr_size()
+        })
+    );
+}
+
+#[test]
+fn test_verify_peaks() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0..11u32 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    let root = mmr.get_root().unwrap();
+    let ancestry_proof = mmr.gen_ancestry_proof(8).expect("gen proof");
+    let (_, prev_root) = mmr.get_ancestor_peaks_and_root(8).unwrap();
+    let verify = |root: NumberHash, mmr_size: Pos, peaks: &[NumberHash]| {
+        verify_peaks::<_, MergeNumberHash>(root, mmr_size, peaks)
+    };
+
+    assert_eq!(
+        verify(prev_root.clone(), 8, ancestry_proof.prev_peaks()),
+        Ok(true)
+    );
+    let peaks: Vec<NumberHash> = mmr
+        .peaks()
+        .unwrap()
+        .into_iter()
+        .map(|(_, peak)| peak)
+        .collect();
+    assert_eq!(verify(root.clone(), mmr.mmr_size(), &peaks), Ok(true));
+
+    // peaks bagged left to right
+    let reversed: Vec<NumberHash> = peaks.iter().rev().cloned().collect();
+    assert_eq!(verify(root.clone(), mmr.mmr_size(), &reversed), Ok(false));
+    assert_eq!(verify(prev_root, mmr.mmr_size(), &peaks), Ok(false));
+    assert_eq!(
+        verify(root.clone(), mmr.mmr_size(), &peaks[1..]),
+        Err(Error::InvalidPeaks {
+            mmr_size: mmr.mmr_size()
+        })
+    );
+    assert_eq!(
+        verify(root.clone(), 9, &peaks),
+        Err(Error::InvalidMMRSize { mmr_size: 9 })
+    );
+    assert_eq!(
+        verify(root, 0, &[]),
+        Err(Error::InvalidMMRSize { mmr_size: 0 })
+    );
+}
+
+#[test]
+fn test_ancestry_proof_new_validates_prev_peaks() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0..11u32 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    let ancestry_proof = mmr.gen_ancestry_proof(8).expect("gen proof");
+    let proof_items = ancestry_proof.prev_peaks_proof().proof_items().to_vec();
+    let new_proof = |prev_peaks: Vec<NumberHash>, prev_mmr_size: Pos| {
+        AncestryProof::<_, MergeNumberHash>::new(
+            prev_peaks,
+            prev_mmr_size,
+            NodeMerkleProof::new(mmr.mmr_size(), proof_items.clone()),
+        )
+    };
+
+    let prev_peaks = ancestry_proof.prev_peaks().to_vec();
+    assert_eq!(prev_peaks.len(), 2);
+    assert!(new_proof(prev_peaks.clone(), 8).is_ok());
+    // peak count mismatch
+    assert_eq!(
+        new_proof(prev_peaks[..1].to_vec(), 8).err(),
+        Some(Error::CorruptedProof)
+    );
+    // 9 is not a valid mmr size
+    assert_eq!(
+        new_proof(prev_peaks.clone(), 9).err(),
+        Some(Error::CorruptedProof)
+    );
+    // empty ancestor
+    assert_eq!(new_proof(Vec::new(), 0).err(), Some(Error::CorruptedProof));
+    // ancestor larger than the current mmr
+    assert_eq!(
+        new_proof(prev_peaks, 22).err(),
+        Some(Error::AncestorRootNotPredecessor {
+            prev_mmr_size: 22,
+            mmr_size: 19
+        })
+    );
+}
+
+#[test]
+fn test_gen_proof_at_ancestor() {
+    use crate::util::FnStore;
+    use crate::{MMRStoreReadOps, MMR};
+    use std::cell::Cell;
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let mut prev_roots = Vec::new();
+    for i in 0..50u32 {
+        mmr.push(NumberHash::from(i)).unwrap();
+        prev_roots.push(mmr.get_root().expect("get root"));
+    }
+    mmr.commit().unwrap();
+    let root = mmr.get_root().unwrap();
+
+    let reads = Cell::new(0);
+    let counting_store = FnStore::new(|pos| {
+        reads.set(reads.get() + 1);
+        (&store).get_elem(pos)
+    });
+    let mmr = MMR::<_, MergeNumberHash, _>::new(mmr.mmr_size(), counting_store);
+
+    let prev_leaf_index = 30;
+    let prev_mmr_size = leaf_index_to_mmr_size(prev_leaf_index);
+    let leaves: Vec<_> = [3u32, 17, 30]
+        .iter()
+        .map(|i| (leaf_index_to_pos(*i as Pos), NumberHash::from(*i)))
+        .collect();
+    let pos_list: Vec<Pos> = leaves.iter().map(|(pos, _)| *pos).collect();
+    let (proof, ancestry_proof) = mmr
+        .gen_proof_at_ancestor(pos_list.clone(), prev_mmr_size)
+        .unwrap();
+    let shared_reads = reads.replace(0);
+
+    let prev_root = prev_roots[prev_leaf_index as usize].clone();
+    assert_eq!(proof.mmr_size(), prev_mmr_size);
+    assert!(proof.verify(prev_root.clone(), leaves).unwrap());
+    assert!(ancestry_proof.verify_ancestor(root, prev_root).unwrap());
+
+    // the same proofs generated separately read the ancestor peaks twice
+    let ancestor_store = FnStore::new(|pos| mmr.store().get_elem(pos));
+    let separate_proof = MMR::<_, MergeNumberHash, _>::new(prev_mmr_size, ancestor_store)
+        .gen_proof(pos_list.clone())
+        .unwrap();
+    let separate_ancestry_proof = mmr.gen_ancestry_proof(prev_mmr_size).unwrap();
+    assert_eq!(separate_proof.proof_items(), proof.proof_items());
+    assert_eq!(
+        separate_ancestry_proof.prev_peaks_proof().proof_items(),
+        ancestry_proof.prev_peaks_proof().proof_items()
+    );
+    assert!(shared_reads < reads.get());
+
+    assert_eq!(
+        mmr.gen_proof_at_ancestor(pos_list, mmr.mmr_size() + 1)
+            .err(),
+        Some(Error::AncestorRootNotPredecessor {
+            prev_mmr_size: mmr.mmr_size() + 1,
+            mmr_size: mmr.mmr_size()
+        })
+    );
+}
+
+#[test]
+fn test_update_proof() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    mmr.push(NumberHash::from(0)).unwrap();
+    let mut sizes = vec![mmr.mmr_size()];
+    let mut roots = vec![mmr.get_root().unwrap()];
+    // blocks of 0 to 6 leaves, a light client follows along from root to root
+    let mut blocks: Vec<Vec<NumberHash>> = Vec::new();
+    let mut leaf = 1u32;
+    for block in 0..20 {
+        let leaves: Vec<_> = (0..block % 7).map(|i| NumberHash::from(leaf + i)).collect();
+        leaf += leaves.len() as u32;
+        for leaf in &leaves {
+            mmr.push(leaf.clone()).unwrap();
+        }
+        blocks.push(leaves);
+        sizes.push(mmr.mmr_size());
+        roots.push(mmr.get_root().unwrap());
+    }
+    mmr.commit().unwrap();
+
+    for (i, leaves) in blocks.iter().enumerate() {
+        let proof = MemMMR::<_, MergeNumberHash>::new(sizes[i + 1], &store)
+            .gen_update_proof(sizes[i])
+            .unwrap();
+        assert_eq!(proof.leaves_count(), leaves.len() as Pos);
+        assert!(proof
+            .verify(roots[i].clone(), roots[i + 1].clone(), leaves.clone())
+            .unwrap());
+        // the new peaks bag into the new root
+        let peaks = proof.calculate_peaks(leaves.clone()).unwrap();
+        assert_eq!(
+            peaks.iter().map(|(pos, _)| *pos).collect::<Vec<_>>(),
+            crate::helper::get_peaks(sizes[i + 1])
+        );
+        if !leaves.is_empty() {
+            let mut tampered = leaves.clone();
+            tampered[0] = NumberHash::from(u32::MAX);
+            assert!(!proof
+                .verify(roots[i].clone(), roots[i + 1].clone(), tampered)
+                .unwrap());
+            assert_eq!(
+                proof.verify(roots[i].clone(), roots[i + 1].clone(), leaves[1..].to_vec()),
+                Err(Error::GenProofForInvalidLeaves)
+            );
+            assert!(!proof
+                .verify(roots[i + 1].clone(), roots[i + 1].clone(), leaves.clone())
+                .unwrap());
+        }
+    }
+
+    // a proof spanning several blocks
+    let proof = mmr.gen_update_proof(sizes[3]).unwrap();
+    let leaves: Vec<_> = blocks[3..].concat();
+    assert!(proof
+        .verify(roots[3].clone(), mmr.get_root().unwrap(), leaves)
+        .unwrap());
+
+    assert_eq!(
+        mmr.gen_update_proof(mmr.mmr_size() + 1).err(),
+        Some(Error::AncestorRootNotPredecessor {
+            prev_mmr_size: mmr.mmr_size() + 1,
+            mmr_size: mmr.mmr_size()
+        })
+    );
+    assert_eq!(
+        mmr.gen_update_proof(2).err(),
+        Some(Error::InvalidMMRSize { mmr_size: 2 })
+    );
+    assert_eq!(
+        UpdateProof::<NumberHash, MergeNumberHash>::new(vec![], 3, 4).err(),
+        Some(Error::InvalidPeaks { mmr_size: 3 })
+    );
+}
+
+#[test]
+fn test_ancestry_proof_omits_prev_peaks() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0..300u32 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    for i in 0..300 {
+        let prev_size = leaf_index_to_mmr_size(i);
+        let ancestry_proof = mmr.gen_ancestry_proof(prev_size).unwrap();
+        // the verifier reconstructs the prev peaks, so they never show up as proof items
+        let prev_peaks_positions = get_peaks(prev_size);
+        assert!(ancestry_proof
+            .prev_peaks_proof()
+            .proof_items()
+            .iter()
+            .all(|(pos, item)| !prev_peaks_positions.contains(pos)
+                && !ancestry_proof.prev_peaks().contains(item)));
+    }
+}
+
+#[test]
+fn test_ancestry_proof_of_itself() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..40 {
+        mmr.push(NumberHash::from(i)).unwrap();
+        let root = mmr.get_root().unwrap();
+        let proof = mmr.gen_ancestry_proof(mmr.mmr_size()).unwrap();
+        assert!(proof.prev_peaks_proof().proof_items().is_empty(), "{}", i);
+        assert_eq!(
+            proof.verify_ancestor(root.clone(), root.clone()),
+            Ok(true),
+            "{}",
+            i
+        );
+        // true iff both roots are the root
+        assert_eq!(
+            proof.verify_ancestor(root.clone(), NumberHash::from(100)),
+            Ok(false)
+        );
+        assert_eq!(
+            proof.verify_ancestor(NumberHash::from(100), root.clone()),
+            Ok(false)
+        );
+        assert_eq!(
+            proof.verify_ancestor(NumberHash::from(100), NumberHash::from(100)),
+            Ok(false)
+        );
+        assert_eq!(
+            proof.verify_against_peaks(&mmr.peaks().unwrap(), root),
+            Ok(true)
+        );
+        assert_eq!(
+            mmr.gen_ancestry_proof_for_leaf_count(mmr.leaves_count())
+                .unwrap()
+                .prev_mmr_size(),
+            mmr.mmr_size()
         );
     }
 }
@@ -0,0 +1,35 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::{
+    util::{MemStore, MmrState},
+    AncestryProof, MerkleProof, Pos,
+};
+use arbitrary::{Arbitrary, Unstructured};
+use proptest::prelude::*;
+
+impl<'a> Arbitrary<'a> for NumberHash {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(NumberHash::from(u32::arbitrary(u)?))
+    }
+}
+
+proptest! {
+    // mirrors the fuzz targets, so malformed proofs get some coverage without cargo-fuzz
+    #[test]
+    fn test_verify_arbitrary_proofs(data in prop::collection::vec(any::<u8>(), 0..512)) {
+        let mut u = Unstructured::new(&data);
+        let state = MmrState::<NumberHash>::arbitrary(&mut u).unwrap();
+        let store = MemStore::default();
+        let (mmr, positions) = state.build::<MergeNumberHash>(&store).unwrap();
+        let root = mmr.get_root().unwrap();
+
+        let proof = MerkleProof::<NumberHash, MergeNumberHash>::arbitrary(&mut u).unwrap();
+        let leaves: Vec<(Pos, NumberHash)> = u.arbitrary().unwrap();
+        let _ = proof.verify(root.clone(), leaves);
+
+        let prev_mmr_size = mmr.mmr_size() - positions.len() as Pos / 2;
+        if let Ok((_, prev_root)) = mmr.get_ancestor_peaks_and_root(prev_mmr_size) {
+            let proof = AncestryProof::<NumberHash, MergeNumberHash>::arbitrary(&mut u).unwrap();
+            let _ = proof.verify_ancestor(root, prev_root);
+        }
+    }
+}
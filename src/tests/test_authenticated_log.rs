@@ -0,0 +1,86 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::{AuthenticatedLog, Error, LogProof, MerkleProof, Pos, SeqNo};
+
+#[test]
+fn test_authenticated_log() {
+    let store = MemStore::default();
+    let mut log = AuthenticatedLog::new(MemMMR::<_, MergeNumberHash>::new(0, &store));
+    assert!(log.is_empty());
+    for i in 0u32..20 {
+        assert_eq!(log.append(NumberHash::from(i)), Ok(i as SeqNo));
+    }
+    log.commit().unwrap();
+    assert_eq!(log.len(), 20);
+    let root = log.root().unwrap();
+
+    let proof = log.prove(13).unwrap();
+    assert_eq!(proof.entries(), 13..14);
+    assert_eq!(proof.log_len(), 20);
+    assert_eq!(
+        proof.verify(root.clone(), vec![NumberHash::from(13)]),
+        Ok(true)
+    );
+    assert_eq!(
+        proof.verify(root.clone(), vec![NumberHash::from(12)]),
+        Ok(false)
+    );
+
+    let proof = log.prove_range(5..17).unwrap();
+    let entries: Vec<_> = (5u32..17).map(NumberHash::from).collect();
+    assert_eq!(proof.verify(root.clone(), entries.clone()), Ok(true));
+    assert_eq!(
+        proof.verify(root.clone(), entries[1..].to_vec()),
+        Err(Error::CorruptedProof)
+    );
+    // the proof travels without the log
+    let merkle_proof = MerkleProof::<_, MergeNumberHash>::new(
+        proof.proof().mmr_size(),
+        proof.proof().proof_items().to_vec(),
+    );
+    let decoded = LogProof::new(proof.entries(), merkle_proof);
+    assert_eq!(decoded.verify(root, entries), Ok(true));
+
+    assert_eq!(
+        log.prove(20).err(),
+        Some(Error::SeqNoOutOfRange { seq_no: 20 })
+    );
+    assert_eq!(
+        log.prove_range(15..21).err(),
+        Some(Error::SeqNoOutOfRange { seq_no: 20 })
+    );
+    assert_eq!(
+        log.prove_range(3..3).err(),
+        Some(Error::GenProofForInvalidLeaves)
+    );
+}
+
+#[test]
+fn test_log_consistency() {
+    let store = MemStore::default();
+    let mut log = AuthenticatedLog::new(MemMMR::<_, MergeNumberHash>::new(0, &store));
+    let mut roots = Vec::new();
+    for i in 0u32..20 {
+        log.append(NumberHash::from(i)).unwrap();
+        roots.push(log.root().unwrap());
+    }
+    let root = log.root().unwrap();
+
+    for since_seq in [1u32, 7, 16, 20] {
+        let prev_root = roots[since_seq as usize - 1].clone();
+        let proof = log.consistency(since_seq as SeqNo).unwrap();
+        assert_eq!(
+            proof.verify_ancestor_for_leaf_count(root.clone(), prev_root.clone(), since_seq as Pos),
+            Ok(true)
+        );
+        // claiming another length fails
+        assert_eq!(
+            proof.verify_ancestor_for_leaf_count(root.clone(), prev_root, (since_seq + 1) as Pos),
+            Ok(false)
+        );
+    }
+    assert_eq!(
+        log.consistency(21).err(),
+        Some(Error::SeqNoOutOfRange { seq_no: 21 })
+    );
+}
@@ -0,0 +1,91 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::helper::get_peaks;
+use crate::util::{MemMMR, MemStore};
+use crate::{leaf_index_to_pos, Error, Merge, Pos};
+
+#[test]
+fn test_bagging_trace() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..11 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    let trace = mmr.gen_bagging_trace().unwrap();
+    let peaks = mmr.peaks().unwrap();
+    assert_eq!(trace.suffixes().len(), peaks.len());
+    assert_eq!(trace.root(), Some(&mmr.get_root().unwrap()));
+    assert_eq!(trace.suffix(2), Some(&peaks[2].1));
+    assert_eq!(
+        trace.suffix(1),
+        Some(&MergeNumberHash::merge_peaks(&peaks[2].1, &peaks[1].1).unwrap())
+    );
+}
+
+#[test]
+fn test_verify_suffix() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..11 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    // the mountains hold leaves 0..8, 8..10 and 10
+    for (peak_index, leaf_indices) in [(0, vec![2, 5]), (1, vec![9]), (2, vec![10])] {
+        let leaves: Vec<_> = leaf_indices
+            .into_iter()
+            .map(|i: u32| (leaf_index_to_pos(i as Pos), NumberHash::from(i)))
+            .collect();
+        let (proof, trace) = mmr
+            .gen_proof_with_bagging_trace(leaves.iter().map(|(pos, _)| *pos).collect())
+            .unwrap();
+        let commitment = trace.suffix(peak_index).unwrap().clone();
+        assert_eq!(
+            trace.verify_suffix(&commitment, peak_index, &proof, leaves.clone()),
+            Ok(true)
+        );
+        assert_eq!(
+            proof.verify(trace.root().unwrap().clone(), leaves.clone()),
+            Ok(true)
+        );
+
+        let other_commitment = trace.suffix((peak_index + 1) % 3).unwrap();
+        assert_eq!(
+            trace.verify_suffix(other_commitment, peak_index, &proof, leaves.clone()),
+            Ok(false)
+        );
+        let mut wrong_leaves = leaves;
+        wrong_leaves[0].1 = NumberHash::from(42);
+        assert_eq!(
+            trace.verify_suffix(&commitment, peak_index, &proof, wrong_leaves),
+            Ok(false)
+        );
+    }
+
+    // leaves of another mountain than the committed suffix starts with
+    let pos = leaf_index_to_pos(9);
+    let (proof, trace) = mmr.gen_proof_with_bagging_trace(vec![pos]).unwrap();
+    assert_eq!(
+        trace.verify_suffix(
+            trace.suffix(0).unwrap(),
+            0,
+            &proof,
+            vec![(pos, NumberHash::from(9))]
+        ),
+        Err(Error::CorruptedProof)
+    );
+    assert_eq!(
+        trace.verify_suffix(
+            trace.suffix(0).unwrap(),
+            get_peaks(mmr.mmr_size()).len(),
+            &proof,
+            vec![(pos, NumberHash::from(9))]
+        ),
+        Err(Error::CorruptedProof)
+    );
+}
+
+#[test]
+fn test_bagging_trace_on_empty() {
+    let store = MemStore::<NumberHash>::default();
+    let mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    assert_eq!(mmr.gen_bagging_trace().err(), Some(Error::GetRootOnEmpty));
+}
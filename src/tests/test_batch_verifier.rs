@@ -0,0 +1,104 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::{BatchVerifier, Error, Merge, Pos, Result};
+use std::cell::Cell;
+
+thread_local! {
+    static MERGES: Cell<usize> = const { Cell::new(0) };
+}
+
+// counts the merges of `MergeNumberHash`
+struct CountingMerge;
+
+impl Merge for CountingMerge {
+    type Item = NumberHash;
+    type Error = Error;
+    fn merge(lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item> {
+        MERGES.with(|merges| merges.set(merges.get() + 1));
+        MergeNumberHash::merge(lhs, rhs)
+    }
+}
+
+fn count_merges(f: impl FnOnce() -> Result<bool>) -> (Result<bool>, usize) {
+    MERGES.with(|merges| merges.set(0));
+    let result = f();
+    (result, MERGES.with(|merges| merges.get()))
+}
+
+#[test]
+fn test_batch_verifier() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, CountingMerge>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..100)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().unwrap();
+
+    let mut batch = BatchVerifier::new(root.clone());
+    let mut separate_merges = 0;
+    for (i, pos) in positions.iter().take(64).enumerate() {
+        let leaves = vec![(*pos, NumberHash::from(i as u32))];
+        let proof = mmr.gen_proof(vec![*pos]).unwrap();
+        let (verified, merges) = count_merges(|| proof.verify(root.clone(), leaves.clone()));
+        assert_eq!(verified, Ok(true));
+        separate_merges += merges;
+        batch.add(proof, leaves);
+    }
+    assert_eq!(batch.len(), 64);
+    let (verified, batch_merges) = count_merges(|| batch.verify());
+    assert_eq!(verified, Ok(true));
+    // every node of the first mountain of 64 leaves is hashed once, plus the bagging
+    assert!(batch_merges < separate_merges / 4);
+
+    // a single wrong leaf fails the batch
+    let proof = mmr.gen_proof(vec![positions[70]]).unwrap();
+    batch.add(proof, vec![(positions[70], NumberHash::from(71))]);
+    assert_eq!(batch.verify(), Ok(false));
+
+    // proofs of different sizes can't share a root
+    let mut batch = BatchVerifier::new(root);
+    let proof = mmr.gen_proof(vec![positions[0]]).unwrap();
+    batch.add(proof, vec![(positions[0], NumberHash::from(0))]);
+    mmr.push(NumberHash::from(100)).unwrap();
+    let proof = mmr.gen_proof(vec![positions[0]]).unwrap();
+    batch.add(proof, vec![(positions[0], NumberHash::from(0))]);
+    assert_eq!(batch.verify(), Err(Error::CorruptedProof));
+
+    assert_eq!(
+        BatchVerifier::<NumberHash, CountingMerge>::new(NumberHash::from(0)).verify(),
+        Ok(true)
+    );
+}
+
+#[test]
+fn test_verify_batch() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..20)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().unwrap();
+    let leaf = |i: usize| (positions[i], NumberHash::from(i as u32));
+
+    let proof = mmr
+        .gen_proof(vec![
+            positions[1],
+            positions[5],
+            positions[9],
+            positions[17],
+        ])
+        .unwrap();
+    let groups = vec![
+        vec![leaf(1), leaf(5)],
+        vec![leaf(5), leaf(9)],
+        vec![leaf(17)],
+    ];
+    assert_eq!(proof.verify_batch(root.clone(), groups), Ok(true));
+
+    // groups disagreeing on a leaf
+    let groups = vec![
+        vec![leaf(1), leaf(5), leaf(9), leaf(17)],
+        vec![(positions[5], NumberHash::from(6))],
+    ];
+    assert_eq!(proof.verify_batch(root, groups), Ok(false));
+}
@@ -0,0 +1,125 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::interop::beefy::{k_index, AncestryProof, LeafProof, MmrLeaf, SimplifiedProof};
+use crate::util::{MemMMR, MemStore};
+use crate::{leaf_index_to_pos, Error, MerkleProof, Pos};
+
+#[test]
+fn test_k_index() {
+    // mountains of 8, 2 and 1 leaves
+    let k_indices: Vec<_> = (0..11).map(|i| k_index(i, 11).unwrap()).collect();
+    assert_eq!(k_indices, vec![0, 1, 2, 3, 4, 5, 6, 7, 0, 1, 0]);
+    assert_eq!(k_index(11, 11), Err(Error::CorruptedProof));
+}
+
+#[test]
+fn test_leaf_proof_round_trip() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..11 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    let root = mmr.get_root().unwrap();
+    let positions = vec![leaf_index_to_pos(9), leaf_index_to_pos(2)];
+    let proof = mmr.gen_proof(positions.clone()).unwrap();
+    let items = proof.proof_items().to_vec();
+
+    let leaf_proof = LeafProof::from_proof(proof, positions).unwrap();
+    assert_eq!(leaf_proof.leaf_indices, vec![2, 9]);
+    assert_eq!(leaf_proof.leaf_count, 11);
+    assert_eq!(leaf_proof.items, items);
+    assert_eq!(leaf_proof.mmr_size(), Ok(19));
+    assert_eq!(
+        leaf_proof.mmr_leaves(vec![NumberHash::from(2), NumberHash::from(9)]),
+        Ok(vec![
+            MmrLeaf {
+                k_index: 2,
+                leaf_index: 2,
+                hash: NumberHash::from(2)
+            },
+            MmrLeaf {
+                k_index: 1,
+                leaf_index: 9,
+                hash: NumberHash::from(9)
+            },
+        ])
+    );
+
+    let (proof, positions) = leaf_proof.into_proof::<MergeNumberHash>().unwrap();
+    assert_eq!(proof.mmr_size(), mmr.mmr_size());
+    let leaves = positions
+        .into_iter()
+        .zip([NumberHash::from(2), NumberHash::from(9)])
+        .collect();
+    assert_eq!(proof.verify(root, leaves), Ok(true));
+
+    let out_of_range = LeafProof {
+        leaf_indices: vec![11],
+        leaf_count: 11,
+        items,
+    };
+    assert_eq!(
+        out_of_range.into_proof::<MergeNumberHash>().err(),
+        Some(Error::CorruptedProof)
+    );
+}
+
+#[test]
+fn test_ancestry_proof_round_trip() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let mut prev_root = None;
+    for i in 0u32..20 {
+        mmr.push(NumberHash::from(i)).unwrap();
+        if i == 6 {
+            prev_root = Some(mmr.get_root().unwrap());
+        }
+    }
+    let proof = mmr.gen_ancestry_proof_for_leaf_count(7).unwrap();
+    let beefy_proof = AncestryProof::from_proof(&proof);
+    assert_eq!(beefy_proof.prev_leaf_count, 7);
+    assert_eq!(beefy_proof.leaf_count, 20);
+    assert_eq!(beefy_proof.prev_peaks, proof.prev_peaks());
+
+    let proof = beefy_proof.into_proof::<MergeNumberHash>().unwrap();
+    assert_eq!(
+        proof.verify_ancestor(mmr.get_root().unwrap(), prev_root.unwrap()),
+        Ok(true)
+    );
+}
+
+#[test]
+fn test_simplified_proof() {
+    for count in [1u32, 2, 3, 7, 11, 64, 100] {
+        let store = MemStore::default();
+        let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+        for i in 0..count {
+            mmr.push(NumberHash::from(i)).unwrap();
+        }
+        let root = mmr.get_root().unwrap();
+        for i in 0..count {
+            let pos = leaf_index_to_pos(i as Pos);
+            let proof = mmr.gen_proof(vec![pos]).unwrap();
+            let simplified = SimplifiedProof::from_proof(proof, pos).unwrap();
+            assert_eq!(
+                simplified.verify::<MergeNumberHash>(root.clone(), NumberHash::from(i)),
+                Ok(true)
+            );
+            assert_eq!(
+                simplified.verify::<MergeNumberHash>(root.clone(), NumberHash::from(count)),
+                Ok(false)
+            );
+        }
+    }
+
+    // the leaf at 3 is a peak, so its proof is just the peak on the left
+    let proof = MerkleProof::<NumberHash, MergeNumberHash>::new(4, vec![NumberHash::from(0); 2]);
+    assert_eq!(
+        SimplifiedProof::from_proof(proof, 3).err(),
+        Some(Error::CorruptedProof)
+    );
+    let proof = MerkleProof::<NumberHash, MergeNumberHash>::new(4, vec![]);
+    assert_eq!(
+        SimplifiedProof::from_proof(proof, 2).err(),
+        Some(Error::GenProofForInvalidLeaves)
+    );
+}
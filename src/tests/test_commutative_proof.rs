@@ -0,0 +1,107 @@
+use super::{new_blake2b, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::{
+    leaf_index_to_pos, CommutativeMerge, CommutativeMerkleProof, Error, Merge, MerkleProof, Pos,
+    Result,
+};
+use proptest::prelude::*;
+
+/// Hashes the sorted pair, so the order of the items doesn't matter.
+struct MergeSorted;
+
+impl Merge for MergeSorted {
+    type Item = NumberHash;
+    type Error = Error;
+    fn merge(lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item> {
+        let (first, second) = if lhs.0 <= rhs.0 {
+            (lhs, rhs)
+        } else {
+            (rhs, lhs)
+        };
+        let mut hasher = new_blake2b();
+        let mut hash = [0u8; 32];
+        hasher.update(&first.0);
+        hasher.update(&second.0);
+        hasher.finalize(&mut hash);
+        Ok(NumberHash(hash.to_vec().into()))
+    }
+}
+
+impl CommutativeMerge for MergeSorted {}
+
+fn test_commutative_proof(count: u32) {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeSorted>::new(0, &store);
+    let positions: Vec<Pos> = (0..count)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().expect("get root");
+    for (i, pos) in positions.iter().enumerate() {
+        let proof = mmr.gen_commutative_proof(*pos).expect("gen proof");
+        assert_eq!(
+            proof.proof_items().len(),
+            mmr.gen_proof(vec![*pos]).unwrap().proof_items().len()
+        );
+        assert!(proof
+            .verify(root.clone(), NumberHash::from(i as u32))
+            .unwrap());
+        assert!(!proof.verify(root.clone(), NumberHash::from(count)).unwrap());
+    }
+}
+
+#[test]
+fn test_commutative_proof_small() {
+    for count in 1..=20 {
+        test_commutative_proof(count);
+    }
+}
+
+#[test]
+fn test_into_commutative_invalid() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeSorted>::new(0, &store);
+    for i in 0..11u32 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    let pos = leaf_index_to_pos(5);
+    let proof = mmr.gen_proof(vec![pos]).unwrap();
+    let items = proof.proof_items().to_vec();
+
+    // not the leaf the proof was generated for
+    assert_eq!(
+        proof.into_commutative(leaf_index_to_pos(10)).err(),
+        Some(Error::CorruptedProof)
+    );
+    // not a leaf
+    assert_eq!(
+        MerkleProof::<_, MergeSorted>::new(mmr.mmr_size(), items.clone())
+            .into_commutative(2)
+            .err(),
+        Some(Error::GenProofForInvalidLeaves)
+    );
+    assert_eq!(
+        MerkleProof::<_, MergeSorted>::new(mmr.mmr_size(), items[1..].to_vec())
+            .into_commutative(pos)
+            .err(),
+        Some(Error::CorruptedProof)
+    );
+
+    // the folding order still matters
+    let mut reordered = mmr
+        .gen_commutative_proof(pos)
+        .unwrap()
+        .proof_items()
+        .to_vec();
+    reordered.swap(0, 1);
+    let root = mmr.get_root().unwrap();
+    assert!(!CommutativeMerkleProof::<_, MergeSorted>::new(reordered)
+        .verify(root, NumberHash::from(5))
+        .unwrap());
+}
+
+proptest! {
+    #[test]
+    fn test_random_commutative_proof(count in 20u32..200u32) {
+        test_commutative_proof(count);
+    }
+}
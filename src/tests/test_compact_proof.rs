@@ -0,0 +1,154 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::{
+    util::{MemMMR, MemStore},
+    CompactMerkleProof, Error, Pos,
+};
+use proptest::prelude::*;
+use rand::{seq::SliceRandom, thread_rng, Rng};
+
+fn test_compact_multiproof(count: u32, proof_elem: Vec<u32>) {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..count)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().expect("get root");
+    let pos_list: Vec<Pos> = proof_elem
+        .iter()
+        .map(|elem| positions[*elem as usize])
+        .collect();
+    let proof = mmr
+        .gen_compact_multiproof(pos_list.clone())
+        .expect("gen proof");
+    // no hash is included twice, so the proof carries no more hashes than the positional one
+    assert_eq!(
+        proof.proof_items().len(),
+        mmr.gen_proof(pos_list).unwrap().proof_items().len()
+    );
+    let leaves: Vec<_> = proof_elem
+        .iter()
+        .map(|elem| (positions[*elem as usize], NumberHash::from(*elem)))
+        .collect();
+    assert!(proof.verify(root.clone(), leaves.clone()).unwrap());
+    let mut sorted_leaves = leaves.clone();
+    sorted_leaves.sort_by_key(|(pos, _)| *pos);
+    sorted_leaves.dedup_by_key(|(pos, _)| *pos);
+    let mut items: Vec<_> = sorted_leaves.into_iter().map(|(_, item)| item).collect();
+    assert!(proof.verify_leaves(root.clone(), items.clone()).unwrap());
+
+    let mut tampered = leaves.clone();
+    tampered[0].1 = NumberHash::from(count);
+    assert!(!proof.verify(root.clone(), tampered).unwrap());
+    items.push(NumberHash::from(count));
+    assert_eq!(
+        proof.verify_leaves(root.clone(), items.clone()),
+        Err(Error::CorruptedProof)
+    );
+    items.truncate(items.len() - 2);
+    assert_eq!(
+        proof.verify_leaves(root.clone(), items),
+        Err(Error::CorruptedProof)
+    );
+
+    if count > 1 && leaves.len() < count as usize {
+        // leaves must match the positions described by the proof
+        let missing = (0..count)
+            .find(|elem| !proof_elem.contains(elem))
+            .expect("missing leaf");
+        let mut moved = leaves;
+        moved[0] = (positions[missing as usize], NumberHash::from(missing));
+        assert_eq!(proof.verify(root, moved), Err(Error::CorruptedProof));
+    }
+}
+
+#[test]
+fn test_compact_multiproof_single_leaf() {
+    test_compact_multiproof(1, vec![0]);
+    test_compact_multiproof(11, vec![0]);
+    test_compact_multiproof(11, vec![5]);
+    test_compact_multiproof(11, vec![10]);
+}
+
+#[test]
+fn test_compact_multiproof_many_leaves() {
+    test_compact_multiproof(11, vec![3, 7]);
+    test_compact_multiproof(11, vec![4, 5, 6]);
+    test_compact_multiproof(11, (0..11).collect());
+    test_compact_multiproof(100, vec![3, 5, 13, 64, 97]);
+}
+
+#[test]
+fn test_compact_multiproof_invalid() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    (0u32..11).for_each(|i| {
+        mmr.push(NumberHash::from(i)).unwrap();
+    });
+    assert_eq!(
+        mmr.gen_compact_multiproof(vec![]).err(),
+        Some(Error::GenProofForInvalidLeaves)
+    );
+    assert_eq!(
+        mmr.gen_compact_multiproof(vec![2]).err(),
+        Some(Error::GenProofForInvalidLeaves)
+    );
+    assert_eq!(
+        mmr.gen_compact_multiproof(vec![19]).err(),
+        Some(Error::PositionOutOfRange { pos: 19 })
+    );
+
+    let root = mmr.get_root().unwrap();
+    let leaves = vec![(0, NumberHash::from(0))];
+    let proof = mmr.gen_compact_multiproof(vec![0]).unwrap();
+    assert!(proof.verify(root.clone(), leaves.clone()).unwrap());
+    let mut descriptor = proof.descriptor().to_vec();
+    // non-zero padding
+    *descriptor.last_mut().unwrap() |= 0x80;
+    let padded = CompactMerkleProof::<_, MergeNumberHash>::new(
+        proof.mmr_size(),
+        descriptor,
+        proof.proof_items().to_vec(),
+    );
+    assert_eq!(
+        padded.verify(root.clone(), leaves.clone()),
+        Err(Error::CorruptedProof)
+    );
+    // missing hash
+    let truncated = CompactMerkleProof::<_, MergeNumberHash>::new(
+        proof.mmr_size(),
+        proof.descriptor().to_vec(),
+        proof.proof_items()[1..].to_vec(),
+    );
+    assert_eq!(truncated.verify(root, leaves), Err(Error::CorruptedProof));
+}
+
+#[test]
+fn test_compact_multiproof_replaces_positions() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..1000)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().unwrap();
+    let elems: Vec<u32> = (0..100).map(|i| i * 10 + 3).collect();
+    let pos_list: Vec<Pos> = elems.iter().map(|elem| positions[*elem as usize]).collect();
+    let proof = mmr.gen_compact_multiproof(pos_list.clone()).unwrap();
+    let leaves: Vec<_> = elems.iter().map(|elem| NumberHash::from(*elem)).collect();
+    assert!(proof.verify_leaves(root, leaves).unwrap());
+
+    // the descriptor takes a fraction of the space of the positions it replaces
+    let positions_size = pos_list.len() * core::mem::size_of::<Pos>();
+    assert!(proof.descriptor().len() * 3 <= positions_size);
+}
+
+proptest! {
+    #[test]
+    fn test_random_compact_multiproof(count in 10u32..500u32) {
+        let mut leaves: Vec<u32> = (0..count).collect();
+        let mut rng = thread_rng();
+        leaves.shuffle(&mut rng);
+        let leaves_count = rng.gen_range(1..count - 1);
+        leaves.truncate(leaves_count as usize);
+        test_compact_multiproof(count, leaves);
+    }
+}
@@ -0,0 +1,72 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::{DualMerge, Error, Merge, Pos, Result};
+
+// the aux digest stands in for e.g. a keccak hash of the payload
+struct MergeDual;
+
+impl Merge for MergeDual {
+    type Item = NumberHash;
+    type Error = Error;
+    fn merge(lhs: &NumberHash, rhs: &NumberHash) -> Result<NumberHash> {
+        MergeNumberHash::merge(lhs, rhs)
+    }
+}
+
+impl DualMerge for MergeDual {
+    type Aux = NumberHash;
+    fn merge_leaf(hash: &NumberHash, aux: &NumberHash) -> Result<NumberHash> {
+        MergeNumberHash::merge(hash, aux)
+    }
+}
+
+fn aux(i: u32) -> NumberHash {
+    NumberHash::from(1000 + i)
+}
+
+#[test]
+fn test_dual_proof() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeDual>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push_dual(NumberHash::from(i), &aux(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().unwrap();
+
+    let proof = mmr
+        .gen_dual_proof(vec![
+            (positions[7], NumberHash::from(7)),
+            (positions[2], NumberHash::from(2)),
+        ])
+        .unwrap();
+    assert_eq!(
+        proof.leaf_hashes(),
+        &[
+            (positions[2], NumberHash::from(2)),
+            (positions[7], NumberHash::from(7))
+        ][..]
+    );
+    assert_eq!(
+        proof.verify(
+            root.clone(),
+            vec![(positions[7], aux(7)), (positions[2], aux(2))]
+        ),
+        Ok(true)
+    );
+    // the leaf nodes commit to the aux digests
+    assert_eq!(
+        proof.verify(
+            root.clone(),
+            vec![(positions[2], aux(7)), (positions[7], aux(2))]
+        ),
+        Ok(false)
+    );
+    assert_eq!(
+        proof.verify(root.clone(), vec![(positions[2], aux(2))]),
+        Err(Error::CorruptedProof)
+    );
+    assert_eq!(
+        proof.verify(root, vec![(positions[2], aux(2)), (positions[8], aux(8))]),
+        Err(Error::CorruptedProof)
+    );
+}
@@ -0,0 +1,128 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::dump::{fmt_hex_abbreviated, NodeDisplay};
+use crate::util::{MemMMR, MemStore};
+use crate::{Error, Pos};
+use core::fmt;
+
+impl NodeDisplay for NumberHash {
+    fn fmt_node(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_hex_abbreviated(&self.0, f)
+    }
+}
+
+fn mmr_with_leaves(
+    store: &MemStore<NumberHash>,
+    count: u32,
+) -> MemMMR<'_, NumberHash, MergeNumberHash> {
+    let mut mmr = MemMMR::new(0, store);
+    for i in 0..count {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    mmr
+}
+
+#[test]
+fn test_dump_structure() {
+    let store = MemStore::default();
+    let mmr = mmr_with_leaves(&store, 11);
+    let dump = mmr.dump_structure(Pos::MAX);
+    assert_eq!(dump.omitted_positions, 0);
+    assert_eq!(
+        dump.to_string(),
+        "mmr_size 19, leaves 11, peaks [14, 17, 18]\n\
+         mountain 14 (height 3)\n  h3: 14\n  h2: 6 13\n  h1: 2 5 9 12\n  h0: 0 1 3 4 7 8 10 11\n\
+         mountain 17 (height 1)\n  h1: 17\n  h0: 15 16\n\
+         mountain 18 (height 0)\n  h0: 18\n"
+    );
+
+    // the limit cuts the dump at a position
+    let dump = mmr.dump_structure(5);
+    assert_eq!(dump.omitted_positions, 14);
+    assert_eq!(
+        dump.to_string(),
+        "mmr_size 19, leaves 11, peaks [14, 17, 18]\n\
+         mountain 14 (height 3)\n  h3:\n  h2:\n  h1: 2\n  h0: 0 1 3 4\n\
+         ... 14 more positions\n"
+    );
+
+    let store = MemStore::default();
+    let empty = mmr_with_leaves(&store, 0);
+    assert_eq!(
+        empty.dump_structure(10).to_string(),
+        "mmr_size 0, leaves 0, peaks []\n"
+    );
+}
+
+#[test]
+fn test_dump_nodes() {
+    let store = MemStore::default();
+    let mmr = mmr_with_leaves(&store, 2);
+    let dump = mmr.dump_nodes(Pos::MAX).unwrap();
+    let node = |pos| {
+        let elem = mmr.batch().get_elem(pos).unwrap().unwrap();
+        format!(
+            "{:02x}{:02x}{:02x}{:02x}..",
+            elem.0[0], elem.0[1], elem.0[2], elem.0[3]
+        )
+    };
+    assert_eq!(
+        dump.to_string(),
+        format!(
+            "mmr_size 3, leaves 2, peaks [2]\nmountain 2 (height 1)\n  h1: 2:{}\n  h0: 0:{} 1:{}\n",
+            node(2),
+            node(0),
+            node(1)
+        )
+    );
+}
+
+#[test]
+fn test_mmr_to_dot() {
+    let store = MemStore::default();
+    let mmr = mmr_with_leaves(&store, 3);
+    assert_eq!(
+        mmr.to_dot(0..mmr.mmr_size()),
+        "digraph mmr {\n  label=\"mmr_size 4, leaves 3\";\n  node [shape=box];\n\
+         \x20 subgraph cluster_2 {\n    label=\"mountain 2 (height 1)\";\n\
+         \x20   n0 [label=\"0\"];\n    n1 [label=\"1\"];\n    n2 [label=\"2\", peripheries=2];\n  }\n\
+         \x20 n2 -> n0;\n  n2 -> n1;\n\
+         \x20 subgraph cluster_3 {\n    label=\"mountain 3 (height 0)\";\n\
+         \x20   n3 [label=\"3\", peripheries=2];\n  }\n\
+         \x20 { rank=same; n0; n1; n3; }\n}\n"
+    );
+
+    // edges to nodes out of the range are left out
+    let dot = mmr.to_dot(1..3);
+    assert!(!dot.contains("n0"));
+    assert!(dot.contains("n2 -> n1;"));
+    assert!(!dot.contains("cluster_3"));
+}
+
+#[test]
+fn test_proof_to_dot() {
+    let store = MemStore::default();
+    let mmr = mmr_with_leaves(&store, 11);
+    let proof = mmr.gen_proof(vec![3]).unwrap();
+    let dot = proof.to_dot(&[3]).unwrap();
+    assert!(dot.contains("label=\"mmr_size 19, leaves [3], 4 proof items, 4 expected\";"));
+    assert!(dot.contains("n3 [label=\"3\", style=filled, fillcolor=palegreen];"));
+    for pos in [4, 2, 13] {
+        assert!(dot.contains(&format!(
+            "n{} [label=\"{}\", style=filled, fillcolor=orange];",
+            pos, pos
+        )));
+    }
+    // the bagged rhs peaks
+    for pos in [17, 18] {
+        assert!(dot.contains(&format!(
+            "n{} [label=\"{}\", style=\"filled,dashed\", fillcolor=orange];",
+            pos, pos
+        )));
+    }
+    for pos in [5, 6, 14] {
+        assert!(dot.contains(&format!("n{} [label=\"{}\", style=dashed];", pos, pos)));
+    }
+    assert!(dot.contains("n0 [label=\"0\"];"));
+
+    assert_eq!(proof.to_dot(&[5]), Err(Error::GenProofForInvalidLeaves));
+}
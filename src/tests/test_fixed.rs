@@ -0,0 +1,122 @@
+use super::new_blake2b;
+use crate::{
+    util::{MemMMR, MemStore},
+    verify_fixed, Error, FixedHash, Merge, Pos, Result,
+};
+use proptest::prelude::*;
+
+type Hash = FixedHash<32>;
+
+struct MergeFixed;
+
+impl Merge for MergeFixed {
+    type Item = Hash;
+    type Error = Error;
+    fn merge(lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item> {
+        let mut hasher = new_blake2b();
+        let mut hash = [0u8; 32];
+        hasher.update(lhs.as_ref());
+        hasher.update(rhs.as_ref());
+        hasher.finalize(&mut hash);
+        Ok(hash.into())
+    }
+}
+
+fn leaf(i: u32) -> Hash {
+    let mut hash = [0u8; 32];
+    hash[..4].copy_from_slice(&i.to_le_bytes());
+    hash.into()
+}
+
+fn test_verify_fixed(count: u32) {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeFixed>::new(0, &store);
+    let positions: Vec<Pos> = (0..count).map(|i| mmr.push(leaf(i)).unwrap()).collect();
+    let root = mmr.get_root().expect("get root");
+    for (i, pos) in positions.iter().enumerate() {
+        let proof = mmr.gen_proof(vec![*pos]).expect("gen proof");
+        let items = proof.proof_items();
+        let mmr_size = mmr.mmr_size();
+        assert!(
+            verify_fixed::<MergeFixed, 32>(&root, mmr_size, *pos, leaf(i as u32), items).unwrap()
+        );
+        assert!(
+            !verify_fixed::<MergeFixed, 32>(&root, mmr_size, *pos, leaf(count), items).unwrap()
+        );
+        if !items.is_empty() {
+            assert_eq!(
+                verify_fixed::<MergeFixed, 32>(&root, mmr_size, *pos, leaf(i as u32), &items[1..]),
+                Err(Error::CorruptedProof)
+            );
+        }
+    }
+}
+
+#[test]
+fn test_verify_fixed_small() {
+    for count in 1..=20 {
+        test_verify_fixed(count);
+    }
+}
+
+#[test]
+fn test_verify_fixed_invalid_leaf() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeFixed>::new(0, &store);
+    (0u32..11).for_each(|i| {
+        mmr.push(leaf(i)).unwrap();
+    });
+    let root = mmr.get_root().unwrap();
+    // not a leaf
+    assert_eq!(
+        verify_fixed::<MergeFixed, 32>(&root, mmr.mmr_size(), 2, leaf(0), &[]),
+        Err(Error::GenProofForInvalidLeaves)
+    );
+    // beyond the mmr
+    assert_eq!(
+        verify_fixed::<MergeFixed, 32>(&root, mmr.mmr_size(), 19, leaf(0), &[]),
+        Err(Error::GenProofForInvalidLeaves)
+    );
+}
+
+proptest! {
+    #[test]
+    fn test_random_verify_fixed(count in 20u32..200u32) {
+        test_verify_fixed(count);
+    }
+}
+
+#[cfg(feature = "subtle")]
+#[test]
+fn test_verify_ct() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeFixed>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11).map(|i| mmr.push(leaf(i)).unwrap()).collect();
+    let root = mmr.get_root().unwrap();
+    let wrong_root = leaf(31337);
+
+    let leaves = vec![(positions[3], leaf(3)), (positions[9], leaf(9))];
+    let proof = mmr.gen_proof(vec![positions[3], positions[9]]).unwrap();
+    assert_eq!(proof.verify_ct(root, leaves.clone()), Ok(true));
+    assert_eq!(proof.verify_ct(wrong_root, leaves), Ok(false));
+
+    let node_proof = mmr.gen_node_proof(vec![5]).unwrap();
+    let node = mmr.batch().get_elem(5).unwrap().unwrap();
+    assert_eq!(node_proof.verify_ct(root, vec![(5, node)]), Ok(true));
+    assert_eq!(node_proof.verify_ct(wrong_root, vec![(5, node)]), Ok(false));
+
+    let (_, prev_root) = mmr.get_ancestor_peaks_and_root(8).unwrap();
+    let ancestry_proof = mmr.gen_ancestry_proof(8).unwrap();
+    for (root, prev_root) in [
+        (root, prev_root),
+        (wrong_root, prev_root),
+        (root, wrong_root),
+        (wrong_root, wrong_root),
+    ] {
+        assert_eq!(
+            ancestry_proof.verify_ancestor_ct(root, prev_root),
+            ancestry_proof.verify_ancestor(root, prev_root)
+        );
+    }
+    assert_eq!(ancestry_proof.verify_ancestor_ct(root, prev_root), Ok(true));
+}
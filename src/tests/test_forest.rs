@@ -0,0 +1,95 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::{Error, ForestBuilder, MMRStoreReadOps, Pos, Shard};
+
+fn build_shards(leaves: &[NumberHash], shard_leaves: Pos) -> Vec<Shard<NumberHash>> {
+    // every shard on its own thread
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = leaves
+            .chunks(shard_leaves as usize)
+            .enumerate()
+            .map(|(index, chunk)| {
+                scope.spawn(move || {
+                    Shard::build::<MergeNumberHash>(shard_leaves, index as Pos, chunk.to_vec())
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap().unwrap())
+            .collect()
+    })
+}
+
+#[test]
+fn test_forest_matches_serial_pushes() {
+    for (count, shard_leaves) in [(1000u32, 64), (1024, 64), (1, 4), (7, 8), (100, 1), (33, 2)] {
+        let leaves: Vec<NumberHash> = (0..count).map(NumberHash::from).collect();
+
+        let serial_store = MemStore::default();
+        let mut serial = MemMMR::<_, MergeNumberHash>::new(0, &serial_store);
+        for leaf in &leaves {
+            serial.push(leaf.clone()).unwrap();
+        }
+        serial.commit().unwrap();
+
+        let store = MemStore::default();
+        let mut builder =
+            ForestBuilder::<_, MergeNumberHash, _>::new(shard_leaves, &store).unwrap();
+        for shard in build_shards(&leaves, shard_leaves) {
+            assert_eq!(shard.index(), builder.next_index());
+            builder.append_shard(shard).unwrap();
+        }
+        let mmr = builder.finish().unwrap();
+
+        assert_eq!(mmr.mmr_size(), serial.mmr_size());
+        assert_eq!(mmr.get_root(), serial.get_root());
+        assert_eq!((&store).mmr_size(), Ok(Some(serial.mmr_size())));
+        for pos in 0..serial.mmr_size() {
+            assert_eq!((&store).get_elem(pos), (&serial_store).get_elem(pos));
+        }
+    }
+}
+
+#[test]
+fn test_forest_rejects_unexpected_shards() {
+    let leaves: Vec<NumberHash> = (0u32..20).map(NumberHash::from).collect();
+    let shard = |index: usize, count: usize| {
+        Shard::build::<MergeNumberHash>(8, index as Pos, leaves[index * 8..][..count].to_vec())
+            .unwrap()
+    };
+    assert_eq!(
+        Shard::build::<MergeNumberHash>(6, 0, leaves[..6].to_vec()).err(),
+        Some(Error::InvalidLeafCount { leaf_count: 6 })
+    );
+    assert_eq!(
+        Shard::build::<MergeNumberHash>(8, 0, leaves[..9].to_vec()).err(),
+        Some(Error::InvalidLeafCount { leaf_count: 9 })
+    );
+    assert_eq!(
+        Shard::build::<MergeNumberHash>(8, 0, Vec::new()).err(),
+        Some(Error::InvalidLeafCount { leaf_count: 0 })
+    );
+
+    let store = MemStore::default();
+    let mut builder = ForestBuilder::<_, MergeNumberHash, _>::new(8, &store).unwrap();
+    // out of order
+    assert_eq!(
+        builder.append_shard(shard(1, 8)),
+        Err(Error::UnexpectedShard { index: 1 })
+    );
+    // another shard size
+    let other = Shard::build::<MergeNumberHash>(4, 0, leaves[..4].to_vec()).unwrap();
+    assert_eq!(
+        builder.append_shard(other),
+        Err(Error::UnexpectedShard { index: 0 })
+    );
+    builder.append_shard(shard(0, 8)).unwrap();
+    builder.append_shard(shard(1, 4)).unwrap();
+    // nothing after a partial shard
+    assert_eq!(
+        builder.append_shard(shard(2, 4)),
+        Err(Error::UnexpectedShard { index: 2 })
+    );
+    assert_eq!(builder.finish().unwrap().leaves_count(), 12);
+}
@@ -1,16 +1,19 @@
 use super::{MergeNumberHash, NumberHash};
 use crate::{
-    helper::{get_peak_map, get_peaks, pos_height_in_tree},
+    helper::{
+        common_ancestor, get_peak_map, get_peaks, is_descendant_pos, is_valid_mmr_size,
+        leaf_count_to_mmr_size, leftmost_leaf, pos_height_in_tree, rightmost_leaf, subtree_range,
+    },
     leaf_index_to_mmr_size, leaf_index_to_pos,
     util::MemStore,
-    MMR,
+    Pos, PositionScheme, PostOrderScheme, MMR,
 };
 use lazy_static::lazy_static;
 use proptest::prelude::*;
 
 lazy_static! {
     /// Positions of 0..100_000 elem
-    static ref INDEX_TO_POS: Vec<u64> = {
+    static ref INDEX_TO_POS: Vec<Pos> = {
         let store = MemStore::default();
         let mut mmr = MMR::<_,MergeNumberHash,_>::new(0, &store);
         (0u32..100_000)
@@ -18,7 +21,7 @@ lazy_static! {
             .collect()
     };
     /// mmr size when 0..100_000 elem
-    static ref INDEX_TO_MMR_SIZE: Vec<u64> = {
+    static ref INDEX_TO_MMR_SIZE: Vec<Pos> = {
         let store = MemStore::default();
         let mut mmr = MMR::<_,MergeNumberHash,_>::new(0, &store);
         (0u32..100_000)
@@ -76,7 +79,7 @@ fn test_get_peak_map() {
 
 #[test]
 fn test_get_peaks() {
-    assert_eq!(get_peaks(0), vec![]);
+    assert_eq!(get_peaks(0), Vec::<Pos>::new());
     assert_eq!(get_peaks(1), vec![0]);
     assert_eq!(get_peaks(3), vec![2]);
     assert_eq!(get_peaks(4), vec![2, 3]);
@@ -93,15 +96,162 @@ fn test_get_peaks() {
     assert_eq!(get_peaks(19), vec![14, 17, 18]);
 }
 
+#[test]
+fn test_is_valid_mmr_size() {
+    let valid: Vec<Pos> = (0..20).filter(|size| is_valid_mmr_size(*size)).collect();
+    assert_eq!(valid, vec![0, 1, 3, 4, 7, 8, 10, 11, 15, 16, 18, 19]);
+}
+
+#[test]
+fn test_leaf_count_to_mmr_size() {
+    assert_eq!(leaf_count_to_mmr_size(0), None);
+    for leaf_count in 1..100 {
+        assert_eq!(
+            leaf_count_to_mmr_size(leaf_count),
+            Some(leaf_index_to_mmr_size(leaf_count - 1))
+        );
+    }
+    assert_eq!(leaf_count_to_mmr_size(1 << (Pos::BITS - 1)), Some(Pos::MAX));
+    assert_eq!(leaf_count_to_mmr_size((1 << (Pos::BITS - 1)) + 1), None);
+}
+
+#[test]
+fn test_subtree_helpers() {
+    assert_eq!(subtree_range(14), 0..15);
+    assert_eq!(subtree_range(13), 7..14);
+    assert_eq!(subtree_range(18), 18..19);
+    assert_eq!((leftmost_leaf(13), rightmost_leaf(13)), (7, 11));
+    assert_eq!((leftmost_leaf(17), rightmost_leaf(17)), (15, 16));
+    assert_eq!(common_ancestor(3, 8), 14);
+    assert_eq!(common_ancestor(9, 10), 13);
+    assert_eq!(common_ancestor(5, 4), 5);
+    // beyond the MMR of 11 leaves
+    assert_eq!(common_ancestor(14, 18), 30);
+
+    let ancestors = |mut pos: Pos| {
+        let mut ancestors = vec![pos];
+        while pos < 300 {
+            pos = PostOrderScheme::parent_pos(pos);
+            ancestors.push(pos);
+        }
+        ancestors
+    };
+    for a in 0..200 {
+        let ancestors_a = ancestors(a);
+        for b in 0..200 {
+            assert_eq!(is_descendant_pos(a, b), ancestors(b).contains(&a));
+            let common = ancestors(b)
+                .into_iter()
+                .find(|pos| ancestors_a.contains(pos))
+                .unwrap();
+            assert_eq!(common_ancestor(a, b), common);
+        }
+        let leaves: Vec<Pos> = subtree_range(a)
+            .filter(|pos| pos_height_in_tree(*pos) == 0)
+            .collect();
+        assert_eq!(leaves.first(), Some(&leftmost_leaf(a)));
+        assert_eq!(leaves.last(), Some(&rightmost_leaf(a)));
+    }
+}
+
+/// Only the coordinate mappings of the post-order numbering, so the provided methods get used.
+struct CoordsOnly;
+
+impl PositionScheme for CoordsOnly {
+    fn node_pos(height: u8, index: Pos) -> Pos {
+        PostOrderScheme::node_pos(height, index)
+    }
+
+    fn node_coords(pos: Pos) -> (u8, Pos) {
+        PostOrderScheme::node_coords(pos)
+    }
+}
+
+/// The flat in-order numbering, leaves at even positions.
+struct InOrderScheme;
+
+impl PositionScheme for InOrderScheme {
+    fn node_pos(height: u8, index: Pos) -> Pos {
+        (((index << 1) | 1) << height) - 1
+    }
+
+    fn node_coords(pos: Pos) -> (u8, Pos) {
+        let height = (pos + 1).trailing_zeros() as u8;
+        (height, (pos + 1) >> (height + 1))
+    }
+}
+
+#[test]
+fn test_post_order_scheme() {
+    //           14
+    //        /       \
+    //      6          13
+    //    /   \       /   \
+    //   2     5     9     12     17
+    //  / \   /  \  / \   /  \   /  \
+    // 0   1 3   4 7   8 10  11 15  16 18
+    assert_eq!(PostOrderScheme::node_coords(13), (2, 1));
+    assert_eq!(PostOrderScheme::node_pos(1, 4), 17);
+    assert_eq!(PostOrderScheme::peaks(11), vec![14, 17, 18]);
+    assert_eq!(PostOrderScheme::peaks(0), Vec::<Pos>::new());
+
+    let mmr_size = leaf_index_to_mmr_size(1000);
+    for pos in 0..mmr_size {
+        let (height, index) = PostOrderScheme::node_coords(pos);
+        assert_eq!(height, pos_height_in_tree(pos));
+        assert_eq!(PostOrderScheme::node_pos(height, index), pos);
+        assert_eq!(
+            PostOrderScheme::parent_pos(pos),
+            CoordsOnly::parent_pos(pos)
+        );
+        assert_eq!(
+            PostOrderScheme::sibling_pos(pos),
+            CoordsOnly::sibling_pos(pos)
+        );
+        assert_eq!(
+            PostOrderScheme::children_pos(pos),
+            CoordsOnly::children_pos(pos)
+        );
+        assert_eq!(
+            PostOrderScheme::sibling_and_parent_pos(pos, height),
+            CoordsOnly::sibling_and_parent_pos(pos, height)
+        );
+    }
+    for leaf_count in 1..1000 {
+        assert_eq!(
+            PostOrderScheme::leaf_index_to_pos(leaf_count),
+            leaf_index_to_pos(leaf_count)
+        );
+        assert_eq!(
+            PostOrderScheme::peaks(leaf_count),
+            CoordsOnly::peaks(leaf_count)
+        );
+    }
+}
+
+#[test]
+fn test_convert_position_scheme() {
+    assert_eq!(InOrderScheme::peaks(11), vec![7, 17, 20]);
+    assert_eq!(PostOrderScheme::convert::<InOrderScheme>(14), 7);
+    for pos in 0..leaf_index_to_mmr_size(1000) {
+        let in_order = PostOrderScheme::convert::<InOrderScheme>(pos);
+        assert_eq!(InOrderScheme::convert::<PostOrderScheme>(in_order), pos);
+        assert_eq!(
+            PostOrderScheme::convert::<InOrderScheme>(PostOrderScheme::parent_pos(pos)),
+            InOrderScheme::parent_pos(in_order)
+        );
+    }
+}
+
 proptest! {
     #[test]
     fn test_leaf_index_to_pos_randomly(index in 0..INDEX_TO_POS.len()) {
-        let pos = leaf_index_to_pos(index as u64);
+        let pos = leaf_index_to_pos(index as Pos);
         assert_eq!(pos, INDEX_TO_POS[index]);
     }
 
     #[test]
     fn test_leaf_index_to_mmr_size_randomly(index in 0..INDEX_TO_MMR_SIZE.len()) {
-        assert_eq!(leaf_index_to_mmr_size(index as u64), INDEX_TO_MMR_SIZE[index]);
+        assert_eq!(leaf_index_to_mmr_size(index as Pos), INDEX_TO_MMR_SIZE[index]);
     }
 }
@@ -0,0 +1,98 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::helper::{self, huge, Pos};
+use crate::util::{MemMMR, MemStore};
+use crate::Error;
+use proptest::prelude::*;
+
+#[test]
+fn test_huge_helpers_beyond_u64() {
+    let index = u64::MAX as u128;
+    assert_eq!(huge::leaf_index_to_mmr_size(index), (1 << 65) - 1);
+    assert_eq!(huge::leaf_index_to_pos(index), (1 << 65) - 66);
+    assert_eq!(huge::leaf_count_to_mmr_size(1 << 64), Some((1 << 65) - 1));
+    assert_eq!(huge::leaf_count_to_mmr_size(u128::MAX), None);
+    assert!(huge::is_valid_mmr_size((1 << 65) - 1));
+    assert!(huge::is_valid_mmr_size(1 << 65));
+    assert!(!huge::is_valid_mmr_size((1 << 65) + 1));
+    assert_eq!(huge::get_peak_map((1 << 65) - 1), 1 << 64);
+    assert_eq!(huge::get_peaks((1 << 65) - 1), vec![(1 << 65) - 2]);
+    assert_eq!(
+        huge::get_peaks((1 << 65) + 2),
+        vec![(1 << 65) - 2, (1 << 65) + 1]
+    );
+    assert_eq!(huge::pos_height_in_tree((1 << 65) - 2), 64);
+    assert_eq!(huge::parent_offset(64), 1 << 65);
+    assert_eq!(huge::sibling_offset(64), (1 << 65) - 1);
+}
+
+proptest! {
+    #[test]
+    fn test_huge_helpers_match(index in 0..Pos::MAX >> 2, mmr_size in 0..Pos::MAX) {
+        let wide_index = index as u128;
+        prop_assert_eq!(
+            huge::leaf_index_to_pos(wide_index),
+            helper::leaf_index_to_pos(index) as u128
+        );
+        prop_assert_eq!(
+            huge::leaf_index_to_mmr_size(wide_index),
+            helper::leaf_index_to_mmr_size(index) as u128
+        );
+        let wide_size = mmr_size as u128;
+        prop_assert_eq!(
+            huge::is_valid_mmr_size(wide_size),
+            helper::is_valid_mmr_size(mmr_size)
+        );
+        prop_assert_eq!(
+            huge::pos_height_in_tree(wide_size),
+            helper::pos_height_in_tree(mmr_size)
+        );
+        prop_assert_eq!(
+            huge::get_peak_map(wide_size),
+            helper::get_peak_map(mmr_size) as u128
+        );
+        let peaks: Vec<u128> = helper::get_peaks(mmr_size)
+            .into_iter()
+            .map(|peak| peak as u128)
+            .collect();
+        prop_assert_eq!(huge::get_peaks(wide_size), peaks);
+    }
+}
+
+#[test]
+fn test_largest_mmr() {
+    // a single mountain of `2^(BITS - 1)` leaves fills all positions
+    let last_index = Pos::MAX >> 1;
+    assert_eq!(helper::leaf_index_to_mmr_size(last_index), Pos::MAX);
+    assert_eq!(
+        helper::leaf_index_to_pos(last_index),
+        Pos::MAX - Pos::BITS as Pos
+    );
+    assert_eq!(
+        helper::parent_offset(Pos::BITS as u8 - 2),
+        1 << (Pos::BITS - 1)
+    );
+}
+
+#[test]
+#[should_panic(expected = "position overflow")]
+fn test_leaf_index_overflow() {
+    helper::leaf_index_to_mmr_size((Pos::MAX >> 1) + 1);
+}
+
+#[test]
+#[should_panic(expected = "position overflow")]
+fn test_offset_overflow() {
+    helper::parent_offset(Pos::BITS as u8 - 1);
+}
+
+#[test]
+fn test_push_overflow() {
+    let store = MemStore::default();
+    // a leaf next to a single mountain needs no merges, so no nodes are read
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(Pos::MAX >> 1, &store);
+    assert_eq!(mmr.push(NumberHash::from(0)), Ok(Pos::MAX >> 1));
+
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(Pos::MAX, &store);
+    assert_eq!(mmr.push(NumberHash::from(0)), Err(Error::PositionOverflow));
+    assert_eq!(mmr.mmr_size(), Pos::MAX);
+}
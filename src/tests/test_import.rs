@@ -0,0 +1,150 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::{import, leaf_index_to_pos, Error, Pos, MMR};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+fn build_mmr(store: &MemStore<NumberHash>, count: u32) -> MemMMR<'_, NumberHash, MergeNumberHash> {
+    let mut mmr = MemMMR::new(0, store);
+    for i in 0u32..count {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    mmr.commit().unwrap();
+    mmr
+}
+
+#[test]
+fn test_from_leaf_hashes() {
+    let store = MemStore::default();
+    let root = build_mmr(&store, 11).get_root().unwrap();
+    let leaves = (0u32..11).map(NumberHash::from);
+
+    let imported_store = MemStore::default();
+    let mut mmr =
+        import::from_leaf_hashes::<_, MergeNumberHash, _>(leaves.clone(), &root, &imported_store)
+            .unwrap();
+    // nothing is written before the commit
+    assert_eq!(imported_store.len(), 0);
+    mmr.commit().unwrap();
+    let mmr = MMR::<_, MergeNumberHash, _>::open(&imported_store).unwrap();
+    assert_eq!(mmr.get_root(), Ok(root.clone()));
+    let proof = mmr.gen_proof(vec![leaf_index_to_pos(5)]).unwrap();
+    assert_eq!(
+        proof.verify(root, vec![(leaf_index_to_pos(5), NumberHash::from(5))]),
+        Ok(true)
+    );
+
+    let other_root = build_mmr(&MemStore::default(), 10).get_root().unwrap();
+    assert_eq!(
+        import::from_leaf_hashes::<_, MergeNumberHash, _>(
+            leaves,
+            &other_root,
+            &MemStore::default()
+        )
+        .err(),
+        Some(Error::UnexpectedRoot)
+    );
+}
+
+#[test]
+fn test_from_node_hashes() {
+    let store = MemStore::default();
+    let mmr = build_mmr(&store, 11);
+    let root = mmr.get_root().unwrap();
+    let nodes: Vec<NumberHash> = (0..mmr.mmr_size())
+        .map(|pos| mmr.batch().get_elem(pos).unwrap().unwrap())
+        .collect();
+
+    let imported_store = MemStore::default();
+    let mut imported =
+        import::from_node_hashes::<_, MergeNumberHash, _>(nodes.clone(), &root, &imported_store)
+            .unwrap();
+    assert_eq!(imported.mmr_size(), mmr.mmr_size());
+    imported.push(NumberHash::from(11)).unwrap();
+    imported.commit().unwrap();
+    let mut mmr = mmr;
+    mmr.push(NumberHash::from(11)).unwrap();
+    assert_eq!(imported.get_root(), mmr.get_root());
+
+    // a parent that isn't the merge of its children
+    let mut tampered = nodes.clone();
+    tampered[4] = NumberHash::from(100);
+    assert_eq!(
+        import::from_node_hashes::<_, MergeNumberHash, _>(tampered, &root, &MemStore::default())
+            .err(),
+        Some(Error::UnexpectedNode { pos: 5 })
+    );
+    assert_eq!(
+        import::from_node_hashes::<_, MergeNumberHash, _>(
+            nodes[..nodes.len() - 2].to_vec(),
+            &root,
+            &MemStore::default()
+        )
+        .err(),
+        Some(Error::InvalidMMRSize {
+            mmr_size: nodes.len() as Pos - 2
+        })
+    );
+    assert_eq!(
+        import::from_node_hashes::<_, MergeNumberHash, _>(
+            nodes[..nodes.len() - 1].to_vec(),
+            &root,
+            &MemStore::default()
+        )
+        .err(),
+        Some(Error::UnexpectedRoot)
+    );
+}
+
+#[test]
+fn test_import_cancellable() {
+    let store = MemStore::default();
+    let mmr = build_mmr(&store, 11);
+    let root = mmr.get_root().unwrap();
+    let nodes: Vec<NumberHash> = (0..mmr.mmr_size())
+        .map(|pos| mmr.batch().get_elem(pos).unwrap().unwrap())
+        .collect();
+    let leaves = (0u32..11).map(NumberHash::from);
+
+    let cancel = AtomicBool::new(false);
+    let imported_store = MemStore::default();
+    let imported_size = import::from_leaf_hashes_cancellable::<_, MergeNumberHash, _>(
+        leaves.clone(),
+        &root,
+        &imported_store,
+        &cancel,
+    )
+    .unwrap()
+    .mmr_size();
+    assert_eq!(imported_size, mmr.mmr_size());
+    let imported_size = import::from_node_hashes_cancellable::<_, MergeNumberHash, _>(
+        nodes.clone(),
+        &root,
+        &imported_store,
+        &cancel,
+    )
+    .unwrap()
+    .mmr_size();
+    assert_eq!(imported_size, mmr.mmr_size());
+
+    cancel.store(true, Ordering::Relaxed);
+    assert_eq!(
+        import::from_leaf_hashes_cancellable::<_, MergeNumberHash, _>(
+            leaves,
+            &root,
+            &MemStore::default(),
+            &cancel
+        )
+        .err(),
+        Some(Error::Cancelled)
+    );
+    assert_eq!(
+        import::from_node_hashes_cancellable::<_, MergeNumberHash, _>(
+            nodes,
+            &root,
+            &MemStore::default(),
+            &cancel
+        )
+        .err(),
+        Some(Error::Cancelled)
+    );
+}
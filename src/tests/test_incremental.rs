@@ -2,6 +2,7 @@ use proptest::proptest;
 
 use super::{MergeNumberHash, NumberHash};
 use crate::util::{MemMMR, MemStore};
+use crate::Pos;
 
 proptest! {
     #[test]
@@ -16,7 +17,7 @@ fn test_incremental_with_params(start: u32, steps: usize, turns: usize) {
 
     let mut curr = 0;
 
-    let _positions: Vec<u64> = (0u32..start)
+    let _positions: Vec<Pos> = (0u32..start)
         .map(|_| {
             let pos = mmr.push(NumberHash::from(curr)).unwrap();
             curr += 1;
@@ -0,0 +1,164 @@
+use super::{new_blake2b, MergeNumberHash, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::{leaf_index_to_pos, Error, KaryMMR, KaryMerge, Merge, Pos, Result};
+use proptest::prelude::*;
+
+impl KaryMerge for MergeNumberHash {
+    const ARITY: usize = 2;
+
+    fn merge_children(children: &[NumberHash]) -> Result<NumberHash> {
+        MergeNumberHash::merge(&children[0], &children[1])
+    }
+}
+
+struct MergeQuad;
+
+impl Merge for MergeQuad {
+    type Item = NumberHash;
+    type Error = Error;
+
+    fn merge(lhs: &NumberHash, rhs: &NumberHash) -> Result<NumberHash> {
+        MergeNumberHash::merge(lhs, rhs)
+    }
+}
+
+impl KaryMerge for MergeQuad {
+    const ARITY: usize = 4;
+
+    fn merge_children(children: &[NumberHash]) -> Result<NumberHash> {
+        let mut hasher = new_blake2b();
+        let mut hash = [0u8; 32];
+        for child in children {
+            hasher.update(&child.0);
+        }
+        hasher.finalize(&mut hash);
+        Ok(NumberHash(hash.to_vec().into()))
+    }
+}
+
+/// The root of the 4-ary MMR of `leaves`, merging the mountains recursively.
+fn quad_root(leaves: &[NumberHash]) -> NumberHash {
+    fn tree_root(leaves: &[NumberHash]) -> NumberHash {
+        if leaves.len() == 1 {
+            return leaves[0].clone();
+        }
+        let quarter = leaves.len() / 4;
+        let children: Vec<_> = leaves.chunks(quarter).map(tree_root).collect();
+        MergeQuad::merge_children(&children).unwrap()
+    }
+    let mut peaks = Vec::new();
+    let mut rest = leaves;
+    while !rest.is_empty() {
+        let mut span = 1;
+        while span * 4 <= rest.len() {
+            span *= 4;
+        }
+        peaks.push(tree_root(&rest[..span]));
+        rest = &rest[span..];
+    }
+    peaks
+        .into_iter()
+        .rev()
+        .reduce(|rhs, lhs| MergeQuad::merge_peaks(&rhs, &lhs).unwrap())
+        .unwrap()
+}
+
+#[test]
+fn test_binary_kary_mmr() {
+    let kary_store = MemStore::default();
+    let mut kary_mmr = KaryMMR::<_, MergeNumberHash, _>::new(0, &kary_store);
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..50 {
+        assert_eq!(kary_mmr.push(NumberHash::from(i)), Ok(Pos::from(i)));
+        mmr.push(NumberHash::from(i)).unwrap();
+        assert_eq!(kary_mmr.mmr_size(), mmr.mmr_size());
+        assert_eq!(kary_mmr.get_root(), mmr.get_root());
+    }
+    assert_eq!(kary_mmr.leaf_pos(37), leaf_index_to_pos(37));
+    let kary_proof = kary_mmr.gen_proof(vec![3, 17, 42]).unwrap();
+    let proof = mmr
+        .gen_proof(vec![
+            leaf_index_to_pos(3),
+            leaf_index_to_pos(17),
+            leaf_index_to_pos(42),
+        ])
+        .unwrap();
+    assert_eq!(kary_proof.proof_items(), proof.proof_items());
+}
+
+#[test]
+fn test_quad_mmr() {
+    let store = MemStore::default();
+    let mut mmr = KaryMMR::<_, MergeQuad, _>::new(0, &store);
+    assert_eq!(mmr.get_root(), Err(Error::GetRootOnEmpty));
+    let leaves: Vec<_> = (0u32..70).map(NumberHash::from).collect();
+    for (i, leaf) in leaves.iter().enumerate() {
+        mmr.push(leaf.clone()).unwrap();
+        assert_eq!(mmr.get_root(), Ok(quad_root(&leaves[..=i])));
+    }
+    // mountains of 64, 4, 1 and 1 leaves
+    assert_eq!(mmr.peaks().unwrap().len(), 4);
+    assert_eq!(mmr.mmr_size(), 85 + 5 + 1 + 1);
+
+    let root = mmr.get_root().unwrap();
+    let proof = mmr.gen_proof(vec![5]).unwrap();
+    // three levels of three siblings, then the bagged peaks on the right
+    assert_eq!(proof.proof_items().len(), 10);
+    assert_eq!(
+        proof.verify(root.clone(), vec![(5, leaves[5].clone())]),
+        Ok(true)
+    );
+    assert_eq!(
+        proof.verify(root.clone(), vec![(5, leaves[6].clone())]),
+        Ok(false)
+    );
+    assert_eq!(
+        proof.verify(root, vec![(70, leaves[5].clone())]),
+        Err(Error::GenProofForInvalidLeaves)
+    );
+    assert_eq!(
+        mmr.gen_proof(vec![70]).err(),
+        Some(Error::GenProofForInvalidLeaves)
+    );
+}
+
+#[test]
+fn test_quad_mmr_reopen() {
+    let store = MemStore::default();
+    let mut mmr = KaryMMR::<_, MergeQuad, _>::new(0, &store);
+    for i in 0u32..20 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    mmr.commit().unwrap();
+    let root = mmr.get_root().unwrap();
+    let mut mmr = KaryMMR::<_, MergeQuad, _>::new(20, &store);
+    assert_eq!(mmr.get_root(), Ok(root));
+    mmr.push(NumberHash::from(20)).unwrap();
+    let leaves: Vec<_> = (0u32..21).map(NumberHash::from).collect();
+    assert_eq!(mmr.get_root(), Ok(quad_root(&leaves)));
+}
+
+proptest! {
+    #[test]
+    fn test_quad_proof(count in 1u32..200, seed in any::<u64>()) {
+        let store = MemStore::default();
+        let mut mmr = KaryMMR::<_, MergeQuad, _>::new(0, &store);
+        for i in 0..count {
+            mmr.push(NumberHash::from(i)).unwrap();
+        }
+        let root = mmr.get_root().unwrap();
+        let leaf_indices: Vec<u32> = (0..count)
+            .filter(|i| (seed >> (i % 64)) & 1 == 1)
+            .collect();
+        prop_assume!(!leaf_indices.is_empty());
+        let proof = mmr
+            .gen_proof(leaf_indices.iter().map(|i| *i as Pos).collect())
+            .unwrap();
+        let leaves = leaf_indices
+            .iter()
+            .map(|i| (*i as Pos, NumberHash::from(*i)))
+            .collect();
+        prop_assert_eq!(proof.verify(root, leaves), Ok(true));
+    }
+}
@@ -0,0 +1,86 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::{leaf_index_to_pos, Error, MMRStoreReadOps, MMRStoreWriteOps, Pos, MMR};
+
+#[test]
+fn test_index_all_leaves() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    // leaves 0..10, then 0..5 again
+    mmr.push(NumberHash::from(100)).unwrap();
+    mmr.set_leaf_index(true);
+    for i in (0u32..10).chain(0..5) {
+        mmr.push(NumberHash::from(i)).unwrap();
+        if i == 7 {
+            mmr.commit().unwrap();
+        }
+    }
+    // leaves pushed before the index was set aren't found
+    assert_eq!(mmr.find_leaf(&NumberHash::from(100)), Ok(vec![]));
+    assert_eq!(
+        mmr.find_leaf(&NumberHash::from(3)),
+        Ok(vec![leaf_index_to_pos(4), leaf_index_to_pos(14)])
+    );
+    assert_eq!(
+        mmr.find_leaf(&NumberHash::from(9)),
+        Ok(vec![leaf_index_to_pos(10)])
+    );
+    assert_eq!(mmr.find_leaf(&NumberHash::from(42)), Ok(vec![]));
+
+    mmr.commit().unwrap();
+    let reopened = MemMMR::<_, MergeNumberHash>::open(&store).unwrap();
+    assert_eq!(
+        reopened.find_leaf(&NumberHash::from(3)),
+        Ok(vec![leaf_index_to_pos(4), leaf_index_to_pos(14)])
+    );
+
+    // rewinding forgets the dropped leaves
+    mmr.rewind(leaf_index_to_pos(14)).unwrap();
+    assert_eq!(
+        mmr.find_leaf(&NumberHash::from(3)),
+        Ok(vec![leaf_index_to_pos(4)])
+    );
+}
+
+#[test]
+fn test_index_first_leaves() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    mmr.set_leaf_index_first();
+    for i in (0u32..10).chain(0..5) {
+        mmr.push(NumberHash::from(i)).unwrap();
+        if i == 7 {
+            mmr.commit().unwrap();
+        }
+    }
+    for i in 0u32..10 {
+        assert_eq!(
+            mmr.find_leaf(&NumberHash::from(i)),
+            Ok(vec![leaf_index_to_pos(i as Pos)])
+        );
+    }
+}
+
+#[test]
+fn test_index_without_store_support() {
+    struct NoLeafIndexStore(MemStore<NumberHash>);
+
+    impl MMRStoreReadOps<NumberHash> for &NoLeafIndexStore {
+        fn get_elem(&self, pos: Pos) -> crate::Result<Option<NumberHash>> {
+            (&self.0).get_elem(pos)
+        }
+    }
+
+    impl MMRStoreWriteOps<NumberHash> for &NoLeafIndexStore {
+        fn append(&mut self, pos: Pos, elems: Vec<NumberHash>) -> crate::Result<()> {
+            (&self.0).append(pos, elems)
+        }
+    }
+
+    let store = NoLeafIndexStore(MemStore::default());
+    let mut mmr = MMR::<_, MergeNumberHash, _>::new(0, &store);
+    mmr.set_leaf_index(true);
+    mmr.push(NumberHash::from(0)).unwrap();
+    assert_eq!(mmr.find_leaf(&NumberHash::from(0)), Ok(vec![0]));
+    assert!(matches!(mmr.commit(), Err(Error::StoreError(_))));
+}
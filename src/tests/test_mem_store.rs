@@ -0,0 +1,195 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::{Error, MMRStoreReadOps, Pos};
+
+impl AsRef<[u8]> for NumberHash {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for NumberHash {
+    fn from(bytes: Vec<u8>) -> Self {
+        NumberHash(bytes.into())
+    }
+}
+
+fn populated_store(count: u32) -> (MemStore<NumberHash>, NumberHash) {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0..count {
+        mmr.push_with_leaf_meta(NumberHash::from(i), i.to_le_bytes().to_vec())
+            .unwrap();
+    }
+    let root = mmr.get_root().unwrap();
+    mmr.commit().unwrap();
+    (store, root)
+}
+
+#[test]
+fn test_len_and_iter() {
+    let (mut store, _) = populated_store(11);
+    assert_eq!(store.len(), 19);
+    assert!(!store.is_empty());
+    assert!(MemStore::<NumberHash>::default().is_empty());
+
+    let positions: Vec<Pos> = store.iter().map(|(pos, _)| pos).collect();
+    assert_eq!(positions, (0..19).collect::<Vec<_>>());
+    assert_eq!(store.get(0), Some(&NumberHash::from(0)));
+    assert_eq!(store.get(19), None);
+}
+
+#[test]
+fn test_bytes_roundtrip() {
+    let (store, root) = populated_store(11);
+    let restored = MemStore::<NumberHash>::from_bytes(&store.to_bytes()).unwrap();
+    assert_eq!(restored.len(), store.len());
+    for pos in 0..19 {
+        assert_eq!((&restored).get_elem(pos), (&store).get_elem(pos));
+    }
+    assert_eq!(
+        (&restored).get_leaf_meta(7),
+        Ok(Some(7u32.to_le_bytes().to_vec()))
+    );
+
+    let mmr = MemMMR::<_, MergeNumberHash>::open(&restored).unwrap();
+    assert_eq!(mmr.mmr_size(), 19);
+    assert_eq!(mmr.get_root(), Ok(root));
+}
+
+#[test]
+fn test_from_bytes_rejects_malformed_input() {
+    let (store, _) = populated_store(3);
+    let bytes = store.to_bytes();
+    for len in 0..bytes.len() {
+        assert_eq!(
+            MemStore::<NumberHash>::from_bytes(&bytes[..len]).err(),
+            Some(Error::CorruptedSnapshot)
+        );
+    }
+    let mut trailing = bytes;
+    trailing.push(0);
+    assert_eq!(
+        MemStore::<NumberHash>::from_bytes(&trailing).err(),
+        Some(Error::CorruptedSnapshot)
+    );
+}
+
+#[test]
+fn test_fn_store() {
+    use crate::util::FnStore;
+    use crate::MMR;
+
+    let (store, root) = populated_store(11);
+    let fn_store = FnStore::new(|pos| (&store).get_elem(pos));
+    let mmr = MMR::<_, MergeNumberHash, _>::new(19, fn_store);
+    assert_eq!(mmr.get_root(), Ok(root.clone()));
+    let pos = crate::leaf_index_to_pos(5);
+    let proof = mmr.gen_proof(vec![pos]).unwrap();
+    assert!(proof
+        .verify(root, vec![(pos, NumberHash::from(5))])
+        .unwrap());
+
+    // lookup failures surface as is
+    let failing = FnStore::new(|_| Err(Error::StoreError("unreachable peer".into())));
+    let mmr = MMR::<NumberHash, MergeNumberHash, _>::new(19, failing);
+    assert_eq!(
+        mmr.get_root(),
+        Err(Error::StoreError("unreachable peer".into()))
+    );
+}
+
+#[test]
+fn test_find_divergence() {
+    use crate::util::{find_divergence, FnStore};
+    use crate::{leaf_index_to_pos, MMRStoreWriteOps};
+
+    let (a, _) = populated_store(11);
+    let (b, _) = populated_store(11);
+    assert_eq!(find_divergence(&&a, &&b, 19), Ok(None));
+    // a prefix of a larger MMR agrees as well
+    let (longer, _) = populated_store(20);
+    assert_eq!(find_divergence(&&a, &&longer, 19), Ok(None));
+
+    // a different leaf, with the nodes above it rebuilt
+    for index in [0u32, 5, 9, 10] {
+        let store = MemStore::default();
+        let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+        for i in 0u32..11 {
+            let leaf = if i == index { 100 } else { i };
+            mmr.push(NumberHash::from(leaf)).unwrap();
+        }
+        mmr.commit().unwrap();
+        assert_eq!(
+            find_divergence(&&a, &&store, 19),
+            Ok(Some(leaf_index_to_pos(index as Pos)))
+        );
+    }
+
+    // only an interior node and its parent differ, e.g. a buggy merge
+    let tampered = FnStore::new(|pos| match pos {
+        13 => Ok(Some(NumberHash::from(1013))),
+        14 => Ok(Some(NumberHash::from(1014))),
+        _ => (&a).get_elem(pos),
+    });
+    assert_eq!(find_divergence(&&a, &tampered, 19), Ok(Some(13)));
+
+    // a node missing from one store disagrees
+    let pruned = MemStore::default();
+    for pos in 0..19 {
+        if pos != 17 && pos != 16 {
+            let elem = (&a).get_elem(pos).unwrap().unwrap();
+            (&pruned).append(pos, vec![elem]).unwrap();
+        }
+    }
+    assert_eq!(find_divergence(&&a, &&pruned, 19), Ok(Some(16)));
+    let empty = MemStore::<NumberHash>::default();
+    assert_eq!(
+        find_divergence(&&empty, &&empty, 19),
+        Err(Error::InconsistentStore { pos: 14 })
+    );
+
+    assert_eq!(
+        find_divergence(&&a, &&b, 20),
+        Err(Error::InvalidMMRSize { mmr_size: 20 })
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_throttled_store() {
+    use crate::util::ThrottledStore;
+    use crate::MMR;
+    use std::time::{Duration, Instant};
+
+    let mem_store = MemStore::default();
+    let store = ThrottledStore::new(&mem_store).with_write_latency(Duration::from_millis(1));
+    let mut mmr = MMR::<_, MergeNumberHash, _>::new(0, &store);
+    for i in 0u32..11 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    // pushes read the pending nodes, not the store
+    assert_eq!((store.reads(), store.writes()), (0, 0));
+    mmr.commit().unwrap();
+    let writes = store.writes();
+    assert!(writes > 0);
+    assert_eq!(store.reads(), 0);
+    assert_eq!(mem_store.len(), 19);
+
+    let store = ThrottledStore::new(&mem_store).with_read_latency(Duration::from_millis(2));
+    let mmr = MMR::<_, MergeNumberHash, _>::new(19, &store);
+    let root = mmr.get_root().unwrap();
+    // peak 14, then the adjacent peaks 17 and 18 in a single range read
+    assert_eq!(store.reads(), 2);
+    store.reset_counters();
+    let started = Instant::now();
+    let pos = crate::leaf_index_to_pos(5);
+    let proof = mmr.gen_proof(vec![pos]).unwrap();
+    let reads = store.reads();
+    assert!(reads > 0);
+    assert!(started.elapsed() >= Duration::from_millis(2 * reads));
+    assert_eq!(store.writes(), 0);
+    assert!(proof
+        .verify(root, vec![(pos, NumberHash::from(5))])
+        .unwrap());
+}
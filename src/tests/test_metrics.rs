@@ -0,0 +1,62 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::{
+    metrics::{set_metrics, Metrics},
+    util::{MemMMR, MemStore},
+    Pos,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Default)]
+struct Counters {
+    store_reads: AtomicU64,
+    merges: AtomicU64,
+    proof_items: AtomicU64,
+    verification_steps: AtomicU64,
+}
+
+impl Metrics for Counters {
+    fn store_reads(&self, count: u64) {
+        self.store_reads.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn merges(&self, count: u64) {
+        self.merges.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn proof_items(&self, count: u64) {
+        self.proof_items.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn verification_steps(&self, count: u64) {
+        self.verification_steps.fetch_add(count, Ordering::Relaxed);
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref COUNTERS: Counters = Counters::default();
+}
+
+#[test]
+fn test_metrics_counters() {
+    // the sink is process wide and other tests run concurrently, so only lower bounds hold
+    set_metrics(&*COUNTERS)
+        .ok()
+        .expect("metrics installed once");
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    // 11 leaves take 19 nodes, so 8 merges
+    assert!(COUNTERS.merges.load(Ordering::Relaxed) >= 8);
+    mmr.commit().expect("commit");
+
+    let root = mmr.get_root().expect("get root");
+    assert!(COUNTERS.store_reads.load(Ordering::Relaxed) > 0);
+    let proof = mmr.gen_proof(vec![positions[5]]).expect("gen proof");
+    assert!(COUNTERS.proof_items.load(Ordering::Relaxed) >= proof.proof_items().len() as u64);
+    assert!(proof
+        .verify(root, vec![(positions[5], NumberHash::from(5))])
+        .unwrap());
+    assert!(COUNTERS.verification_steps.load(Ordering::Relaxed) > 0);
+}
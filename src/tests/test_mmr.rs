@@ -1,18 +1,19 @@
 use super::{MergeNumberHash, NumberHash};
 use crate::{
-    helper::pos_height_in_tree,
-    leaf_index_to_mmr_size,
+    helper::{get_peaks, pos_height_in_tree},
+    leaf_index_to_mmr_size, leaf_index_to_pos,
     util::{MemMMR, MemStore},
-    Error,
+    verify_proof_streaming, verify_with_peaks, Error, MMRStoreReadOps, Pos, ProofSource,
+    VerifyLimits,
 };
 use faster_hex::hex_string;
 use proptest::prelude::*;
-use rand::{seq::SliceRandom, thread_rng};
+use rand::{seq::SliceRandom, thread_rng, Rng};
 
 fn test_mmr(count: u32, proof_elem: Vec<u32>) {
     let store = MemStore::default();
     let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
-    let positions: Vec<u64> = (0u32..count)
+    let positions: Vec<Pos> = (0u32..count)
         .map(|i| mmr.push(NumberHash::from(i)).unwrap())
         .collect();
     let root = mmr.get_root().expect("get root");
@@ -40,7 +41,7 @@ fn test_mmr(count: u32, proof_elem: Vec<u32>) {
 fn test_gen_new_root_from_proof(count: u32) {
     let store = MemStore::default();
     let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
-    let positions: Vec<u64> = (0u32..count)
+    let positions: Vec<Pos> = (0u32..count)
         .map(|i| mmr.push(NumberHash::from(i)).unwrap())
         .collect();
     let elem = count - 1;
@@ -55,7 +56,7 @@ fn test_gen_new_root_from_proof(count: u32) {
             vec![(pos, NumberHash::from(elem))],
             new_pos,
             NumberHash::from(new_elem),
-            leaf_index_to_mmr_size(new_elem.into()),
+            leaf_index_to_mmr_size(new_elem as Pos),
         )
         .unwrap();
     assert_eq!(calculated_root, root);
@@ -83,6 +84,592 @@ fn test_empty_mmr_root() {
     assert_eq!(Err(Error::GetRootOnEmpty), mmr.get_root());
 }
 
+#[test]
+fn test_empty_root_sentinel() {
+    use crate::{verifier, Merge, MerkleProof};
+
+    struct MergeWithEmptyRoot;
+
+    impl Merge for MergeWithEmptyRoot {
+        type Item = NumberHash;
+        type Error = Error;
+        fn merge(lhs: &Self::Item, rhs: &Self::Item) -> crate::Result<Self::Item> {
+            MergeNumberHash::merge(lhs, rhs)
+        }
+        fn empty_root() -> Option<Self::Item> {
+            Some(NumberHash::default())
+        }
+    }
+
+    let empty_root = NumberHash::default();
+    let store = MemStore::<NumberHash>::default();
+    let mut mmr = MemMMR::<_, MergeWithEmptyRoot>::new(0, &store);
+    assert_eq!(mmr.get_root(), Ok(empty_root.clone()));
+
+    let proof = MerkleProof::<_, MergeWithEmptyRoot>::new(0, Vec::new());
+    assert_eq!(proof.verify(empty_root.clone(), Vec::new()), Ok(true));
+    assert_eq!(proof.verify(NumberHash::from(0), Vec::new()), Ok(false));
+    assert!(
+        verifier::verify::<_, MergeWithEmptyRoot>(empty_root.clone(), 0, Vec::new(), &[]).unwrap()
+    );
+    // nothing else proves the empty MMR
+    assert_eq!(
+        proof.verify(empty_root.clone(), vec![(0, NumberHash::from(0))]),
+        Err(Error::CorruptedProof)
+    );
+    let proof = MerkleProof::<_, MergeWithEmptyRoot>::new(0, vec![NumberHash::from(0)]);
+    assert_eq!(
+        proof.verify(empty_root.clone(), Vec::new()),
+        Err(Error::CorruptedProof)
+    );
+
+    // the sentinel is only the root while the MMR is empty
+    mmr.push(NumberHash::from(0)).unwrap();
+    assert_eq!(mmr.get_root(), Ok(NumberHash::from(0)));
+}
+
+fn test_padded_root(count: u32) {
+    let empty = NumberHash::default();
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let padded_store = MemStore::default();
+    let mut padded_mmr = MemMMR::<_, MergeNumberHash>::new(0, &padded_store);
+    for i in 0..count {
+        mmr.push(NumberHash::from(i)).unwrap();
+        padded_mmr.push(NumberHash::from(i)).unwrap();
+    }
+    // a perfect tree has a single peak, which is its root
+    for _ in count..count.next_power_of_two() {
+        padded_mmr.push(empty.clone()).unwrap();
+    }
+    assert_eq!(
+        mmr.get_root_padded(empty).unwrap(),
+        padded_mmr.get_root().unwrap()
+    );
+}
+
+#[test]
+fn test_get_root_padded() {
+    for count in [1, 2, 3, 5, 8, 11, 22, 33] {
+        test_padded_root(count);
+    }
+    let store = MemStore::<NumberHash>::default();
+    let mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    assert_eq!(
+        Err(Error::GetRootOnEmpty),
+        mmr.get_root_padded(NumberHash::default())
+    );
+}
+
+#[test]
+fn test_proof_source() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let mut positions = Vec::new();
+    for i in 0u32..11 {
+        positions.push(mmr.push(NumberHash::from(i)).unwrap());
+    }
+    let committed_root = mmr.get_root().unwrap();
+    mmr.commit().unwrap();
+    // leave the last 3 leaves pending
+    for i in 11u32..14 {
+        positions.push(mmr.push(NumberHash::from(i)).unwrap());
+    }
+    let root = mmr.get_root().unwrap();
+    assert_eq!(mmr.committed_mmr_size(), leaf_index_to_mmr_size(10));
+
+    assert_eq!(
+        mmr.get_root_with_source(ProofSource::IncludePending),
+        Ok(root.clone())
+    );
+    assert_eq!(
+        mmr.get_root_with_source(ProofSource::CommittedOnly),
+        Ok(committed_root.clone())
+    );
+
+    let leaf = (positions[3], NumberHash::from(3));
+    let proof = mmr
+        .gen_proof_with_source(vec![leaf.0], ProofSource::CommittedOnly)
+        .unwrap();
+    assert_eq!(proof.mmr_size(), leaf_index_to_mmr_size(10));
+    assert!(proof.verify(committed_root, vec![leaf.clone()]).unwrap());
+    let proof = mmr
+        .gen_proof_with_source(vec![leaf.0], ProofSource::IncludePending)
+        .unwrap();
+    assert!(proof.verify(root, vec![leaf]).unwrap());
+
+    assert_eq!(
+        mmr.gen_proof_with_source(vec![positions[12]], ProofSource::CommittedOnly)
+            .err(),
+        Some(Error::PositionOutOfRange { pos: positions[12] })
+    );
+}
+
+#[test]
+fn test_batch_capacity_and_auto_commit() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::with_capacity(0, &store, 64);
+    assert_eq!(mmr.batch().pending_len(), 0);
+    let preallocated = mmr.batch().estimated_memory();
+    assert!(preallocated > 0);
+    for i in 0u32..4 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    // 4 leaves and 3 parents
+    assert_eq!(mmr.batch().pending_len(), 7);
+    assert!(mmr.batch().estimated_memory() > preallocated);
+    mmr.commit().unwrap();
+    assert_eq!(mmr.batch().pending_len(), 0);
+
+    // commits whenever 10 elements are pending
+    mmr.set_auto_commit(Some(10));
+    assert_eq!(mmr.auto_commit(), Some(10));
+    let mut max_pending = 0;
+    for i in 4u32..100 {
+        mmr.push_auto_commit(NumberHash::from(i)).unwrap();
+        max_pending = max_pending.max(mmr.batch().pending_len());
+    }
+    assert!(max_pending < 10);
+    assert!(mmr.committed_mmr_size() > leaf_index_to_mmr_size(90));
+    mmr.commit().unwrap();
+
+    let expected_store = MemStore::default();
+    let mut expected = MemMMR::<_, MergeNumberHash>::new(0, &expected_store);
+    for i in 0u32..100 {
+        expected.push(NumberHash::from(i)).unwrap();
+    }
+    assert_eq!(mmr.get_root(), expected.get_root());
+
+    // without a threshold it's a plain push
+    mmr.set_auto_commit(None);
+    mmr.push_auto_commit(NumberHash::from(100)).unwrap();
+    assert_eq!(mmr.batch().pending_len(), 1);
+}
+
+#[test]
+fn test_push_raw() {
+    use super::new_blake2b;
+    use crate::{LeafHasher, Merge};
+
+    // prefixes leaves with 0 to tell them apart from nodes
+    struct MergeLeafHash;
+
+    impl Merge for MergeLeafHash {
+        type Item = NumberHash;
+        type Error = Error;
+        fn merge(lhs: &NumberHash, rhs: &NumberHash) -> crate::Result<NumberHash> {
+            MergeNumberHash::merge(lhs, rhs)
+        }
+    }
+
+    impl LeafHasher for MergeLeafHash {
+        fn hash_leaf(leaf: &[u8]) -> crate::Result<NumberHash> {
+            let mut hasher = new_blake2b();
+            let mut hash = [0u8; 32];
+            hasher.update(&[0]);
+            hasher.update(leaf);
+            hasher.finalize(&mut hash);
+            Ok(NumberHash(hash.to_vec().into()))
+        }
+    }
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeLeafHash>::new(0, &store);
+    let payloads: Vec<String> = (0..11).map(|i| format!("payload {}", i)).collect();
+    let positions: Vec<Pos> = payloads
+        .iter()
+        .map(|payload| mmr.push_raw(payload).unwrap())
+        .collect();
+    assert_eq!(mmr.push_raw(b"raw bytes"), Ok(leaf_index_to_pos(11)));
+
+    let hashes_store = MemStore::default();
+    let mut hashed = MemMMR::<_, MergeLeafHash>::new(0, &hashes_store);
+    for payload in payloads
+        .iter()
+        .map(String::as_bytes)
+        .chain([&b"raw bytes"[..]])
+    {
+        hashed
+            .push(MergeLeafHash::hash_leaf(payload).unwrap())
+            .unwrap();
+    }
+    let root = mmr.get_root().unwrap();
+    assert_eq!(hashed.get_root(), Ok(root.clone()));
+
+    let leaf = MergeLeafHash::hash_leaf(payloads[5].as_bytes()).unwrap();
+    let proof = mmr.gen_proof(vec![positions[5]]).unwrap();
+    assert!(proof.verify(root, vec![(positions[5], leaf)]).unwrap());
+}
+
+#[test]
+fn test_from_peaks() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..11 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    let peaks = mmr.peaks().unwrap();
+
+    let light_store = MemStore::default();
+    let mut light_mmr =
+        MemMMR::<_, MergeNumberHash>::from_peaks(peaks.clone(), mmr.mmr_size(), &light_store)
+            .unwrap();
+    assert_eq!(light_mmr.get_root(), mmr.get_root());
+    for i in 11u32..30 {
+        mmr.push(NumberHash::from(i)).unwrap();
+        light_mmr.push(NumberHash::from(i)).unwrap();
+        assert_eq!(light_mmr.get_root(), mmr.get_root());
+    }
+
+    let size = leaf_index_to_mmr_size(10);
+    assert_eq!(
+        MemMMR::<_, MergeNumberHash>::from_peaks(peaks.clone(), size + 1, &MemStore::default())
+            .err(),
+        Some(Error::InvalidMMRSize { mmr_size: size + 1 })
+    );
+    assert_eq!(
+        MemMMR::<_, MergeNumberHash>::from_peaks(peaks[1..].to_vec(), size, &MemStore::default())
+            .err(),
+        Some(Error::InvalidPeaks { mmr_size: size })
+    );
+}
+
+#[test]
+fn test_verify_with_peaks() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let mmr_size = mmr.mmr_size();
+    let peaks = mmr.peaks().unwrap();
+    for leaves in [
+        vec![0],
+        vec![2, 4],
+        vec![9],
+        vec![10],
+        vec![1, 9],
+        vec![3, 10],
+    ] {
+        let entries: Vec<_> = leaves
+            .iter()
+            .map(|i| (positions[*i], NumberHash::from(*i as u32)))
+            .collect();
+        let proof = mmr
+            .gen_proof(entries.iter().map(|(pos, _)| *pos).collect())
+            .unwrap();
+        assert!(verify_with_peaks::<_, MergeNumberHash>(
+            peaks.clone(),
+            mmr_size,
+            entries.clone(),
+            proof.proof_items()
+        )
+        .unwrap());
+
+        let mut tampered_peaks = peaks.clone();
+        for (_pos, peak) in tampered_peaks.iter_mut() {
+            *peak = NumberHash::from(31337);
+        }
+        assert!(!verify_with_peaks::<_, MergeNumberHash>(
+            tampered_peaks,
+            mmr_size,
+            entries,
+            proof.proof_items()
+        )
+        .unwrap());
+    }
+
+    let proof = mmr.gen_proof(vec![positions[9]]).unwrap();
+    assert_eq!(
+        verify_with_peaks::<_, MergeNumberHash>(
+            peaks[1..].to_vec(),
+            mmr_size,
+            vec![(positions[9], NumberHash::from(9))],
+            proof.proof_items()
+        ),
+        Err(Error::InvalidPeaks { mmr_size })
+    );
+}
+
+#[test]
+fn test_verify_with_known_peaks() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().unwrap();
+    // peaks of the 11 leaves mmr are [14, 17, 18]
+    let peaks = mmr.peaks().unwrap();
+    let wrong_root = NumberHash::from(31337);
+    for leaves in [vec![2, 4], vec![9], vec![10], vec![1, 9], vec![3, 10]] {
+        let entries: Vec<_> = leaves
+            .iter()
+            .map(|i| (positions[*i], NumberHash::from(*i as u32)))
+            .collect();
+        let proof = mmr
+            .gen_proof(entries.iter().map(|(pos, _)| *pos).collect())
+            .unwrap();
+        for known_peaks in [&peaks[..0], &peaks[..1], &peaks[1..], &peaks[..]] {
+            assert_eq!(
+                proof.verify_with_known_peaks(root.clone(), entries.clone(), known_peaks),
+                Ok(true)
+            );
+        }
+        // with all peaks known the root isn't needed
+        assert_eq!(
+            proof.verify_with_known_peaks(wrong_root.clone(), entries.clone(), &peaks),
+            Ok(true)
+        );
+        assert_eq!(
+            proof.verify_with_known_peaks(wrong_root.clone(), entries.clone(), &[]),
+            Ok(false)
+        );
+
+        let mut tampered = entries.clone();
+        tampered[0].1 = NumberHash::from(31337);
+        assert_eq!(
+            proof.verify_with_known_peaks(root.clone(), tampered, &peaks),
+            Ok(false)
+        );
+    }
+
+    // a known peak of a mountain without leaves stands in for its proof item
+    let proof = mmr.gen_proof(vec![positions[9]]).unwrap();
+    let leaves = vec![(positions[9], NumberHash::from(9))];
+    let mut tampered_peaks = peaks.clone();
+    tampered_peaks[0].1 = NumberHash::from(31337);
+    assert_eq!(
+        proof.verify_with_known_peaks(root.clone(), leaves.clone(), &tampered_peaks[..1]),
+        Ok(false)
+    );
+    assert_eq!(
+        proof.verify_with_known_peaks(root.clone(), leaves.clone(), &[(16, peaks[1].1.clone())]),
+        Err(Error::InvalidPeaks {
+            mmr_size: mmr.mmr_size()
+        })
+    );
+    assert_eq!(
+        proof.verify_with_known_peaks(root, Vec::new(), &peaks),
+        Err(Error::CorruptedProof)
+    );
+}
+
+#[test]
+fn test_verify_with_limits() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..100)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().unwrap();
+    let leaves: Vec<_> = [3, 40, 41, 99]
+        .iter()
+        .map(|i| (positions[*i], NumberHash::from(*i as u32)))
+        .collect();
+    let proof = mmr
+        .gen_proof(leaves.iter().map(|(pos, _)| *pos).collect())
+        .unwrap();
+    let limits = VerifyLimits::default();
+    assert!(proof
+        .verify_with_limits(root.clone(), leaves.clone(), &limits)
+        .unwrap());
+
+    for limits in [
+        VerifyLimits {
+            max_proof_items: proof.proof_items().len() - 1,
+            ..limits
+        },
+        VerifyLimits {
+            max_nodes: leaves.len() - 1,
+            ..limits
+        },
+        VerifyLimits {
+            max_iterations: 5,
+            ..limits
+        },
+    ] {
+        assert_eq!(
+            proof.verify_with_limits(root.clone(), leaves.clone(), &limits),
+            Err(Error::LimitExceeded)
+        );
+    }
+}
+
+#[test]
+fn test_leaf_meta() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..10 {
+        mmr.push_with_leaf_meta(NumberHash::from(i), i.to_le_bytes().to_vec())
+            .unwrap();
+    }
+    // pending metadata is visible before the commit
+    assert_eq!(mmr.get_leaf_meta(3), Ok(Some(3u32.to_le_bytes().to_vec())));
+    assert_eq!((&store).get_leaf_meta(3), Ok(None));
+    mmr.commit().expect("commit changes");
+    assert_eq!(
+        (&store).get_leaf_meta(3),
+        Ok(Some(3u32.to_le_bytes().to_vec()))
+    );
+    assert_eq!(mmr.get_leaf_meta(10), Ok(None));
+
+    // rewinding drops the metadata of the removed leaves
+    mmr.rewind(leaf_index_to_mmr_size(4)).expect("rewind");
+    assert_eq!(mmr.get_leaf_meta(4), Ok(Some(4u32.to_le_bytes().to_vec())));
+    assert_eq!(mmr.get_leaf_meta(5), Ok(None));
+}
+
+#[test]
+fn test_leaf_meta_unsupported() {
+    use crate::{MMRStoreWriteOps, MMR};
+
+    // only stores the nodes
+    #[derive(Default)]
+    struct NodeStore(MemStore<NumberHash>);
+
+    impl MMRStoreReadOps<NumberHash> for &NodeStore {
+        fn get_elem(&self, pos: Pos) -> crate::Result<Option<NumberHash>> {
+            (&self.0).get_elem(pos)
+        }
+    }
+
+    impl MMRStoreWriteOps<NumberHash> for &NodeStore {
+        fn append(&mut self, pos: Pos, elems: Vec<NumberHash>) -> crate::Result<()> {
+            (&self.0).append(pos, elems)
+        }
+    }
+
+    let store = NodeStore::default();
+    let mut mmr = MMR::<_, MergeNumberHash, _>::new(0, &store);
+    mmr.push(NumberHash::from(0)).unwrap();
+    // rejected before the push, rather than failing the commit
+    assert_eq!(
+        mmr.push_with_leaf_meta(NumberHash::from(1), vec![1]),
+        Err(Error::StoreError(
+            "leaf metadata is not supported".to_string()
+        ))
+    );
+    assert_eq!(mmr.mmr_size(), 1);
+    mmr.commit().unwrap();
+    assert_eq!(mmr.batch().pending_len(), 0);
+}
+
+fn test_merge_proofs(count: u32, elems: Vec<u32>, other_elems: Vec<u32>) {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..count)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().expect("get root");
+    let pos_list: Vec<Pos> = elems.iter().map(|i| positions[*i as usize]).collect();
+    let other_pos_list: Vec<Pos> = other_elems.iter().map(|i| positions[*i as usize]).collect();
+    let proof = mmr.gen_proof(pos_list.clone()).expect("gen proof");
+    let other_proof = mmr.gen_proof(other_pos_list.clone()).expect("gen proof");
+
+    let merged = proof
+        .merge(pos_list.clone(), other_proof, other_pos_list.clone())
+        .expect("merge proofs");
+    let all_pos_list: Vec<Pos> = pos_list.into_iter().chain(other_pos_list).collect();
+    let expected = mmr.gen_proof(all_pos_list).expect("gen proof");
+    assert_eq!(merged.proof_items(), expected.proof_items());
+    let leaves = elems
+        .iter()
+        .chain(other_elems.iter())
+        .map(|i| (positions[*i as usize], NumberHash::from(*i)))
+        .collect();
+    assert!(merged.verify(root, leaves).unwrap());
+}
+
+#[test]
+fn test_merge_proofs_disjoint() {
+    test_merge_proofs(11, vec![0], vec![10]);
+    test_merge_proofs(11, vec![2, 4], vec![3]);
+    test_merge_proofs(100, vec![5, 64], vec![30, 99]);
+    test_merge_proofs(1, vec![0], vec![0]);
+}
+
+#[test]
+fn test_merge_proofs_mismatch() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let proof = mmr.gen_proof(vec![positions[0]]).unwrap();
+    let other_proof = mmr.gen_proof(vec![positions[5]]).unwrap();
+    // positions that don't belong to the proof
+    assert_eq!(
+        proof
+            .merge(
+                vec![positions[0], positions[1]],
+                other_proof,
+                vec![positions[5]]
+            )
+            .err(),
+        Some(Error::CorruptedProof)
+    );
+}
+
+proptest! {
+    #[test]
+    fn test_merge_proofs_randomly(
+        (count, elems, other_elems) in (1u32..300).prop_flat_map(|count| (
+            Just(count),
+            prop::collection::vec(0..count, 1..10),
+            prop::collection::vec(0..count, 1..10),
+        ))
+    ) {
+        test_merge_proofs(count, elems, other_elems);
+    }
+}
+
+#[test]
+fn test_rebuild_range() {
+    use crate::MMRStoreWriteOps;
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..50 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    mmr.commit().expect("commit changes");
+    let root = mmr.get_root().unwrap();
+
+    // corrupt the parent of leaves 10 and 11, its grandparent and the peak of the first mountain
+    let corrupted = [leaf_index_to_pos(11) + 1, leaf_index_to_pos(11) + 2, 62];
+    for pos in corrupted {
+        (&store).overwrite(pos, NumberHash::from(31337)).unwrap();
+    }
+    assert_ne!(mmr.get_root().unwrap(), root);
+
+    assert_eq!(mmr.rebuild_range(10..12), Ok(corrupted.len() as Pos));
+    assert_eq!(mmr.get_root().unwrap(), root);
+    mmr.commit().expect("commit changes");
+    let mmr = MemMMR::<_, MergeNumberHash>::new(mmr.mmr_size(), &store);
+    assert_eq!(mmr.get_root().unwrap(), root);
+
+    let mut mmr = mmr;
+    assert_eq!(mmr.rebuild_range(0..50), Ok(0));
+    assert_eq!(
+        mmr.rebuild_range(0..51),
+        Err(Error::InvalidLeafCount { leaf_count: 51 })
+    );
+}
+
+#[test]
+fn test_inconsistent_store_reports_position() {
+    let store = MemStore::<NumberHash>::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..2 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    mmr.commit().expect("commit changes");
+    // the store only holds the first 3 nodes, the peak at 6 is missing
+    let mmr = MemMMR::<_, MergeNumberHash>::new(7, &store);
+    assert_eq!(Err(Error::InconsistentStore { pos: 6 }), mmr.get_root());
+}
+
 #[test]
 fn test_mmr_3_peaks() {
     test_mmr(11, vec![5]);
@@ -150,38 +737,508 @@ fn test_gen_proof_with_duplicate_leaves() {
     test_mmr(10, vec![5, 5]);
 }
 
-fn test_invalid_proof_verification(
-    leaf_count: u32,
-    positions_to_verify: Vec<u64>,
-    // positions of entries that should be tampered
-    tampered_positions: Vec<usize>,
-    // optionally handroll proof from these positions
-    handrolled_proof_positions: Option<Vec<u64>>,
-) {
-    use crate::{Merge, MerkleProof};
-    use std::fmt::{Debug, Formatter};
-
-    // Simple item struct to allow debugging the contents of MMR nodes/peaks
-    #[derive(Clone, PartialEq)]
-    enum MyItem {
-        Number(u32),
-        Merged(Box<MyItem>, Box<MyItem>),
-    }
-
-    impl Debug for MyItem {
-        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-            match self {
-                MyItem::Number(x) => f.write_fmt(format_args!("{}", x)),
-                MyItem::Merged(a, b) => f.write_fmt(format_args!("Merged({:#?}, {:#?})", a, b)),
-            }
-        }
+#[test]
+fn test_verify_conflicting_duplicate_leaves() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..11 {
+        mmr.push(NumberHash::from(i)).unwrap();
     }
-
-    #[derive(Debug)]
-    struct MyMerge;
-
-    impl Merge for MyMerge {
-        type Item = MyItem;
+    let root = mmr.get_root().unwrap();
+    let pos = leaf_index_to_pos(5);
+    let proof = mmr.gen_proof(vec![pos]).unwrap();
+    let good = (pos, NumberHash::from(5));
+    let bad = (pos, NumberHash::from(6));
+    assert_eq!(
+        proof.verify(root.clone(), vec![good.clone(), good.clone()]),
+        Ok(true)
+    );
+    // whichever comes first, a position given with two leaves doesn't verify
+    assert_eq!(
+        proof.verify(root.clone(), vec![good.clone(), bad.clone()]),
+        Err(Error::CorruptedProof)
+    );
+    assert_eq!(
+        proof.verify(root.clone(), vec![bad.clone(), good.clone()]),
+        Err(Error::CorruptedProof)
+    );
+    let path: Vec<_> = mmr
+        .path_to_peak(pos)
+        .unwrap()
+        .into_iter()
+        .map(|(_, item)| item)
+        .collect();
+    assert_eq!(
+        verify_with_peaks::<_, MergeNumberHash>(
+            mmr.peaks().unwrap(),
+            mmr.mmr_size(),
+            vec![good.clone(), good.clone()],
+            &path
+        ),
+        Ok(true)
+    );
+    assert_eq!(
+        verify_with_peaks::<_, MergeNumberHash>(
+            mmr.peaks().unwrap(),
+            mmr.mmr_size(),
+            vec![good.clone(), bad.clone()],
+            &path
+        ),
+        Err(Error::CorruptedProof)
+    );
+    let mut buf = vec![None; 8];
+    assert_eq!(
+        crate::verifier::verify_in::<_, MergeNumberHash>(
+            root,
+            proof.mmr_size(),
+            vec![good, bad],
+            proof.proof_items(),
+            &mut buf
+        ),
+        Err(Error::CorruptedProof)
+    );
+}
+
+#[test]
+fn test_gen_proof_out_of_range() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..11 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    // leaf index 11 taken for a position, the first one beyond the MMR
+    assert_eq!(
+        mmr.gen_proof(vec![leaf_index_to_pos(3), 19]).err(),
+        Some(Error::PositionOutOfRange { pos: 19 })
+    );
+    // reported before any non-leaf
+    assert_eq!(
+        mmr.gen_proof(vec![2, 25, 21]).err(),
+        Some(Error::PositionOutOfRange { pos: 25 })
+    );
+    assert_eq!(
+        mmr.gen_proof_reporting_missing(vec![100]).err(),
+        Some(Error::PositionOutOfRange { pos: 100 })
+    );
+    assert_eq!(
+        mmr.gen_node_proof(vec![14, 19]).err(),
+        Some(Error::PositionOutOfRange { pos: 19 })
+    );
+}
+
+#[test]
+fn test_gen_proof_with_positions() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().unwrap();
+
+    let pos_list = vec![positions[7], positions[2], positions[7], positions[5]];
+    let (proof, canonical) = mmr.gen_proof_with_positions(pos_list).unwrap();
+    assert_eq!(canonical, vec![positions[2], positions[5], positions[7]]);
+    assert_eq!(
+        proof.proof_items(),
+        mmr.gen_proof(canonical.clone()).unwrap().proof_items()
+    );
+
+    // leaves verify in any order
+    let mut leaves: Vec<_> = [7u32, 2, 5]
+        .iter()
+        .map(|i| (positions[*i as usize], NumberHash::from(*i)))
+        .collect();
+    assert!(proof.verify(root.clone(), leaves.clone()).unwrap());
+    leaves.reverse();
+    assert!(proof.verify(root, leaves).unwrap());
+
+    // a single leaf MMR dedups to the leaf itself
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    mmr.push(NumberHash::from(0)).unwrap();
+    let (proof, canonical) = mmr.gen_proof_with_positions(vec![0, 0]).unwrap();
+    assert_eq!(canonical, vec![0]);
+    assert!(proof.proof_items().is_empty());
+}
+
+#[test]
+fn test_verify_positions_against_store() {
+    use crate::MMRStoreWriteOps;
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    mmr.commit().unwrap();
+    let root = mmr.get_root().unwrap();
+    let pos_list = vec![positions[7], positions[2], positions[2]];
+    let proof = mmr.gen_proof(pos_list.clone()).unwrap();
+    assert_eq!(
+        proof.verify_positions_against_store(root.clone(), &store, pos_list.clone()),
+        Ok(true)
+    );
+
+    // a corrupted leaf
+    (&store)
+        .append(positions[2], vec![NumberHash::from(100)])
+        .unwrap();
+    assert_eq!(
+        proof.verify_positions_against_store(root.clone(), &store, pos_list),
+        Ok(false)
+    );
+
+    let empty = MemStore::default();
+    assert_eq!(
+        proof.verify_positions_against_store(root, &empty, vec![positions[7]]),
+        Err(Error::InconsistentStore { pos: positions[7] })
+    );
+}
+
+#[test]
+fn test_simulate_push() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..20 {
+        let elem = NumberHash::from(i);
+        let mmr_size = mmr.mmr_size();
+        let (pos, root) = mmr.simulate_push(&elem).unwrap();
+        // nothing changes until the actual push
+        assert_eq!(mmr.mmr_size(), mmr_size);
+        assert!(mmr.batch().get_elem(pos).unwrap().is_none());
+        assert_eq!(mmr.push(elem).unwrap(), pos);
+        assert_eq!(mmr.get_root().unwrap(), root);
+        if i == 10 {
+            mmr.commit().unwrap();
+        }
+    }
+
+    // candidates get different roots
+    let (_, root_a) = mmr.simulate_push(&NumberHash::from(100)).unwrap();
+    let (_, root_b) = mmr.simulate_push(&NumberHash::from(101)).unwrap();
+    assert_ne!(root_a, root_b);
+}
+
+#[test]
+fn test_proof_positions() {
+    use crate::proof_positions;
+    use crate::verifier::bagging_peaks_hashes;
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+
+    for (i, first) in positions.iter().enumerate() {
+        for second in &positions[i..] {
+            let pos_list = vec![*second, *first];
+            let item_positions = proof_positions(mmr.mmr_size(), pos_list.clone()).unwrap();
+            let proof = mmr.gen_proof(pos_list).unwrap();
+            let mut items: Vec<NumberHash> = item_positions
+                .iter()
+                .map(|pos| mmr.batch().get_elem(*pos).unwrap().unwrap())
+                .collect();
+            // trailing peaks get bagged into a single item
+            let bagged = items.split_off(proof.proof_items().len() - 1);
+            items.push(bagging_peaks_hashes::<_, MergeNumberHash>(bagged).unwrap());
+            assert_eq!(items, proof.proof_items());
+        }
+    }
+    assert_eq!(proof_positions(19, vec![3]), Ok(vec![4, 2, 13, 17, 18]));
+    assert_eq!(proof_positions(1, vec![0]), Ok(vec![]));
+
+    assert_eq!(
+        proof_positions(19, vec![]),
+        Err(Error::GenProofForInvalidLeaves)
+    );
+    assert_eq!(
+        proof_positions(19, vec![2]),
+        Err(Error::GenProofForInvalidLeaves)
+    );
+    assert_eq!(
+        proof_positions(19, vec![19]),
+        Err(Error::GenProofForInvalidLeaves)
+    );
+    assert_eq!(
+        proof_positions(20, vec![0]),
+        Err(Error::InvalidMMRSize { mmr_size: 20 })
+    );
+}
+
+#[test]
+fn test_prove_without_partial_eq() {
+    use crate::{MMRStoreWriteOps, Merge, MerkleProof};
+
+    /// A node type without equality, wrapping the test hash.
+    #[derive(Clone)]
+    struct Opaque(NumberHash);
+
+    struct MergeOpaque;
+
+    impl Merge for MergeOpaque {
+        type Item = Opaque;
+        type Error = Error;
+        fn merge(lhs: &Opaque, rhs: &Opaque) -> crate::Result<Opaque> {
+            MergeNumberHash::merge(&lhs.0, &rhs.0).map(Opaque)
+        }
+    }
+
+    struct OpaqueStore(MemStore<NumberHash>);
+
+    impl MMRStoreReadOps<Opaque> for &OpaqueStore {
+        fn get_elem(&self, pos: Pos) -> crate::Result<Option<Opaque>> {
+            Ok((&self.0).get_elem(pos)?.map(Opaque))
+        }
+    }
+
+    impl MMRStoreWriteOps<Opaque> for &OpaqueStore {
+        fn append(&mut self, pos: Pos, elems: Vec<Opaque>) -> crate::Result<()> {
+            (&self.0).append(pos, elems.into_iter().map(|elem| elem.0).collect())
+        }
+    }
+
+    let store = OpaqueStore(MemStore::default());
+    let mut mmr = crate::MMR::<_, MergeOpaque, _>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(Opaque(NumberHash::from(i))).unwrap())
+        .collect();
+    mmr.commit().unwrap();
+    let root = mmr.get_root().unwrap().0;
+    let proof = mmr.gen_proof(vec![positions[2], positions[7]]).unwrap();
+    assert!(mmr.gen_node_proof(vec![positions[2], 13]).is_ok());
+    assert!(mmr.gen_ancestry_proof(leaf_index_to_mmr_size(4)).is_ok());
+
+    // the verifier side has equality
+    let proof = MerkleProof::<_, MergeNumberHash>::new(
+        proof.mmr_size(),
+        proof
+            .proof_items()
+            .iter()
+            .map(|item| item.0.clone())
+            .collect(),
+    );
+    let leaves = vec![
+        (positions[2], NumberHash::from(2)),
+        (positions[7], NumberHash::from(7)),
+    ];
+    assert!(proof.verify(root, leaves).unwrap());
+}
+
+#[test]
+fn test_get_roots_at() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let mut sizes = Vec::new();
+    let mut roots = Vec::new();
+    for i in 0u32..40 {
+        mmr.push(NumberHash::from(i)).unwrap();
+        sizes.push(mmr.mmr_size());
+        roots.push(mmr.get_root().unwrap());
+        if i == 25 {
+            mmr.commit().unwrap();
+        }
+    }
+    assert_eq!(mmr.get_roots_at(&sizes), Ok(roots.clone()));
+    // any order, with repetitions
+    assert_eq!(
+        mmr.get_roots_at(&[sizes[30], sizes[3], sizes[30], sizes[0]]),
+        Ok(vec![
+            roots[30].clone(),
+            roots[3].clone(),
+            roots[30].clone(),
+            roots[0].clone()
+        ])
+    );
+    assert_eq!(mmr.get_roots_at(&[]), Ok(vec![]));
+
+    assert_eq!(
+        mmr.get_roots_at(&[sizes[3], 5]),
+        Err(Error::InvalidMMRSize { mmr_size: 5 })
+    );
+    let mmr_size = mmr.mmr_size();
+    assert_eq!(
+        mmr.get_roots_at(&[leaf_index_to_mmr_size(40)]),
+        Err(Error::AncestorRootNotPredecessor {
+            prev_mmr_size: leaf_index_to_mmr_size(40),
+            mmr_size
+        })
+    );
+    assert_eq!(mmr.get_roots_at(&[0]), Err(Error::GetRootOnEmpty));
+}
+
+#[test]
+fn test_merkle_proof_try_new() {
+    use crate::MerkleProof;
+    type Proof = MerkleProof<NumberHash, MergeNumberHash>;
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..11 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    let proof = mmr.gen_proof(vec![leaf_index_to_pos(5)]).unwrap();
+    let items = proof.proof_items().to_vec();
+    assert!(Proof::try_new(mmr.mmr_size(), items.clone()).is_ok());
+    assert!(Proof::try_new(0, Vec::new()).is_ok());
+    assert!(Proof::try_new(1, Vec::new()).is_ok());
+    // the largest perfect mountain, its size isn't truncated on 32 bit targets
+    assert!(Proof::try_new(Pos::MAX >> 1, items.clone()).is_ok());
+    assert_eq!(
+        Proof::try_new(mmr.mmr_size() + 1, items.clone()).err(),
+        Some(Error::InvalidMMRSize {
+            mmr_size: mmr.mmr_size() + 1
+        })
+    );
+    // one item per node besides a proven leaf at most
+    assert_eq!(Proof::try_new(1, items).err(), Some(Error::CorruptedProof));
+    let items = vec![NumberHash::from(0); 3];
+    assert_eq!(Proof::try_new(3, items).err(), Some(Error::CorruptedProof));
+}
+
+#[test]
+fn test_validate_shape() {
+    use crate::{proof_positions, MerkleProof};
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let mmr_size = mmr.mmr_size();
+    let root = mmr.get_root().unwrap();
+    for pos in &positions {
+        let proof = mmr.gen_proof(vec![*pos]).unwrap();
+        assert_eq!(proof.validate_shape(mmr_size, &[*pos, *pos]), Ok(()));
+
+        // the trailing peaks listed one by one verify as well
+        let unbagged: Vec<NumberHash> = proof_positions(mmr_size, vec![*pos])
+            .unwrap()
+            .iter()
+            .map(|pos| mmr.batch().get_elem(*pos).unwrap().unwrap())
+            .collect();
+        let mut items = unbagged.clone();
+        let unbagged = MerkleProof::<_, MergeNumberHash>::new(mmr_size, unbagged);
+        assert_eq!(unbagged.validate_shape(mmr_size, &[*pos]), Ok(()));
+        // but only the bagged ones are canonical
+        assert_eq!(proof.validate_canonical(mmr_size, &[*pos]), Ok(()));
+        if unbagged.proof_items().len() != proof.proof_items().len() {
+            assert_eq!(
+                unbagged.validate_canonical(mmr_size, &[*pos]),
+                Err(Error::CorruptedProof)
+            );
+        }
+        let leaf = mmr.batch().get_elem(*pos).unwrap().unwrap();
+        assert!(unbagged.verify(root.clone(), vec![(*pos, leaf)]).unwrap());
+
+        items.push(NumberHash::from(0));
+        let too_long = MerkleProof::<_, MergeNumberHash>::new(mmr_size, items);
+        assert_eq!(
+            too_long.validate_shape(mmr_size, &[*pos]),
+            Err(Error::CorruptedProof)
+        );
+    }
+
+    let proof = mmr.gen_proof(vec![positions[3]]).unwrap();
+    let items = proof.proof_items();
+    let too_short =
+        MerkleProof::<_, MergeNumberHash>::new(mmr_size, items[..items.len() - 1].to_vec());
+    assert_eq!(
+        too_short.validate_shape(mmr_size, &[positions[3]]),
+        Err(Error::CorruptedProof)
+    );
+    assert_eq!(
+        proof.validate_shape(leaf_index_to_mmr_size(9), &[positions[3]]),
+        Err(Error::CorruptedProof)
+    );
+    assert_eq!(
+        proof.validate_shape(20, &[positions[3]]),
+        Err(Error::InvalidMMRSize { mmr_size: 20 })
+    );
+    assert_eq!(
+        proof.validate_shape(mmr_size, &[2]),
+        Err(Error::GenProofForInvalidLeaves)
+    );
+    assert_eq!(
+        proof.validate_shape(mmr_size, &[]),
+        Err(Error::GenProofForInvalidLeaves)
+    );
+}
+
+#[test]
+fn test_path_to_peak() {
+    use crate::verifier::calculate_peak_root;
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let peaks = mmr.peaks().unwrap();
+
+    // leaf 5 (pos 8) climbs 8 -> 9 -> 13 -> 14
+    let path = mmr.path_to_peak(positions[5]).unwrap();
+    let path_positions: Vec<Pos> = path.iter().map(|(pos, _)| *pos).collect();
+    assert_eq!(path_positions, vec![7, 12, 6]);
+
+    for (i, pos) in positions.iter().enumerate() {
+        let path = mmr.path_to_peak(*pos).unwrap();
+        let (peak_pos, peak) = peaks.iter().find(|(peak_pos, _)| peak_pos >= pos).unwrap();
+        let items: Vec<NumberHash> = path.into_iter().map(|(_, item)| item).collect();
+        let calculated = calculate_peak_root::<_, MergeNumberHash>(
+            vec![(*pos, NumberHash::from(i as u32))],
+            *peak_pos,
+            &items,
+        )
+        .unwrap();
+        assert_eq!(&calculated, peak);
+    }
+    // the last leaf is a peak by itself
+    assert!(mmr.path_to_peak(positions[10]).unwrap().is_empty());
+
+    assert_eq!(
+        mmr.path_to_peak(2).err(),
+        Some(Error::GenProofForInvalidLeaves)
+    );
+    assert_eq!(
+        mmr.path_to_peak(mmr.mmr_size()).err(),
+        Some(Error::GenProofForInvalidLeaves)
+    );
+}
+
+fn test_invalid_proof_verification(
+    leaf_count: u32,
+    positions_to_verify: Vec<Pos>,
+    // positions of entries that should be tampered
+    tampered_positions: Vec<usize>,
+    // optionally handroll proof from these positions
+    handrolled_proof_positions: Option<Vec<Pos>>,
+) {
+    use crate::{Merge, MerkleProof};
+    use std::fmt::{Debug, Formatter};
+
+    // Simple item struct to allow debugging the contents of MMR nodes/peaks
+    #[derive(Clone, PartialEq)]
+    enum MyItem {
+        Number(u32),
+        Merged(Box<MyItem>, Box<MyItem>),
+    }
+
+    impl Debug for MyItem {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                MyItem::Number(x) => f.write_fmt(format_args!("{}", x)),
+                MyItem::Merged(a, b) => f.write_fmt(format_args!("Merged({:#?}, {:#?})", a, b)),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct MyMerge;
+
+    impl Merge for MyMerge {
+        type Item = MyItem;
+        type Error = Error;
         fn merge(lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item, crate::Error> {
             Ok(MyItem::Merged(Box::new(lhs.clone()), Box::new(rhs.clone())))
         }
@@ -189,14 +1246,14 @@ fn test_invalid_proof_verification(
 
     let store = MemStore::default();
     let mut mmr = MemMMR::<_, MyMerge>::new(0, &store);
-    let mut positions: Vec<u64> = Vec::new();
+    let mut positions: Vec<Pos> = Vec::new();
     for i in 0u32..leaf_count {
         let pos = mmr.push(MyItem::Number(i)).unwrap();
         positions.push(pos);
     }
     let root = mmr.get_root().unwrap();
 
-    let entries_to_verify: Vec<(u64, MyItem)> = positions_to_verify
+    let entries_to_verify: Vec<(Pos, MyItem)> = positions_to_verify
         .iter()
         .map(|pos| (*pos, mmr.batch().get_elem(*pos).unwrap().unwrap()))
         .collect();
@@ -287,3 +1344,1035 @@ proptest! {
         test_gen_new_root_from_proof(count);
     }
 }
+
+#[test]
+fn test_verify_under_peak() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    // peaks of the 11 leaves mmr are [14, 17, 18]
+    for (leaves, peak_pos) in [(vec![2, 4], 14), (vec![9], 17), (vec![10], 18)] {
+        let leaf_positions: Vec<Pos> = leaves.iter().map(|i| positions[*i]).collect();
+        let proof = mmr.gen_proof(leaf_positions.clone()).expect("gen proof");
+        let peak_hash = mmr.batch().get_elem(peak_pos).unwrap().unwrap();
+        let entries: Vec<_> = leaves
+            .iter()
+            .map(|i| (positions[*i], NumberHash::from(*i as u32)))
+            .collect();
+        assert!(proof
+            .verify_under_peak(peak_hash.clone(), peak_pos, entries.clone())
+            .unwrap());
+
+        let tampered = vec![(leaf_positions[0], NumberHash::from(31337))];
+        let tampered_proof = mmr.gen_proof(vec![leaf_positions[0]]).unwrap();
+        assert!(!tampered_proof
+            .verify_under_peak(peak_hash.clone(), peak_pos, tampered)
+            .unwrap());
+    }
+
+    let proof = mmr.gen_proof(vec![positions[9]]).unwrap();
+    let peak_hash = mmr.batch().get_elem(17).unwrap().unwrap();
+    // not a peak
+    assert_eq!(
+        proof.verify_under_peak(
+            peak_hash.clone(),
+            16,
+            vec![(positions[9], NumberHash::from(9))]
+        ),
+        Err(Error::CorruptedProof)
+    );
+    // leaf not under the peak
+    assert_eq!(
+        proof.verify_under_peak(peak_hash, 17, vec![(positions[10], NumberHash::from(10))]),
+        Err(Error::CorruptedProof)
+    );
+}
+
+#[test]
+fn test_rewind() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let mut sizes_and_roots = Vec::new();
+    for i in 0u32..20 {
+        mmr.push(NumberHash::from(i)).unwrap();
+        sizes_and_roots.push((mmr.mmr_size(), mmr.get_root().unwrap()));
+    }
+    mmr.commit().expect("commit changes");
+    // keep some elements pending in the batch
+    for i in 20u32..25 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+
+    let mmr_size = mmr.mmr_size();
+    assert_eq!(
+        mmr.rewind(mmr_size + 1),
+        Err(Error::AncestorRootNotPredecessor {
+            prev_mmr_size: mmr_size + 1,
+            mmr_size
+        })
+    );
+    assert_eq!(mmr.rewind(20), Err(Error::InvalidMMRSize { mmr_size: 20 }));
+
+    let (size, root) = sizes_and_roots[10].clone();
+    mmr.rewind(size).expect("rewind");
+    assert_eq!(mmr.mmr_size(), size);
+    assert_eq!(mmr.get_root().unwrap(), root);
+    assert!(mmr.batch().get_elem(size).unwrap().is_none());
+    mmr.commit().expect("commit changes");
+    assert!((&store).get_elem(size - 1).unwrap().is_some());
+    assert!((&store).get_elem(size).unwrap().is_none());
+
+    // appending after a rewind reproduces the original history
+    for i in 11u32..20 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    assert_eq!(
+        (mmr.mmr_size(), mmr.get_root().unwrap()),
+        sizes_and_roots[19]
+    );
+
+    mmr.rewind(0).expect("rewind");
+    assert!(mmr.is_empty());
+}
+
+#[test]
+fn test_verify_without_clone() {
+    use crate::{Merge, MerkleProof};
+
+    // an item that can't be cloned, e.g. a heavyweight accumulator value
+    #[derive(PartialEq, Debug)]
+    struct Unclonable(NumberHash);
+
+    struct MergeUnclonable;
+
+    impl Merge for MergeUnclonable {
+        type Item = Unclonable;
+        type Error = Error;
+        fn merge(lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item, Error> {
+            MergeNumberHash::merge(&lhs.0, &rhs.0).map(Unclonable)
+        }
+    }
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().expect("get root");
+    let proof = mmr
+        .gen_proof(vec![positions[3], positions[7]])
+        .expect("gen proof");
+    let leaves = || {
+        vec![
+            (positions[3], Unclonable(NumberHash::from(3))),
+            (positions[7], Unclonable(NumberHash::from(7))),
+        ]
+    };
+    let unclonable_proof = || {
+        MerkleProof::<_, MergeUnclonable>::new(
+            proof.mmr_size(),
+            proof
+                .proof_items()
+                .iter()
+                .cloned()
+                .map(Unclonable)
+                .collect(),
+        )
+    };
+
+    assert_eq!(
+        unclonable_proof().into_calculate_root(leaves()),
+        Ok(Unclonable(root.clone()))
+    );
+    assert!(unclonable_proof()
+        .into_verify(Unclonable(root.clone()), leaves())
+        .unwrap());
+    assert!(!unclonable_proof()
+        .into_verify(
+            Unclonable(root),
+            vec![(positions[3], Unclonable(NumberHash::from(4)))]
+        )
+        .unwrap());
+}
+
+#[test]
+fn test_merge_with_pos() {
+    use super::new_blake2b;
+    use crate::Merge;
+
+    // commits to the parent position, like grin's hash-with-index
+    struct MergeNumberHashWithPos;
+
+    impl Merge for MergeNumberHashWithPos {
+        type Item = NumberHash;
+        type Error = Error;
+        fn merge(lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item, Error> {
+            MergeNumberHash::merge(lhs, rhs)
+        }
+        fn merge_with_pos(
+            parent_pos: Pos,
+            lhs: &Self::Item,
+            rhs: &Self::Item,
+        ) -> Result<Self::Item, Error> {
+            let mut hasher = new_blake2b();
+            let mut hash = [0u8; 32];
+            hasher.update(&parent_pos.to_le_bytes());
+            hasher.update(&lhs.0);
+            hasher.update(&rhs.0);
+            hasher.finalize(&mut hash);
+            Ok(NumberHash(hash.to_vec().into()))
+        }
+    }
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHashWithPos>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().expect("get root");
+    assert_ne!(
+        "f6794677f37a57df6a5ec36ce61036e43a36c1a009d05c81c9aa685dde1fd6e3",
+        hex_string(&root.0)
+    );
+
+    let leaves = vec![
+        (positions[2], NumberHash::from(2)),
+        (positions[6], NumberHash::from(6)),
+    ];
+    let proof = mmr
+        .gen_proof(leaves.iter().map(|(pos, _)| *pos).collect())
+        .expect("gen proof");
+    assert!(proof.verify(root.clone(), leaves.clone()).unwrap());
+    let node_proof = mmr
+        .gen_node_proof(leaves.iter().map(|(pos, _)| *pos).collect())
+        .expect("gen node proof");
+    assert!(node_proof.verify(root.clone(), leaves).unwrap());
+
+    let ancestry_proof = mmr.gen_ancestry_proof(8).expect("gen ancestry proof");
+    let (_, prev_root) = mmr.get_ancestor_peaks_and_root(8).unwrap();
+    assert!(ancestry_proof.verify_ancestor(root, prev_root).unwrap());
+}
+
+#[test]
+fn test_leaves_count_and_peaks() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    assert_eq!(mmr.leaves_count(), 0);
+    assert_eq!(mmr.peak_map(), 0);
+    assert!(mmr.peaks().unwrap().is_empty());
+
+    (0u32..11).for_each(|i| {
+        mmr.push(NumberHash::from(i)).unwrap();
+    });
+    assert_eq!(mmr.leaves_count(), 11);
+    assert_eq!(mmr.peak_map(), 0b1011);
+    // nothing is committed yet, so the peaks come from the batch
+    let peaks = mmr.peaks().unwrap();
+    assert_eq!(
+        peaks.iter().map(|(pos, _)| *pos).collect::<Vec<_>>(),
+        vec![14, 17, 18]
+    );
+    assert_eq!(peaks[2].1, NumberHash::from(10));
+    assert!((&store).get_elem(18).unwrap().is_none());
+}
+
+fn test_gen_new_root_from_proof_with_new_leaves(count: u32, appended: u32, proof_elem: Vec<u32>) {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..count)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let proof = mmr
+        .gen_proof(
+            proof_elem
+                .iter()
+                .map(|elem| positions[*elem as usize])
+                .collect(),
+        )
+        .expect("gen proof");
+    let new_leaves: Vec<(Pos, NumberHash)> = (count..count + appended)
+        .map(|i| (mmr.push(NumberHash::from(i)).unwrap(), NumberHash::from(i)))
+        .collect();
+    let root = mmr.get_root().expect("get root");
+    let calculated_root = proof
+        .calculate_root_with_new_leaves(
+            proof_elem
+                .iter()
+                .map(|elem| (positions[*elem as usize], NumberHash::from(*elem)))
+                .collect(),
+            new_leaves,
+            mmr.mmr_size(),
+        )
+        .unwrap();
+    assert_eq!(calculated_root, root);
+}
+
+#[test]
+fn test_gen_root_from_proof_with_new_leaves() {
+    test_gen_new_root_from_proof_with_new_leaves(11, 1, vec![10]);
+    test_gen_new_root_from_proof_with_new_leaves(11, 5, vec![10]);
+    test_gen_new_root_from_proof_with_new_leaves(11, 0, vec![10]);
+    test_gen_new_root_from_proof_with_new_leaves(8, 9, vec![3, 7]);
+    // the single peak right of the leaf isn't bagged
+    test_gen_new_root_from_proof_with_new_leaves(10, 7, vec![5]);
+}
+
+#[test]
+fn test_gen_root_from_proof_with_invalid_new_leaves() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let leaves = || vec![(positions[10], NumberHash::from(10))];
+    let proof = mmr.gen_proof(vec![positions[10]]).unwrap();
+    // new leaves must continue at the current size
+    assert_eq!(
+        proof.calculate_root_with_new_leaves(leaves(), vec![(20, NumberHash::from(11))], 22),
+        Err(Error::GenProofForInvalidLeaves)
+    );
+    // mismatching new mmr size
+    assert_eq!(
+        proof.calculate_root_with_new_leaves(leaves(), vec![(19, NumberHash::from(11))], 20),
+        Err(Error::CorruptedProof)
+    );
+    // the rhs peaks are bagged in the proof for the first leaf
+    let proof = mmr.gen_proof(vec![positions[0]]).unwrap();
+    assert_eq!(
+        proof.calculate_root_with_new_leaves(
+            vec![(positions[0], NumberHash::from(0))],
+            vec![(19, NumberHash::from(11))],
+            21
+        ),
+        Err(Error::CorruptedProof)
+    );
+}
+
+proptest! {
+    #[test]
+    fn test_random_gen_root_with_new_leaves(count in 1u32..500u32, appended in 0u32..100u32) {
+        test_gen_new_root_from_proof_with_new_leaves(count, appended, vec![count - 1]);
+    }
+}
+
+#[test]
+fn test_gen_proof_reads_runs() {
+    use crate::{MMRStoreWriteOps, MMR};
+    use std::cell::Cell;
+    use std::ops::Range;
+
+    // counts the round-trips to the backing store
+    #[derive(Default)]
+    struct CountingStore {
+        store: MemStore<NumberHash>,
+        reads: Cell<usize>,
+    }
+
+    impl MMRStoreReadOps<NumberHash> for &CountingStore {
+        fn get_elem(&self, pos: Pos) -> crate::Result<Option<NumberHash>> {
+            self.reads.set(self.reads.get() + 1);
+            (&self.store).get_elem(pos)
+        }
+
+        fn get_elems(&self, range: Range<Pos>) -> crate::Result<Vec<Option<NumberHash>>> {
+            self.reads.set(self.reads.get() + 1);
+            (&self.store).get_elems(range)
+        }
+    }
+
+    impl MMRStoreWriteOps<NumberHash> for &CountingStore {
+        fn append(&mut self, pos: Pos, elems: Vec<NumberHash>) -> crate::Result<()> {
+            (&self.store).append(pos, elems)
+        }
+    }
+
+    let store = CountingStore::default();
+    let mut mmr = MMR::<_, MergeNumberHash, _>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    mmr.commit().expect("commit changes");
+    let mmr = MMR::<_, MergeNumberHash, _>::new(mmr.mmr_size(), &store);
+
+    // the peaks 17 and 18 are read in one go
+    store.reads.set(0);
+    let root = mmr.get_root().unwrap();
+    assert_eq!(store.reads.get(), 2);
+
+    // siblings 0, 5 and 13 plus the bagged peaks 17 and 18
+    store.reads.set(0);
+    let proof = mmr.gen_proof(vec![positions[1]]).unwrap();
+    assert_eq!(store.reads.get(), 4);
+    assert!(proof
+        .verify(root, vec![(positions[1], NumberHash::from(1))])
+        .unwrap());
+}
+
+#[test]
+fn test_commit_transaction() {
+    use crate::{MMRStoreWriteOps, MMR};
+    use std::cell::{Cell, RefCell};
+
+    // records the transaction calls and appended positions, and fails appends reaching
+    // `fail_from`
+    #[derive(Default)]
+    struct TransactionalStore {
+        store: MemStore<NumberHash>,
+        calls: RefCell<Vec<&'static str>>,
+        appends: RefCell<Vec<(Pos, usize)>>,
+        fail_from: Cell<Option<Pos>>,
+    }
+
+    impl MMRStoreReadOps<NumberHash> for &TransactionalStore {
+        fn get_elem(&self, pos: Pos) -> crate::Result<Option<NumberHash>> {
+            (&self.store).get_elem(pos)
+        }
+    }
+
+    impl MMRStoreWriteOps<NumberHash> for &TransactionalStore {
+        fn append(&mut self, pos: Pos, elems: Vec<NumberHash>) -> crate::Result<()> {
+            if self
+                .fail_from
+                .get()
+                .is_some_and(|fail_from| pos + elems.len() as Pos > fail_from)
+            {
+                return Err(Error::StoreError("disk full".to_string()));
+            }
+            self.calls.borrow_mut().push("append");
+            self.appends.borrow_mut().push((pos, elems.len()));
+            (&self.store).append(pos, elems)
+        }
+
+        fn overwrite(&mut self, pos: Pos, elem: NumberHash) -> crate::Result<()> {
+            self.calls.borrow_mut().push("overwrite");
+            (&self.store).overwrite(pos, elem)
+        }
+
+        fn start_transaction(&mut self) -> crate::Result<()> {
+            self.calls.borrow_mut().push("start");
+            Ok(())
+        }
+
+        fn commit_transaction(&mut self) -> crate::Result<()> {
+            self.calls.borrow_mut().push("commit");
+            Ok(())
+        }
+
+        fn abort_transaction(&mut self) -> crate::Result<()> {
+            self.calls.borrow_mut().push("abort");
+            Ok(())
+        }
+    }
+
+    let store = TransactionalStore::default();
+    let mut mmr = MMR::<_, MergeNumberHash, _>::new(0, &store);
+    mmr.push(NumberHash::from(0)).unwrap();
+    mmr.push(NumberHash::from(1)).unwrap();
+    mmr.commit().unwrap();
+    // the contiguous pushes are written at once
+    assert_eq!(*store.calls.borrow(), vec!["start", "append", "commit"]);
+    assert_eq!(*store.appends.borrow(), vec![(0, 3)]);
+
+    store.appends.borrow_mut().clear();
+    for i in 2u32..11 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    mmr.commit().unwrap();
+    assert_eq!(*store.appends.borrow(), vec![(3, 16)]);
+    let expected_store = MemStore::default();
+    let mut expected = MemMMR::<_, MergeNumberHash>::new(0, &expected_store);
+    for i in 0u32..11 {
+        expected.push(NumberHash::from(i)).unwrap();
+    }
+    assert_eq!(mmr.get_root(), expected.get_root());
+
+    let store = TransactionalStore {
+        fail_from: Cell::new(Some(1)),
+        ..Default::default()
+    };
+    let mut mmr = MMR::<_, MergeNumberHash, _>::new(0, &store);
+    mmr.push(NumberHash::from(0)).unwrap();
+    mmr.push(NumberHash::from(1)).unwrap();
+    assert_eq!(
+        mmr.commit().err(),
+        Some(Error::StoreError("disk full".to_string()))
+    );
+    assert_eq!(*store.calls.borrow(), vec!["start", "abort"]);
+
+    // the overwrite is written before the pushes fail, so the transaction has a partial write
+    let store = TransactionalStore {
+        fail_from: Cell::new(Some(3)),
+        ..Default::default()
+    };
+    let mut mmr = MMR::<_, MergeNumberHash, _>::new(0, &store);
+    mmr.push(NumberHash::from(0)).unwrap();
+    mmr.push(NumberHash::from(1)).unwrap();
+    mmr.commit().unwrap();
+    store.calls.borrow_mut().clear();
+    mmr.batch_mut().overwrite(0, NumberHash::from(0));
+    mmr.push(NumberHash::from(2)).unwrap();
+    assert_eq!(
+        mmr.commit().err(),
+        Some(Error::StoreError("disk full".to_string()))
+    );
+    assert_eq!(*store.calls.borrow(), vec!["start", "overwrite", "abort"]);
+    assert_eq!(*store.appends.borrow(), vec![(0, 3)]);
+
+    // the failed batch is kept, so the commit can be retried
+    assert_eq!(mmr.batch().pending_len(), 2);
+    store.fail_from.set(None);
+    mmr.commit().unwrap();
+    assert_eq!(mmr.batch().pending_len(), 0);
+    assert_eq!(*store.appends.borrow(), vec![(0, 3), (3, 1)]);
+    let reopened = MMR::<_, MergeNumberHash, _>::new(mmr.mmr_size(), &store);
+    assert_eq!(reopened.get_root(), mmr.get_root());
+}
+
+fn test_proof_streaming(count: u32, elems: Vec<u32>) {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..count)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().expect("get root");
+    let leaves: Vec<_> = elems
+        .iter()
+        .map(|i| (positions[*i as usize], NumberHash::from(*i)))
+        .collect();
+    let mut proof = Vec::new();
+    mmr.gen_proof_streaming(leaves.iter().map(|(pos, _)| *pos).collect(), |pos, item| {
+        proof.push((pos, item));
+        Ok(())
+    })
+    .expect("gen proof");
+    assert!(proof.windows(2).all(|items| items[0].0 < items[1].0));
+
+    let mmr_size = mmr.mmr_size();
+    let verify = |leaves: Vec<(Pos, NumberHash)>, proof: Vec<(Pos, NumberHash)>| {
+        verify_proof_streaming::<_, MergeNumberHash>(root.clone(), mmr_size, leaves, proof)
+    };
+    assert_eq!(verify(leaves.clone(), proof.clone()), Ok(true));
+
+    // every copy of a duplicate leaf, leaves given twice must agree
+    let mut tampered = leaves.clone();
+    let tampered_pos = tampered[0].0;
+    for (_, leaf) in tampered.iter_mut().filter(|(pos, _)| *pos == tampered_pos) {
+        *leaf = NumberHash::from(31337);
+    }
+    assert_eq!(verify(tampered, proof.clone()), Ok(false));
+    if !proof.is_empty() {
+        let mut missing = proof.clone();
+        missing.pop();
+        assert!(!verify(leaves.clone(), missing).unwrap_or(false));
+    }
+    if proof.len() > 1 {
+        let mut reordered = proof;
+        reordered.rotate_left(1);
+        assert_eq!(verify(leaves, reordered), Err(Error::CorruptedProof));
+    }
+}
+
+#[test]
+fn test_gen_proof_streaming() {
+    test_proof_streaming(1, vec![0]);
+    test_proof_streaming(11, vec![0]);
+    test_proof_streaming(11, vec![10]);
+    test_proof_streaming(11, vec![3, 4, 9]);
+    test_proof_streaming(100, vec![5, 64, 30, 99]);
+    test_proof_streaming(100, (0..100).collect());
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0..11u32 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    let sink = |_pos: Pos, _item: NumberHash| Ok(());
+    assert_eq!(
+        mmr.gen_proof_streaming(vec![], sink),
+        Err(Error::GenProofForInvalidLeaves)
+    );
+    assert_eq!(
+        mmr.gen_proof_streaming(vec![2], sink),
+        Err(Error::GenProofForInvalidLeaves)
+    );
+    assert_eq!(
+        mmr.gen_proof_streaming(vec![19], sink),
+        Err(Error::PositionOutOfRange { pos: 19 })
+    );
+    // errors of the sink abort the generation
+    assert_eq!(
+        mmr.gen_proof_streaming(vec![0], |_, _| Err(Error::StoreError("full".to_string()))),
+        Err(Error::StoreError("full".to_string()))
+    );
+}
+
+proptest! {
+    #[test]
+    fn test_gen_proof_streaming_randomly(
+        (count, elems) in (1u32..500).prop_flat_map(|count| (
+            Just(count),
+            prop::collection::vec(0..count, 1..20),
+        ))
+    ) {
+        test_proof_streaming(count, elems);
+    }
+}
+
+#[test]
+fn test_merge_custom_error() {
+    use crate::Merge;
+
+    // a backend failure with its own payload, e.g. from a hardware signer
+    #[derive(Debug)]
+    struct HsmError {
+        code: u8,
+    }
+
+    impl From<HsmError> for Error {
+        fn from(err: HsmError) -> Self {
+            Error::MergeError(format!("hsm error {}", err.code))
+        }
+    }
+
+    struct MergeHsm;
+
+    impl Merge for MergeHsm {
+        type Item = NumberHash;
+        type Error = HsmError;
+        fn merge(lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item, HsmError> {
+            // the hsm refuses to hash the poisoned item
+            if *rhs == NumberHash::from(u32::MAX) {
+                return Err(HsmError { code: 7 });
+            }
+            MergeNumberHash::merge(lhs, rhs).map_err(|_| HsmError { code: 0 })
+        }
+    }
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeHsm>::new(0, &store);
+    let pos = mmr.push(NumberHash::from(0)).unwrap();
+    assert_eq!(
+        mmr.push(NumberHash::from(u32::MAX)).err(),
+        Some(Error::MergeError("hsm error 7".to_string()))
+    );
+
+    // the failed push left the mmr untouched
+    mmr.push(NumberHash::from(1)).unwrap();
+    let proof = mmr.gen_proof(vec![pos]).unwrap();
+    assert!(proof
+        .verify(mmr.get_root().unwrap(), vec![(pos, NumberHash::from(0))])
+        .unwrap());
+
+    // verification surfaces the error too
+    let proof = crate::MerkleProof::<_, MergeHsm>::new(3, vec![NumberHash::from(u32::MAX)]);
+    assert_eq!(
+        proof
+            .verify(NumberHash::from(0), vec![(pos, NumberHash::from(0))])
+            .err(),
+        Some(Error::MergeError("hsm error 7".to_string()))
+    );
+}
+
+#[test]
+fn test_can_prove_and_covered_positions() {
+    use crate::{proof_positions, MerkleProof};
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+
+    // leaves 0, 1 and 10
+    let pos_list = vec![positions[10], positions[0]];
+    let proof = mmr.gen_proof(pos_list.clone()).unwrap();
+    assert!(proof.can_prove(&pos_list));
+    assert!(proof.can_prove(&[positions[0], positions[10], positions[0]]));
+    // the sibling leaf 1 is carried as a proof item
+    assert_eq!(
+        proof.covered_positions(&pos_list),
+        vec![positions[0], positions[1], positions[10]]
+    );
+
+    // a differently shaped leaf set
+    assert!(!proof.can_prove(&[positions[0], positions[9]]));
+    assert!(!proof.can_prove(&[positions[0], positions[4]]));
+    assert!(proof
+        .covered_positions(&[positions[0], positions[9]])
+        .is_empty());
+    // not leaves, or beyond the mmr
+    assert!(!proof.can_prove(&[]));
+    assert!(!proof.can_prove(&[2, positions[10]]));
+    assert!(!proof.can_prove(&[positions[0], 19]));
+
+    // the rhs peaks listed one by one are accepted like by `validate_shape`
+    let pos_list = vec![positions[3]];
+    let unbagged: Vec<NumberHash> = proof_positions(mmr.mmr_size(), pos_list.clone())
+        .unwrap()
+        .iter()
+        .map(|pos| mmr.batch().get_elem(*pos).unwrap().unwrap())
+        .collect();
+    let unbagged = MerkleProof::<_, MergeNumberHash>::new(mmr.mmr_size(), unbagged);
+    assert!(unbagged.can_prove(&pos_list));
+    assert_eq!(
+        unbagged.covered_positions(&pos_list),
+        vec![positions[2], positions[3], positions[10]]
+    );
+
+    // the shape doesn't tell leaf sets with the same proof layout apart
+    assert!(proof.can_prove(&[positions[1], positions[10]]));
+    assert!(proof.can_prove(&[positions[0]]));
+
+    let store = MemStore::default();
+    let mut single = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    single.push(NumberHash::from(0)).unwrap();
+    let proof = single.gen_proof(vec![0]).unwrap();
+    assert!(proof.can_prove(&[0]));
+    assert_eq!(proof.covered_positions(&[0]), vec![0]);
+}
+
+proptest! {
+    #[test]
+    fn test_can_prove_generated_proofs(
+        (count, elems) in (1u32..300).prop_flat_map(|count| (
+            Just(count),
+            prop::collection::vec(0..count, 1..10),
+        ))
+    ) {
+        let store = MemStore::default();
+        let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+        let positions: Vec<Pos> = (0u32..count)
+            .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+            .collect();
+        let pos_list: Vec<Pos> = elems.iter().map(|i| positions[*i as usize]).collect();
+        let proof = mmr.gen_proof(pos_list.clone()).unwrap();
+        prop_assert!(proof.can_prove(&pos_list));
+        let covered = proof.covered_positions(&pos_list);
+        prop_assert!(pos_list.iter().all(|pos| covered.contains(pos)));
+        // the items carry the hashes of the other covered leaves
+        let items = proof.proof_items();
+        for pos in covered.iter().filter(|pos| !pos_list.contains(pos)) {
+            let leaf = mmr.batch().get_elem(*pos).unwrap().unwrap();
+            prop_assert!(items.contains(&leaf));
+        }
+    }
+}
+
+#[test]
+fn test_open_restores_committed_size() {
+    let store = MemStore::default();
+    let mmr = MemMMR::<NumberHash, MergeNumberHash>::open(&store).unwrap();
+    assert!(mmr.is_empty());
+
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..11 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    mmr.commit().unwrap();
+    let root = mmr.get_root().unwrap();
+    // pending pushes don't move the persisted size
+    mmr.push(NumberHash::from(11)).unwrap();
+
+    let reopened = MemMMR::<_, MergeNumberHash>::open(&store).unwrap();
+    assert_eq!(reopened.mmr_size(), 19);
+    assert_eq!(reopened.get_root(), Ok(root));
+
+    mmr.rewind(leaf_index_to_mmr_size(6)).unwrap();
+    mmr.commit().unwrap();
+    let reopened = MemMMR::<_, MergeNumberHash>::open(&store).unwrap();
+    assert_eq!(reopened.mmr_size(), leaf_index_to_mmr_size(6));
+    assert_eq!(reopened.get_root(), mmr.get_root());
+}
+
+#[test]
+fn test_open_rejects_invalid_size() {
+    use crate::MMRStoreWriteOps;
+
+    let store = MemStore::<NumberHash>::default();
+    (&store).set_mmr_size(2).unwrap();
+    assert_eq!(
+        MemMMR::<_, MergeNumberHash>::open(&store).err(),
+        Some(Error::InvalidMMRSize { mmr_size: 2 })
+    );
+}
+
+#[test]
+fn test_gen_proof_in_arena() {
+    use crate::ProofArena;
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..100)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().unwrap();
+
+    let mut arena = ProofArena::new();
+    for leaves in [&[3usize, 70, 12][..], &[99], &[0, 1, 2, 50], &[63, 64]] {
+        let pos_list: Vec<Pos> = leaves.iter().map(|i| positions[*i]).collect();
+        let proof = mmr.gen_proof_in(&pos_list, &mut arena).unwrap();
+        let expected = mmr.gen_proof(pos_list.clone()).unwrap();
+        assert_eq!(proof.proof_items(), expected.proof_items());
+        assert_eq!(proof.mmr_size(), expected.mmr_size());
+        let leaves = leaves
+            .iter()
+            .map(|i| (positions[*i], NumberHash::from(*i as u32)))
+            .collect();
+        assert_eq!(proof.verify(root.clone(), leaves), Ok(true));
+        arena.recycle(proof);
+    }
+    // errors leave the arena usable
+    assert_eq!(
+        mmr.gen_proof_in(&[positions[0], 1000], &mut arena).err(),
+        Some(Error::PositionOutOfRange { pos: 1000 })
+    );
+    assert_eq!(
+        mmr.gen_proof_in(&[], &mut arena).err(),
+        Some(Error::GenProofForInvalidLeaves)
+    );
+    let proof = mmr.gen_proof_in(&[positions[42]], &mut arena).unwrap();
+    assert_eq!(
+        proof.verify(root, vec![(positions[42], NumberHash::from(42))]),
+        Ok(true)
+    );
+
+    // a single leaf MMR proves with no items
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    mmr.push(NumberHash::from(0)).unwrap();
+    let proof = mmr.gen_proof_in(&[0], &mut arena).unwrap();
+    assert!(proof.proof_items().is_empty());
+}
+
+#[test]
+fn test_gen_proof_with_scratch() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..100 {
+        let pos = mmr.push(NumberHash::from(i)).unwrap();
+        let root = mmr.get_root().unwrap();
+        let proof = mmr.gen_proof_with_scratch(&[pos]).unwrap();
+        assert_eq!(
+            proof.proof_items(),
+            mmr.gen_proof(vec![pos]).unwrap().proof_items()
+        );
+        assert_eq!(
+            proof.verify(root, vec![(pos, NumberHash::from(i))]),
+            Ok(true)
+        );
+        mmr.recycle_proof(proof);
+    }
+    assert_eq!(
+        mmr.gen_proof_with_scratch(&[1000]).err(),
+        Some(Error::PositionOutOfRange { pos: 1000 })
+    );
+    assert!(mmr.gen_proof_with_scratch(&[0, 1]).is_ok());
+}
+
+#[test]
+fn test_push_detailed() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let mut mirror = std::collections::BTreeMap::new();
+    for i in 0u32..11 {
+        let pushed = mmr.push_detailed(NumberHash::from(i)).unwrap();
+        assert_eq!(pushed.leaf_pos, leaf_index_to_pos(i as Pos));
+        assert_eq!(pushed.peaks, get_peaks(mmr.mmr_size()));
+        mirror.insert(pushed.leaf_pos, NumberHash::from(i));
+        mirror.extend(pushed.parents);
+    }
+    mmr.commit().unwrap();
+
+    // the mirrored nodes are exactly the stored ones
+    let mut store = store;
+    let stored: Vec<_> = store
+        .iter()
+        .map(|(pos, elem)| (pos, elem.clone()))
+        .collect();
+    assert_eq!(stored, mirror.into_iter().collect::<Vec<_>>());
+
+    // pushing leaf 3 merges twice, into a single peak
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..3 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    let pushed = mmr.push_detailed(NumberHash::from(3)).unwrap();
+    assert_eq!(pushed.leaf_pos, 4);
+    let parents: Vec<Pos> = pushed.parents.iter().map(|(pos, _)| *pos).collect();
+    assert_eq!(parents, vec![5, 6]);
+    assert_eq!(pushed.parents[1].1, mmr.get_root().unwrap());
+    assert_eq!(pushed.peaks, vec![6]);
+}
+
+#[test]
+fn test_cancellable() {
+    use crate::util::FnStore;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..100)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    mmr.commit().unwrap();
+
+    let cancel = AtomicBool::new(false);
+    let pos_list = vec![positions[0], positions[70]];
+    assert_eq!(
+        mmr.gen_proof_cancellable(pos_list.clone(), &cancel)
+            .unwrap()
+            .proof_items(),
+        mmr.gen_proof(pos_list.clone()).unwrap().proof_items()
+    );
+    assert_eq!(mmr.rebuild_range_cancellable(0..100, &cancel), Ok(0));
+
+    cancel.store(true, Ordering::Relaxed);
+    assert_eq!(
+        mmr.gen_proof_cancellable(pos_list.clone(), &cancel).err(),
+        Some(Error::Cancelled)
+    );
+    assert_eq!(
+        mmr.rebuild_range_cancellable(0..100, &cancel),
+        Err(Error::Cancelled)
+    );
+
+    // a client disconnecting while the proof reads its nodes
+    cancel.store(false, Ordering::Relaxed);
+    let disconnecting_store = FnStore::new(|pos| {
+        cancel.store(true, Ordering::Relaxed);
+        (&store).get_elem(pos)
+    });
+    let mmr = crate::MMR::<_, MergeNumberHash, _>::new(mmr.mmr_size(), disconnecting_store);
+    assert_eq!(
+        mmr.gen_proof_cancellable(pos_list, &cancel).err(),
+        Some(Error::Cancelled)
+    );
+}
+
+#[test]
+fn test_gen_single_proof() {
+    for count in [1u32, 2, 3, 7, 11, 64, 100] {
+        let store = MemStore::default();
+        let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+        let positions: Vec<Pos> = (0..count)
+            .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+            .collect();
+        let root = mmr.get_root().unwrap();
+        for (i, pos) in positions.iter().enumerate() {
+            let proof = mmr.gen_single_proof(*pos).unwrap();
+            let expected = mmr.gen_proof(vec![*pos]).unwrap();
+            assert_eq!(proof.proof_items(), expected.proof_items());
+            assert_eq!(
+                proof.verify(root.clone(), vec![(*pos, NumberHash::from(i as u32))]),
+                Ok(true)
+            );
+        }
+        let mmr_size = mmr.mmr_size();
+        assert_eq!(
+            mmr.gen_single_proof(mmr_size).err(),
+            Some(Error::PositionOutOfRange { pos: mmr_size })
+        );
+    }
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..4 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    assert_eq!(
+        mmr.gen_single_proof(2).err(),
+        Some(Error::GenProofForInvalidLeaves)
+    );
+}
+
+#[test]
+fn test_gen_proof_matches_arena() {
+    use crate::{ProofArena, MMR};
+
+    // proof generation keeps no scratch space in the MMR, so it can be shared across threads
+    fn assert_sync<T: Sync>() {}
+    assert_sync::<MMR<NumberHash, MergeNumberHash, ()>>();
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..100)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().unwrap();
+
+    // unsorted, duplicated and failing position lists in turns
+    for leaves in [
+        &[70usize, 3, 70, 12][..],
+        &[99],
+        &[50, 2, 1, 0],
+        &[64, 63, 64],
+    ] {
+        let pos_list: Vec<Pos> = leaves.iter().map(|i| positions[*i]).collect();
+        assert_eq!(
+            mmr.gen_proof(vec![pos_list[0], 1000]).err(),
+            Some(Error::PositionOutOfRange { pos: 1000 })
+        );
+        let proof = mmr.gen_proof(pos_list.clone()).unwrap();
+        let expected = mmr.gen_proof_in(&pos_list, &mut ProofArena::new()).unwrap();
+        assert_eq!(proof.proof_items(), expected.proof_items());
+        let leaves = leaves
+            .iter()
+            .map(|i| (positions[*i], NumberHash::from(*i as u32)))
+            .collect();
+        assert_eq!(proof.verify(root.clone(), leaves), Ok(true));
+    }
+}
+
+#[test]
+fn test_gen_proof_reporting_missing() {
+    use crate::MMR;
+
+    // a store that lost the nodes at `pruned`
+    struct PrunedStore {
+        store: MemStore<NumberHash>,
+        pruned: Vec<Pos>,
+    }
+
+    impl MMRStoreReadOps<NumberHash> for &PrunedStore {
+        fn get_elem(&self, pos: Pos) -> crate::Result<Option<NumberHash>> {
+            if self.pruned.contains(&pos) {
+                return Ok(None);
+            }
+            (&self.store).get_elem(pos)
+        }
+    }
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    mmr.commit().unwrap();
+    let root = mmr.get_root().unwrap();
+
+    // the proof of leaf 0 needs 1, 5, 13 and the bagged 17, 18
+    let pruned = PrunedStore {
+        store: store.clone(),
+        pruned: vec![1, 3, 5],
+    };
+    let mmr = MMR::<_, MergeNumberHash, _>::new(19, &pruned);
+    assert_eq!(
+        mmr.gen_proof(vec![positions[0]]).err(),
+        Some(Error::InconsistentStore { pos: 1 })
+    );
+    assert_eq!(
+        mmr.gen_proof_reporting_missing(vec![positions[0]]).err(),
+        Some(Error::MissingNodes {
+            positions: vec![1, 5]
+        })
+    );
+
+    // proofs that don't touch the pruned nodes still work
+    let proof = mmr.gen_proof_reporting_missing(vec![positions[7]]).unwrap();
+    assert!(proof
+        .verify(root, vec![(positions[7], NumberHash::from(7))])
+        .unwrap());
+}
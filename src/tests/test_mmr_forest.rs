@@ -0,0 +1,174 @@
+use super::{new_blake2b, MergeNumberHash, NumberHash};
+use crate::mmr_forest::{NamedStoreReadOps, NamedStoreWriteOps};
+use crate::util::{MemMMR, MemStore};
+use crate::{leaf_index_to_pos, Error, LeafHasher, Merge, MmrForest, Pos, Result};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+struct MergeLeafHash;
+
+impl Merge for MergeLeafHash {
+    type Item = NumberHash;
+    type Error = Error;
+    fn merge(lhs: &NumberHash, rhs: &NumberHash) -> Result<NumberHash> {
+        MergeNumberHash::merge(lhs, rhs)
+    }
+}
+
+impl LeafHasher for MergeLeafHash {
+    fn hash_leaf(leaf: &[u8]) -> Result<NumberHash> {
+        let mut hasher = new_blake2b();
+        let mut hash = [0u8; 32];
+        hasher.update(&[0]);
+        hasher.update(leaf);
+        hasher.finalize(&mut hash);
+        Ok(NumberHash(hash.to_vec().into()))
+    }
+}
+
+#[derive(Default)]
+struct Tables {
+    elems: BTreeMap<(String, Pos), NumberHash>,
+    sizes: BTreeMap<String, Pos>,
+}
+
+#[derive(Clone, Default)]
+struct NamedStore(Rc<RefCell<Tables>>);
+
+impl NamedStoreReadOps<NumberHash> for NamedStore {
+    fn get_elem(&self, name: &str, pos: Pos) -> Result<Option<NumberHash>> {
+        Ok(self.0.borrow().elems.get(&(name.to_string(), pos)).cloned())
+    }
+
+    fn mmr_size(&self, name: &str) -> Result<Option<Pos>> {
+        Ok(self.0.borrow().sizes.get(name).copied())
+    }
+}
+
+impl NamedStoreWriteOps<NumberHash> for NamedStore {
+    fn append(&mut self, name: &str, pos: Pos, elems: Vec<NumberHash>) -> Result<()> {
+        let mut tables = self.0.borrow_mut();
+        for (i, elem) in elems.into_iter().enumerate() {
+            tables
+                .elems
+                .insert((name.to_string(), pos + i as Pos), elem);
+        }
+        Ok(())
+    }
+
+    fn set_mmr_size(&mut self, name: &str, mmr_size: Pos) -> Result<()> {
+        self.0.borrow_mut().sizes.insert(name.to_string(), mmr_size);
+        Ok(())
+    }
+}
+
+fn super_leaf(name: &str, root: &NumberHash) -> NumberHash {
+    let name_hash = MergeLeafHash::hash_leaf(name.as_bytes()).unwrap();
+    MergeLeafHash::merge(&name_hash, root).unwrap()
+}
+
+#[test]
+fn test_mmr_forest() {
+    let store = NamedStore::default();
+    let mut forest =
+        MmrForest::<_, MergeLeafHash, _>::open(store.clone(), &["events", "blocks", "events"])
+            .unwrap();
+    assert_eq!(forest.names().collect::<Vec<_>>(), vec!["blocks", "events"]);
+    for i in 0u32..11 {
+        forest.push("blocks", NumberHash::from(i)).unwrap();
+    }
+    for i in 100u32..105 {
+        forest.push("events", NumberHash::from(i)).unwrap();
+    }
+    assert_eq!(
+        forest.push("receipts", NumberHash::from(0)),
+        Err(Error::UnknownMmr {
+            name: "receipts".to_string()
+        })
+    );
+    assert!(forest.mmr("receipts").is_none());
+
+    // the super-root is the root over the named roots in name order
+    let blocks_root = forest.mmr("blocks").unwrap().get_root().unwrap();
+    let events_root = forest.mmr("events").unwrap().get_root().unwrap();
+    let super_store = MemStore::default();
+    let mut super_mmr = MemMMR::<_, MergeLeafHash>::new(0, &super_store);
+    super_mmr.push(super_leaf("blocks", &blocks_root)).unwrap();
+    super_mmr.push(super_leaf("events", &events_root)).unwrap();
+    let super_root = forest.super_root().unwrap();
+    assert_eq!(super_mmr.get_root(), Ok(super_root.clone()));
+
+    let proof = forest
+        .gen_proof("events", vec![leaf_index_to_pos(3)])
+        .unwrap();
+    assert_eq!(proof.name(), "events");
+    assert_eq!(proof.mmr_index(), 1);
+    let leaves = vec![(leaf_index_to_pos(3), NumberHash::from(103))];
+    assert_eq!(proof.verify(super_root.clone(), leaves.clone()), Ok(true));
+    assert_eq!(
+        proof.verify(
+            super_root.clone(),
+            vec![(leaf_index_to_pos(3), NumberHash::from(3))]
+        ),
+        Ok(false)
+    );
+    // the same leaf proven in the other MMR doesn't verify
+    let blocks_proof = forest
+        .gen_proof("blocks", vec![leaf_index_to_pos(3)])
+        .unwrap();
+    assert_eq!(
+        blocks_proof.verify(super_root.clone(), leaves.clone()),
+        Ok(false)
+    );
+    assert_eq!(
+        forest.gen_proof("receipts", vec![0]).err(),
+        Some(Error::UnknownMmr {
+            name: "receipts".to_string()
+        })
+    );
+
+    // reopening restores the committed MMRs
+    forest.commit().unwrap();
+    let reopened = MmrForest::<_, MergeLeafHash, _>::open(store, &["blocks", "events"]).unwrap();
+    assert_eq!(reopened.super_root(), Ok(super_root.clone()));
+    let proof = reopened
+        .gen_proof("events", vec![leaf_index_to_pos(3)])
+        .unwrap();
+    assert_eq!(proof.verify(super_root, leaves), Ok(true));
+}
+
+#[test]
+fn test_mmr_forest_with_empty_mmr() {
+    let store = NamedStore::default();
+    let mut forest = MmrForest::<_, MergeLeafHash, _>::open(store, &["blocks", "events"]).unwrap();
+    for i in 0u32..5 {
+        forest.push("events", NumberHash::from(i)).unwrap();
+    }
+
+    // the empty MMR has the name hash alone as its leaf
+    let events_root = forest.mmr("events").unwrap().get_root().unwrap();
+    let super_store = MemStore::default();
+    let mut super_mmr = MemMMR::<_, MergeLeafHash>::new(0, &super_store);
+    super_mmr
+        .push(MergeLeafHash::hash_leaf(b"blocks").unwrap())
+        .unwrap();
+    super_mmr.push(super_leaf("events", &events_root)).unwrap();
+    let super_root = forest.super_root().unwrap();
+    assert_eq!(super_mmr.get_root(), Ok(super_root.clone()));
+
+    let proof = forest
+        .gen_proof("events", vec![leaf_index_to_pos(2)])
+        .unwrap();
+    assert_eq!(
+        proof.verify(
+            super_root.clone(),
+            vec![(leaf_index_to_pos(2), NumberHash::from(2))]
+        ),
+        Ok(true)
+    );
+    assert_eq!(
+        forest.gen_proof("blocks", vec![0]).err(),
+        Some(Error::PositionOutOfRange { pos: 0 })
+    );
+}
@@ -1,18 +1,19 @@
 use super::{MergeNumberHash, NumberHash};
 use crate::{
+    ancestry_proof::expected_node_proof_size,
     leaf_index_to_mmr_size,
     util::{MemMMR, MemStore},
-    Error,
+    Error, Pos,
 };
 use core::ops::Shl;
 use faster_hex::hex_string;
 use proptest::prelude::*;
-use rand::{seq::SliceRandom, thread_rng};
+use rand::{seq::SliceRandom, thread_rng, Rng};
 
 fn test_mmr(count: u32, proof_elem: Vec<u32>) {
     let store = MemStore::default();
     let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
-    let positions: Vec<u64> = (0u32..count)
+    let positions: Vec<Pos> = (0u32..count)
         .map(|i| mmr.push(NumberHash::from(i)).unwrap())
         .collect();
     let root = mmr.get_root().expect("get root");
@@ -45,7 +46,7 @@ fn test_mmr(count: u32, proof_elem: Vec<u32>) {
 fn test_gen_new_root_from_proof(count: u32) {
     let store = MemStore::default();
     let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
-    let positions: Vec<u64> = (0u32..count)
+    let positions: Vec<Pos> = (0u32..count)
         .map(|i| mmr.push(NumberHash::from(i)).unwrap())
         .collect();
     let elem = count - 1;
@@ -60,7 +61,7 @@ fn test_gen_new_root_from_proof(count: u32) {
             vec![(pos, NumberHash::from(elem))],
             new_pos,
             NumberHash::from(new_elem),
-            leaf_index_to_mmr_size(new_elem.into()),
+            leaf_index_to_mmr_size(new_elem as Pos),
         )
         .unwrap();
     assert_eq!(calculated_root, root);
@@ -157,13 +158,13 @@ fn test_gen_proof_with_duplicate_leaves() {
 
 fn test_invalid_proof_verification(
     leaf_count: u32,
-    positions_to_verify: Vec<u64>,
+    positions_to_verify: Vec<Pos>,
     // positions of entries that should be tampered
     tampered_positions: Vec<usize>,
     // optionally handroll proof from these positions
-    handrolled_proof_positions: Option<Vec<u64>>,
+    handrolled_proof_positions: Option<Vec<Pos>>,
     // optionally handroll tampered proof from these positions
-    handrolled_tampered_proof_positions: Option<Vec<u64>>,
+    handrolled_tampered_proof_positions: Option<Vec<Pos>>,
 ) {
     use crate::{ancestry_proof::NodeMerkleProof, Merge};
     use std::fmt::{Debug, Formatter};
@@ -189,22 +190,23 @@ fn test_invalid_proof_verification(
 
     impl Merge for MyMerge {
         type Item = MyItem;
+        type Error = crate::Error;
         fn merge(lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item, crate::Error> {
-            return Ok(MyItem::Merged(Box::new(lhs.clone()), Box::new(rhs.clone())));
+            Ok(MyItem::Merged(Box::new(lhs.clone()), Box::new(rhs.clone())))
         }
     }
 
     // Let's build a simple MMR with the numbers 0 to 6
     let store = MemStore::default();
     let mut mmr = MemMMR::<_, MyMerge>::new(0, &store);
-    let mut positions: Vec<u64> = Vec::new();
+    let mut positions: Vec<Pos> = Vec::new();
     for i in 0u32..leaf_count {
         let pos = mmr.push(MyItem::Number(i)).unwrap();
         positions.push(pos);
     }
     let root = mmr.get_root().unwrap();
 
-    let entries_to_verify: Vec<(u64, MyItem)> = positions_to_verify
+    let entries_to_verify: Vec<(Pos, MyItem)> = positions_to_verify
         .iter()
         .map(|pos| (*pos, mmr.batch().get_elem(*pos).unwrap().unwrap()))
         .collect();
@@ -294,12 +296,12 @@ prop_compose! {
     }
 }
 
-fn nodes_subset(subset_index: u128, position_count: u8) -> Vec<u64> {
+fn nodes_subset(subset_index: u128, position_count: u8) -> Vec<Pos> {
     let mut positions = vec![];
 
     for index in 0..position_count {
         if (1 << index) & subset_index != 0 {
-            positions.push(index as u64)
+            positions.push(index as Pos)
         }
     }
 
@@ -314,7 +316,7 @@ proptest! {
     #[test]
     fn test_mmr_generic_proof_proptest(
         (leaves_count, (positions, tampered_node_position)) in (1..=MAX_LEAVES_COUNT)
-            .prop_flat_map(|leaves_count| {let mmr_size = leaf_index_to_mmr_size(leaves_count as u64 - 1);
+            .prop_flat_map(|leaves_count| {let mmr_size = leaf_index_to_mmr_size(leaves_count as Pos - 1);
                                            let subset_index = 1u128..1u128.shl(mmr_size as u8);
                                            (Just(leaves_count),
                                             (Just(mmr_size), subset_index).prop_flat_map(|(mmr_size, subset_index)| {
@@ -354,3 +356,168 @@ proptest! {
         test_gen_new_root_from_proof(count);
     }
 }
+
+#[test]
+fn test_verify_with_borrowed_proof_items() {
+    use crate::MerkleProofRef;
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().expect("get root");
+    let nodes = vec![
+        (positions[3], NumberHash::from(3)),
+        (5, mmr.batch().get_elem(5).unwrap().unwrap()),
+    ];
+    let proof = mmr
+        .gen_node_proof(nodes.iter().map(|(pos, _)| *pos).collect())
+        .expect("gen proof");
+
+    // e.g. items decoded into a buffer owned by someone else
+    let wire_items: Vec<(Pos, NumberHash)> = proof.proof_items().to_vec();
+    let proof_ref = MerkleProofRef::<_, MergeNumberHash>::new(proof.mmr_size(), &wire_items);
+    assert_eq!(proof_ref.calculate_root(nodes.clone()), Ok(root.clone()));
+    assert!(proof_ref.verify(root.clone(), nodes.clone()).unwrap());
+    assert!(MerkleProofRef::from(&proof)
+        .verify(root.clone(), nodes)
+        .unwrap());
+    assert!(!proof_ref
+        .verify(root.clone(), vec![(positions[3], NumberHash::from(4))])
+        .unwrap_or(false));
+    // there is nothing to verify without nodes
+    let peaks = mmr.peaks().unwrap();
+    assert_eq!(
+        MerkleProofRef::<_, MergeNumberHash>::new(mmr.mmr_size(), &peaks).calculate_root(vec![]),
+        Err(Error::CorruptedProof)
+    );
+}
+
+#[test]
+fn test_reject_conflicting_duplicate_nodes() {
+    use crate::NodeMerkleProof;
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().expect("get root");
+    let nodes = vec![(positions[3], NumberHash::from(3))];
+    let proof = mmr.gen_node_proof(vec![positions[3]]).expect("gen proof");
+    let items = proof.proof_items().to_vec();
+    let (sib_pos, sib_item) = items[0].clone();
+
+    // duplicates agreeing with each other are harmless
+    let mut repeated = items.clone();
+    repeated.push((sib_pos, sib_item.clone()));
+    let repeated = NodeMerkleProof::<_, MergeNumberHash>::new(proof.mmr_size(), repeated);
+    assert_eq!(repeated.verify(root.clone(), nodes.clone()), Ok(true));
+    let mut with_sibling = nodes.clone();
+    with_sibling.push((sib_pos, sib_item));
+    assert_eq!(proof.verify(root.clone(), with_sibling), Ok(true));
+
+    // conflicting ones are rejected, whichever comes first
+    let mut conflicting = items.clone();
+    conflicting.insert(0, (sib_pos, NumberHash::from(100)));
+    let conflicting = NodeMerkleProof::<_, MergeNumberHash>::new(proof.mmr_size(), conflicting);
+    assert_eq!(
+        conflicting.verify(root.clone(), nodes.clone()),
+        Err(Error::CorruptedProof)
+    );
+    let mut with_sibling = nodes.clone();
+    with_sibling.push((sib_pos, NumberHash::from(100)));
+    assert_eq!(
+        proof.verify(root.clone(), with_sibling),
+        Err(Error::CorruptedProof)
+    );
+    assert_eq!(
+        proof.verify(
+            root,
+            vec![
+                (positions[3], NumberHash::from(3)),
+                (positions[3], NumberHash::from(4))
+            ]
+        ),
+        Err(Error::CorruptedProof)
+    );
+}
+
+#[test]
+fn test_verify_with_node_policy() {
+    use crate::NodePolicy;
+
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..11)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().expect("get root");
+    let leaves = vec![
+        (positions[3], NumberHash::from(3)),
+        (positions[8], NumberHash::from(8)),
+    ];
+    let with_node = vec![
+        (positions[3], NumberHash::from(3)),
+        (13, mmr.batch().get_elem(13).unwrap().unwrap()),
+    ];
+
+    let proof = mmr
+        .gen_node_proof(leaves.iter().map(|(pos, _)| *pos).collect())
+        .expect("gen proof");
+    for policy in [NodePolicy::LeavesOnly, NodePolicy::AllowNodes] {
+        assert!(proof
+            .verify_with_policy(root.clone(), leaves.clone(), policy)
+            .unwrap());
+    }
+
+    let proof = mmr
+        .gen_node_proof(with_node.iter().map(|(pos, _)| *pos).collect())
+        .expect("gen proof");
+    assert!(proof
+        .verify_with_policy(root.clone(), with_node.clone(), NodePolicy::AllowNodes)
+        .unwrap());
+    assert_eq!(
+        proof.verify_with_policy(root, with_node, NodePolicy::LeavesOnly),
+        Err(Error::GenProofForInvalidLeaves)
+    );
+}
+
+#[test]
+fn test_expected_node_proof_size() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..20 {
+        mmr.push(NumberHash::from(i)).unwrap();
+        let mmr_size = mmr.mmr_size();
+        // every subset of the nodes up to 7 leaves, ancestors and descendants alike
+        let subsets: Vec<Vec<Pos>> = if mmr_size <= MAX_POS.into() {
+            (1u128..1 << mmr_size)
+                .map(|subset| nodes_subset(subset, mmr_size as u8))
+                .collect()
+        } else {
+            vec![
+                vec![0],
+                vec![mmr_size - 1],
+                vec![2, 6, 7, 14],
+                (0..mmr_size).step_by(3).collect(),
+            ]
+        };
+        for pos_list in subsets {
+            let proof = mmr.gen_node_proof(pos_list.clone()).unwrap();
+            assert_eq!(
+                expected_node_proof_size(mmr_size, pos_list),
+                Ok(proof.proof_items().len())
+            );
+        }
+        assert_eq!(
+            expected_node_proof_size(mmr_size, Vec::new()),
+            Err(Error::GenProofForInvalidNodes)
+        );
+        assert_eq!(
+            expected_node_proof_size(mmr_size, vec![0, mmr_size]),
+            Err(Error::PositionOutOfRange { pos: mmr_size })
+        );
+    }
+}
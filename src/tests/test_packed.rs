@@ -0,0 +1,178 @@
+use super::{new_blake2b, MergeNumberHash, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::{Error, Merge, MerkleProof, PackedItemProof, PackedMMR, PackedMerge, Pos, Result};
+
+impl PackedMerge for MergeNumberHash {
+    fn merge_packed(lhs: &NumberHash, rhs: &NumberHash) -> Result<NumberHash> {
+        let mut hasher = new_blake2b();
+        let mut hash = [0u8; 32];
+        // set the nodes within a leaf apart from the MMR nodes
+        hasher.update(&[1]);
+        hasher.update(&lhs.0);
+        hasher.update(&rhs.0);
+        hasher.finalize(&mut hash);
+        Ok(NumberHash(hash.to_vec().into()))
+    }
+}
+
+// packing fails on the item 13
+struct MergeRejecting13;
+
+impl Merge for MergeRejecting13 {
+    type Item = NumberHash;
+    type Error = Error;
+    fn merge(lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item> {
+        MergeNumberHash::merge(lhs, rhs)
+    }
+}
+
+impl PackedMerge for MergeRejecting13 {
+    fn merge_packed(lhs: &NumberHash, rhs: &NumberHash) -> Result<NumberHash> {
+        if *lhs == NumberHash::from(13) || *rhs == NumberHash::from(13) {
+            return Err(Error::StoreError("rejected item".into()));
+        }
+        MergeNumberHash::merge_packed(lhs, rhs)
+    }
+}
+
+#[test]
+fn test_packed_item_proofs() {
+    let store = MemStore::default();
+    let mut mmr = PackedMMR::<_, MergeNumberHash, _, 4>::new(MemMMR::new(0, &store)).unwrap();
+    let items: Vec<NumberHash> = (0u32..43).map(NumberHash::from).collect();
+    for (i, item) in items.iter().enumerate() {
+        assert_eq!(mmr.push_item(item.clone()), Ok(i as Pos));
+        if i == 39 {
+            mmr.commit().unwrap();
+        }
+    }
+    // 10 full chunks, the last 3 items pending
+    assert_eq!(mmr.leaves_count(), 10);
+    assert_eq!(mmr.items_count(), 43);
+    assert_eq!(mmr.pending_items(), &items[40..]);
+    assert_eq!(mmr.commit(), Err(Error::PendingItems { count: 3 }));
+
+    // each leaf is the binary tree of its chunk
+    let leaf = MergeNumberHash::merge_packed(
+        &MergeNumberHash::merge_packed(&items[4], &items[5]).unwrap(),
+        &MergeNumberHash::merge_packed(&items[6], &items[7]).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(mmr.batch().get_elem(1).unwrap(), Some(leaf));
+
+    let root = mmr.get_root().unwrap();
+    for item_index in [0usize, 5, 6, 23, 39] {
+        let chunk = &items[item_index / 4 * 4..][..4];
+        let proof = mmr.gen_item_proof(item_index as Pos, chunk).unwrap();
+        assert_eq!(proof.item_path().len(), 2);
+        assert!(proof
+            .verify(root.clone(), items[item_index].clone())
+            .unwrap());
+        assert!(!proof
+            .verify(root.clone(), items[item_index ^ 1].clone())
+            .unwrap());
+    }
+
+    // pending items, wrong chunks
+    assert_eq!(
+        mmr.gen_item_proof(41, &items[40..]).err(),
+        Some(Error::GenProofForInvalidLeaves)
+    );
+    assert_eq!(
+        mmr.gen_item_proof(5, &items[4..7]).err(),
+        Some(Error::GenProofForInvalidLeaves)
+    );
+    assert_eq!(
+        mmr.gen_item_proof(5, &items[8..12]).err(),
+        Some(Error::InconsistentStore { pos: 1 })
+    );
+
+    let (inner, pending) = mmr.into_inner();
+    assert_eq!(inner.leaves_count(), 10);
+    assert_eq!(pending, &items[40..]);
+}
+
+#[test]
+fn test_packed_item_proof_from_parts() {
+    let store = MemStore::default();
+    let mut mmr = PackedMMR::<_, MergeNumberHash, _, 4>::new(MemMMR::new(0, &store)).unwrap();
+    let items: Vec<NumberHash> = (0u32..20).map(NumberHash::from).collect();
+    for item in &items {
+        mmr.push_item(item.clone()).unwrap();
+    }
+    let root = mmr.get_root().unwrap();
+    let proof = mmr.gen_item_proof(9, &items[8..12]).unwrap();
+    let leaf_proof = || {
+        MerkleProof::new(
+            proof.leaf_proof().mmr_size(),
+            proof.leaf_proof().proof_items().to_vec(),
+        )
+    };
+
+    let rebuilt = PackedItemProof::<_, MergeNumberHash, 4>::new(
+        proof.item_index(),
+        proof.item_path().to_vec(),
+        leaf_proof(),
+    )
+    .unwrap();
+    assert_eq!(rebuilt.verify(root, items[9].clone()), Ok(true));
+
+    // the path must climb exactly the chunk, however long it is
+    for len in [0, 1, 3, 200] {
+        assert_eq!(
+            PackedItemProof::<_, MergeNumberHash, 4>::new(
+                9,
+                vec![items[0].clone(); len],
+                leaf_proof(),
+            )
+            .err(),
+            Some(Error::CorruptedProof)
+        );
+    }
+}
+
+#[test]
+fn test_packed_chunk_size() {
+    let store = MemStore::default();
+    assert_eq!(
+        PackedMMR::<_, MergeNumberHash, _, 3>::new(MemMMR::new(0, &store)).err(),
+        Some(Error::InvalidLeafCount { leaf_count: 3 })
+    );
+
+    // a single item per leaf is a plain MMR
+    let mut mmr = PackedMMR::<_, MergeNumberHash, _, 1>::new(MemMMR::new(0, &store)).unwrap();
+    for i in 0u32..5 {
+        mmr.push_item(NumberHash::from(i)).unwrap();
+    }
+    assert!(mmr.pending_items().is_empty());
+    let proof = mmr.gen_item_proof(3, &[NumberHash::from(3)]).unwrap();
+    assert!(proof.item_path().is_empty());
+    assert!(proof
+        .verify(mmr.get_root().unwrap(), NumberHash::from(3))
+        .unwrap());
+}
+
+#[test]
+fn test_failed_pack_drops_the_item() {
+    let store = MemStore::default();
+    let mut mmr = PackedMMR::<_, MergeRejecting13, _, 4>::new(MemMMR::new(0, &store)).unwrap();
+    for i in 0u32..3 {
+        mmr.push_item(NumberHash::from(i)).unwrap();
+    }
+    assert_eq!(
+        mmr.push_item(NumberHash::from(13)),
+        Err(Error::StoreError("rejected item".into()))
+    );
+    assert_eq!(mmr.pending_items().len(), 3);
+    assert_eq!(mmr.leaves_count(), 0);
+
+    // the chunk completes with the next item
+    assert_eq!(mmr.push_item(NumberHash::from(3)), Ok(3));
+    assert!(mmr.pending_items().is_empty());
+    assert_eq!(mmr.leaves_count(), 1);
+    let chunk: Vec<_> = (0u32..4).map(NumberHash::from).collect();
+    let proof = mmr.gen_item_proof(3, &chunk).unwrap();
+    assert!(proof
+        .verify(mmr.get_root().unwrap(), NumberHash::from(3))
+        .unwrap());
+}
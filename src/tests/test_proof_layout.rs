@@ -0,0 +1,115 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::{
+    helper::{iter_peaks, pos_height_in_tree},
+    util::{MemMMR, MemStore},
+    Error, NodeMerkleProof, Pos, ProofLayout,
+};
+use proptest::prelude::*;
+
+fn test_layout_roundtrip(count: u32, proof_elem: Vec<u32>) {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..count)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let root = mmr.get_root().expect("get root");
+    let pos_list: Vec<Pos> = proof_elem
+        .iter()
+        .map(|elem| positions[*elem as usize])
+        .collect();
+    let proof = mmr.gen_node_proof(pos_list.clone()).expect("gen proof");
+
+    let (layout, hashes) = proof.to_layout(&pos_list).expect("to layout");
+    assert_eq!(layout.mmr_size(), mmr.mmr_size());
+    // one bit per candidate: the peaks and the siblings along the paths to them
+    let peaks: Vec<Pos> = iter_peaks(mmr.mmr_size()).collect();
+    let max_candidates = peaks.len()
+        + pos_list
+            .iter()
+            .map(|pos| {
+                let peak_pos = peaks.iter().find(|peak_pos| *peak_pos >= pos).unwrap();
+                (pos_height_in_tree(*peak_pos) - pos_height_in_tree(*pos)) as usize
+            })
+            .sum::<usize>();
+    assert!(layout.bitmap().len() <= max_candidates.div_ceil(8));
+    let decoded =
+        NodeMerkleProof::<_, MergeNumberHash>::from_layout(&layout, hashes.clone()).unwrap();
+    assert_eq!(decoded.proof_items(), proof.proof_items());
+    let nodes = proof_elem
+        .iter()
+        .map(|elem| (positions[*elem as usize], NumberHash::from(*elem)))
+        .collect();
+    assert!(decoded.verify(root, nodes).unwrap());
+
+    // hashes must match the bitmap exactly
+    let mut extra_hashes = hashes;
+    extra_hashes.push(NumberHash::from(0));
+    assert_eq!(
+        NodeMerkleProof::<_, MergeNumberHash>::from_layout(&layout, extra_hashes).err(),
+        Some(Error::CorruptedProof)
+    );
+}
+
+#[test]
+fn test_layout_single_leaf() {
+    test_layout_roundtrip(11, vec![5]);
+}
+
+#[test]
+fn test_layout_multiple_leaves() {
+    test_layout_roundtrip(100, vec![0, 3, 17, 63, 64, 99]);
+}
+
+#[test]
+fn test_layout_shrinks_sparse_proofs() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let positions: Vec<Pos> = (0u32..1000)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    let pos_list: Vec<Pos> = (0..10).map(|i| positions[i * 97 + 3]).collect();
+    let proof = mmr.gen_node_proof(pos_list.clone()).unwrap();
+    let (layout, hashes) = proof.to_layout(&pos_list).unwrap();
+
+    // positions as 8 byte integers next to 32 byte hashes
+    let items = proof.proof_items().len();
+    let hash_len = hashes[0].0.len();
+    let with_positions = items * (8 + hash_len);
+    let with_layout = items * hash_len + layout.bitmap().len();
+    // the bitmap takes a fraction of the positions it replaces
+    assert!(layout.bitmap().len() * 4 <= items * 8);
+    // saving most of the fifth of the proof the positions take up
+    assert!(with_layout * 100 <= with_positions * 85);
+}
+
+#[test]
+fn test_layout_rejects_bad_padding() {
+    let layout = ProofLayout::new(19, vec![0], vec![0b1000_0000]);
+    assert_eq!(
+        NodeMerkleProof::<_, MergeNumberHash>::from_layout(&layout, Vec::new()).err(),
+        Some(Error::CorruptedProof)
+    );
+}
+
+prop_compose! {
+    fn count_elem(count: u32)
+                (elem in 0..count)
+                -> (u32, u32) {
+                    (count, elem)
+    }
+}
+
+proptest! {
+    #[test]
+    fn test_layout_random((count, elem) in (1u32..500).prop_flat_map(count_elem)) {
+        test_layout_roundtrip(count, vec![elem]);
+    }
+
+    #[test]
+    fn test_layout_random_leaves(
+        (count, elems) in (1u32..500)
+            .prop_flat_map(|count| (Just(count), prop::collection::vec(0..count, 1..20)))
+    ) {
+        test_layout_roundtrip(count, elems);
+    }
+}
@@ -0,0 +1,132 @@
+use super::reference::ReferenceMmr;
+use super::{new_blake2b, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::{leaf_index_to_mmr_size, MMRStoreReadOps, Merge, Pos, Result};
+use proptest::prelude::*;
+
+/// Commits to the position and tells peaks apart, so misplaced nodes change the root.
+struct MergePositioned;
+
+impl MergePositioned {
+    fn hash(parts: &[&[u8]]) -> NumberHash {
+        let mut hasher = new_blake2b();
+        let mut hash = [0u8; 32];
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize(&mut hash);
+        NumberHash(hash.to_vec().into())
+    }
+}
+
+impl Merge for MergePositioned {
+    type Item = NumberHash;
+    type Error = crate::Error;
+    fn merge(lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item> {
+        Ok(Self::hash(&[&lhs.0, &rhs.0]))
+    }
+    fn merge_with_pos(parent_pos: Pos, lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item> {
+        Ok(Self::hash(&[&parent_pos.to_le_bytes(), &lhs.0, &rhs.0]))
+    }
+    fn merge_peaks(peak1: &Self::Item, peak2: &Self::Item) -> Result<Self::Item> {
+        Ok(Self::hash(&[b"peaks", &peak1.0, &peak2.0]))
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Op {
+    Push(u32),
+    Commit,
+    /// Rewind to the given fraction, in 256ths, of the leaves.
+    Rewind(u8),
+    /// Prove the leaves at the given indices, modulo the leaves count.
+    Prove(Vec<u16>),
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        6 => any::<u32>().prop_map(Op::Push),
+        1 => Just(Op::Commit),
+        1 => any::<u8>().prop_map(Op::Rewind),
+        2 => prop::collection::vec(any::<u16>(), 1..6).prop_map(Op::Prove),
+    ]
+}
+
+fn check_prove(
+    mmr: &MemMMR<'_, NumberHash, MergePositioned>,
+    reference: &ReferenceMmr<NumberHash, MergePositioned>,
+    leaves: &[NumberHash],
+    indices: &[u16],
+) -> std::result::Result<(), TestCaseError> {
+    let root = reference.root().unwrap().expect("not empty");
+    let proven: Vec<(Pos, NumberHash)> = indices
+        .iter()
+        .map(|i| *i as usize % leaves.len())
+        .map(|i| (reference.leaf_pos(i), leaves[i].clone()))
+        .collect();
+    let proof = mmr
+        .gen_proof(proven.iter().map(|(pos, _)| *pos).collect())
+        .unwrap();
+    prop_assert_eq!(proof.verify(root.clone(), proven.clone()), Ok(true));
+
+    // neither a tampered leaf nor a tampered item may verify
+    let mut tampered_leaves = proven.clone();
+    tampered_leaves[0].1 = NumberHash::default();
+    prop_assert_ne!(proof.verify(root.clone(), tampered_leaves), Ok(true));
+    if !proof.proof_items().is_empty() {
+        let mut items = proof.proof_items().to_vec();
+        items[0] = NumberHash::default();
+        let tampered_proof = crate::MerkleProof::<_, MergePositioned>::new(proof.mmr_size(), items);
+        prop_assert_ne!(tampered_proof.verify(root, proven), Ok(true));
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn test_against_reference(ops in prop::collection::vec(op(), 1..80)) {
+        let store = MemStore::default();
+        let mut mmr = MemMMR::<_, MergePositioned>::new(0, &store);
+        let mut reference = ReferenceMmr::<NumberHash, MergePositioned>::new();
+        let mut leaves: Vec<NumberHash> = Vec::new();
+
+        for op in ops {
+            match op {
+                Op::Push(leaf) => {
+                    let leaf = NumberHash::from(leaf);
+                    let pos = mmr.push(leaf.clone()).unwrap();
+                    reference.push(leaf.clone());
+                    leaves.push(leaf);
+                    prop_assert_eq!(pos, reference.leaf_pos(leaves.len() - 1));
+                }
+                Op::Commit => mmr.commit().unwrap(),
+                Op::Rewind(fraction) => {
+                    let leaves_count = leaves.len() * fraction as usize / 256;
+                    let mmr_size = match leaves_count {
+                        0 => 0,
+                        count => leaf_index_to_mmr_size(count as Pos - 1),
+                    };
+                    mmr.rewind(mmr_size).unwrap();
+                    reference.rewind(leaves_count);
+                    leaves.truncate(leaves_count);
+                }
+                Op::Prove(indices) => {
+                    if !leaves.is_empty() {
+                        check_prove(&mmr, &reference, &leaves, &indices)?;
+                    }
+                }
+            }
+
+            prop_assert_eq!(mmr.mmr_size(), reference.mmr_size());
+            prop_assert_eq!(mmr.leaves_count() as usize, reference.leaves_count());
+            prop_assert_eq!(mmr.get_root().ok(), reference.root().unwrap());
+            prop_assert_eq!(mmr.peaks().unwrap(), reference.peaks().unwrap());
+        }
+
+        // every node ends up in the store as the reference lays it out
+        mmr.commit().unwrap();
+        for (pos, node) in reference.nodes().unwrap().into_iter().enumerate() {
+            prop_assert_eq!((&store).get_elem(pos as Pos).unwrap(), Some(node));
+        }
+    }
+}
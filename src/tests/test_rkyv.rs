@@ -0,0 +1,88 @@
+use super::new_blake2b;
+use crate::types::ArchivedLeafIndex;
+use crate::util::{MemMMR, MemStore};
+use crate::{leaf_index_to_pos, ArchivedMerkleProof, Error, LeafIndex, Merge, Result};
+use rkyv::rancor;
+
+type Hash = [u8; 32];
+
+struct MergeHash;
+
+impl Merge for MergeHash {
+    type Item = Hash;
+    type Error = Error;
+
+    fn merge(lhs: &Hash, rhs: &Hash) -> Result<Hash> {
+        let mut hasher = new_blake2b();
+        let mut hash = [0u8; 32];
+        hasher.update(lhs);
+        hasher.update(rhs);
+        hasher.finalize(&mut hash);
+        Ok(hash)
+    }
+}
+
+fn leaf(i: u8) -> Hash {
+    [i; 32]
+}
+
+#[test]
+fn test_archived_proof() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeHash>::new(0, &store);
+    for i in 0u8..11 {
+        mmr.push(leaf(i)).unwrap();
+    }
+    let root = mmr.get_root().unwrap();
+    let positions = vec![leaf_index_to_pos(2), leaf_index_to_pos(9)];
+    let proof = mmr.gen_proof(positions.clone()).unwrap();
+    let bytes = rkyv::to_bytes::<rancor::Error>(&proof).unwrap();
+
+    let archived =
+        rkyv::access::<ArchivedMerkleProof<Hash, MergeHash>, rancor::Error>(&bytes).unwrap();
+    assert_eq!(archived.mmr_size(), proof.mmr_size());
+    assert_eq!(archived.proof_items(), proof.proof_items());
+    let leaves = vec![(positions[0], leaf(2)), (positions[1], leaf(9))];
+    assert_eq!(archived.verify(root, leaves.clone()), Ok(true));
+    assert_eq!(archived.verify(leaf(0), leaves), Ok(false));
+    assert_eq!(
+        archived.verify(root, vec![(positions[0], leaf(3)), (positions[1], leaf(9))]),
+        Ok(false)
+    );
+
+    let deserialized = archived.to_proof().unwrap();
+    assert_eq!(deserialized.proof_items(), proof.proof_items());
+    assert_eq!(deserialized.mmr_size(), proof.mmr_size());
+
+    // truncated bytes fail the check
+    assert!(
+        rkyv::access::<ArchivedMerkleProof<Hash, MergeHash>, rancor::Error>(
+            &bytes[..bytes.len() - 1]
+        )
+        .is_err()
+    );
+}
+
+#[test]
+fn test_archived_proof_with_invalid_size() {
+    let proof = crate::MerkleProof::<Hash, MergeHash>::new(5, vec![leaf(1)]);
+    let bytes = rkyv::to_bytes::<rancor::Error>(&proof).unwrap();
+    let archived =
+        rkyv::access::<ArchivedMerkleProof<Hash, MergeHash>, rancor::Error>(&bytes).unwrap();
+    assert_eq!(
+        archived.to_proof().err(),
+        Some(Error::InvalidMMRSize { mmr_size: 5 })
+    );
+}
+
+#[test]
+fn test_archived_leaf_index() {
+    let leaf_index = LeafIndex::new(42);
+    let bytes = rkyv::to_bytes::<rancor::Error>(&leaf_index).unwrap();
+    let archived = rkyv::access::<ArchivedLeafIndex, rancor::Error>(&bytes).unwrap();
+    assert_eq!(archived.get(), 42);
+    assert_eq!(
+        rkyv::deserialize::<LeafIndex, rancor::Error>(archived).unwrap(),
+        leaf_index
+    );
+}
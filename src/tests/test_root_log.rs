@@ -0,0 +1,88 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::{leaf_index_to_mmr_size, Error, MMRStoreReadOps, MMRStoreWriteOps, Pos, RootLog, MMR};
+
+#[test]
+fn test_log_every_push() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    mmr.set_root_log(Some(RootLog::EveryPush)).unwrap();
+    let mut roots = Vec::new();
+    for i in 0u32..20 {
+        mmr.push(NumberHash::from(i)).unwrap();
+        roots.push((mmr.mmr_size(), mmr.get_root().unwrap()));
+    }
+    for (mmr_size, root) in &roots {
+        assert_eq!(mmr.root_at_size(*mmr_size), Ok(Some(root.clone())));
+    }
+    // sizes between leaves and beyond the MMR have no root
+    assert_eq!(mmr.root_at_size(2), Ok(None));
+    assert_eq!(mmr.root_at_size(mmr.mmr_size() + 1), Ok(None));
+
+    mmr.commit().unwrap();
+    let reopened = MemMMR::<_, MergeNumberHash>::open(&store).unwrap();
+    for (mmr_size, root) in &roots {
+        assert_eq!(reopened.root_at_size(*mmr_size), Ok(Some(root.clone())));
+    }
+
+    // rewinding forgets the roots of the dropped sizes
+    let prev_mmr_size = leaf_index_to_mmr_size(9);
+    mmr.rewind(prev_mmr_size).unwrap();
+    assert_eq!((&store).get_root_at(roots[10].0), Ok(None));
+    assert_eq!(
+        mmr.root_at_size(prev_mmr_size),
+        Ok(Some(roots[9].1.clone()))
+    );
+}
+
+#[test]
+fn test_log_every_commit() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    mmr.set_root_log(Some(RootLog::EveryCommit)).unwrap();
+    let mut committed = Vec::new();
+    for batch in 0u32..4 {
+        for i in 0..5 {
+            mmr.push(NumberHash::from(batch * 5 + i)).unwrap();
+        }
+        // the root is only calculated at commit time, yet visible before the commit
+        assert_eq!(mmr.batch().get_root_at(mmr.mmr_size()), Ok(None));
+        let root = mmr.get_root().unwrap();
+        assert_eq!(mmr.root_at_size(mmr.mmr_size()), Ok(Some(root.clone())));
+        mmr.commit().unwrap();
+        committed.push((mmr.mmr_size(), root));
+    }
+    for (mmr_size, root) in &committed {
+        assert_eq!((&store).get_root_at(*mmr_size), Ok(Some(root.clone())));
+    }
+    // the roots between commits aren't logged
+    assert_eq!(mmr.root_at_size(leaf_index_to_mmr_size(3)), Ok(None));
+}
+
+#[test]
+fn test_log_without_store_support() {
+    struct NoRootLogStore(MemStore<NumberHash>);
+
+    impl MMRStoreReadOps<NumberHash> for &NoRootLogStore {
+        fn get_elem(&self, pos: Pos) -> crate::Result<Option<NumberHash>> {
+            (&self.0).get_elem(pos)
+        }
+    }
+
+    impl MMRStoreWriteOps<NumberHash> for &NoRootLogStore {
+        fn append(&mut self, pos: Pos, elems: Vec<NumberHash>) -> crate::Result<()> {
+            (&self.0).append(pos, elems)
+        }
+    }
+
+    let store = NoRootLogStore(MemStore::default());
+    let mut mmr = MMR::<_, MergeNumberHash, _>::new(0, &store);
+    // rejected upfront, rather than failing the commit
+    assert_eq!(
+        mmr.set_root_log(Some(RootLog::EveryPush)),
+        Err(Error::StoreError("root log is not supported".to_string()))
+    );
+    assert_eq!(mmr.root_log(), None);
+    mmr.push(NumberHash::from(0)).unwrap();
+    mmr.commit().unwrap();
+}
@@ -0,0 +1,203 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::{
+    leaf_index_to_mmr_size, leaf_index_to_pos, AncestryProof, Error, MerkleProof, NodeMerkleProof,
+    PackedItemProof, PackedMMR, UpdateProof,
+};
+use codec::{Compact, Decode, Encode};
+
+impl Encode for NumberHash {
+    fn encode_to<W: codec::Output + ?Sized>(&self, dest: &mut W) {
+        self.0.to_vec().encode_to(dest);
+    }
+}
+
+impl Decode for NumberHash {
+    fn decode<I: codec::Input>(input: &mut I) -> Result<Self, codec::Error> {
+        Ok(NumberHash(Vec::<u8>::decode(input)?.into()))
+    }
+}
+
+fn build_mmr(store: &MemStore<NumberHash>, count: u32) -> MemMMR<'_, NumberHash, MergeNumberHash> {
+    let mut mmr = MemMMR::new(0, store);
+    for i in 0..count {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    mmr
+}
+
+#[test]
+fn test_merkle_proof_roundtrip() {
+    let store = MemStore::default();
+    let mmr = build_mmr(&store, 300);
+    let root = mmr.get_root().unwrap();
+    let positions = vec![leaf_index_to_pos(3), leaf_index_to_pos(150)];
+    let proof = mmr.gen_proof(positions.clone()).unwrap();
+
+    let encoded = proof.encode();
+    let decoded = MerkleProof::<NumberHash, MergeNumberHash>::decode(&mut &encoded[..]).unwrap();
+    assert_eq!(decoded.mmr_size(), proof.mmr_size());
+    assert_eq!(decoded.proof_items(), proof.proof_items());
+    let leaves = positions
+        .iter()
+        .map(|pos| (*pos, mmr.batch().get_elem(*pos).unwrap().unwrap()))
+        .collect();
+    assert!(decoded.verify(root, leaves).unwrap());
+}
+
+#[test]
+fn test_merkle_proof_format_version() {
+    type Proof = MerkleProof<NumberHash, MergeNumberHash>;
+    let proof = Proof::new(3, vec![NumberHash::from(1)]);
+    let encoded = proof.encode();
+    assert_eq!(encoded[0], Proof::FORMAT_VERSION);
+    assert_eq!(
+        Proof::from_encoded(&encoded).unwrap().proof_items(),
+        proof.proof_items()
+    );
+
+    let mut unknown_version = encoded.clone();
+    unknown_version[0] = Proof::FORMAT_VERSION + 1;
+    assert!(Proof::decode(&mut &unknown_version[..]).is_err());
+    assert_eq!(
+        Proof::from_encoded(&unknown_version).err(),
+        Some(Error::UnsupportedProofVersion {
+            version: Proof::FORMAT_VERSION + 1
+        })
+    );
+    assert_eq!(
+        Proof::from_encoded(&encoded[..encoded.len() - 1]).err(),
+        Some(Error::CorruptedProof)
+    );
+    let mut trailing = encoded;
+    trailing.push(0);
+    assert_eq!(
+        Proof::from_encoded(&trailing).err(),
+        Some(Error::CorruptedProof)
+    );
+    assert_eq!(Proof::from_encoded(&[]).err(), Some(Error::CorruptedProof));
+}
+
+#[test]
+fn test_node_proof_positions_are_compact() {
+    // a mountain of 2^30 leaves
+    let proof = NodeMerkleProof::<NumberHash, MergeNumberHash>::new(
+        (1 << 31) - 1,
+        vec![(1, NumberHash::from(1)), (1 << 30, NumberHash::from(2))],
+    );
+    let encoded = proof.encode();
+    let mut expected = Compact((1u64 << 31) - 1).encode();
+    expected.extend(Compact(2u32).encode());
+    expected.extend(Compact(1u64).encode());
+    expected.extend(NumberHash::from(1).encode());
+    expected.extend(Compact(1u64 << 30).encode());
+    expected.extend(NumberHash::from(2).encode());
+    assert_eq!(encoded, expected);
+
+    let decoded =
+        NodeMerkleProof::<NumberHash, MergeNumberHash>::decode(&mut &encoded[..]).unwrap();
+    assert_eq!(decoded.proof_items(), proof.proof_items());
+}
+
+#[test]
+fn test_decode_rejects_invalid_proofs() {
+    type Node = NodeMerkleProof<NumberHash, MergeNumberHash>;
+    let item = NumberHash::from(1);
+    for proof in [
+        Node::new(5, vec![(1, item.clone())]),
+        Node::new(4, vec![(1, item.clone()), (4, item.clone())]),
+        Node::new(4, vec![(2, item.clone()), (1, item.clone())]),
+        Node::new(4, vec![(1, item.clone()), (1, item.clone())]),
+    ] {
+        assert!(Node::decode(&mut &proof.encode()[..]).is_err());
+    }
+    assert!(Node::decode(&mut &Node::new(4, vec![(1, item.clone())]).encode()[..]).is_ok());
+
+    type Proof = MerkleProof<NumberHash, MergeNumberHash>;
+    assert_eq!(
+        Proof::from_encoded(&Proof::new(5, vec![item.clone()]).encode()).err(),
+        Some(Error::CorruptedProof)
+    );
+    assert_eq!(
+        Proof::from_encoded(&Proof::new(1, vec![item]).encode()).err(),
+        Some(Error::CorruptedProof)
+    );
+}
+
+#[test]
+fn test_ancestry_proof_roundtrip() {
+    let store = MemStore::default();
+    let mmr = build_mmr(&store, 100);
+    let root = mmr.get_root().unwrap();
+    let prev_mmr_size = leaf_index_to_mmr_size(40);
+    let (_, prev_root) = mmr.get_ancestor_peaks_and_root(prev_mmr_size).unwrap();
+    let proof = mmr.gen_ancestry_proof(prev_mmr_size).unwrap();
+
+    let encoded = proof.encode();
+    let decoded = AncestryProof::<NumberHash, MergeNumberHash>::decode(&mut &encoded[..]).unwrap();
+    assert_eq!(decoded.prev_mmr_size(), prev_mmr_size);
+    assert_eq!(decoded.prev_peaks(), proof.prev_peaks());
+    assert!(decoded.verify_ancestor(root, prev_root).unwrap());
+}
+
+#[test]
+fn test_update_proof_roundtrip() {
+    let store = MemStore::default();
+    let mmr = build_mmr(&store, 100);
+    let prev_mmr_size = leaf_index_to_mmr_size(40);
+    let (_, prev_root) = mmr.get_ancestor_peaks_and_root(prev_mmr_size).unwrap();
+    let proof = mmr.gen_update_proof(prev_mmr_size).unwrap();
+
+    let encoded = proof.encode();
+    let decoded = UpdateProof::<NumberHash, MergeNumberHash>::decode(&mut &encoded[..]).unwrap();
+    assert_eq!(decoded.prev_mmr_size(), prev_mmr_size);
+    assert_eq!(decoded.mmr_size(), mmr.mmr_size());
+    assert_eq!(decoded.prev_peaks(), proof.prev_peaks());
+    let leaves = (41..100u32).map(NumberHash::from).collect();
+    assert!(decoded
+        .verify(prev_root, mmr.get_root().unwrap(), leaves)
+        .unwrap());
+    // the sizes are checked on decoding
+    let mut invalid = Compact(2u64).encode();
+    invalid.extend_from_slice(&encoded[Compact(prev_mmr_size).encode().len()..]);
+    assert!(UpdateProof::<NumberHash, MergeNumberHash>::decode(&mut &invalid[..]).is_err());
+}
+
+#[test]
+fn test_packed_item_proof_roundtrip() {
+    let store = MemStore::default();
+    let mut mmr = PackedMMR::<_, MergeNumberHash, _, 4>::new(MemMMR::new(0, &store)).unwrap();
+    let items: Vec<NumberHash> = (0u32..20).map(NumberHash::from).collect();
+    for item in &items {
+        mmr.push_item(item.clone()).unwrap();
+    }
+    let root = mmr.get_root().unwrap();
+    let proof = mmr.gen_item_proof(9, &items[8..12]).unwrap();
+
+    let encoded = proof.encode();
+    let decoded =
+        PackedItemProof::<NumberHash, MergeNumberHash, 4>::decode(&mut &encoded[..]).unwrap();
+    assert_eq!(decoded.item_index(), 9);
+    assert_eq!(decoded.item_path(), proof.item_path());
+    assert_eq!(decoded.verify(root, items[9].clone()), Ok(true));
+    // a chunk of 8 items takes another sibling
+    assert!(PackedItemProof::<NumberHash, MergeNumberHash, 8>::decode(&mut &encoded[..]).is_err());
+}
+
+#[test]
+fn test_decode_rejects_malformed_input() {
+    let store = MemStore::default();
+    let mmr = build_mmr(&store, 100);
+    let prev_mmr_size = leaf_index_to_mmr_size(40);
+    let encoded = mmr.gen_ancestry_proof(prev_mmr_size).unwrap().encode();
+
+    // truncated input
+    assert!(AncestryProof::<NumberHash, MergeNumberHash>::decode(
+        &mut &encoded[..encoded.len() - 1]
+    )
+    .is_err());
+    // an ancestor size that isn't a valid mmr size
+    let mut invalid = Compact(2u64).encode();
+    invalid.extend_from_slice(&encoded[Compact(prev_mmr_size).encode().len()..]);
+    assert!(AncestryProof::<NumberHash, MergeNumberHash>::decode(&mut &invalid[..]).is_err());
+}
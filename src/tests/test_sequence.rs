@@ -42,6 +42,7 @@ impl NumberRange {
 
 impl Merge for MergeNumberRange {
     type Item = NumberRange;
+    type Error = crate::Error;
     fn merge(lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item> {
         Ok(Self::Item {
             start: lhs.start,
@@ -0,0 +1,108 @@
+use super::new_blake2b;
+use crate::snapshot::{SNAPSHOT_MAGIC, SNAPSHOT_VERSION};
+use crate::util::{MemMMR, MemStore};
+use crate::{leaf_index_to_pos, Error, FixedHash, MMRStoreReadOps, Merge, Pos, Result};
+
+type Hash = FixedHash<32>;
+
+struct MergeFixed;
+
+impl Merge for MergeFixed {
+    type Item = Hash;
+    type Error = Error;
+    fn merge(lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item> {
+        let mut hasher = new_blake2b();
+        let mut hash = [0u8; 32];
+        hasher.update(lhs.as_ref());
+        hasher.update(rhs.as_ref());
+        hasher.finalize(&mut hash);
+        Ok(hash.into())
+    }
+}
+
+fn leaf(i: u32) -> Hash {
+    let mut hash = [0u8; 32];
+    hash[..4].copy_from_slice(&i.to_le_bytes());
+    hash.into()
+}
+
+fn export(store: &MemStore<Hash>, count: u32) -> (MemMMR<'_, Hash, MergeFixed>, Vec<u8>) {
+    let mut mmr = MemMMR::new(0, store);
+    for i in 0..count {
+        mmr.push(leaf(i)).unwrap();
+    }
+    let mut snapshot = Vec::new();
+    mmr.export_snapshot(&mut snapshot).unwrap();
+    (mmr, snapshot)
+}
+
+#[test]
+fn test_snapshot_roundtrip() {
+    let store = MemStore::default();
+    let (mmr, snapshot) = export(&store, 2000);
+    assert_eq!(&snapshot[..4], &SNAPSHOT_MAGIC);
+    assert_eq!(snapshot[4], SNAPSHOT_VERSION);
+    assert_eq!(snapshot.len() as Pos, 13 + 36 * (mmr.mmr_size() + 1));
+
+    let imported_store = MemStore::default();
+    let mut imported =
+        MemMMR::<_, MergeFixed>::import_snapshot(&snapshot[..], &imported_store).unwrap();
+    assert_eq!(imported.mmr_size(), mmr.mmr_size());
+    assert_eq!(imported.get_root().unwrap(), mmr.get_root().unwrap());
+    assert_eq!((&imported_store).mmr_size(), Ok(Some(mmr.mmr_size())));
+
+    // the imported MMR can prove old leaves and keep growing
+    let pos = leaf_index_to_pos(1234);
+    let proof = imported.gen_proof(vec![pos]).unwrap();
+    assert!(proof
+        .verify(imported.get_root().unwrap(), vec![(pos, leaf(1234))])
+        .unwrap());
+    imported.push(leaf(2000)).unwrap();
+    let store = MemStore::default();
+    let (expected, _) = export(&store, 2001);
+    assert_eq!(imported.get_root().unwrap(), expected.get_root().unwrap());
+}
+
+#[test]
+fn test_export_empty_mmr() {
+    let store = MemStore::default();
+    let mmr = MemMMR::<Hash, MergeFixed>::new(0, &store);
+    assert_eq!(
+        mmr.export_snapshot(Vec::new()).err(),
+        Some(Error::GetRootOnEmpty)
+    );
+}
+
+#[test]
+fn test_import_corrupted_snapshot() {
+    let store = MemStore::default();
+    let (_, snapshot) = export(&store, 100);
+    let import = |snapshot: &[u8]| {
+        let store = MemStore::default();
+        let result = MemMMR::<_, MergeFixed>::import_snapshot(snapshot, &store).map(|_| ());
+        // nothing is written on failure
+        if result.is_err() {
+            assert!((&store).get_elem(0).unwrap().is_none());
+        }
+        result
+    };
+    assert_eq!(import(&snapshot), Ok(()));
+
+    // every flipped bit past the magic is detected
+    for index in 5..snapshot.len() {
+        let mut corrupted = snapshot.clone();
+        corrupted[index] ^= 1;
+        assert!(import(&corrupted).is_err(), "flipped byte {}", index);
+    }
+
+    let mut unknown_version = snapshot.clone();
+    unknown_version[4] = SNAPSHOT_VERSION + 1;
+    assert_eq!(import(&unknown_version), Err(Error::CorruptedSnapshot));
+    assert_eq!(
+        import(&snapshot[..snapshot.len() - 1]),
+        Err(Error::CorruptedSnapshot)
+    );
+    let mut trailing = snapshot;
+    trailing.push(0);
+    assert_eq!(import(&trailing), Err(Error::CorruptedSnapshot));
+}
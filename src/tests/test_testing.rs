@@ -0,0 +1,35 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::helper::{is_valid_mmr_size, pos_height_in_tree};
+use crate::testing::{corrupted_proof, leaf_subset, mmr_size, valid_proof};
+use crate::Pos;
+use proptest::prelude::*;
+
+fn leaf() -> impl Strategy<Value = NumberHash> + Clone {
+    any::<u32>().prop_map(NumberHash::from)
+}
+
+proptest! {
+    #[test]
+    fn test_mmr_size_strategy(mmr_size in mmr_size(1000)) {
+        prop_assert!(mmr_size > 0 && is_valid_mmr_size(mmr_size));
+    }
+
+    #[test]
+    fn test_leaf_subset_strategy(positions in (1 as Pos..1000).prop_flat_map(leaf_subset)) {
+        prop_assert!(!positions.is_empty());
+        prop_assert!(positions.windows(2).all(|w| w[0] < w[1]));
+        prop_assert!(positions.iter().all(|pos| pos_height_in_tree(*pos) == 0));
+    }
+
+    #[test]
+    fn test_valid_proof_strategy(case in valid_proof::<_, MergeNumberHash, _>(leaf(), 300)) {
+        prop_assert_eq!(case.verify(), Ok(true));
+    }
+
+    #[test]
+    fn test_corrupted_proof_strategy(
+        case in corrupted_proof::<_, MergeNumberHash, _>(leaf(), 300)
+    ) {
+        prop_assert!(!case.verify().unwrap_or(false));
+    }
+}
@@ -0,0 +1,89 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::{
+    leaf_index_to_pos, Error, LivenessProof, Merge, MerkleProof, Pos, Result, TombstoneMerge,
+};
+use core::convert::TryInto;
+
+const TOMBSTONE_TAG: &[u8] = b"tomb";
+
+struct MergeTombstone;
+
+impl Merge for MergeTombstone {
+    type Item = NumberHash;
+    type Error = Error;
+    fn merge(lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item> {
+        MergeNumberHash::merge(lhs, rhs)
+    }
+}
+
+impl TombstoneMerge for MergeTombstone {
+    fn tombstone(leaf_index: Pos) -> Result<Self::Item> {
+        let mut bytes = TOMBSTONE_TAG.to_vec();
+        bytes.extend_from_slice(&leaf_index.to_le_bytes());
+        Ok(NumberHash(bytes.into()))
+    }
+
+    fn tombstoned(item: &Self::Item) -> Option<Pos> {
+        let index = item.0.strip_prefix(TOMBSTONE_TAG)?;
+        Some(Pos::from_le_bytes(index.try_into().ok()?))
+    }
+}
+
+#[test]
+fn test_liveness_proof() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeTombstone>::new(0, &store);
+    for i in 0u32..10 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    mmr.mark_deleted(3).unwrap();
+    for i in 10u32..13 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    let root = mmr.get_root().unwrap();
+
+    let proof = mmr.gen_liveness_proof(5).unwrap().unwrap();
+    assert_eq!(proof.leaf_index(), 5);
+    assert_eq!(proof.later_leaves().len(), 8);
+    assert_eq!(proof.verify(root.clone(), NumberHash::from(5)), Ok(true));
+    assert_eq!(proof.verify(root.clone(), NumberHash::from(6)), Ok(false));
+    let last_proof = mmr.gen_liveness_proof(13).unwrap().unwrap();
+    assert_eq!(
+        last_proof.verify(root.clone(), NumberHash::from(12)),
+        Ok(true)
+    );
+
+    // the deleted leaf has no liveness proof, and a forged one lists its tombstone
+    assert!(mmr.gen_liveness_proof(3).unwrap().is_none());
+    let later_leaves: Vec<_> = (4u32..10)
+        .map(NumberHash::from)
+        .chain(Some(MergeTombstone::tombstone(3).unwrap()))
+        .chain((10u32..13).map(NumberHash::from))
+        .collect();
+    let forged = LivenessProof::new(3, later_leaves, proof_of(&mmr, 3));
+    assert_eq!(forged.verify(root.clone(), NumberHash::from(3)), Ok(false));
+
+    // leaving out the later leaves is no proof of liveness
+    let truncated = LivenessProof::new(3, Vec::new(), proof_of(&mmr, 3));
+    assert_eq!(
+        truncated.verify(root, NumberHash::from(3)),
+        Err(Error::CorruptedProof)
+    );
+
+    assert_eq!(
+        mmr.gen_liveness_proof(14).err(),
+        Some(Error::GenProofForInvalidLeaves)
+    );
+    assert_eq!(mmr.mark_deleted(14), Err(Error::GenProofForInvalidLeaves));
+}
+
+fn proof_of(
+    mmr: &MemMMR<NumberHash, MergeTombstone>,
+    leaf_index: Pos,
+) -> MerkleProof<NumberHash, MergeTombstone> {
+    let pos_list = (leaf_index..mmr.leaves_count())
+        .map(leaf_index_to_pos)
+        .collect();
+    mmr.gen_proof(pos_list).unwrap()
+}
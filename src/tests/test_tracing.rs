@@ -0,0 +1,94 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::Pos;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// Records the names of the spans and the messages of the events.
+#[derive(Default)]
+struct Recorder {
+    next_id: AtomicU64,
+    spans: Mutex<Vec<&'static str>>,
+    events: Mutex<Vec<String>>,
+}
+
+struct MessageVisitor(Option<String>);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = Some(format!("{:?}", value));
+        }
+    }
+}
+
+impl Subscriber for &'static Recorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        self.spans.lock().unwrap().push(span.metadata().name());
+        Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut visitor = MessageVisitor(None);
+        event.record(&mut visitor);
+        if let Some(message) = visitor.0 {
+            self.events.lock().unwrap().push(message);
+        }
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[test]
+fn test_tracing_spans_and_events() {
+    // leaked, as the dispatcher wants a 'static subscriber
+    let recorder: &'static Recorder = Box::leak(Box::default());
+    tracing::subscriber::with_default(recorder, || {
+        let store = MemStore::default();
+        let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+        let positions: Vec<Pos> = (0u32..11)
+            .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+            .collect();
+        mmr.commit().unwrap();
+        let root = mmr.get_root().unwrap();
+        let proof = mmr.gen_proof(vec![positions[5]]).unwrap();
+        assert!(proof
+            .verify(root, vec![(positions[5], NumberHash::from(5))])
+            .unwrap());
+    });
+
+    let spans = recorder.spans.lock().unwrap();
+    assert_eq!(spans.iter().filter(|name| **name == "mmr_push").count(), 11);
+    for name in ["mmr_commit", "mmr_gen_proof", "mmr_calculate_root"] {
+        assert!(spans.contains(&name), "missing span {}", name);
+    }
+    let events = recorder.events.lock().unwrap();
+    for message in [
+        "merges",
+        "store reads",
+        "proof positions",
+        "proof items",
+        "level",
+        "verification steps",
+        "peaks calculated",
+    ] {
+        assert!(
+            events.iter().any(|event| event == message),
+            "missing event {}",
+            message
+        );
+    }
+}
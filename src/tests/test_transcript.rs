@@ -0,0 +1,101 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::{leaf_index_to_pos, MMRStoreReadOps, Merge, MergeStep};
+
+#[test]
+fn test_verify_with_transcript() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..11 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    mmr.commit().unwrap();
+    let root = mmr.get_root().unwrap();
+    let leaves = vec![
+        (leaf_index_to_pos(2), NumberHash::from(2)),
+        (leaf_index_to_pos(9), NumberHash::from(9)),
+    ];
+    let proof = mmr
+        .gen_proof(leaves.iter().map(|(pos, _)| *pos).collect())
+        .unwrap();
+    let (verified, transcript) = proof
+        .verify_with_transcript(root.clone(), leaves.clone())
+        .unwrap();
+    assert!(verified);
+
+    // three merges up the first mountain, one in the second, then two baggings
+    let steps = transcript.steps();
+    assert_eq!(steps.len(), 6);
+    assert_eq!(
+        steps[0],
+        MergeStep {
+            parent_pos: Some(5),
+            left_pos: Some(3),
+            left: NumberHash::from(2),
+            right_pos: Some(4),
+            right: (&store).get_elem(4).unwrap().unwrap(),
+            result: (&store).get_elem(5).unwrap().unwrap(),
+        }
+    );
+    for step in &steps[..4] {
+        let parent_pos = step.parent_pos.unwrap();
+        assert_eq!(
+            Some(&step.result),
+            (&store).get_elem(parent_pos).unwrap().as_ref()
+        );
+        for (pos, item) in [(step.left_pos, &step.left), (step.right_pos, &step.right)] {
+            assert_eq!(
+                Some(item),
+                (&store).get_elem(pos.unwrap()).unwrap().as_ref()
+            );
+        }
+    }
+    // the last two peaks are bagged first, then the bag with the first peak
+    let peaks = mmr.peaks().unwrap();
+    assert_eq!(steps[4].parent_pos, None);
+    assert_eq!(
+        (steps[4].left_pos, steps[4].right_pos),
+        (Some(peaks[2].0), Some(peaks[1].0))
+    );
+    assert_eq!(
+        (steps[5].left_pos, steps[5].right_pos),
+        (None, Some(peaks[0].0))
+    );
+    assert_eq!(
+        steps[5].result,
+        MergeNumberHash::merge_peaks(&steps[4].result, &peaks[0].1).unwrap()
+    );
+    assert_eq!(steps[5].result, root);
+
+    let (verified, wrong_transcript) = proof
+        .verify_with_transcript(NumberHash::from(42), leaves)
+        .unwrap();
+    assert!(!verified);
+    assert_eq!(wrong_transcript, transcript);
+}
+
+#[test]
+fn test_transcript_of_bagged_peaks() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..11 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    let root = mmr.get_root().unwrap();
+    let pos = leaf_index_to_pos(7);
+    let proof = mmr.gen_proof(vec![pos]).unwrap();
+    let (verified, transcript) = proof
+        .verify_with_transcript(root.clone(), vec![(pos, NumberHash::from(7))])
+        .unwrap();
+    assert!(verified);
+
+    // the proof bags the two peaks on the right into one item without position
+    let steps = transcript.into_steps();
+    assert_eq!(steps.len(), 4);
+    let bagging = steps.last().unwrap();
+    assert_eq!(
+        (bagging.left_pos, bagging.right_pos),
+        (None, Some(mmr.peaks().unwrap()[0].0))
+    );
+    assert_eq!(bagging.result, root);
+}
@@ -0,0 +1,127 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::{
+    leaf_index_to_pos, Error, LeafIndex, MMRStoreReadOps, MerkleProof, MmrSize, NodePosition, Pos,
+};
+
+#[test]
+fn test_conversions() {
+    assert_eq!(MmrSize::new(19).map(MmrSize::get), Ok(19));
+    assert_eq!(
+        MmrSize::new(17),
+        Err(Error::InvalidMMRSize { mmr_size: 17 })
+    );
+    assert_eq!(MmrSize::from_leaf_count(11), MmrSize::new(19));
+    assert_eq!(MmrSize::from_leaf_count(0), MmrSize::new(0));
+
+    let size = MmrSize::new(19).unwrap();
+    assert_eq!(size.leaf_count(), 11);
+    assert_eq!(size.next_leaf(), LeafIndex::new(11));
+    assert_eq!(size.next_pos(), NodePosition::new(19));
+    assert_eq!(size.next_leaf().to_pos(), size.next_pos());
+    assert_eq!(
+        size.peaks().map(NodePosition::get).collect::<Vec<_>>(),
+        vec![14, 17, 18]
+    );
+    assert!(size.contains(NodePosition::new(18)));
+    assert!(!size.contains(NodePosition::new(19)));
+
+    for index in 0..100 {
+        let leaf = LeafIndex::new(index);
+        let pos = leaf.to_pos();
+        assert_eq!(pos.get(), leaf_index_to_pos(index));
+        assert!(pos.is_leaf());
+        assert_eq!(pos.leaf_index(), Some(leaf));
+        // the leaf is the last node of the MMR it completes, unless it merges with peaks
+        assert!(leaf.mmr_size().contains(pos));
+        assert_eq!(leaf.mmr_size().leaf_count(), index + 1);
+    }
+    assert_eq!(NodePosition::new(14).height(), 3);
+    assert_eq!(NodePosition::new(14).leaf_index(), None);
+}
+
+#[test]
+fn test_typed_proofs() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::with_size(MmrSize::default(), &store);
+    for (index, i) in (0u32..11).enumerate() {
+        let pos = mmr.push_leaf(NumberHash::from(i)).unwrap();
+        assert_eq!(pos, LeafIndex::new(index as Pos).to_pos());
+    }
+    assert_eq!(mmr.size(), MmrSize::from_leaf_count(11).unwrap());
+    mmr.commit().unwrap();
+    let reopened = MemMMR::<_, MergeNumberHash>::with_size(mmr.size(), &store);
+    assert_eq!(reopened.get_root(), mmr.get_root());
+    let root = mmr.get_root().unwrap();
+
+    let leaves = [LeafIndex::new(3), LeafIndex::new(10)];
+    let proof = mmr.gen_leaf_proof(&leaves).unwrap();
+    assert_eq!(
+        proof.proof_items(),
+        mmr.gen_proof(leaves.iter().map(|leaf| leaf.to_pos().get()).collect())
+            .unwrap()
+            .proof_items()
+    );
+    assert_eq!(
+        proof.verify_leaves(
+            root.clone(),
+            vec![
+                (leaves[0], NumberHash::from(3)),
+                (leaves[1], NumberHash::from(10))
+            ]
+        ),
+        Ok(true)
+    );
+    assert_eq!(
+        proof.verify_leaves(
+            root,
+            vec![
+                (leaves[0], NumberHash::from(10)),
+                (leaves[1], NumberHash::from(3))
+            ]
+        ),
+        Ok(false)
+    );
+    // the index of the next leaf is beyond the MMR
+    assert_eq!(
+        mmr.gen_leaf_proof(&[mmr.size().next_leaf()]).err(),
+        Some(Error::PositionOutOfRange { pos: 19 })
+    );
+}
+
+#[test]
+fn test_typed_node_and_ancestry_proofs() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    let mut prev = None;
+    for i in 0u32..11 {
+        mmr.push(NumberHash::from(i)).unwrap();
+        if i == 6 {
+            prev = Some((mmr.size(), mmr.get_root().unwrap()));
+        }
+    }
+    mmr.commit().unwrap();
+    let root = mmr.get_root().unwrap();
+
+    let positions = [NodePosition::new(6), NodePosition::new(17)];
+    let proof = mmr.gen_node_proof_at(&positions).unwrap();
+    assert_eq!(proof.size(), Ok(mmr.size()));
+    let nodes = positions
+        .iter()
+        .map(|pos| (pos.get(), (&store).get_elem(pos.get()).unwrap().unwrap()))
+        .collect();
+    assert_eq!(proof.verify(root.clone(), nodes), Ok(true));
+
+    let (prev_size, prev_root) = prev.unwrap();
+    let ancestry_proof = mmr.gen_ancestry_proof_since(prev_size).unwrap();
+    assert_eq!(ancestry_proof.prev_size(), Ok(prev_size));
+    assert_eq!(ancestry_proof.verify_ancestor(root, prev_root), Ok(true));
+    assert_eq!(
+        mmr.gen_leaf_proof(&[LeafIndex::new(3)]).unwrap().size(),
+        Ok(mmr.size())
+    );
+    assert_eq!(
+        MerkleProof::<NumberHash, MergeNumberHash>::new(17, vec![]).size(),
+        Err(Error::InvalidMMRSize { mmr_size: 17 })
+    );
+}
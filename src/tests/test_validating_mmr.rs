@@ -0,0 +1,51 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::{Error, Merge, Result, ValidatingMmr};
+use std::cell::Cell;
+
+thread_local! {
+    static MERGES: Cell<u64> = const { Cell::new(0) };
+}
+
+// every 5th merge gives a different result
+struct FlakyMerge;
+
+impl Merge for FlakyMerge {
+    type Item = NumberHash;
+    type Error = Error;
+    fn merge(lhs: &Self::Item, rhs: &Self::Item) -> Result<Self::Item> {
+        let merges = MERGES.with(|merges| {
+            merges.set(merges.get() + 1);
+            merges.get()
+        });
+        if merges.is_multiple_of(5) {
+            MergeNumberHash::merge(rhs, lhs)
+        } else {
+            MergeNumberHash::merge(lhs, rhs)
+        }
+    }
+}
+
+#[test]
+fn test_deterministic_merge_passes() {
+    let store = MemStore::default();
+    let mut mmr = ValidatingMmr::new(MemMMR::<_, MergeNumberHash>::new(0, &store));
+    for i in 0u32..500 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    for pos in 0..mmr.mmr_size() {
+        mmr.check_parent(pos).unwrap();
+    }
+    mmr.commit().unwrap();
+    let mmr = mmr.into_inner();
+    assert_eq!(mmr.committed_mmr_size(), mmr.mmr_size());
+}
+
+#[test]
+fn test_nondeterministic_merge_is_caught() {
+    MERGES.with(|merges| merges.set(0));
+    let store = MemStore::default();
+    let mut mmr = ValidatingMmr::with_seed(MemMMR::<_, FlakyMerge>::new(0, &store), 42);
+    let result = (0u32..200).try_for_each(|i| mmr.push(NumberHash::from(i)).map(|_| ()));
+    assert!(matches!(result, Err(Error::NondeterministicMerge { .. })));
+}
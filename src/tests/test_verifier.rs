@@ -0,0 +1,239 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::helper::get_peaks;
+use crate::util::{MemMMR, MemStore};
+use crate::{verifier, Error, Pos};
+use proptest::prelude::*;
+
+fn build_mmr(
+    store: &MemStore<NumberHash>,
+    count: u32,
+) -> (MemMMR<'_, NumberHash, MergeNumberHash>, Vec<Pos>) {
+    let mut mmr = MemMMR::new(0, store);
+    let positions = (0u32..count)
+        .map(|i| mmr.push(NumberHash::from(i)).unwrap())
+        .collect();
+    (mmr, positions)
+}
+
+#[test]
+fn test_peak_root() {
+    let store = MemStore::default();
+    let (mmr, positions) = build_mmr(&store, 11);
+    // leaves 3 and 5 sit under the peak at 14
+    let leaves = vec![
+        (positions[3], NumberHash::from(3)),
+        (positions[5], NumberHash::from(5)),
+    ];
+    let proof = mmr.gen_proof(vec![positions[3], positions[5]]).unwrap();
+    let peak = mmr.batch().get_elem(14).unwrap().unwrap();
+    // the first items are the siblings in the mountain, followed by the bagged rhs peaks
+    let siblings = &proof.proof_items()[..proof.proof_items().len() - 1];
+    assert_eq!(
+        verifier::calculate_peak_root::<_, MergeNumberHash>(leaves.clone(), 14, siblings),
+        Ok(peak)
+    );
+    assert_eq!(
+        verifier::calculate_peak_root::<_, MergeNumberHash>(
+            leaves.clone(),
+            14,
+            proof.proof_items()
+        ),
+        Err(Error::CorruptedProof)
+    );
+    assert_eq!(
+        verifier::calculate_peak_root::<_, MergeNumberHash>(leaves, 6, siblings),
+        Err(Error::GenProofForInvalidLeaves)
+    );
+}
+
+#[test]
+fn test_bagging_peaks_hashes() {
+    let store = MemStore::default();
+    let (mmr, _) = build_mmr(&store, 11);
+    let peaks = get_peaks(mmr.mmr_size())
+        .into_iter()
+        .map(|pos| mmr.batch().get_elem(pos).unwrap().unwrap())
+        .collect();
+    assert_eq!(
+        verifier::bagging_peaks_hashes::<_, MergeNumberHash>(peaks),
+        mmr.get_root()
+    );
+    assert_eq!(
+        verifier::bagging_peaks_hashes::<NumberHash, MergeNumberHash>(Vec::new()),
+        Err(Error::CorruptedProof)
+    );
+}
+
+proptest! {
+    #[test]
+    fn test_verify_matches_merkle_proof(
+        (count, elems) in (1u32..300).prop_flat_map(|count| (
+            Just(count),
+            prop::collection::vec(0..count, 1..10),
+        ))
+    ) {
+        let store = MemStore::default();
+        let (mmr, positions) = build_mmr(&store, count);
+        let leaves: Vec<_> = elems
+            .iter()
+            .map(|i| (positions[*i as usize], NumberHash::from(*i)))
+            .collect();
+        let proof = mmr
+            .gen_proof(leaves.iter().map(|(pos, _)| *pos).collect())
+            .unwrap();
+        let root = mmr.get_root().unwrap();
+        prop_assert!(verifier::verify::<_, MergeNumberHash>(
+            root.clone(),
+            mmr.mmr_size(),
+            leaves.clone(),
+            proof.proof_items(),
+        )
+        .unwrap());
+        prop_assert_eq!(
+            verifier::calculate_root::<_, MergeNumberHash>(
+                leaves.clone(),
+                mmr.mmr_size(),
+                proof.proof_items(),
+            ),
+            proof.calculate_root(leaves.clone())
+        );
+        let mut buf = vec![None; leaves.len() + 65];
+        prop_assert_eq!(
+            verifier::calculate_root_in::<_, MergeNumberHash>(
+                leaves.clone(),
+                mmr.mmr_size(),
+                proof.proof_items(),
+                &mut buf,
+            ),
+            proof.calculate_root(leaves)
+        );
+    }
+}
+
+#[test]
+fn test_verify_in() {
+    let store = MemStore::default();
+    let (mmr, positions) = build_mmr(&store, 11);
+    let root = mmr.get_root().unwrap();
+    // leaves 3 and 5 given in reverse, with 5 repeated
+    let leaves = [
+        (positions[5], NumberHash::from(5)),
+        (positions[3], NumberHash::from(3)),
+        (positions[5], NumberHash::from(5)),
+    ];
+    let proof = mmr.gen_proof(vec![positions[3], positions[5]]).unwrap();
+
+    // 2 leaves, 3 peaks and the bagged rhs peaks
+    let mut buf: [Option<(Pos, NumberHash)>; 6] = Default::default();
+    assert_eq!(
+        verifier::verify_in::<_, MergeNumberHash>(
+            root.clone(),
+            mmr.mmr_size(),
+            leaves.iter().cloned(),
+            proof.proof_items(),
+            &mut buf,
+        ),
+        Ok(true)
+    );
+    assert_eq!(
+        verifier::verify_in::<_, MergeNumberHash>(
+            root.clone(),
+            mmr.mmr_size(),
+            vec![(positions[3], NumberHash::from(4)), leaves[0].clone()],
+            proof.proof_items(),
+            &mut buf,
+        ),
+        Ok(false)
+    );
+    assert_eq!(
+        verifier::verify_in::<_, MergeNumberHash>(
+            root.clone(),
+            mmr.mmr_size(),
+            leaves.iter().cloned(),
+            proof.proof_items(),
+            &mut buf[..5],
+        ),
+        Err(Error::LimitExceeded)
+    );
+    assert_eq!(
+        verifier::verify_in::<_, MergeNumberHash>(
+            root,
+            mmr.mmr_size(),
+            leaves.iter().cloned(),
+            proof.proof_items(),
+            &mut buf[..1],
+        ),
+        Err(Error::LimitExceeded)
+    );
+
+    let peak = mmr.batch().get_elem(14).unwrap().unwrap();
+    let siblings = &proof.proof_items()[..proof.proof_items().len() - 1];
+    assert_eq!(
+        verifier::calculate_peak_root_in::<_, MergeNumberHash>(
+            leaves.iter().cloned(),
+            14,
+            siblings,
+            &mut buf[..2],
+        ),
+        Ok(peak)
+    );
+    assert_eq!(
+        verifier::calculate_peak_root_in::<_, MergeNumberHash>(
+            leaves.iter().cloned(),
+            6,
+            siblings,
+            &mut buf,
+        ),
+        Err(Error::GenProofForInvalidLeaves)
+    );
+}
+
+#[test]
+fn test_raw_schedule() {
+    use crate::raw;
+
+    let store = MemStore::default();
+    let (mmr, positions) = build_mmr(&store, 11);
+    let leaves = vec![
+        (positions[3], NumberHash::from(3)),
+        (positions[5], NumberHash::from(5)),
+    ];
+    let proof = mmr.gen_proof(vec![positions[3], positions[5]]).unwrap();
+    let peaks: Vec<_> = get_peaks(mmr.mmr_size())
+        .into_iter()
+        .map(|pos| mmr.batch().get_elem(pos).unwrap().unwrap())
+        .collect();
+
+    // both leaves sit under the first peak, followed by the bagged rhs peaks
+    let peaks_hashes = raw::calculate_peaks_hashes::<_, MergeNumberHash>(
+        leaves.clone(),
+        mmr.mmr_size(),
+        proof.proof_items(),
+    )
+    .unwrap();
+    assert_eq!(peaks_hashes.len(), 2);
+    assert_eq!(peaks_hashes[0], peaks[0]);
+    assert_eq!(
+        raw::bagging_peaks_hashes::<_, MergeNumberHash>(peaks_hashes),
+        mmr.get_root()
+    );
+
+    // the items after the mountain stay in the iterator
+    let mut proof_iter = proof.proof_items().iter();
+    assert_eq!(
+        raw::calculate_peak_root::<_, MergeNumberHash, _>(leaves.clone(), 14, &mut proof_iter),
+        Ok(peaks[0].clone())
+    );
+    assert_eq!(proof_iter.count(), 1);
+
+    let mut proof_iter = proof.proof_items().iter();
+    let unsorted = leaves.into_iter().rev().collect();
+    assert_eq!(
+        raw::calculate_peak_root::<_, MergeNumberHash, _>(unsorted, 14, &mut proof_iter),
+        Err(Error::GenProofForInvalidLeaves)
+    );
+    assert_eq!(
+        raw::calculate_peak_root::<_, MergeNumberHash, _>(Vec::new(), 14, &mut proof_iter),
+        Err(Error::CorruptedProof)
+    );
+}
@@ -0,0 +1,112 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::util::MemStore;
+use crate::{leaf_index_to_pos, Error, MMRStoreWriteOps, Pos, VersionedStore, MMR};
+
+type VersionedMMR<'a, 'b> =
+    MMR<NumberHash, MergeNumberHash, &'a VersionedStore<NumberHash, &'b MemStore<NumberHash>>>;
+
+#[test]
+fn test_proof_isolated_from_concurrent_commit() {
+    let mem_store = MemStore::default();
+    let store = VersionedStore::new(0, &mem_store);
+    let mut writer = VersionedMMR::new(0, &store);
+    for i in 0u32..11 {
+        writer.push(NumberHash::from(i)).unwrap();
+    }
+    writer.commit().unwrap();
+    let version = store.version();
+    assert_eq!(version, 1);
+    let root = writer.get_root().unwrap();
+
+    // a reader opened on the same store pins the version before the writer commits again
+    let reader = VersionedMMR::open(&store).unwrap();
+    let pinned = reader.at_version(version).unwrap();
+    for i in 11u32..40 {
+        writer.push(NumberHash::from(i)).unwrap();
+    }
+    writer.commit().unwrap();
+    assert_eq!(store.version(), 2);
+    assert_ne!(writer.get_root().unwrap(), root);
+
+    assert_eq!(pinned.mmr_size(), reader.mmr_size());
+    assert_eq!(pinned.get_root(), Ok(root.clone()));
+    let pos = leaf_index_to_pos(7);
+    let proof = pinned.gen_proof(vec![pos]).unwrap();
+    assert_eq!(proof.mmr_size(), pinned.mmr_size());
+    assert_eq!(
+        proof.verify(root, vec![(pos, NumberHash::from(7))]),
+        Ok(true)
+    );
+}
+
+#[test]
+fn test_pinned_version_keeps_rewritten_nodes() {
+    let mem_store = MemStore::default();
+    let store = VersionedStore::new(0, &mem_store);
+    let mut mmr = VersionedMMR::new(0, &store);
+    for i in 0u32..50 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    mmr.commit().unwrap();
+    let root = mmr.get_root().unwrap();
+
+    // corrupt the parent of leaves 10 and 11 and the peak of the first mountain, then repair them
+    let corrupted_pos = [leaf_index_to_pos(11) + 1, 62];
+    let mut writes = &store;
+    writes.start_transaction().unwrap();
+    for pos in corrupted_pos {
+        writes.overwrite(pos, NumberHash::from(31337)).unwrap();
+    }
+    writes.commit_transaction().unwrap();
+    let corrupted = store.version();
+    let corrupted_root = mmr.at_version(corrupted).unwrap().get_root().unwrap();
+    assert_ne!(corrupted_root, root);
+    assert_eq!(mmr.rebuild_range(10..12), Ok(corrupted_pos.len() as Pos));
+    mmr.commit().unwrap();
+
+    // the repair is a new version, the corrupted one still reads the old nodes
+    assert_eq!(store.version(), corrupted + 1);
+    assert_eq!(mmr.at_version(corrupted + 1).unwrap().get_root(), Ok(root));
+    assert_eq!(
+        mmr.at_version(corrupted).unwrap().get_root(),
+        Ok(corrupted_root)
+    );
+
+    store.prune(corrupted + 1);
+    assert_eq!(
+        mmr.at_version(corrupted).err(),
+        Some(Error::UnknownVersion { version: corrupted })
+    );
+}
+
+#[test]
+fn test_rewind_retires_versions() {
+    let mem_store = MemStore::default();
+    let store = VersionedStore::new(0, &mem_store);
+    let mut mmr = VersionedMMR::new(0, &store);
+    for i in 0u32..8 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    mmr.commit().unwrap();
+    let small = store.version();
+    for i in 8u32..16 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    mmr.commit().unwrap();
+    let large = store.version();
+    let pinned = mmr.at_version(large).unwrap();
+    let small_size = store.mmr_size_at(small).unwrap();
+
+    mmr.rewind(small_size).unwrap();
+    // the pinned MMR fails instead of mixing the rewound nodes with the old ones
+    assert_eq!(
+        pinned.get_root(),
+        Err(Error::UnknownVersion { version: large })
+    );
+    assert_eq!(store.mmr_size_at(store.version()), Ok(small_size));
+    assert_eq!(mmr.at_version(small).unwrap().get_root(), mmr.get_root());
+    assert_eq!(
+        mmr.at_version(42).err(),
+        Some(Error::UnknownVersion { version: 42 })
+    );
+}
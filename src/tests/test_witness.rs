@@ -0,0 +1,140 @@
+use super::{MergeNumberHash, NumberHash};
+use crate::util::{MemMMR, MemStore};
+use crate::witness::{MAX_PATH_LEN, MAX_PEAKS};
+use crate::{leaf_index_to_pos, verify_witness, Error, Pos};
+
+#[test]
+fn test_witness() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..11 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    let root = mmr.get_root().unwrap();
+    let leaves: Vec<_> = [9u32, 2, 10]
+        .iter()
+        .map(|i| (leaf_index_to_pos(*i as Pos), NumberHash::from(*i)))
+        .collect();
+    let proof = mmr
+        .gen_proof(leaves.iter().map(|(pos, _)| *pos).collect())
+        .unwrap();
+    let witness = proof.to_witness(leaves).unwrap();
+    assert_eq!(witness.mmr_size, mmr.mmr_size());
+    assert_eq!(witness.peak_count, 3);
+    assert_eq!(witness.peaks.len(), MAX_PEAKS);
+    let peaks: Vec<_> = mmr
+        .peaks()
+        .unwrap()
+        .into_iter()
+        .map(|(_, peak)| peak)
+        .collect();
+    assert_eq!(witness.peaks[..3], peaks[..]);
+
+    // sorted by position, leaf 2 is the left child of a right child of a left child
+    let leaf = &witness.leaves[0];
+    assert_eq!(leaf.pos, leaf_index_to_pos(2));
+    assert_eq!((leaf.path_len, leaf.peak_index), (3, 0));
+    assert_eq!(leaf.path.len(), MAX_PATH_LEN);
+    assert_eq!(leaf.directions[..4], [false, true, false, false]);
+    // leaf 10 is a peak of its own
+    assert_eq!(
+        (witness.leaves[2].path_len, witness.leaves[2].peak_index),
+        (0, 2)
+    );
+    assert_eq!(
+        verify_witness::<_, MergeNumberHash>(root.clone(), &witness),
+        Ok(true)
+    );
+
+    let mut flipped = witness.clone();
+    flipped.leaves[0].directions[1] = false;
+    assert_eq!(
+        verify_witness::<_, MergeNumberHash>(root.clone(), &flipped),
+        Ok(false)
+    );
+    let mut wrong_leaf = witness.clone();
+    wrong_leaf.leaves[1].leaf = NumberHash::from(42);
+    assert_eq!(
+        verify_witness::<_, MergeNumberHash>(root.clone(), &wrong_leaf),
+        Ok(false)
+    );
+    assert_eq!(
+        verify_witness::<_, MergeNumberHash>(NumberHash::from(42), &witness),
+        Ok(false)
+    );
+    let mut too_many_peaks = witness.clone();
+    too_many_peaks.peak_count = 4;
+    assert_eq!(
+        verify_witness::<_, MergeNumberHash>(root.clone(), &too_many_peaks),
+        Err(Error::CorruptedProof)
+    );
+
+    // counts past the fixed bounds are rejected even when the arrays are long enough
+    let mut long_path = witness.clone();
+    long_path.leaves[0].path.resize(200, NumberHash::default());
+    long_path.leaves[0].directions.resize(200, false);
+    long_path.leaves[0].path_len = 200;
+    assert_eq!(
+        verify_witness::<_, MergeNumberHash>(root.clone(), &long_path),
+        Err(Error::CorruptedProof)
+    );
+    let mut huge_mmr = witness;
+    huge_mmr.mmr_size = Pos::MAX;
+    huge_mmr.peaks.resize(MAX_PEAKS + 1, NumberHash::default());
+    huge_mmr.peak_count = MAX_PEAKS + 1;
+    assert_eq!(
+        verify_witness::<_, MergeNumberHash>(root, &huge_mmr),
+        Err(Error::CorruptedProof)
+    );
+}
+
+#[test]
+fn test_witness_path_past_mmr() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..11 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    let root = mmr.get_root().unwrap();
+    let pos = leaf_index_to_pos(10);
+    let proof = mmr.gen_proof(vec![pos]).unwrap();
+    let mut witness = proof.to_witness(vec![(pos, NumberHash::from(10))]).unwrap();
+    // the last leaf is a peak, a path of the full length climbs past the MMR
+    witness.leaves[0].path_len = MAX_PATH_LEN;
+    for height in 0..MAX_PATH_LEN {
+        witness.leaves[0].directions[height] = height > 0;
+    }
+    assert_eq!(
+        verify_witness::<_, MergeNumberHash>(root, &witness),
+        Ok(false)
+    );
+}
+
+#[test]
+fn test_witness_of_bagged_peaks() {
+    let store = MemStore::default();
+    let mut mmr = MemMMR::<_, MergeNumberHash>::new(0, &store);
+    for i in 0u32..11 {
+        mmr.push(NumberHash::from(i)).unwrap();
+    }
+    let root = mmr.get_root().unwrap();
+    let pos = leaf_index_to_pos(5);
+    let proof = mmr.gen_proof(vec![pos]).unwrap();
+    let witness = proof.to_witness(vec![(pos, NumberHash::from(5))]).unwrap();
+    // the two peaks on the right are bagged into one
+    assert_eq!(witness.peak_count, 2);
+    assert_eq!(
+        verify_witness::<_, MergeNumberHash>(root.clone(), &witness),
+        Ok(true)
+    );
+
+    // with another leaf the items are taken for other siblings, leaving out the peaks on the right
+    let witness = proof
+        .to_witness(vec![(pos, NumberHash::from(5)), (0, NumberHash::from(0))])
+        .unwrap();
+    assert_eq!(witness.peak_count, 1);
+    assert_eq!(
+        verify_witness::<_, MergeNumberHash>(root, &witness),
+        Ok(false)
+    );
+}
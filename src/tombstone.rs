@@ -0,0 +1,108 @@
+//! Soft deletion with tombstone leaves
+//!
+//! Leaves are never removed from an MMR, so applications such as revocation lists mark a leaf as
+//! deleted by pushing a tombstone leaf referencing it, see `TombstoneMerge`. A leaf is live as
+//! long as no later leaf is its tombstone.
+//!
+//! The leaves aren't sorted, so there is no proof of the absence of a tombstone short of the
+//! leaves it could be among. A `LivenessProof` proves the leaf together with every leaf pushed
+//! after it, and the verifier checks that none of them is its tombstone. The proof grows with the
+//! number of later leaves, and it only shows liveness as of the root it is verified against.
+
+use crate::helper::{get_peak_map, leaf_index_to_pos};
+use crate::mmr_store::MMRStoreReadOps;
+use crate::vec::Vec;
+use crate::{Error, MerkleProof, Pos, Result, TombstoneMerge, MMR};
+use core::iter;
+
+/// Proof that the leaf at `leaf_index` has no tombstone among the leaves of the MMR.
+#[derive(Debug)]
+pub struct LivenessProof<T, M> {
+    leaf_index: Pos,
+    /// All leaves after the proven one, in order.
+    later_leaves: Vec<T>,
+    /// Proof of the leaf and the later leaves.
+    proof: MerkleProof<T, M>,
+}
+
+impl<T, M> LivenessProof<T, M> {
+    pub fn new(leaf_index: Pos, later_leaves: Vec<T>, proof: MerkleProof<T, M>) -> Self {
+        LivenessProof {
+            leaf_index,
+            later_leaves,
+            proof,
+        }
+    }
+
+    pub fn leaf_index(&self) -> Pos {
+        self.leaf_index
+    }
+
+    pub fn later_leaves(&self) -> &[T] {
+        &self.later_leaves
+    }
+
+    pub fn proof(&self) -> &MerkleProof<T, M> {
+        &self.proof
+    }
+}
+
+impl<T: Clone + PartialEq, M: TombstoneMerge<Item = T>> LivenessProof<T, M> {
+    /// Verify that `leaf` is at `leaf_index` and not deleted in the MMR of `root`, failing with
+    /// `CorruptedProof` unless the proof has all leaves after it.
+    pub fn verify(&self, root: T, leaf: T) -> Result<bool> {
+        let leaves_count = get_peak_map(self.proof.mmr_size());
+        if self.leaf_index >= leaves_count
+            || leaves_count - self.leaf_index - 1 != self.later_leaves.len() as Pos
+        {
+            return Err(Error::CorruptedProof);
+        }
+        if self
+            .later_leaves
+            .iter()
+            .any(|later_leaf| M::tombstoned(later_leaf) == Some(self.leaf_index))
+        {
+            return Ok(false);
+        }
+        let leaves = (self.leaf_index..leaves_count)
+            .map(leaf_index_to_pos)
+            .zip(iter::once(leaf).chain(self.later_leaves.iter().cloned()))
+            .collect();
+        self.proof.verify(root, leaves)
+    }
+}
+
+impl<T: Clone, M: TombstoneMerge<Item = T>, S: MMRStoreReadOps<T>> MMR<T, M, S> {
+    /// Mark the leaf at `leaf_index` as deleted by pushing its tombstone, returning the position
+    /// of the tombstone. Fails with `GenProofForInvalidLeaves` if there is no such leaf.
+    pub fn mark_deleted(&mut self, leaf_index: Pos) -> Result<Pos> {
+        if leaf_index >= self.leaves_count() {
+            return Err(Error::GenProofForInvalidLeaves);
+        }
+        let tombstone = M::tombstone(leaf_index).map_err(Into::into)?;
+        self.push(tombstone)
+    }
+
+    /// Prove that the leaf at `leaf_index` is not deleted, `None` if a later leaf is its
+    /// tombstone. Fails with `GenProofForInvalidLeaves` if there is no such leaf.
+    pub fn gen_liveness_proof(&self, leaf_index: Pos) -> Result<Option<LivenessProof<T, M>>> {
+        let leaves_count = self.leaves_count();
+        if leaf_index >= leaves_count {
+            return Err(Error::GenProofForInvalidLeaves);
+        }
+        let pos_list: Vec<Pos> = (leaf_index..leaves_count).map(leaf_index_to_pos).collect();
+        let mut later_leaves = Vec::with_capacity(pos_list.len() - 1);
+        for pos in &pos_list[1..] {
+            let later_leaf = self
+                .batch()
+                .get_elem(*pos)?
+                .ok_or(Error::InconsistentStore { pos: *pos })?;
+            if M::tombstoned(&later_leaf) == Some(leaf_index) {
+                return Ok(None);
+            }
+            later_leaves.push(later_leaf);
+        }
+        let proof = self.gen_proof(pos_list)?;
+        Ok(Some(LivenessProof::new(leaf_index, later_leaves, proof)))
+    }
+}
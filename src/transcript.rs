@@ -0,0 +1,114 @@
+//! Verification transcripts
+//!
+//! `MerkleProof::verify_with_transcript` records every merge of a verification, in the order it
+//! is performed, as evidence of the verification or as the merge schedule for the witness of a
+//! circuit verifying the same proof.
+
+use crate::helper::{get_peaks, parent_offset, pos_height_in_tree};
+use crate::vec::Vec;
+use crate::verifier::{calculate_peaks_hashes_with, merge_node, Iterations};
+use crate::{Error, Merge, MerkleProof, Pos, Result};
+
+/// A merge of a verification, with the items in the order they are passed to the merge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeStep<T> {
+    /// Position of the merged node, `None` when bagging peaks.
+    pub parent_pos: Option<Pos>,
+    /// Position of the left item, `None` for a bag of several peaks.
+    pub left_pos: Option<Pos>,
+    pub left: T,
+    /// Position of the right item, `None` for a bag of several peaks.
+    pub right_pos: Option<Pos>,
+    pub right: T,
+    pub result: T,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transcript<T> {
+    steps: Vec<MergeStep<T>>,
+}
+
+impl<T> Transcript<T> {
+    pub fn new(steps: Vec<MergeStep<T>>) -> Self {
+        Transcript { steps }
+    }
+
+    /// The merges of the mountains from the bottom up and left to right, then the bagging of
+    /// the peaks from right to left, the last one resulting in the root.
+    pub fn steps(&self) -> &[MergeStep<T>] {
+        &self.steps
+    }
+
+    pub fn into_steps(self) -> Vec<MergeStep<T>> {
+        self.steps
+    }
+}
+
+impl<T: Clone + PartialEq, M: Merge<Item = T>> MerkleProof<T, M> {
+    /// Same as `verify`, also returning the transcript of the merges. Bagging the peaks merges
+    /// the bag on the right first, see `Merge::merge_peaks`.
+    pub fn verify_with_transcript(
+        &self,
+        root: T,
+        leaves: Vec<(Pos, T)>,
+    ) -> Result<(bool, Transcript<T>)> {
+        let mmr_size = self.mmr_size();
+        if mmr_size == 0 && leaves.is_empty() && M::empty_root().is_some() {
+            // nothing is merged for the empty root
+            let verified = self.verify(root, leaves)?;
+            return Ok((verified, Transcript::new(Vec::new())));
+        }
+        let mut steps = Vec::new();
+        let mut record_merge = |pos: Pos, lhs: &T, rhs: &T| -> Result<T> {
+            let result = merge_node::<T, M>(pos, lhs, rhs)?;
+            // the right child directly precedes its parent, the left child its whole subtree
+            let offset = parent_offset(pos_height_in_tree(pos) - 1);
+            steps.push(MergeStep {
+                parent_pos: Some(pos),
+                left_pos: Some(pos - offset),
+                left: lhs.clone(),
+                right_pos: Some(pos - 1),
+                right: rhs.clone(),
+                result: result.clone(),
+            });
+            Ok(result)
+        };
+        let mut peaks_hashes = calculate_peaks_hashes_with(
+            leaves,
+            mmr_size,
+            self.proof_items().iter(),
+            &mut Iterations::unbounded(),
+            &mut record_merge,
+        )?;
+
+        // the last peak hash is the bag of the remaining peaks if the proof bagged them
+        let peaks = get_peaks(mmr_size);
+        let mut peaks_pos: Vec<_> = (0..peaks_hashes.len())
+            .map(|i| peaks.get(i).copied())
+            .collect();
+        if peaks_hashes.len() < peaks.len() {
+            if let Some(pos) = peaks_pos.last_mut() {
+                *pos = None;
+            }
+        }
+        while peaks_hashes.len() > 1 {
+            let right_peak = peaks_hashes.pop().expect("pop");
+            let left_peak = peaks_hashes.pop().expect("pop");
+            let right_pos = peaks_pos.pop().flatten();
+            let left_pos = peaks_pos.pop().flatten();
+            let result = M::merge_peaks(&right_peak, &left_peak).map_err(Into::into)?;
+            steps.push(MergeStep {
+                parent_pos: None,
+                left_pos: right_pos,
+                left: right_peak,
+                right_pos: left_pos,
+                right: left_peak,
+                result: result.clone(),
+            });
+            peaks_hashes.push(result);
+            peaks_pos.push(None);
+        }
+        let calculated_root = peaks_hashes.pop().ok_or(Error::CorruptedProof)?;
+        Ok((calculated_root == root, Transcript::new(steps)))
+    }
+}
@@ -0,0 +1,184 @@
+//! Typed sizes, leaf indices and positions
+//!
+//! MMR sizes, leaf indices and node positions are all `Pos`, so passing one for another
+//! compiles and fails much later, e.g. a leaf index given to `gen_proof` proves some other
+//! node. The newtypes here only convert between each other through the named functions, and
+//! typed counterparts of the `Pos` based API, which stays as it is, take and return them:
+//!
+//! - `MMR::with_size`, `MMR::size` and `MMR::push_leaf`
+//! - `MMR::gen_leaf_proof`, `MMR::gen_node_proof_at` and `MMR::gen_ancestry_proof_since`
+//! - `MerkleProof::verify_leaves`
+//! - `MerkleProof::size`, `NodeMerkleProof::size`, `AncestryProof::prev_size`,
+//!   `UpdateProof::prev_size` and `UpdateProof::size`
+
+use crate::helper::{
+    get_peak_map, is_valid_mmr_size, iter_peaks, leaf_count_to_mmr_size, leaf_index_to_mmr_size,
+    PositionScheme, PostOrderScheme,
+};
+use crate::{Error, Pos, Result};
+use core::fmt;
+
+/// The number of nodes of an MMR, always a valid size.
+///
+/// Archived sizes aren't deserialized, as they may be invalid, see `ArchivedMmrSize::get` and
+/// `MmrSize::new`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize))]
+pub struct MmrSize(Pos);
+
+/// The index of a leaf in insertion order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+pub struct LeafIndex(Pos);
+
+/// The position of a node, in the post-order the nodes are stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+pub struct NodePosition(Pos);
+
+impl MmrSize {
+    /// Fails with `InvalidMMRSize` if no MMR has `mmr_size` nodes.
+    pub fn new(mmr_size: Pos) -> Result<Self> {
+        if !is_valid_mmr_size(mmr_size) {
+            return Err(Error::InvalidMMRSize { mmr_size });
+        }
+        Ok(MmrSize(mmr_size))
+    }
+
+    /// The size of the MMR with `leaf_count` leaves, failing with `InvalidLeafCount` if it
+    /// doesn't fit in `Pos`.
+    pub fn from_leaf_count(leaf_count: Pos) -> Result<Self> {
+        if leaf_count == 0 {
+            return Ok(MmrSize(0));
+        }
+        leaf_count_to_mmr_size(leaf_count)
+            .map(MmrSize)
+            .ok_or(Error::InvalidLeafCount { leaf_count })
+    }
+
+    /// `mmr_size` taken as valid, e.g. the size of an `MMR`.
+    #[cfg(not(feature = "verify-only"))]
+    pub(crate) fn new_unchecked(mmr_size: Pos) -> Self {
+        debug_assert!(is_valid_mmr_size(mmr_size));
+        MmrSize(mmr_size)
+    }
+
+    pub fn get(self) -> Pos {
+        self.0
+    }
+
+    pub fn leaf_count(self) -> Pos {
+        get_peak_map(self.0)
+    }
+
+    /// The index the next pushed leaf gets.
+    pub fn next_leaf(self) -> LeafIndex {
+        LeafIndex(self.leaf_count())
+    }
+
+    /// The position the next pushed leaf gets.
+    pub fn next_pos(self) -> NodePosition {
+        NodePosition(self.0)
+    }
+
+    pub fn contains(self, pos: NodePosition) -> bool {
+        pos.0 < self.0
+    }
+
+    /// The positions of the peaks from left to right.
+    pub fn peaks(self) -> impl Iterator<Item = NodePosition> {
+        iter_peaks(self.0).map(NodePosition)
+    }
+}
+
+impl LeafIndex {
+    pub fn new(index: Pos) -> Self {
+        LeafIndex(index)
+    }
+
+    pub fn get(self) -> Pos {
+        self.0
+    }
+
+    /// The position of the leaf.
+    pub fn to_pos(self) -> NodePosition {
+        NodePosition(PostOrderScheme::leaf_index_to_pos(self.0))
+    }
+
+    /// The size of the MMR right after pushing this leaf.
+    pub fn mmr_size(self) -> MmrSize {
+        MmrSize(leaf_index_to_mmr_size(self.0))
+    }
+}
+
+impl NodePosition {
+    pub fn new(pos: Pos) -> Self {
+        NodePosition(pos)
+    }
+
+    pub fn get(self) -> Pos {
+        self.0
+    }
+
+    pub fn height(self) -> u8 {
+        PostOrderScheme::pos_height(self.0)
+    }
+
+    pub fn is_leaf(self) -> bool {
+        self.height() == 0
+    }
+
+    /// The index of the leaf at this position, `None` for parents.
+    pub fn leaf_index(self) -> Option<LeafIndex> {
+        match PostOrderScheme::node_coords(self.0) {
+            (0, index) => Some(LeafIndex(index)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MmrSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for LeafIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for NodePosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl ArchivedMmrSize {
+    /// The archived size, which may be invalid.
+    pub fn get(&self) -> Pos {
+        self.0.to_native()
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl ArchivedLeafIndex {
+    pub fn get(&self) -> Pos {
+        self.0.to_native()
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl ArchivedNodePosition {
+    pub fn get(&self) -> Pos {
+        self.0.to_native()
+    }
+}
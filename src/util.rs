@@ -1,9 +1,26 @@
-use crate::collections::{BTreeMap, VecDeque};
-use crate::{vec::Vec, MMRStoreReadOps, MMRStoreWriteOps, Result, MMR};
-use core::cell::RefCell;
+use crate::collections::BTreeMap;
+use crate::helper::{get_peak_map, is_valid_mmr_size, iter_peaks, pos_height_in_tree};
+use crate::{
+    vec::Vec, Error, MMRStoreDeleteOps, MMRStoreReadOps, MMRStoreWriteOps, Pos, Result, MMR,
+};
+use core::cell::{Cell, RefCell};
+use core::convert::TryInto;
+use core::ops::Range;
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+pub use crate::helper::VeqDequeExt;
 
 #[derive(Clone)]
-pub struct MemStore<T>(RefCell<BTreeMap<u64, T>>);
+pub struct MemStore<T> {
+    elems: RefCell<BTreeMap<Pos, T>>,
+    leaf_metas: RefCell<BTreeMap<Pos, Vec<u8>>>,
+    mmr_size: Cell<Option<Pos>>,
+    // logged roots by mmr size, only filled with the `root-log` feature
+    roots: RefCell<BTreeMap<Pos, T>>,
+    // indexed leaves by position, only filled with the `leaf-index` feature
+    leaf_index: RefCell<BTreeMap<Pos, T>>,
+}
 
 impl<T> Default for MemStore<T> {
     fn default() -> Self {
@@ -13,39 +30,504 @@ impl<T> Default for MemStore<T> {
 
 impl<T> MemStore<T> {
     fn new() -> Self {
-        MemStore(RefCell::new(Default::default()))
+        MemStore {
+            elems: RefCell::new(Default::default()),
+            leaf_metas: RefCell::new(Default::default()),
+            mmr_size: Cell::new(None),
+            roots: RefCell::new(Default::default()),
+            leaf_index: RefCell::new(Default::default()),
+        }
+    }
+
+    /// Number of stored elements.
+    pub fn len(&self) -> usize {
+        self.elems.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elems.borrow().is_empty()
+    }
+
+    /// Iterate over the elements in position order. Taking `&mut self` hands out plain borrows,
+    /// without tracking them at runtime, so the store must not be shared with an MMR meanwhile.
+    pub fn iter(&mut self) -> impl Iterator<Item = (Pos, &T)> {
+        self.elems.get_mut().iter().map(|(pos, elem)| (*pos, elem))
+    }
+
+    /// Get the element at `pos`, borrowed like `iter`.
+    pub fn get(&mut self, pos: Pos) -> Option<&T> {
+        self.elems.get_mut().get(&pos)
     }
 }
 
+impl<T: AsRef<[u8]>> MemStore<T> {
+    /// Serialize the elements, the leaf metadata, the logged roots, the indexed leaves and the
+    /// MMR size, see
+    /// `from_bytes`.
+    ///
+    /// The tables are written as an entry count followed by the entries, each a key and a
+    /// length prefixed value. Integers are little endian, counts and keys `Pos`, lengths `u32`.
+    /// The size follows as a `0`/`1` presence byte and the `Pos` size.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_table(
+            &mut bytes,
+            self.elems.borrow().iter().map(|(k, v)| (*k, v.as_ref())),
+        );
+        write_table(
+            &mut bytes,
+            self.leaf_metas.borrow().iter().map(|(k, v)| (*k, &v[..])),
+        );
+        write_table(
+            &mut bytes,
+            self.roots.borrow().iter().map(|(k, v)| (*k, v.as_ref())),
+        );
+        write_table(
+            &mut bytes,
+            self.leaf_index
+                .borrow()
+                .iter()
+                .map(|(k, v)| (*k, v.as_ref())),
+        );
+        match self.mmr_size.get() {
+            Some(mmr_size) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&mmr_size.to_le_bytes());
+            }
+            None => bytes.push(0),
+        }
+        bytes
+    }
+}
+
+impl<T: From<Vec<u8>>> MemStore<T> {
+    /// Restore a store written by `to_bytes`, failing with `CorruptedSnapshot` on malformed input.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self> {
+        let elems = read_table(&mut bytes)?
+            .into_iter()
+            .map(|(pos, elem)| (pos, T::from(elem)))
+            .collect();
+        let leaf_metas = read_table(&mut bytes)?.into_iter().collect();
+        let roots = read_table(&mut bytes)?
+            .into_iter()
+            .map(|(mmr_size, root)| (mmr_size, T::from(root)))
+            .collect();
+        let indexed_leaves = read_table(&mut bytes)?
+            .into_iter()
+            .map(|(pos, leaf)| (pos, T::from(leaf)))
+            .collect();
+        let mmr_size = match take_array(&mut bytes)? {
+            [0] => None,
+            [1] => Some(Pos::from_le_bytes(take_array(&mut bytes)?)),
+            _ => return Err(Error::CorruptedSnapshot),
+        };
+        if !bytes.is_empty() {
+            return Err(Error::CorruptedSnapshot);
+        }
+        Ok(MemStore {
+            elems: RefCell::new(elems),
+            leaf_metas: RefCell::new(leaf_metas),
+            mmr_size: Cell::new(mmr_size),
+            roots: RefCell::new(roots),
+            leaf_index: RefCell::new(indexed_leaves),
+        })
+    }
+}
+
+fn write_table<'a>(bytes: &mut Vec<u8>, entries: impl ExactSizeIterator<Item = (Pos, &'a [u8])>) {
+    bytes.extend_from_slice(&(entries.len() as Pos).to_le_bytes());
+    for (key, value) in entries {
+        bytes.extend_from_slice(&key.to_le_bytes());
+        bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(value);
+    }
+}
+
+fn read_table(bytes: &mut &[u8]) -> Result<Vec<(Pos, Vec<u8>)>> {
+    let count = Pos::from_le_bytes(take_array(bytes)?);
+    // don't trust the count for preallocation
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let key = Pos::from_le_bytes(take_array(bytes)?);
+        let len = u32::from_le_bytes(take_array(bytes)?) as usize;
+        if bytes.len() < len {
+            return Err(Error::CorruptedSnapshot);
+        }
+        let (value, rest) = bytes.split_at(len);
+        entries.push((key, value.to_vec()));
+        *bytes = rest;
+    }
+    Ok(entries)
+}
+
+fn take_array<const N: usize>(bytes: &mut &[u8]) -> Result<[u8; N]> {
+    if bytes.len() < N {
+        return Err(Error::CorruptedSnapshot);
+    }
+    let (array, rest) = bytes.split_at(N);
+    *bytes = rest;
+    Ok(array.try_into().expect("checked length"))
+}
+
 impl<T: Clone> MMRStoreReadOps<T> for &MemStore<T> {
-    fn get_elem(&self, pos: u64) -> Result<Option<T>> {
-        Ok(self.0.borrow().get(&pos).cloned())
+    fn get_elem(&self, pos: Pos) -> Result<Option<T>> {
+        Ok(self.elems.borrow().get(&pos).cloned())
+    }
+
+    fn get_elems(&self, range: Range<Pos>) -> Result<Vec<Option<T>>> {
+        let store = self.elems.borrow();
+        Ok(range.map(|pos| store.get(&pos).cloned()).collect())
+    }
+
+    fn get_leaf_meta(&self, leaf_index: Pos) -> Result<Option<Vec<u8>>> {
+        Ok(self.leaf_metas.borrow().get(&leaf_index).cloned())
+    }
+
+    fn mmr_size(&self) -> Result<Option<Pos>> {
+        Ok(self.mmr_size.get())
+    }
+
+    #[cfg(feature = "root-log")]
+    fn get_root_at(&self, mmr_size: Pos) -> Result<Option<T>> {
+        Ok(self.roots.borrow().get(&mmr_size).cloned())
+    }
+
+    #[cfg(feature = "leaf-index")]
+    fn get_leaf_positions(&self, leaf: &T) -> Result<Vec<Pos>>
+    where
+        T: PartialEq,
+    {
+        // a scan, backends with many leaves would key the index by leaf
+        Ok(self
+            .leaf_index
+            .borrow()
+            .iter()
+            .filter(|(_, indexed)| *indexed == leaf)
+            .map(|(pos, _)| *pos)
+            .collect())
     }
 }
 
 impl<T> MMRStoreWriteOps<T> for &MemStore<T> {
-    fn append(&mut self, pos: u64, elems: Vec<T>) -> Result<()> {
-        let mut store = self.0.borrow_mut();
+    fn append(&mut self, pos: Pos, elems: Vec<T>) -> Result<()> {
+        let mut store = self.elems.borrow_mut();
         for (i, elem) in elems.into_iter().enumerate() {
-            store.insert(pos + i as u64, elem);
+            store.insert(pos + i as Pos, elem);
         }
         Ok(())
     }
+
+    fn overwrite(&mut self, pos: Pos, elem: T) -> Result<()> {
+        self.elems.borrow_mut().insert(pos, elem);
+        Ok(())
+    }
+
+    fn supports_leaf_meta(&self) -> bool {
+        true
+    }
+
+    fn append_leaf_meta(&mut self, leaf_index: Pos, meta: Vec<u8>) -> Result<()> {
+        self.leaf_metas.borrow_mut().insert(leaf_index, meta);
+        Ok(())
+    }
+
+    fn set_mmr_size(&mut self, mmr_size: Pos) -> Result<()> {
+        self.mmr_size.set(Some(mmr_size));
+        Ok(())
+    }
+
+    #[cfg(feature = "root-log")]
+    fn supports_root_log(&self) -> bool {
+        true
+    }
+
+    #[cfg(feature = "root-log")]
+    fn append_root(&mut self, mmr_size: Pos, root: T) -> Result<()> {
+        self.roots.borrow_mut().insert(mmr_size, root);
+        Ok(())
+    }
+
+    #[cfg(feature = "leaf-index")]
+    fn append_leaf_index(&mut self, pos: Pos, leaf: T) -> Result<()> {
+        self.leaf_index.borrow_mut().insert(pos, leaf);
+        Ok(())
+    }
+}
+
+impl<T> MMRStoreDeleteOps<T> for &MemStore<T> {
+    fn truncate(&mut self, mmr_size: Pos) -> Result<()> {
+        self.elems.borrow_mut().split_off(&mmr_size);
+        self.leaf_metas
+            .borrow_mut()
+            .split_off(&get_peak_map(mmr_size));
+        self.mmr_size
+            .set(self.mmr_size.get().map(|size| size.min(mmr_size)));
+        self.roots.borrow_mut().split_off(&(mmr_size + 1));
+        self.leaf_index.borrow_mut().split_off(&mmr_size);
+        Ok(())
+    }
 }
 
 pub type MemMMR<'a, T, M> = MMR<T, M, &'a MemStore<T>>;
 
-pub trait VeqDequeExt<T: Ord> {
-    fn insert_sorted(&mut self, value: T);
+/// A read-only store backed by a lookup function, e.g. an RPC call or a state trie lookup.
+pub struct FnStore<F>(F);
+
+impl<F> FnStore<F> {
+    pub fn new(get_elem: F) -> Self {
+        FnStore(get_elem)
+    }
 }
 
-impl<T: Ord> VeqDequeExt<T> for VecDeque<T> {
-    fn insert_sorted(&mut self, value: T) {
-        match self.binary_search(&value) {
-            Ok(_pos) => {
-                // element already in vector @ `pos`
+impl<T, F: Fn(Pos) -> Result<Option<T>>> MMRStoreReadOps<T> for FnStore<F> {
+    fn get_elem(&self, pos: Pos) -> Result<Option<T>> {
+        (self.0)(pos)
+    }
+}
+
+/// A store sleeping for `read_latency` on every read and `write_latency` on every write of the
+/// wrapped store and counting them, to profile against the latency of a disk or a network
+/// rather than memory.
+///
+/// Range reads and reads of metadata count as one read each, writes of nodes, metadata and
+/// sizes as one write each. Transactions are passed through without latency.
+#[cfg(feature = "std")]
+pub struct ThrottledStore<S> {
+    store: RefCell<S>,
+    read_latency: Duration,
+    write_latency: Duration,
+    reads: Cell<u64>,
+    writes: Cell<u64>,
+}
+
+#[cfg(feature = "std")]
+impl<S> ThrottledStore<S> {
+    /// Wrap `store` without latency, see `with_read_latency` and `with_write_latency`.
+    pub fn new(store: S) -> Self {
+        ThrottledStore {
+            store: RefCell::new(store),
+            read_latency: Duration::ZERO,
+            write_latency: Duration::ZERO,
+            reads: Cell::new(0),
+            writes: Cell::new(0),
+        }
+    }
+
+    pub fn with_read_latency(mut self, latency: Duration) -> Self {
+        self.read_latency = latency;
+        self
+    }
+
+    pub fn with_write_latency(mut self, latency: Duration) -> Self {
+        self.write_latency = latency;
+        self
+    }
+
+    /// Number of reads since the store was created or the counters were reset.
+    pub fn reads(&self) -> u64 {
+        self.reads.get()
+    }
+
+    /// Number of writes since the store was created or the counters were reset.
+    pub fn writes(&self) -> u64 {
+        self.writes.get()
+    }
+
+    pub fn reset_counters(&self) {
+        self.reads.set(0);
+        self.writes.set(0);
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store.into_inner()
+    }
+
+    fn read(&self) -> core::cell::Ref<'_, S> {
+        self.reads.set(self.reads.get() + 1);
+        if !self.read_latency.is_zero() {
+            std::thread::sleep(self.read_latency);
+        }
+        self.store.borrow()
+    }
+
+    fn write(&self) -> core::cell::RefMut<'_, S> {
+        self.writes.set(self.writes.get() + 1);
+        if !self.write_latency.is_zero() {
+            std::thread::sleep(self.write_latency);
+        }
+        self.store.borrow_mut()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, S: MMRStoreReadOps<T>> MMRStoreReadOps<T> for &ThrottledStore<S> {
+    fn get_elem(&self, pos: Pos) -> Result<Option<T>> {
+        self.read().get_elem(pos)
+    }
+
+    fn get_elems(&self, range: Range<Pos>) -> Result<Vec<Option<T>>> {
+        self.read().get_elems(range)
+    }
+
+    fn get_leaf_meta(&self, leaf_index: Pos) -> Result<Option<Vec<u8>>> {
+        self.read().get_leaf_meta(leaf_index)
+    }
+
+    #[cfg(feature = "root-log")]
+    fn get_root_at(&self, mmr_size: Pos) -> Result<Option<T>> {
+        self.read().get_root_at(mmr_size)
+    }
+
+    #[cfg(feature = "leaf-index")]
+    fn get_leaf_positions(&self, leaf: &T) -> Result<Vec<Pos>>
+    where
+        T: PartialEq,
+    {
+        self.read().get_leaf_positions(leaf)
+    }
+
+    fn mmr_size(&self) -> Result<Option<Pos>> {
+        self.read().mmr_size()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, S: MMRStoreWriteOps<T>> MMRStoreWriteOps<T> for &ThrottledStore<S> {
+    fn append(&mut self, pos: Pos, elems: Vec<T>) -> Result<()> {
+        self.write().append(pos, elems)
+    }
+
+    fn overwrite(&mut self, pos: Pos, elem: T) -> Result<()> {
+        self.write().overwrite(pos, elem)
+    }
+
+    fn supports_leaf_meta(&self) -> bool {
+        self.store.borrow().supports_leaf_meta()
+    }
+
+    fn append_leaf_meta(&mut self, leaf_index: Pos, meta: Vec<u8>) -> Result<()> {
+        self.write().append_leaf_meta(leaf_index, meta)
+    }
+
+    #[cfg(feature = "root-log")]
+    fn supports_root_log(&self) -> bool {
+        self.store.borrow().supports_root_log()
+    }
+
+    #[cfg(feature = "root-log")]
+    fn append_root(&mut self, mmr_size: Pos, root: T) -> Result<()> {
+        self.write().append_root(mmr_size, root)
+    }
+
+    #[cfg(feature = "leaf-index")]
+    fn append_leaf_index(&mut self, pos: Pos, leaf: T) -> Result<()> {
+        self.write().append_leaf_index(pos, leaf)
+    }
+
+    fn set_mmr_size(&mut self, mmr_size: Pos) -> Result<()> {
+        self.write().set_mmr_size(mmr_size)
+    }
+
+    fn start_transaction(&mut self) -> Result<()> {
+        self.store.borrow_mut().start_transaction()
+    }
+
+    fn commit_transaction(&mut self) -> Result<()> {
+        self.store.borrow_mut().commit_transaction()
+    }
+
+    fn abort_transaction(&mut self) -> Result<()> {
+        self.store.borrow_mut().abort_transaction()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, S: MMRStoreDeleteOps<T>> MMRStoreDeleteOps<T> for &ThrottledStore<S> {
+    fn truncate(&mut self, mmr_size: Pos) -> Result<()> {
+        self.write().truncate(mmr_size)
+    }
+}
+
+/// Find the first position where the MMRs of `mmr_size` in stores `a` and `b` disagree, `None`
+/// if they are the same, e.g. to track down replica drift.
+///
+/// Only the peaks and the children of disagreeing nodes are read, so this relies on a parent
+/// differing whenever one of its nodes does, which holds for stores written by `MMR`. A node
+/// missing from one store disagrees, one missing from both fails with `InconsistentStore`.
+pub fn find_divergence<T: PartialEq>(
+    a: &impl MMRStoreReadOps<T>,
+    b: &impl MMRStoreReadOps<T>,
+    mmr_size: Pos,
+) -> Result<Option<Pos>> {
+    if !is_valid_mmr_size(mmr_size) {
+        return Err(Error::InvalidMMRSize { mmr_size });
+    }
+    let differs = |pos: Pos| -> Result<bool> {
+        match (a.get_elem(pos)?, b.get_elem(pos)?) {
+            (None, None) => Err(Error::InconsistentStore { pos }),
+            (elem_a, elem_b) => Ok(elem_a != elem_b),
+        }
+    };
+    for peak_pos in iter_peaks(mmr_size) {
+        if !differs(peak_pos)? {
+            continue;
+        }
+        // descend into the leftmost disagreeing child, the nodes of a left subtree come first
+        let mut pos = peak_pos;
+        let mut height = pos_height_in_tree(pos);
+        while height > 0 {
+            let left_pos = pos - (1 << height);
+            let right_pos = pos - 1;
+            if differs(left_pos)? {
+                pos = left_pos;
+            } else if differs(right_pos)? {
+                pos = right_pos;
+            } else {
+                // both children agree, so the node itself was merged differently
+                break;
             }
-            Err(pos) => self.insert(pos, value),
+            height -= 1;
+        }
+        return Ok(Some(pos));
+    }
+    Ok(None)
+}
+
+/// A non-empty sequence of leaves, for fuzz targets that need a well-formed MMR to start from.
+#[cfg(feature = "arbitrary")]
+#[derive(Debug, Clone)]
+pub struct MmrState<T> {
+    leaves: Vec<T>,
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a, T: arbitrary::Arbitrary<'a>> arbitrary::Arbitrary<'a> for MmrState<T> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut leaves: Vec<T> = u.arbitrary()?;
+        if leaves.is_empty() {
+            leaves.push(u.arbitrary()?);
         }
+        Ok(MmrState { leaves })
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<T: Clone + PartialEq> MmrState<T> {
+    pub fn leaves(&self) -> &[T] {
+        &self.leaves
+    }
+
+    /// Push all leaves into an MMR backed by `store`, returning it with the leaf positions.
+    pub fn build<'a, M: crate::Merge<Item = T>>(
+        &self,
+        store: &'a MemStore<T>,
+    ) -> Result<(MemMMR<'a, T, M>, Vec<Pos>)> {
+        let mut mmr = MemMMR::new(0, store);
+        let positions = self
+            .leaves
+            .iter()
+            .map(|leaf| mmr.push(leaf.clone()))
+            .collect::<Result<Vec<Pos>>>()?;
+        Ok((mmr, positions))
     }
 }
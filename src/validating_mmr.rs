@@ -0,0 +1,100 @@
+//! Merge determinism checks
+//!
+//! A `Merge` that isn't deterministic, e.g. hashing a struct with `HashMap` ordering, builds an
+//! MMR whose proofs never verify, and the failure only shows up long after the pushes.
+//! `ValidatingMmr` catches it early: after every push it merges the children of one earlier,
+//! pseudo-randomly chosen parent again and compares the result with the stored node.
+
+use crate::helper::{parent_offset, pos_height_in_tree};
+use crate::mmr_store::{MMRStoreReadOps, MMRStoreWriteOps};
+use crate::{Error, Merge, Pos, Result, MMR};
+use core::ops::Deref;
+
+pub struct ValidatingMmr<T, M, S> {
+    mmr: MMR<T, M, S>,
+    rng_state: u64,
+}
+
+impl<T, M, S> ValidatingMmr<T, M, S> {
+    pub fn new(mmr: MMR<T, M, S>) -> Self {
+        Self::with_seed(mmr, 0)
+    }
+
+    /// Same as `new`, choosing the checked parents by `seed`.
+    pub fn with_seed(mmr: MMR<T, M, S>, seed: u64) -> Self {
+        ValidatingMmr {
+            mmr,
+            rng_state: seed,
+        }
+    }
+
+    pub fn into_inner(self) -> MMR<T, M, S> {
+        self.mmr
+    }
+
+    /// splitmix64, good enough to spread the checks over the MMR
+    fn next_random(&mut self) -> u64 {
+        self.rng_state = self.rng_state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl<T, M, S> Deref for ValidatingMmr<T, M, S> {
+    type Target = MMR<T, M, S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.mmr
+    }
+}
+
+impl<T: Clone + PartialEq, M: Merge<Item = T>, S: MMRStoreReadOps<T>> ValidatingMmr<T, M, S> {
+    /// Push `elem`, then check one earlier parent, failing with `NondeterministicMerge` if
+    /// merging its children doesn't reproduce it. The pushed element stays in the MMR either way.
+    pub fn push(&mut self, elem: T) -> Result<Pos> {
+        let pos = self.mmr.push(elem)?;
+        let random_pos = self.next_random() as Pos % self.mmr.mmr_size();
+        self.check_parent(random_pos)?;
+        Ok(pos)
+    }
+
+    /// Merge the children of the parent at or above `pos` again and compare with the stored node.
+    pub fn check_parent(&self, mut pos: Pos) -> Result<()> {
+        let mut height = pos_height_in_tree(pos);
+        if height == 0 {
+            // check the parent of the leaf instead, if it has one yet
+            pos = if pos_height_in_tree(pos + 1) > 0 {
+                pos + 1
+            } else {
+                pos + parent_offset(0)
+            };
+            height = 1;
+        }
+        if pos >= self.mmr.mmr_size() {
+            return Ok(());
+        }
+        let get_elem = |pos| {
+            self.mmr
+                .batch()
+                .get_elem(pos)?
+                .ok_or(Error::InconsistentStore { pos })
+        };
+        let left = get_elem(pos - (1 << height))?;
+        let right = get_elem(pos - 1)?;
+        let merged = M::merge_with_pos(pos, &left, &right).map_err(Into::into)?;
+        if merged != get_elem(pos)? {
+            return Err(Error::NondeterministicMerge { pos });
+        }
+        Ok(())
+    }
+}
+
+impl<T: Clone, M: Merge<Item = T>, S: MMRStoreReadOps<T> + MMRStoreWriteOps<T>>
+    ValidatingMmr<T, M, S>
+{
+    pub fn commit(&mut self) -> Result<()> {
+        self.mmr.commit()
+    }
+}
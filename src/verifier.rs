@@ -0,0 +1,543 @@
+//! Proof verification
+//!
+//! Root calculation, peak bagging and the helper math is all a verifier needs. The functions
+//! here take the proof items laid out as in `MerkleProof::proof_items` and avoid the prover's
+//! queues, so size constrained targets such as wasm32 on-chain verifiers build with the
+//! `verify-only` feature, which leaves out everything but this module, the helpers and the proof
+//! types.
+
+use crate::borrow::Borrow;
+use crate::helper::{iter_peaks, pos_height_in_tree, PositionScheme, PostOrderScheme};
+use crate::vec::Vec;
+use crate::{metrics, Error, Merge, Pos, Result};
+
+/// Calculate the root of an MMR of `mmr_size` from `leaves` and the `proof_items` of a
+/// `MerkleProof`.
+pub fn calculate_root<T: Clone + PartialEq, M: Merge<Item = T>>(
+    leaves: Vec<(Pos, T)>,
+    mmr_size: Pos,
+    proof_items: &[T],
+) -> Result<T> {
+    calculate_root_from_iter::<_, M, _>(
+        leaves,
+        mmr_size,
+        proof_items.iter(),
+        &mut Iterations::unbounded(),
+    )
+}
+
+/// Verify `leaves` against `root`, see `calculate_root`.
+pub fn verify<T: Clone + PartialEq, M: Merge<Item = T>>(
+    root: T,
+    mmr_size: Pos,
+    leaves: Vec<(Pos, T)>,
+    proof_items: &[T],
+) -> Result<bool> {
+    calculate_root::<_, M>(leaves, mmr_size, proof_items)
+        .map(|calculated_root| calculated_root == root)
+}
+
+/// Calculate the root of the mountain at `peak_pos` from `leaves` under it and the siblings
+/// along their paths, ordered as they are consumed bottom-up from left to right.
+pub fn calculate_peak_root<T: Clone + PartialEq, M: Merge<Item = T>>(
+    mut leaves: Vec<(Pos, T)>,
+    peak_pos: Pos,
+    proof_items: &[T],
+) -> Result<T> {
+    if leaves
+        .iter()
+        .any(|(pos, _)| pos_height_in_tree(*pos) > 0 || *pos > peak_pos)
+    {
+        return Err(Error::GenProofForInvalidLeaves);
+    }
+    if leaves.is_empty() {
+        return Err(Error::CorruptedProof);
+    }
+    // ensure leaves are sorted and unique
+    sort_and_dedup_by_pos(&mut leaves)?;
+
+    let mut proof_iter = proof_items.iter();
+    let peak_root = if leaves.len() == 1 && leaves[0].0 == peak_pos {
+        // leaf is the peak
+        leaves.remove(0).1
+    } else {
+        calculate_peak_root_from_iter::<_, M, _>(
+            leaves,
+            peak_pos,
+            &mut proof_iter,
+            &mut Iterations::unbounded(),
+        )?
+    };
+    // ensure nothing left in proof_iter
+    if proof_iter.next().is_some() {
+        return Err(Error::CorruptedProof);
+    }
+    Ok(peak_root)
+}
+
+/// Same as `calculate_root`, with the working set in `buf` instead of the heap, for verifiers
+/// without an allocator.
+///
+/// `buf` needs a slot for every leaf, one for every peak of the MMR and one for the bagged rhs
+/// peaks, otherwise this fails with `LimitExceeded`. Its content is overwritten.
+pub fn calculate_root_in<T: Clone + PartialEq, M: Merge<Item = T>>(
+    leaves: impl IntoIterator<Item = (Pos, T)>,
+    mmr_size: Pos,
+    proof_items: &[T],
+    buf: &mut [Option<(Pos, T)>],
+) -> Result<T> {
+    let leaves_len = insert_leaves(leaves, buf)?;
+    if mmr_size == 0 && leaves_len == 0 {
+        // only an empty proof proves the empty MMR
+        if let Some(empty_root) = M::empty_root() {
+            if !proof_items.is_empty() {
+                return Err(Error::CorruptedProof);
+            }
+            return Ok(empty_root);
+        }
+    }
+    let peaks_len = iter_peaks(mmr_size).count() + 1;
+    if leaves_len + peaks_len > buf.len() {
+        return Err(Error::LimitExceeded);
+    }
+    let (nodes, peaks) = buf.split_at_mut(leaves_len);
+    let peaks_hashes = calculate_peaks_hashes_in(
+        nodes,
+        peaks,
+        mmr_size,
+        proof_items.iter(),
+        &mut Iterations::unbounded(),
+        &mut merge_node::<T, M>,
+    )?;
+
+    // bagging from right to left via hash(right, left).
+    let mut peaks_iter = peaks[..peaks_hashes]
+        .iter_mut()
+        .rev()
+        .map(|peak| peak.take().expect("peak").1);
+    let mut root = peaks_iter.next().ok_or(Error::CorruptedProof)?;
+    for left_peak in peaks_iter {
+        root = M::merge_peaks(&root, &left_peak).map_err(Into::into)?;
+    }
+    Ok(root)
+}
+
+/// Same as `verify`, with the working set in `buf`, see `calculate_root_in`.
+pub fn verify_in<T: Clone + PartialEq, M: Merge<Item = T>>(
+    root: T,
+    mmr_size: Pos,
+    leaves: impl IntoIterator<Item = (Pos, T)>,
+    proof_items: &[T],
+    buf: &mut [Option<(Pos, T)>],
+) -> Result<bool> {
+    calculate_root_in::<_, M>(leaves, mmr_size, proof_items, buf)
+        .map(|calculated_root| calculated_root == root)
+}
+
+/// Same as `calculate_peak_root`, with the working set in `buf`, which needs a slot for every
+/// leaf.
+pub fn calculate_peak_root_in<T: Clone + PartialEq, M: Merge<Item = T>>(
+    leaves: impl IntoIterator<Item = (Pos, T)>,
+    peak_pos: Pos,
+    proof_items: &[T],
+    buf: &mut [Option<(Pos, T)>],
+) -> Result<T> {
+    let leaves_len = insert_leaves(leaves, buf)?;
+    let nodes = &mut buf[..leaves_len];
+    if nodes.iter().any(|node| leaf_pos(node) > peak_pos) {
+        return Err(Error::GenProofForInvalidLeaves);
+    }
+    if nodes.is_empty() {
+        return Err(Error::CorruptedProof);
+    }
+
+    let mut proof_iter = proof_items.iter();
+    let peak_root = if leaves_len == 1 && leaf_pos(&nodes[0]) == peak_pos {
+        // leaf is the peak
+        nodes[0].take().expect("leaf").1
+    } else {
+        calculate_peak_root_with(
+            nodes,
+            peak_pos,
+            &mut proof_iter,
+            &mut Iterations::unbounded(),
+            &mut merge_node::<T, M>,
+        )?
+    };
+    // ensure nothing left in proof_iter
+    if proof_iter.next().is_some() {
+        return Err(Error::CorruptedProof);
+    }
+    Ok(peak_root)
+}
+
+/// Insert `leaves` into `buf` sorted by position, dropping duplicates, and return their number.
+///
+/// Fails with `CorruptedProof` if a position is given twice with different leaves.
+fn insert_leaves<T: PartialEq>(
+    leaves: impl IntoIterator<Item = (Pos, T)>,
+    buf: &mut [Option<(Pos, T)>],
+) -> Result<usize> {
+    let mut len = 0;
+    for (pos, item) in leaves {
+        if pos_height_in_tree(pos) > 0 {
+            return Err(Error::GenProofForInvalidLeaves);
+        }
+        match buf[..len].binary_search_by_key(&pos, leaf_pos) {
+            Ok(index) => {
+                if buf[index].as_ref().map(|(_, leaf)| leaf) != Some(&item) {
+                    return Err(Error::CorruptedProof);
+                }
+            }
+            Err(index) => {
+                *buf.get_mut(len).ok_or(Error::LimitExceeded)? = Some((pos, item));
+                buf[index..=len].rotate_right(1);
+                len += 1;
+            }
+        }
+    }
+    Ok(len)
+}
+
+fn leaf_pos<T>(node: &Option<(Pos, T)>) -> Pos {
+    node.as_ref().expect("leaf").0
+}
+
+/// Bag the `peaks_hashes` from right to left into the MMR root.
+pub fn bagging_peaks_hashes<T, M: Merge<Item = T>>(mut peaks_hashes: Vec<T>) -> Result<T> {
+    // bagging from right to left via hash(right, left).
+    while peaks_hashes.len() > 1 {
+        let right_peak = peaks_hashes.pop().expect("pop");
+        let left_peak = peaks_hashes.pop().expect("pop");
+        peaks_hashes.push(M::merge_peaks(&right_peak, &left_peak).map_err(Into::into)?);
+    }
+    peaks_hashes.pop().ok_or(Error::CorruptedProof)
+}
+
+/// A proof item that is either borrowed from or moved out of a proof, so that verification only
+/// needs `T: Clone` when the proof is borrowed.
+pub(crate) trait ProofItem<T>: Borrow<T> {
+    fn into_item(self) -> T;
+}
+
+impl<T> ProofItem<T> for T {
+    fn into_item(self) -> T {
+        self
+    }
+}
+
+impl<T: Clone> ProofItem<T> for &T {
+    fn into_item(self) -> T {
+        self.clone()
+    }
+}
+
+/// Remaining `calculate_peak_root` iterations, see `VerifyLimits::max_iterations`.
+pub(crate) struct Iterations(pub(crate) u64);
+
+impl Iterations {
+    pub(crate) fn unbounded() -> Self {
+        Iterations(u64::MAX)
+    }
+
+    pub(crate) fn step(&mut self) -> Result<()> {
+        self.0 = self.0.checked_sub(1).ok_or(Error::LimitExceeded)?;
+        Ok(())
+    }
+}
+
+/// Merge two nodes into their parent at `pos`, the default node merge of the `_with` functions.
+pub(crate) fn merge_node<T, M: Merge<Item = T>>(pos: Pos, lhs: &T, rhs: &T) -> Result<T> {
+    M::merge_with_pos(pos, lhs, rhs).map_err(Into::into)
+}
+
+/// Calculate the root of the mountain at `peak_pos` from its sorted and unique `leaves`.
+pub(crate) fn calculate_peak_root_from_iter<T, M: Merge<Item = T>, I: Iterator>(
+    leaves: Vec<(Pos, T)>,
+    peak_pos: Pos,
+    proof_iter: &mut I,
+    iterations: &mut Iterations,
+) -> Result<T>
+where
+    I::Item: Borrow<T>,
+{
+    let mut nodes: Vec<_> = leaves.into_iter().map(Some).collect();
+    calculate_peak_root_with(
+        &mut nodes,
+        peak_pos,
+        proof_iter,
+        iterations,
+        &mut merge_node::<T, M>,
+    )
+}
+
+/// Same as `calculate_peak_root_from_iter`, merging the nodes with `merge_node` and working on
+/// `nodes` in place, so it doesn't allocate.
+///
+/// The nodes are merged one level at a time from left to right, so a left node finds its
+/// sibling either next in the level or in the proof, and a right node always in the proof. A
+/// level has no more parents than nodes, so the parents overwrite the nodes already taken.
+pub(crate) fn calculate_peak_root_with<T, I: Iterator>(
+    nodes: &mut [Option<(Pos, T)>],
+    peak_pos: Pos,
+    proof_iter: &mut I,
+    iterations: &mut Iterations,
+    merge_node: &mut impl FnMut(Pos, &T, &T) -> Result<T>,
+) -> Result<T>
+where
+    I::Item: Borrow<T>,
+{
+    debug_assert!(!nodes.is_empty(), "can't be empty");
+
+    let mut len = nodes.len();
+    let mut height = 0;
+    while len > 0 {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(peak_pos, height, nodes = len, "level");
+        let mut read = 0;
+        let mut write = 0;
+        while read < len {
+            let (pos, item) = nodes[read].take().expect("node");
+            read += 1;
+            metrics::on_verification_steps(1);
+            iterations.step()?;
+            if pos == peak_pos {
+                if write == 0 && read == len {
+                    // return root once all nodes are merged
+                    return Ok(item);
+                } else {
+                    return Err(Error::CorruptedProof);
+                }
+            }
+            let (sib_pos, parent_pos, is_right) =
+                PostOrderScheme::sibling_and_parent_pos(pos, height);
+            let parent_item = if is_right {
+                // implies pos is right sibling, the left one would have been merged already
+                let sibling_item = proof_iter.next().ok_or(Error::CorruptedProof)?;
+                merge_node(parent_pos, sibling_item.borrow(), &item)?
+            } else {
+                // pos is left sibling
+                let next_pos = nodes[..len]
+                    .get(read)
+                    .map(|node| node.as_ref().expect("node").0);
+                if next_pos == Some(sib_pos) {
+                    let (_, sibling_item) = nodes[read].take().expect("node");
+                    read += 1;
+                    merge_node(parent_pos, &item, &sibling_item)?
+                } else {
+                    let sibling_item = proof_iter.next().ok_or(Error::CorruptedProof)?;
+                    merge_node(parent_pos, &item, sibling_item.borrow())?
+                }
+            };
+
+            if parent_pos <= peak_pos {
+                nodes[write] = Some((parent_pos, parent_item));
+                write += 1;
+            } else {
+                return Err(Error::CorruptedProof);
+            }
+        }
+        len = write;
+        height += 1;
+    }
+    Err(Error::CorruptedProof)
+}
+
+pub(crate) fn calculate_peaks_hashes<T: PartialEq, M: Merge<Item = T>, I: Iterator>(
+    leaves: Vec<(Pos, T)>,
+    mmr_size: Pos,
+    proof_iter: I,
+    iterations: &mut Iterations,
+) -> Result<Vec<T>>
+where
+    I::Item: ProofItem<T>,
+{
+    calculate_peaks_hashes_with(
+        leaves,
+        mmr_size,
+        proof_iter,
+        iterations,
+        &mut merge_node::<T, M>,
+    )
+}
+
+/// Same as `calculate_peaks_hashes`, merging the nodes with `merge_node`.
+pub(crate) fn calculate_peaks_hashes_with<T: PartialEq, I: Iterator>(
+    mut leaves: Vec<(Pos, T)>,
+    mmr_size: Pos,
+    proof_iter: I,
+    iterations: &mut Iterations,
+    merge_node: &mut impl FnMut(Pos, &T, &T) -> Result<T>,
+) -> Result<Vec<T>>
+where
+    I::Item: ProofItem<T>,
+{
+    if leaves.iter().any(|(pos, _)| pos_height_in_tree(*pos) > 0) {
+        return Err(Error::GenProofForInvalidLeaves);
+    }
+    // ensure leaves are sorted and unique
+    sort_and_dedup_by_pos(&mut leaves)?;
+
+    let mut nodes: Vec<_> = leaves.into_iter().map(Some).collect();
+    let mut peaks: Vec<_> = (0..=iter_peaks(mmr_size).count()).map(|_| None).collect();
+    let peaks_hashes = calculate_peaks_hashes_in(
+        &mut nodes, &mut peaks, mmr_size, proof_iter, iterations, merge_node,
+    )?;
+    Ok(peaks
+        .into_iter()
+        .take(peaks_hashes)
+        .map(|peak| peak.expect("peak").1)
+        .collect())
+}
+
+/// Calculate the peaks of an MMR of `mmr_size` from the sorted and unique leaves in `nodes`,
+/// the core of `calculate_peaks_hashes_with` and `calculate_root_in`.
+///
+/// The peaks are written to `peaks` from left to right, followed by the rhs peaks bagged by the
+/// prover, and their number is returned. `peaks` needs a slot for every peak of the MMR and one
+/// for the bagged rhs peaks.
+fn calculate_peaks_hashes_in<T, I: Iterator>(
+    nodes: &mut [Option<(Pos, T)>],
+    peaks: &mut [Option<(Pos, T)>],
+    mmr_size: Pos,
+    mut proof_iter: I,
+    iterations: &mut Iterations,
+    merge_node: &mut impl FnMut(Pos, &T, &T) -> Result<T>,
+) -> Result<usize>
+where
+    I::Item: ProofItem<T>,
+{
+    // special handle the only 1 leaf MMR
+    if mmr_size == 1 && nodes.len() == 1 && leaf_pos(&nodes[0]) == 0 {
+        peaks[0] = nodes[0].take();
+        return Ok(1);
+    }
+
+    let mut start = 0;
+    let mut peaks_hashes = 0;
+    for peak_pos in iter_peaks(mmr_size) {
+        let end = start
+            + nodes[start..]
+                .iter()
+                .take_while(|node| leaf_pos(node) <= peak_pos)
+                .count();
+        let peak_root = if end == start + 1 && leaf_pos(&nodes[start]) == peak_pos {
+            // leaf is the peak
+            nodes[start].take().expect("leaf").1
+        } else if end == start {
+            // if empty, means the next proof is a peak root or rhs bagged root
+            if let Some(peak_root) = proof_iter.next() {
+                peak_root.into_item()
+            } else {
+                // means that either all right peaks are bagged, or proof is corrupted
+                // so we break loop and check no items left
+                break;
+            }
+        } else {
+            calculate_peak_root_with(
+                &mut nodes[start..end],
+                peak_pos,
+                &mut proof_iter,
+                iterations,
+                merge_node,
+            )?
+        };
+        peaks[peaks_hashes] = Some((peak_pos, peak_root));
+        peaks_hashes += 1;
+        start = end;
+    }
+
+    // ensure nothing left in leaves
+    if start < nodes.len() {
+        return Err(Error::CorruptedProof);
+    }
+
+    // check rhs peaks
+    if let Some(rhs_peaks_hashes) = proof_iter.next() {
+        peaks[peaks_hashes] = Some((mmr_size, rhs_peaks_hashes.into_item()));
+        peaks_hashes += 1;
+    }
+    #[cfg(feature = "tracing")]
+    tracing::debug!(peaks = peaks_hashes, "peaks calculated");
+    // ensure nothing left in proof_iter
+    if proof_iter.next().is_some() {
+        return Err(Error::CorruptedProof);
+    }
+    Ok(peaks_hashes)
+}
+
+/// merkle proof
+/// 1. sort items by position
+/// 2. calculate root of each peak
+/// 3. bagging peaks
+pub(crate) fn calculate_root_from_iter<T: PartialEq, M: Merge<Item = T>, I: Iterator>(
+    leaves: Vec<(Pos, T)>,
+    mmr_size: Pos,
+    proof_iter: I,
+    iterations: &mut Iterations,
+) -> Result<T>
+where
+    I::Item: ProofItem<T>,
+{
+    #[cfg(feature = "tracing")]
+    let _span =
+        tracing::debug_span!("mmr_calculate_root", mmr_size, leaves = leaves.len()).entered();
+    if mmr_size == 0 && leaves.is_empty() {
+        // only an empty proof proves the empty MMR
+        if let Some(empty_root) = M::empty_root() {
+            let mut proof_iter = proof_iter;
+            if proof_iter.next().is_some() {
+                return Err(Error::CorruptedProof);
+            }
+            return Ok(empty_root);
+        }
+    }
+    let peaks_hashes = calculate_peaks_hashes::<_, M, _>(leaves, mmr_size, proof_iter, iterations)?;
+    bagging_peaks_hashes::<_, M>(peaks_hashes)
+}
+
+/// Push `leaf` onto the `peaks` of an MMR of `mmr_size`, merging equal-height peaks the way
+/// `MMR::push` does, and return the new size.
+pub(crate) fn push_to_peaks<T, M: Merge<Item = T>>(
+    peaks: &mut Vec<(Pos, T)>,
+    mut mmr_size: Pos,
+    leaf: T,
+) -> Result<Pos> {
+    peaks.push((mmr_size, leaf));
+    mmr_size += 1;
+    while peaks.len() > 1 {
+        let (right_pos, _) = &peaks[peaks.len() - 1];
+        let (left_pos, _) = &peaks[peaks.len() - 2];
+        if pos_height_in_tree(*left_pos) != pos_height_in_tree(*right_pos) {
+            break;
+        }
+        let (_, right) = peaks.pop().expect("pop");
+        let (_, left) = peaks.pop().expect("pop");
+        peaks.push((mmr_size, merge_node::<_, M>(mmr_size, &left, &right)?));
+        mmr_size += 1;
+    }
+    Ok(mmr_size)
+}
+
+/// Sort `items` by position and drop the duplicates, failing with `CorruptedProof` if a position
+/// is given twice with different items.
+pub(crate) fn sort_and_dedup_by_pos<T: PartialEq>(items: &mut Vec<(Pos, T)>) -> Result<()> {
+    items.sort_by_key(|(pos, _)| *pos);
+    if items
+        .windows(2)
+        .any(|pair| pair[0].0 == pair[1].0 && pair[0].1 != pair[1].1)
+    {
+        return Err(Error::CorruptedProof);
+    }
+    items.dedup_by(|a, b| a.0 == b.0);
+    Ok(())
+}
+
+pub(crate) fn take_while_vec<T, P: Fn(&T) -> bool>(v: &mut Vec<T>, p: P) -> Vec<T> {
+    for i in 0..v.len() {
+        if !p(&v[i]) {
+            return v.drain(..i).collect();
+        }
+    }
+    core::mem::take(v)
+}
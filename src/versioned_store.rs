@@ -0,0 +1,292 @@
+//! Snapshot isolation for MMRs sharing a store.
+//!
+//! Several `MMR` handles can share one `&VersionedStore`, e.g. a writer pushing and committing
+//! new leaves while readers generate proofs. Each committed transaction publishes a new version,
+//! `MMR::at_version` pins an MMR to one of them, so a proof generated from the pinned MMR only
+//! sees the nodes of that version, whatever the writer commits meanwhile.
+//!
+//! Committed nodes are only overwritten by `MMR::rebuild_range`, the store keeps their previous
+//! values for the older versions until they are pruned. Rewinding the store retires the versions
+//! larger than the new size, reads pinned to them fail with `Error::UnknownVersion`.
+
+use crate::collections::BTreeMap;
+use crate::helper::get_peak_map;
+use crate::{
+    vec, vec::Vec, Error, MMRStoreDeleteOps, MMRStoreReadOps, MMRStoreWriteOps, Pos, Result, MMR,
+};
+use core::cell::{Cell, RefCell};
+
+pub struct VersionedStore<T, S> {
+    store: RefCell<S>,
+    // the mmr size of each version, `None` once retired
+    versions: RefCell<Vec<Option<Pos>>>,
+    // the values rewritten in the store, with the last version they belong to
+    history: RefCell<BTreeMap<Pos, Vec<(u64, T)>>>,
+    // the mmr size written by the open transaction
+    pending_size: Cell<Option<Pos>>,
+    dirty: Cell<bool>,
+}
+
+impl<T, S> VersionedStore<T, S> {
+    /// Wrap `store`, holding an MMR of `mmr_size` as version 0.
+    pub fn new(mmr_size: Pos, store: S) -> Self {
+        VersionedStore {
+            store: RefCell::new(store),
+            versions: RefCell::new(vec![Some(mmr_size)]),
+            history: RefCell::new(BTreeMap::new()),
+            pending_size: Cell::new(None),
+            dirty: Cell::new(false),
+        }
+    }
+
+    /// The latest version, published by the last committed transaction.
+    pub fn version(&self) -> u64 {
+        self.versions.borrow().len() as u64 - 1
+    }
+
+    /// The MMR size of `version`.
+    pub fn mmr_size_at(&self, version: u64) -> Result<Pos> {
+        self.versions
+            .borrow()
+            .get(version as usize)
+            .copied()
+            .flatten()
+            .ok_or(Error::UnknownVersion { version })
+    }
+
+    /// Read-only access to the store as of `version`.
+    pub fn pin(&self, version: u64) -> Result<PinnedStore<'_, T, S>> {
+        let mmr_size = self.mmr_size_at(version)?;
+        Ok(PinnedStore {
+            store: self,
+            version,
+            mmr_size,
+        })
+    }
+
+    /// Retire the versions before `version` and drop the values only they could read.
+    pub fn prune(&self, version: u64) {
+        let mut versions = self.versions.borrow_mut();
+        let end = (version as usize).min(versions.len() - 1);
+        for size in &mut versions[..end] {
+            *size = None;
+        }
+        let mut history = self.history.borrow_mut();
+        for values in history.values_mut() {
+            values.retain(|(last_version, _)| *last_version >= version);
+        }
+        history.retain(|_, values| !values.is_empty());
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store.into_inner()
+    }
+
+    fn latest_mmr_size(&self) -> Pos {
+        // the latest version is never retired
+        self.versions
+            .borrow()
+            .last()
+            .copied()
+            .flatten()
+            .unwrap_or(0)
+    }
+}
+
+impl<T, S: MMRStoreReadOps<T>> MMRStoreReadOps<T> for &VersionedStore<T, S> {
+    fn get_elem(&self, pos: Pos) -> Result<Option<T>> {
+        self.store.borrow().get_elem(pos)
+    }
+
+    fn get_elems(&self, range: core::ops::Range<Pos>) -> Result<Vec<Option<T>>> {
+        self.store.borrow().get_elems(range)
+    }
+
+    fn get_leaf_meta(&self, leaf_index: Pos) -> Result<Option<Vec<u8>>> {
+        self.store.borrow().get_leaf_meta(leaf_index)
+    }
+
+    #[cfg(feature = "root-log")]
+    fn get_root_at(&self, mmr_size: Pos) -> Result<Option<T>> {
+        self.store.borrow().get_root_at(mmr_size)
+    }
+
+    #[cfg(feature = "leaf-index")]
+    fn get_leaf_positions(&self, leaf: &T) -> Result<Vec<Pos>>
+    where
+        T: PartialEq,
+    {
+        self.store.borrow().get_leaf_positions(leaf)
+    }
+
+    fn mmr_size(&self) -> Result<Option<Pos>> {
+        Ok(Some(self.latest_mmr_size()))
+    }
+}
+
+impl<T: Clone, S: MMRStoreReadOps<T> + MMRStoreWriteOps<T>> MMRStoreWriteOps<T>
+    for &VersionedStore<T, S>
+{
+    fn append(&mut self, pos: Pos, elems: Vec<T>) -> Result<()> {
+        self.dirty.set(true);
+        self.store.borrow_mut().append(pos, elems)
+    }
+
+    fn overwrite(&mut self, pos: Pos, elem: T) -> Result<()> {
+        let mut store = self.store.borrow_mut();
+        // keep the committed value for the versions pinned before the rewrite
+        if let Some(prev) = store.get_elem(pos)? {
+            self.history
+                .borrow_mut()
+                .entry(pos)
+                .or_default()
+                .push((self.version(), prev));
+        }
+        self.dirty.set(true);
+        store.overwrite(pos, elem)
+    }
+
+    fn supports_leaf_meta(&self) -> bool {
+        self.store.borrow().supports_leaf_meta()
+    }
+
+    fn append_leaf_meta(&mut self, leaf_index: Pos, meta: Vec<u8>) -> Result<()> {
+        self.dirty.set(true);
+        self.store.borrow_mut().append_leaf_meta(leaf_index, meta)
+    }
+
+    #[cfg(feature = "root-log")]
+    fn supports_root_log(&self) -> bool {
+        self.store.borrow().supports_root_log()
+    }
+
+    #[cfg(feature = "root-log")]
+    fn append_root(&mut self, mmr_size: Pos, root: T) -> Result<()> {
+        self.store.borrow_mut().append_root(mmr_size, root)
+    }
+
+    #[cfg(feature = "leaf-index")]
+    fn append_leaf_index(&mut self, pos: Pos, leaf: T) -> Result<()> {
+        self.store.borrow_mut().append_leaf_index(pos, leaf)
+    }
+
+    fn set_mmr_size(&mut self, mmr_size: Pos) -> Result<()> {
+        self.pending_size.set(Some(mmr_size));
+        self.store.borrow_mut().set_mmr_size(mmr_size)
+    }
+
+    fn start_transaction(&mut self) -> Result<()> {
+        self.store.borrow_mut().start_transaction()
+    }
+
+    /// Publish the writes of the transaction as a new version.
+    fn commit_transaction(&mut self) -> Result<()> {
+        self.store.borrow_mut().commit_transaction()?;
+        let pending_size = self.pending_size.take();
+        if self.dirty.replace(false) || pending_size.is_some() {
+            let mmr_size = pending_size.unwrap_or_else(|| self.latest_mmr_size());
+            self.versions.borrow_mut().push(Some(mmr_size));
+        }
+        Ok(())
+    }
+
+    fn abort_transaction(&mut self) -> Result<()> {
+        self.pending_size.set(None);
+        self.dirty.set(false);
+        self.store.borrow_mut().abort_transaction()
+    }
+}
+
+impl<T, S: MMRStoreDeleteOps<T>> MMRStoreDeleteOps<T> for &VersionedStore<T, S> {
+    /// Truncate the store, retire the versions larger than `mmr_size` and publish the truncated
+    /// store as a new version.
+    fn truncate(&mut self, mmr_size: Pos) -> Result<()> {
+        self.store.borrow_mut().truncate(mmr_size)?;
+        let mut versions = self.versions.borrow_mut();
+        for size in versions.iter_mut() {
+            if size.is_some_and(|size| size > mmr_size) {
+                *size = None;
+            }
+        }
+        versions.push(Some(mmr_size));
+        Ok(())
+    }
+}
+
+/// Read-only access to a `VersionedStore` as of a version, see `VersionedStore::pin`.
+pub struct PinnedStore<'a, T, S> {
+    store: &'a VersionedStore<T, S>,
+    version: u64,
+    mmr_size: Pos,
+}
+
+impl<'a, T, S> PinnedStore<'a, T, S> {
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn check_version(&self) -> Result<()> {
+        self.store.mmr_size_at(self.version).map(|_| ())
+    }
+}
+
+impl<'a, T: Clone, S: MMRStoreReadOps<T>> MMRStoreReadOps<T> for PinnedStore<'a, T, S> {
+    fn get_elem(&self, pos: Pos) -> Result<Option<T>> {
+        self.check_version()?;
+        if pos >= self.mmr_size {
+            return Ok(None);
+        }
+        let history = self.store.history.borrow();
+        let rewritten = history.get(&pos).and_then(|values| {
+            values
+                .iter()
+                .find(|(last_version, _)| *last_version >= self.version)
+        });
+        match rewritten {
+            Some((_, elem)) => Ok(Some(elem.clone())),
+            None => self.store.store.borrow().get_elem(pos),
+        }
+    }
+
+    fn get_leaf_meta(&self, leaf_index: Pos) -> Result<Option<Vec<u8>>> {
+        self.check_version()?;
+        if leaf_index >= get_peak_map(self.mmr_size) {
+            return Ok(None);
+        }
+        self.store.store.borrow().get_leaf_meta(leaf_index)
+    }
+
+    #[cfg(feature = "root-log")]
+    fn get_root_at(&self, mmr_size: Pos) -> Result<Option<T>> {
+        self.check_version()?;
+        if mmr_size > self.mmr_size {
+            return Ok(None);
+        }
+        self.store.store.borrow().get_root_at(mmr_size)
+    }
+
+    #[cfg(feature = "leaf-index")]
+    fn get_leaf_positions(&self, leaf: &T) -> Result<Vec<Pos>>
+    where
+        T: PartialEq,
+    {
+        self.check_version()?;
+        let mut positions = self.store.store.borrow().get_leaf_positions(leaf)?;
+        positions.retain(|pos| *pos < self.mmr_size);
+        Ok(positions)
+    }
+
+    fn mmr_size(&self) -> Result<Option<Pos>> {
+        Ok(Some(self.mmr_size))
+    }
+}
+
+impl<'a, T, M, S> MMR<T, M, &'a VersionedStore<T, S>> {
+    /// The MMR as of `version` of its store, for reads and proofs isolated from later commits.
+    ///
+    /// The pending pushes of this MMR are left out, as are the commits after `version`.
+    pub fn at_version(&self, version: u64) -> Result<MMR<T, M, PinnedStore<'a, T, S>>> {
+        let store = (*self.store()).pin(version)?;
+        Ok(MMR::new(store.mmr_size, store))
+    }
+}
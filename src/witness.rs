@@ -0,0 +1,180 @@
+//! Witnesses for SNARK circuits
+//!
+//! Circuits take inputs of a fixed shape, so `MerkleProof::to_witness` lays out a proof as one
+//! path per leaf, from the leaf up to its peak, padded to `MAX_PATH_LEN` siblings, along with
+//! the bits telling the side of each sibling and the peaks to bag, padded to `MAX_PEAKS`.
+//! `verify_witness` verifies a witness the way a circuit would, to cross-check the circuit
+//! logic against this crate.
+
+use crate::collections::BTreeMap;
+use crate::helper::{
+    get_peaks, parent_offset, pos_height_in_tree, PositionScheme, PostOrderScheme,
+};
+use crate::vec::Vec;
+use crate::verifier::{
+    bagging_peaks_hashes, calculate_peaks_hashes_with, merge_node, sort_and_dedup_by_pos,
+    Iterations,
+};
+use crate::{Error, Merge, MerkleProof, Pos, Result};
+
+/// Length of the longest path from a leaf to its peak, the length of the padded paths.
+pub const MAX_PATH_LEN: usize = Pos::BITS as usize - 1;
+/// The most peaks an MMR has, the length of the padded peaks.
+pub const MAX_PEAKS: usize = Pos::BITS as usize;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MmrWitness<T> {
+    pub mmr_size: Pos,
+    /// Hashes of the peaks from left to right, padded with `T::default()`. If the proof bagged
+    /// the peaks right of the proven leaves into one item, that item is the last peak.
+    pub peaks: Vec<T>,
+    pub peak_count: usize,
+    /// One for each proven leaf, sorted by position.
+    pub leaves: Vec<LeafWitness<T>>,
+}
+
+/// The path of a leaf to its peak.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeafWitness<T> {
+    pub pos: Pos,
+    pub leaf: T,
+    /// Siblings from the leaf up, padded with `T::default()`.
+    pub path: Vec<T>,
+    pub path_len: usize,
+    /// Whether each sibling is on the left, padded with `false`.
+    pub directions: Vec<bool>,
+    /// Index of the peak of the leaf in `MmrWitness::peaks`.
+    pub peak_index: usize,
+}
+
+impl<T: Clone + Default + PartialEq, M: Merge<Item = T>> MerkleProof<T, M> {
+    /// Lay out the proof of `leaves` as a witness, failing like `calculate_root` if it doesn't
+    /// prove them. The witness proves the leaves only if the root doesn't mismatch, see
+    /// `verify_witness`.
+    pub fn to_witness(&self, mut leaves: Vec<(Pos, T)>) -> Result<MmrWitness<T>> {
+        sort_and_dedup_by_pos(&mut leaves)?;
+        // every node the verification passes, to look up the siblings along each path
+        let mut nodes: BTreeMap<Pos, T> = leaves.iter().cloned().collect();
+        let mut record_merge = |pos: Pos, lhs: &T, rhs: &T| -> Result<T> {
+            let parent = merge_node::<T, M>(pos, lhs, rhs)?;
+            let (left_pos, right_pos) =
+                PostOrderScheme::children_pos(pos).ok_or(Error::CorruptedProof)?;
+            nodes.insert(left_pos, lhs.clone());
+            nodes.insert(right_pos, rhs.clone());
+            nodes.insert(pos, parent.clone());
+            Ok(parent)
+        };
+        let mut peaks = calculate_peaks_hashes_with(
+            leaves.clone(),
+            self.mmr_size(),
+            self.proof_items().iter(),
+            &mut Iterations::unbounded(),
+            &mut record_merge,
+        )?;
+
+        let peaks_pos = get_peaks(self.mmr_size());
+        let mut leaf_witnesses = Vec::with_capacity(leaves.len());
+        for (leaf_pos, leaf) in leaves {
+            let peak_index = peaks_pos
+                .iter()
+                .position(|peak_pos| *peak_pos >= leaf_pos)
+                .ok_or(Error::CorruptedProof)?;
+            let mut path = Vec::with_capacity(MAX_PATH_LEN);
+            let mut directions = Vec::with_capacity(MAX_PATH_LEN);
+            let mut pos = leaf_pos;
+            let mut height = 0;
+            while pos < peaks_pos[peak_index] {
+                let (sibling_pos, parent_pos, is_right) =
+                    PostOrderScheme::sibling_and_parent_pos(pos, height);
+                path.push(
+                    nodes
+                        .get(&sibling_pos)
+                        .ok_or(Error::CorruptedProof)?
+                        .clone(),
+                );
+                directions.push(is_right);
+                pos = parent_pos;
+                height += 1;
+            }
+            let path_len = path.len();
+            path.resize(MAX_PATH_LEN, T::default());
+            directions.resize(MAX_PATH_LEN, false);
+            leaf_witnesses.push(LeafWitness {
+                pos: leaf_pos,
+                leaf,
+                path,
+                path_len,
+                directions,
+                peak_index,
+            });
+        }
+        let peak_count = peaks.len();
+        peaks.resize(MAX_PEAKS, T::default());
+        Ok(MmrWitness {
+            mmr_size: self.mmr_size(),
+            peaks,
+            peak_count,
+            leaves: leaf_witnesses,
+        })
+    }
+}
+
+/// Verify `witness` against `root` the way a circuit would: hash each leaf up its path, with
+/// the directions matching its position, to its peak, then bag the peaks. Fails with
+/// `CorruptedProof` if the counts of the witness exceed its arrays, the peaks of the MMR or the
+/// `MAX_PATH_LEN` and `MAX_PEAKS` bounds.
+pub fn verify_witness<T: Clone + PartialEq, M: Merge<Item = T>>(
+    root: T,
+    witness: &MmrWitness<T>,
+) -> Result<bool> {
+    let peaks_pos = get_peaks(witness.mmr_size);
+    if witness.peak_count == 0
+        || witness.peak_count > MAX_PEAKS
+        || witness.peak_count > peaks_pos.len()
+        || witness.peak_count > witness.peaks.len()
+    {
+        return Err(Error::CorruptedProof);
+    }
+    for leaf in &witness.leaves {
+        if leaf.peak_index >= witness.peak_count
+            || leaf.path_len > MAX_PATH_LEN
+            || leaf.path_len > leaf.path.len()
+            || leaf.path_len > leaf.directions.len()
+        {
+            return Err(Error::CorruptedProof);
+        }
+        if leaf.pos >= witness.mmr_size || pos_height_in_tree(leaf.pos) > 0 {
+            return Ok(false);
+        }
+        let mut pos = leaf.pos;
+        let mut node = leaf.leaf.clone();
+        for height in 0..leaf.path_len {
+            let height_u8 = height as u8;
+            let is_right = pos_height_in_tree(pos + 1) > height_u8;
+            if leaf.directions[height] != is_right {
+                return Ok(false);
+            }
+            let sibling = &leaf.path[height];
+            let parent_pos = if is_right {
+                pos.checked_add(1)
+            } else {
+                pos.checked_add(parent_offset(height_u8))
+            };
+            // a path climbing past the MMR can't reach its peak
+            pos = match parent_pos {
+                Some(parent_pos) if parent_pos < witness.mmr_size => parent_pos,
+                _ => return Ok(false),
+            };
+            node = if is_right {
+                merge_node::<T, M>(pos, sibling, &node)?
+            } else {
+                merge_node::<T, M>(pos, &node, sibling)?
+            };
+        }
+        if pos != peaks_pos[leaf.peak_index] || node != witness.peaks[leaf.peak_index] {
+            return Ok(false);
+        }
+    }
+    let peaks = witness.peaks[..witness.peak_count].to_vec();
+    Ok(bagging_peaks_hashes::<T, M>(peaks)? == root)
+}